@@ -0,0 +1,249 @@
+//! 请求捕获：把选中请求的客户端请求体、转换后的 v1internal 请求与上游原始 SSE 行
+//! 写入 data_dir/captures/<时间>-<id>.json（已脱敏），供 `replay-capture` 子命令离线重放。
+//!
+//! 通过 `CAPTURE_MODELS` 选择模型（为空即关闭），`CAPTURE_MAX_BUNDLES` 限制保留数量（按文件名淘汰最旧的，0 表示不限制）。
+
+pub mod replay;
+
+use crate::config::Config;
+use crate::gateway::openai::types::Message;
+use crate::vertex::types::{Request, Response};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const CAPTURE_DIR: &str = "captures";
+const BUNDLE_VERSION: u32 = 1;
+/// 脱敏占位符（project / sessionId / requestId 等每次请求都会变化或属于账号的字段）。
+pub const REDACTED: &str = "<redacted>";
+
+/// 客户端请求中与转换无关、可能标识用户的字段。
+const CLIENT_REDACT_FIELDS: [&str; 4] =
+    ["user", "metadata", "safety_identifier", "prompt_cache_key"];
+
+/// 捕获请求所属的客户端接口。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureProvider {
+    /// /v1/chat/completions
+    Openai,
+    /// /v1/messages
+    Claude,
+    /// /v1beta/models/{model}:generateContent
+    Gemini,
+    /// /v1/responses
+    Responses,
+}
+
+/// 捕获包。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureBundle {
+    pub version: u32,
+    pub id: String,
+    pub created_at: String,
+    pub provider: CaptureProvider,
+    pub path: String,
+    /// 映射后的模型 ID（重放时不依赖模型 ID 映射配置）。
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_name: Option<String>,
+    pub stream: bool,
+    /// 客户端请求体（已移除 user / metadata 等字段）。
+    pub client_request: serde_json::Value,
+    /// Responses API：previous_response_id 对应的已保存历史消息（重放时不依赖响应存储）。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_messages: Option<serde_json::Value>,
+    /// 转换后的 v1internal 请求（已脱敏）。
+    pub vertex_request: serde_json::Value,
+    /// 上游原始 SSE 行；非流式请求为合并后响应的单条 `data:` 行。
+    #[serde(default)]
+    pub upstream_lines: Vec<String>,
+    /// 上游请求失败时的错误信息（此时没有 SSE 行）。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_error: Option<String>,
+}
+
+impl CaptureBundle {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("无法读取捕获包 {}: {e}", path.display()))?;
+        serde_json::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("捕获包格式无效 {}: {e}", path.display()))
+    }
+}
+
+/// 单个请求的捕获记录器；请求结束时调用 [`CaptureRecorder::finish`] 落盘。
+pub struct CaptureRecorder {
+    bundle: CaptureBundle,
+    data_dir: String,
+    max_bundles: usize,
+}
+
+/// 请求转换完成后开始捕获；模型未被 `CAPTURE_MODELS` 选中时返回 None。
+#[allow(clippy::too_many_arguments)]
+pub fn begin(
+    cfg: &Config,
+    provider: CaptureProvider,
+    path: &str,
+    model: &str,
+    api_key_name: Option<&str>,
+    stream: bool,
+    client_body: &[u8],
+    vreq: &Request,
+) -> Option<CaptureRecorder> {
+    if !cfg.capture_enabled_for(model) {
+        return None;
+    }
+    let vertex_request = match redacted_vertex_request(vreq) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("捕获请求失败（序列化 v1internal 请求）: {e}");
+            return None;
+        }
+    };
+
+    let now = chrono::Utc::now();
+    let id = uuid::Uuid::new_v4().simple().to_string()[..12].to_string();
+    Some(CaptureRecorder {
+        bundle: CaptureBundle {
+            version: BUNDLE_VERSION,
+            id: format!("{}-{id}", now.format("%Y%m%d-%H%M%S")),
+            created_at: now.to_rfc3339(),
+            provider,
+            path: path.to_string(),
+            model: model.to_string(),
+            api_key_name: api_key_name.map(str::to_string),
+            stream,
+            client_request: redacted_client_request(client_body),
+            previous_messages: None,
+            vertex_request,
+            upstream_lines: Vec::new(),
+            upstream_error: None,
+        },
+        data_dir: cfg.data_dir.clone(),
+        max_bundles: cfg.capture_max_bundles,
+    })
+}
+
+impl CaptureRecorder {
+    /// Responses API：记录 previous_response_id 续接的历史消息。
+    pub fn set_previous_messages(&mut self, messages: &[Message]) {
+        if messages.is_empty() {
+            return;
+        }
+        let value = sonic_rs::to_string(messages)
+            .map_err(anyhow::Error::from)
+            .and_then(|s| serde_json::from_str(&s).map_err(anyhow::Error::from));
+        match value {
+            Ok(v) => self.bundle.previous_messages = Some(v),
+            Err(e) => tracing::warn!("捕获历史消息失败: {e}"),
+        }
+    }
+
+    /// 记录一行上游原始 SSE（空行忽略）。
+    pub fn push_line(&mut self, line: &[u8]) {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if !line.is_empty() {
+            self.bundle
+                .upstream_lines
+                .push(String::from_utf8_lossy(line).into_owned());
+        }
+    }
+
+    /// 非流式请求：以单条 `data:` 行记录合并后的上游响应。
+    pub fn push_response(&mut self, resp: &Response) {
+        if let Ok(json) = serde_json::to_string(resp) {
+            self.bundle.upstream_lines.push(format!("data: {json}"));
+        }
+    }
+
+    /// 记录上游请求失败（例如转换后的请求被后端以 400 拒绝）。
+    pub fn set_upstream_error(&mut self, msg: &str) {
+        self.bundle.upstream_error = Some(msg.to_string());
+    }
+
+    /// 写入捕获包并淘汰超出保留数量的旧包。
+    pub async fn finish(self) {
+        let dir = Path::new(&self.data_dir).join(CAPTURE_DIR);
+        let path = dir.join(format!("{}.json", self.bundle.id));
+        let res = async {
+            tokio::fs::create_dir_all(&dir).await?;
+            let content = serde_json::to_string_pretty(&self.bundle)?;
+            tokio::fs::write(&path, format!("{content}\n")).await?;
+            anyhow::Ok(())
+        }
+        .await;
+        match res {
+            Ok(()) => tracing::info!("已捕获请求: {}", path.display()),
+            Err(e) => {
+                tracing::warn!("写入捕获包失败 {}: {e}", path.display());
+                return;
+            }
+        }
+
+        if let Err(e) = prune_bundles(&dir, self.max_bundles).await {
+            tracing::warn!("清理旧捕获包失败: {e}");
+        }
+    }
+}
+
+/// v1internal 请求 -> JSON，并把账号相关 / 每次随机的字段替换为占位符。
+pub fn redacted_vertex_request(vreq: &Request) -> serde_json::Result<serde_json::Value> {
+    let mut v = serde_json::to_value(vreq)?;
+    if let Some(obj) = v.as_object_mut() {
+        for key in ["project", "requestId"] {
+            obj.insert(key.to_string(), REDACTED.into());
+        }
+        if let Some(inner) = obj.get_mut("request").and_then(|r| r.as_object_mut()) {
+            inner.insert("sessionId".to_string(), REDACTED.into());
+        }
+    }
+    Ok(v)
+}
+
+fn redacted_client_request(body: &[u8]) -> serde_json::Value {
+    let Ok(mut v) = serde_json::from_slice::<serde_json::Value>(body) else {
+        return String::from_utf8_lossy(body).into_owned().into();
+    };
+    if let Some(obj) = v.as_object_mut() {
+        for key in CLIENT_REDACT_FIELDS {
+            obj.remove(key);
+        }
+    }
+    v
+}
+
+async fn prune_bundles(dir: &Path, max_bundles: usize) -> std::io::Result<()> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    let mut files: Vec<PathBuf> = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    if max_bundles == 0 || files.len() <= max_bundles {
+        return Ok(());
+    }
+    // 文件名以时间开头，按名称排序即按时间排序。
+    files.sort();
+    let excess = files.len() - max_bundles;
+    for path in files.into_iter().take(excess) {
+        tokio::fs::remove_file(&path).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_request_drops_identifying_fields() {
+        let v = redacted_client_request(
+            br#"{"model":"m","user":"u@example.com","metadata":{"user_id":"x"},"messages":[]}"#,
+        );
+        assert_eq!(v, serde_json::json!({"model": "m", "messages": []}));
+        assert_eq!(redacted_client_request(b"not json"), "not json");
+    }
+}
@@ -0,0 +1,500 @@
+//! 离线重放：按捕获包重新执行请求转换与响应改写，上游由本地 mock 后端按捕获的 SSE 行回放。
+//!
+//! 用于把线上的转换问题固化为回归测试：`ant2api replay-capture <bundle.json>`，
+//! 或在测试中调用 [`replay_bundle`] 断言输出。
+
+use super::{CaptureBundle, CaptureProvider, REDACTED, redacted_vertex_request};
+use crate::config::Config;
+use crate::gateway::common::AccountContext;
+use crate::gateway::{claude, gemini, openai};
+use crate::signature::manager::Manager as SignatureManager;
+use crate::util::id;
+use crate::vertex::stream::parse_stream_with_result;
+use crate::vertex::types::{Request, Response};
+use anyhow::Context;
+use axum::Router;
+use axum::body::Bytes;
+use axum::http::{StatusCode, header};
+use axum::response::IntoResponse;
+use serde::Serialize;
+use std::net::SocketAddr;
+
+/// 重放结果。
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayReport {
+    /// 重新转换得到的 v1internal 请求（已脱敏）。
+    pub vertex_request: serde_json::Value,
+    /// 与捕获时的 v1internal 请求不一致（转换逻辑或配置已变化）。
+    pub vertex_request_changed: bool,
+    /// 捕获时上游请求失败的错误信息（此时不回放响应）。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_error: Option<String>,
+    /// 改写后返回给客户端的内容：流式为逐条 SSE 事件，非流式为单个响应体。
+    pub client_output: Vec<String>,
+}
+
+/// 各接口改写响应所需的请求参数。
+enum Pipeline {
+    Openai {
+        include_usage: bool,
+    },
+    Claude {
+        single_tool_use: bool,
+        stop_sequences: Vec<String>,
+    },
+    Gemini,
+    Responses {
+        meta: openai::responses::ResponseMeta,
+    },
+}
+
+/// 重放一个捕获包。
+pub async fn replay_bundle(
+    cfg: &Config,
+    sig_mgr: &SignatureManager,
+    bundle: &CaptureBundle,
+) -> anyhow::Result<ReplayReport> {
+    let account = AccountContext {
+        project_id: REDACTED.to_string(),
+        session_id: REDACTED.to_string(),
+        api_key_name: bundle.api_key_name.clone(),
+        ..AccountContext::default()
+    };
+    let body = serde_json::to_vec(&bundle.client_request)?;
+    let model = bundle.model.as_str();
+
+    let (vreq, request_id, pipeline) = match bundle.provider {
+        CaptureProvider::Openai => {
+            let mut req: openai::types::ChatRequest =
+                sonic_rs::from_slice(&body).context("客户端请求解析失败")?;
+            req.model = model.to_string();
+            let include_usage = req.stream_options.as_ref().is_some_and(|o| o.include_usage);
+            let (vreq, request_id) =
+                openai::convert::to_vertex_request(cfg, sig_mgr, &mut req, &account).await?;
+            (vreq, request_id, Pipeline::Openai { include_usage })
+        }
+        CaptureProvider::Claude => {
+            let mut req: claude::MessagesRequest =
+                sonic_rs::from_slice(&body).context("客户端请求解析失败")?;
+            req.model = model.to_string();
+            let (vreq, request_id) =
+                claude::to_vertex_request(cfg, sig_mgr, &req, &account).await?;
+            let pipeline = Pipeline::Claude {
+                single_tool_use: claude::parallel_tool_use_disabled(req.tool_choice.as_ref()),
+                stop_sequences: std::mem::take(&mut req.stop_sequences),
+            };
+            (vreq, request_id, pipeline)
+        }
+        CaptureProvider::Gemini => {
+            let req: gemini::GenerateContentRequest =
+                sonic_rs::from_slice(&body).context("客户端请求解析失败")?;
+            let (vreq, request_id) = gemini::to_vertex_request(cfg, req, model, &account)?;
+            (vreq, request_id, Pipeline::Gemini)
+        }
+        CaptureProvider::Responses => {
+            let req: openai::responses::ResponsesRequest =
+                sonic_rs::from_slice(&body).context("客户端请求解析失败")?;
+            let history: Vec<openai::types::Message> = match &bundle.previous_messages {
+                // sonic_rs::Value 只能由 sonic_rs 反序列化。
+                Some(v) => sonic_rs::from_str(&v.to_string()).context("历史消息解析失败")?,
+                None => Vec::new(),
+            };
+            let messages = openai::responses::to_chat_messages(&req.input, history);
+            let meta = openai::responses::response_meta(&req, model, openai::stream::now_unix());
+            let mut chat = openai::responses::to_chat_request(&req, model, messages);
+            let (vreq, request_id) =
+                openai::convert::to_vertex_request(cfg, sig_mgr, &mut chat, &account).await?;
+            (vreq, request_id, Pipeline::Responses { meta })
+        }
+    };
+
+    let vertex_request = redacted_vertex_request(&vreq)?;
+    let vertex_request_changed = vertex_request != bundle.vertex_request;
+    if let Some(err) = &bundle.upstream_error {
+        return Ok(ReplayReport {
+            vertex_request,
+            vertex_request_changed,
+            upstream_error: Some(err.clone()),
+            client_output: Vec::new(),
+        });
+    }
+
+    let backend = MockBackend::start(bundle.upstream_lines.clone()).await?;
+    let client_output = async {
+        let resp = backend.send(&vreq).await?;
+        if bundle.stream {
+            Ok(replay_stream(resp, &pipeline, model, &request_id).await)
+        } else {
+            replay_non_stream(resp, &pipeline, model, &request_id, sig_mgr).await
+        }
+    }
+    .await;
+    backend.stop();
+    let client_output = client_output?;
+
+    Ok(ReplayReport {
+        vertex_request,
+        vertex_request_changed,
+        upstream_error: None,
+        client_output,
+    })
+}
+
+async fn replay_stream(
+    resp: reqwest::Response,
+    pipeline: &Pipeline,
+    model: &str,
+    request_id: &str,
+) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    match pipeline {
+        Pipeline::Openai { include_usage } => {
+            let mut writer = openai::stream::StreamWriter::new(
+                id::chat_completion_id(),
+                openai::stream::now_unix(),
+                model.to_string(),
+                request_id.to_string(),
+                *include_usage,
+                false,
+            );
+            let result = parse_stream_with_result(
+                resp,
+                |data| {
+                    out.extend(writer.process_chunk(data).0);
+                    async { Ok(()) }
+                },
+                false,
+                |_| {},
+            )
+            .await
+            .unwrap_or_else(|e| e.result);
+            out.extend(writer.finish_stream(&result));
+        }
+        Pipeline::Claude {
+            single_tool_use,
+            stop_sequences,
+        } => {
+            let mut writer =
+                claude::ClaudeStreamWriter::new(request_id.to_string(), model.to_string());
+            writer.set_single_tool_use(*single_tool_use);
+            writer.set_stop_sequences(stop_sequences);
+            let mut events: Vec<(&'static str, String)> = Vec::new();
+            let result = parse_stream_with_result(
+                resp,
+                |data| {
+                    events.extend(writer.process_chunk(data).0);
+                    let stopped = writer.stop_sequence().is_some();
+                    async move {
+                        if stopped {
+                            anyhow::bail!("已命中 stop sequence");
+                        }
+                        Ok(())
+                    }
+                },
+                false,
+                |_| {},
+            )
+            .await
+            .unwrap_or_else(|e| e.result);
+            events.extend(writer.finish_stream(&result));
+            out.extend(sse_lines(events));
+        }
+        Pipeline::Gemini => {
+            let _ = parse_stream_with_result(
+                resp,
+                |data| {
+                    let chunk = gemini::stream_chunk_response(data, model, request_id);
+                    out.extend(sonic_rs::to_string(&chunk).ok());
+                    async { Ok(()) }
+                },
+                false,
+                |_| {},
+            )
+            .await;
+        }
+        Pipeline::Responses { meta } => {
+            let mut writer =
+                openai::responses::ResponsesStreamWriter::new(meta.clone(), request_id.to_string());
+            let mut events = writer.start();
+            let result = parse_stream_with_result(
+                resp,
+                |data| {
+                    events.extend(writer.process_chunk(data).0);
+                    async { Ok(()) }
+                },
+                false,
+                |_| {},
+            )
+            .await
+            .unwrap_or_else(|e| e.result);
+            events.extend(writer.finish_stream(&result));
+            out.extend(sse_lines(events));
+        }
+    }
+    out
+}
+
+fn sse_lines(events: Vec<(&'static str, String)>) -> impl Iterator<Item = String> {
+    events
+        .into_iter()
+        .map(|(name, data)| format!("event: {name}\ndata: {data}"))
+}
+
+async fn replay_non_stream(
+    resp: reqwest::Response,
+    pipeline: &Pipeline,
+    model: &str,
+    request_id: &str,
+    sig_mgr: &SignatureManager,
+) -> anyhow::Result<Vec<String>> {
+    let merged = parse_stream_with_result(resp, |_| async { Ok(()) }, true, |_| {})
+        .await
+        .map_err(|e| e.source)?
+        .merged_response
+        .context("上游响应为空")?;
+    let vresp: Response = sonic_rs::from_value(&merged)?;
+
+    let body = match pipeline {
+        Pipeline::Openai { .. } => {
+            let out = openai::convert::to_chat_completion(&vresp, model, request_id, sig_mgr).await;
+            sonic_rs::to_string(&out)?
+        }
+        Pipeline::Claude {
+            single_tool_use,
+            stop_sequences,
+        } => {
+            let out = claude::to_messages_response(
+                &vresp,
+                request_id,
+                model,
+                sig_mgr,
+                *single_tool_use,
+                stop_sequences,
+            )
+            .await;
+            sonic_rs::to_string(&out)?
+        }
+        Pipeline::Gemini => {
+            let out = gemini::to_generate_content_response(vresp, model, request_id);
+            sonic_rs::to_string(&out)?
+        }
+        Pipeline::Responses { meta } => {
+            let (out, _) =
+                openai::responses::to_response_object(meta, &vresp, model, request_id, sig_mgr)
+                    .await;
+            sonic_rs::to_string(&out)?
+        }
+    };
+    Ok(vec![body])
+}
+
+/// 本地 mock 后端：校验请求体是合法的 v1internal 请求，然后原样回放捕获的 SSE 行。
+struct MockBackend {
+    addr: SocketAddr,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl MockBackend {
+    async fn start(lines: Vec<String>) -> anyhow::Result<Self> {
+        let mut body = lines.join("\n");
+        body.push('\n');
+        let app = Router::new().fallback(move |req: Bytes| {
+            let body = body.clone();
+            async move {
+                if sonic_rs::from_slice::<Request>(&req).is_err() {
+                    return (StatusCode::BAD_REQUEST, "invalid v1internal request").into_response();
+                }
+                ([(header::CONTENT_TYPE, "text/event-stream")], body).into_response()
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        Ok(Self { addr, server })
+    }
+
+    async fn send(&self, vreq: &Request) -> anyhow::Result<reqwest::Response> {
+        let url = format!(
+            "http://{}/v1internal:streamGenerateContent?alt=sse",
+            self.addr
+        );
+        let resp = reqwest::Client::builder()
+            .no_proxy()
+            .build()?
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(sonic_rs::to_vec(vreq)?)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            anyhow::bail!("mock 后端拒绝请求 {status}: {}", resp.text().await?);
+        }
+        Ok(resp)
+    }
+
+    fn stop(&self) {
+        self.server.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replays_openai_stream_bundle_against_mock_backend() {
+        let dir =
+            std::env::temp_dir().join(format!("ant2api-replay-test-{}", uuid::Uuid::new_v4()));
+        let sig_mgr = SignatureManager::new(&dir.to_string_lossy()).await.unwrap();
+        let cfg = Config::for_test();
+
+        let client_request = serde_json::json!({
+            "model": "gemini-2.5-flash",
+            "stream": true,
+            "user": "someone@example.com",
+            "messages": [{"role": "user", "content": "hello"}],
+        });
+        let mut req: openai::types::ChatRequest =
+            sonic_rs::from_str(&client_request.to_string()).unwrap();
+        let (vreq, _) = openai::convert::to_vertex_request(
+            &cfg,
+            &sig_mgr,
+            &mut req,
+            &AccountContext::default(),
+        )
+        .await
+        .unwrap();
+
+        let mut bundle = CaptureBundle {
+            version: 1,
+            id: "test".to_string(),
+            created_at: String::new(),
+            provider: CaptureProvider::Openai,
+            path: "/v1/chat/completions".to_string(),
+            model: "gemini-2.5-flash".to_string(),
+            api_key_name: None,
+            stream: true,
+            client_request,
+            previous_messages: None,
+            vertex_request: redacted_vertex_request(&vreq).unwrap(),
+            upstream_lines: vec![
+                r#"data: {"response":{"candidates":[{"content":{"role":"model","parts":[{"text":"hi"}]}}]}}"#.to_string(),
+                r#"data: {"response":{"candidates":[{"content":{"role":"model","parts":[{"text":" there"}]},"finishReason":"MAX_TOKENS"}],"usageMetadata":{"promptTokenCount":3,"candidatesTokenCount":2,"totalTokenCount":5}}}"#.to_string(),
+            ],
+            upstream_error: None,
+        };
+
+        let report = replay_bundle(&cfg, &sig_mgr, &bundle).await.unwrap();
+        assert!(!report.vertex_request_changed);
+        let output = report.client_output.join("\n");
+        assert!(output.contains(r#""content":"hi""#), "{output}");
+        assert!(output.contains(r#""finish_reason":"length""#), "{output}");
+
+        // 转换结果变化会被标记；上游失败的捕获只重放转换。
+        bundle.vertex_request["model"] = "other".into();
+        bundle.upstream_error = Some("400 invalid argument".to_string());
+        let report = replay_bundle(&cfg, &sig_mgr, &bundle).await.unwrap();
+        assert!(report.vertex_request_changed);
+        assert!(report.client_output.is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn replays_responses_bundle_with_previous_messages() {
+        let dir =
+            std::env::temp_dir().join(format!("ant2api-replay-test-{}", uuid::Uuid::new_v4()));
+        let sig_mgr = SignatureManager::new(&dir.to_string_lossy()).await.unwrap();
+        let cfg = Config::for_test();
+
+        let mut bundle = CaptureBundle {
+            version: 1,
+            id: "test".to_string(),
+            created_at: String::new(),
+            provider: CaptureProvider::Responses,
+            path: "/v1/responses".to_string(),
+            model: "gemini-2.5-flash".to_string(),
+            api_key_name: None,
+            stream: true,
+            client_request: serde_json::json!({
+                "model": "gemini-2.5-flash",
+                "stream": true,
+                "previous_response_id": "resp_prev",
+                "input": "follow up",
+            }),
+            previous_messages: Some(serde_json::json!([
+                {"role": "user", "content": "earlier question"},
+                {"role": "assistant", "content": "earlier answer"},
+            ])),
+            vertex_request: serde_json::Value::Null,
+            upstream_lines: vec![
+                r#"data: {"response":{"candidates":[{"content":{"role":"model","parts":[{"text":"hi"}]},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":3,"candidatesTokenCount":1,"totalTokenCount":4}}}"#.to_string(),
+            ],
+            upstream_error: None,
+        };
+
+        // 首次重放得到转换结果，写回捕获包后再次重放应保持一致。
+        let report = replay_bundle(&cfg, &sig_mgr, &bundle).await.unwrap();
+        let request = report.vertex_request.to_string();
+        assert!(request.contains("earlier answer"), "{request}");
+        assert!(request.contains("follow up"), "{request}");
+        bundle.vertex_request = report.vertex_request;
+
+        let report = replay_bundle(&cfg, &sig_mgr, &bundle).await.unwrap();
+        assert!(!report.vertex_request_changed);
+        let output = report.client_output.join("\n");
+        assert!(output.contains("event: response.completed"), "{output}");
+        assert!(output.contains(r#""delta":"hi""#), "{output}");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn replays_claude_stream_bundle_with_stop_sequence() {
+        let dir =
+            std::env::temp_dir().join(format!("ant2api-replay-test-{}", uuid::Uuid::new_v4()));
+        let sig_mgr = SignatureManager::new(&dir.to_string_lossy()).await.unwrap();
+        let cfg = Config::for_test();
+
+        let bundle = CaptureBundle {
+            version: 1,
+            id: "test".to_string(),
+            created_at: String::new(),
+            provider: CaptureProvider::Claude,
+            path: "/v1/messages".to_string(),
+            model: "gemini-2.5-flash".to_string(),
+            api_key_name: None,
+            stream: true,
+            client_request: serde_json::json!({
+                "model": "gemini-2.5-flash",
+                "stream": true,
+                "max_tokens": 64,
+                "stop_sequences": ["STOP"],
+                "messages": [{"role": "user", "content": "hello"}],
+            }),
+            previous_messages: None,
+            vertex_request: serde_json::Value::Null,
+            upstream_lines: vec![
+                r#"data: {"response":{"candidates":[{"content":{"role":"model","parts":[{"text":"hi ST"}]}}],"usageMetadata":{"promptTokenCount":3}}}"#.to_string(),
+                r#"data: {"response":{"candidates":[{"content":{"role":"model","parts":[{"text":"OP dropped"}]},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":3,"candidatesTokenCount":4,"totalTokenCount":7}}}"#.to_string(),
+            ],
+            upstream_error: None,
+        };
+
+        let report = replay_bundle(&cfg, &sig_mgr, &bundle).await.unwrap();
+        let output = report.client_output.join("\n");
+        assert!(output.contains(r#""input_tokens":3"#), "{output}");
+        assert!(
+            output.contains(r#""stop_reason":"stop_sequence""#),
+            "{output}"
+        );
+        assert!(output.contains(r#""stop_sequence":"STOP""#), "{output}");
+        assert!(!output.contains("dropped"), "{output}");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -8,6 +8,9 @@ const DEFAULT_PORT: u16 = 8045;
 const DEFAULT_TIMEOUT_MS: u64 = 180_000;
 const DEFAULT_USER_AGENT: &str = "antigravity/1.11.3 windows/amd64";
 const DEFAULT_CACHE_RETENTION_DAYS: u32 = 7;
+const DEFAULT_RATE_LIMIT_COOLDOWN_SECS: u64 = 60;
+const DEFAULT_STICKY_SESSION_TTL_SECS: u64 = 30 * 60;
+const DEFAULT_CAPTURE_MAX_BUNDLES: usize = 200;
 
 pub const DEFAULT_GOOGLE_CLIENT_ID: &str =
     "1071006060591-tmhssin2h21lcre235vtolojh4g403ep.apps.googleusercontent.com";
@@ -38,6 +41,42 @@ pub struct Config {
     pub webui_password: String,
     pub gemini3_media_resolution: String,
     pub cache_retention_days: u32,
+    /// 上游 429/容量不足且未给出 RetryInfo 时，账号的默认冷却秒数。
+    pub rate_limit_cooldown_secs: u64,
+    /// 对话固定到同一账号的时长（秒），0 表示关闭粘性路由。
+    pub sticky_session_ttl_secs: u64,
+    /// accounts.json 令牌加密主密钥（base64，32 字节），优先于密钥文件。
+    pub accounts_encryption_key: String,
+    /// accounts.json 令牌加密主密钥文件路径。
+    pub accounts_encryption_key_file: String,
+    /// 客户端 max_tokens 的处理策略（全局默认）。
+    pub max_tokens_policy: MaxTokensPolicy,
+    /// 按模型覆盖的 max_tokens 策略：（模型 ID 或以 `*` 结尾的前缀，策略），按顺序匹配。
+    pub max_tokens_policy_overrides: Vec<(String, MaxTokensPolicy)>,
+    /// 需要捕获请求/响应的模型（模型 ID 或以 `*` 结尾的前缀，`*` 表示全部），为空表示关闭。
+    pub capture_models: Vec<String>,
+    /// data_dir/captures 中最多保留的捕获包数量（0 表示不限制）。
+    pub capture_max_bundles: usize,
+}
+
+/// 客户端 max_tokens / max_completion_tokens 的处理策略。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MaxTokensPolicy {
+    /// 使用客户端值，超出模型上限时截断到上限；未指定时使用模型上限。
+    #[default]
+    Clamp,
+    /// 忽略客户端值，始终使用模型上限。
+    ModelMax,
+}
+
+impl MaxTokensPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "clamp" => Some(Self::Clamp),
+            "max" | "model_max" => Some(Self::ModelMax),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -80,6 +119,22 @@ struct RawEnv {
     gemini3_media_resolution: Option<String>,
     #[serde(alias = "CACHE_RETENTION_DAYS")]
     cache_retention_days: Option<u32>,
+    #[serde(alias = "RATE_LIMIT_COOLDOWN")]
+    rate_limit_cooldown: Option<u64>,
+    #[serde(alias = "STICKY_SESSION_TTL")]
+    sticky_session_ttl: Option<u64>,
+    #[serde(alias = "ACCOUNTS_ENCRYPTION_KEY")]
+    accounts_encryption_key: Option<String>,
+    #[serde(alias = "ACCOUNTS_ENCRYPTION_KEY_FILE")]
+    accounts_encryption_key_file: Option<String>,
+    #[serde(alias = "MAX_TOKENS_POLICY")]
+    max_tokens_policy: Option<String>,
+    #[serde(alias = "MAX_TOKENS_POLICY_OVERRIDES")]
+    max_tokens_policy_overrides: Option<String>,
+    #[serde(alias = "CAPTURE_MODELS")]
+    capture_models: Option<String>,
+    #[serde(alias = "CAPTURE_MAX_BUNDLES")]
+    capture_max_bundles: Option<usize>,
 }
 
 impl Config {
@@ -114,6 +169,26 @@ impl Config {
             cache_retention_days: raw
                 .cache_retention_days
                 .unwrap_or(DEFAULT_CACHE_RETENTION_DAYS),
+            rate_limit_cooldown_secs: raw
+                .rate_limit_cooldown
+                .unwrap_or(DEFAULT_RATE_LIMIT_COOLDOWN_SECS),
+            sticky_session_ttl_secs: raw
+                .sticky_session_ttl
+                .unwrap_or(DEFAULT_STICKY_SESSION_TTL_SECS),
+            accounts_encryption_key: raw.accounts_encryption_key.unwrap_or_default(),
+            accounts_encryption_key_file: raw.accounts_encryption_key_file.unwrap_or_default(),
+            max_tokens_policy: raw
+                .max_tokens_policy
+                .as_deref()
+                .and_then(MaxTokensPolicy::parse)
+                .unwrap_or_default(),
+            max_tokens_policy_overrides: parse_max_tokens_policy_overrides(
+                raw.max_tokens_policy_overrides.as_deref(),
+            ),
+            capture_models: parse_model_patterns(raw.capture_models.as_deref()),
+            capture_max_bundles: raw
+                .capture_max_bundles
+                .unwrap_or(DEFAULT_CAPTURE_MAX_BUNDLES),
         };
 
         // 兼容 Go 版本的命令行覆盖：-debug <level>
@@ -129,6 +204,24 @@ impl Config {
         cfg
     }
 
+    /// 模型适用的 max_tokens 策略：按顺序匹配覆盖项（精确 ID 或 `前缀*`），否则使用全局策略。
+    pub fn max_tokens_policy_for(&self, model: &str) -> MaxTokensPolicy {
+        match_max_tokens_policy(&self.max_tokens_policy_overrides, model)
+            .unwrap_or(self.max_tokens_policy)
+    }
+
+    /// 是否需要捕获该模型的请求（`CAPTURE_MODELS`）。
+    pub fn capture_enabled_for(&self, model: &str) -> bool {
+        let id = crate::util::model::canonical_model_id(model).to_lowercase();
+        self.capture_models
+            .iter()
+            .any(|pattern| model_pattern_matches(pattern, &id))
+    }
+
+    pub fn rate_limit_cooldown(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.rate_limit_cooldown_secs)
+    }
+
     pub fn effective_google_client_id(&self) -> &str {
         let v = self.google_client_id.trim();
         if v.is_empty() {
@@ -175,6 +268,53 @@ fn parse_status_codes(value: Option<&str>) -> Option<Vec<u16>> {
     if out.is_empty() { None } else { Some(out) }
 }
 
+/// 解析 `model=policy` 列表（逗号分隔），忽略无法识别的项。
+fn parse_max_tokens_policy_overrides(value: Option<&str>) -> Vec<(String, MaxTokensPolicy)> {
+    let Some(value) = value else {
+        return Vec::new();
+    };
+    value
+        .split(',')
+        .filter_map(|part| {
+            let (model, policy) = part.split_once('=')?;
+            let model = model.trim().to_lowercase();
+            if model.is_empty() {
+                return None;
+            }
+            Some((model, MaxTokensPolicy::parse(policy)?))
+        })
+        .collect()
+}
+
+fn match_max_tokens_policy(
+    overrides: &[(String, MaxTokensPolicy)],
+    model: &str,
+) -> Option<MaxTokensPolicy> {
+    let id = crate::util::model::canonical_model_id(model).to_lowercase();
+    overrides
+        .iter()
+        .find(|(pattern, _)| model_pattern_matches(pattern, &id))
+        .map(|(_, policy)| *policy)
+}
+
+/// 解析模型 ID / 前缀列表（逗号分隔，统一小写）。
+fn parse_model_patterns(value: Option<&str>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(|p| p.trim().to_lowercase())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// 精确模型 ID 或 `前缀*`（`*` 匹配全部）；`id` 需已规范化为小写。
+fn model_pattern_matches(pattern: &str, id: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => id.starts_with(prefix),
+        None => id == pattern,
+    }
+}
+
 fn load_dotenv() {
     let Some(dotenv_path) = find_dotenv_path() else {
         return;
@@ -285,3 +425,67 @@ fn strip_inline_comment(value: &str) -> &str {
     }
     value
 }
+
+#[cfg(test)]
+impl Config {
+    /// 测试用配置：不读取环境变量，按需用结构体更新语法覆盖字段。
+    pub fn for_test() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            api_user_agent: "ant2api-test".to_string(),
+            timeout_ms: 1_000,
+            proxy: String::new(),
+            api_key: String::new(),
+            retry_status_codes: Vec::new(),
+            retry_max_attempts: 1,
+            debug: "off".to_string(),
+            endpoint_mode: "production".to_string(),
+            google_client_id: String::new(),
+            google_client_secret: String::new(),
+            data_dir: String::new(),
+            webui_password: String::new(),
+            gemini3_media_resolution: String::new(),
+            cache_retention_days: 7,
+            rate_limit_cooldown_secs: 60,
+            sticky_session_ttl_secs: 1800,
+            accounts_encryption_key: String::new(),
+            accounts_encryption_key_file: String::new(),
+            max_tokens_policy: Default::default(),
+            max_tokens_policy_overrides: Vec::new(),
+            capture_models: Vec::new(),
+            capture_max_bundles: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_tokens_policy_overrides_match_exact_id_or_prefix() {
+        let overrides = parse_max_tokens_policy_overrides(Some(
+            " Claude-Opus-*=max , gemini-3-pro=clamp, bad=unknown, =max",
+        ));
+        assert_eq!(overrides.len(), 2);
+
+        assert_eq!(
+            match_max_tokens_policy(&overrides, "models/claude-opus-4-5-thinking"),
+            Some(MaxTokensPolicy::ModelMax)
+        );
+        assert_eq!(
+            match_max_tokens_policy(&overrides, "Gemini-3-Pro"),
+            Some(MaxTokensPolicy::Clamp)
+        );
+        assert_eq!(
+            match_max_tokens_policy(&overrides, "gemini-3-pro-image"),
+            None
+        );
+
+        let patterns = parse_model_patterns(Some(" Claude-*, ,gemini-3-pro "));
+        assert_eq!(patterns, vec!["claude-*", "gemini-3-pro"]);
+        assert!(model_pattern_matches("*", "gemini-3-flash"));
+        assert!(!model_pattern_matches(&patterns[1], "gemini-3-pro-image"));
+    }
+}
@@ -0,0 +1,93 @@
+//! 会话粘性：把同一对话固定到同一账号（TTL 内），提升后端缓存命中并便于复用思维签名。
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 超过该条目数时清理过期条目；仍超出则整体清空（粘性只是优化，丢失无害）。
+const MAX_ENTRIES: usize = 50_000;
+
+#[derive(Debug)]
+struct Pin {
+    session_id: String,
+    expires_at: Instant,
+}
+
+/// 对话键 -> 账号 sessionId 的内存映射。
+#[derive(Debug)]
+pub struct AccountAffinity {
+    ttl: Duration,
+    pins: Mutex<HashMap<String, Pin>>,
+}
+
+impl AccountAffinity {
+    /// `ttl` 为 0 表示禁用粘性路由。
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            pins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Pin>> {
+        self.pins.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 查找对话当前固定的账号（已过期则返回 None）。
+    pub fn get(&self, key: &str) -> Option<String> {
+        let mut pins = self.lock();
+        let pin = pins.get(key)?;
+        if pin.expires_at <= Instant::now() {
+            pins.remove(key);
+            return None;
+        }
+        Some(pin.session_id.clone())
+    }
+
+    /// 固定（或续期）对话到指定账号。
+    pub fn pin(&self, key: &str, session_id: &str) {
+        if !self.enabled() {
+            return;
+        }
+        let now = Instant::now();
+        let mut pins = self.lock();
+        if pins.len() >= MAX_ENTRIES && !pins.contains_key(key) {
+            pins.retain(|_, p| p.expires_at > now);
+            if pins.len() >= MAX_ENTRIES {
+                pins.clear();
+            }
+        }
+        pins.insert(
+            key.to_string(),
+            Pin {
+                session_id: session_id.to_string(),
+                expires_at: now + self.ttl,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_expire_and_can_be_replaced() {
+        let affinity = AccountAffinity::new(Duration::from_millis(30));
+        affinity.pin("conv", "a");
+        assert_eq!(affinity.get("conv").as_deref(), Some("a"));
+        affinity.pin("conv", "b");
+        assert_eq!(affinity.get("conv").as_deref(), Some("b"));
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(affinity.get("conv").is_none());
+
+        let disabled = AccountAffinity::new(Duration::ZERO);
+        disabled.pin("conv", "a");
+        assert!(disabled.get("conv").is_none());
+    }
+}
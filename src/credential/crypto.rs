@@ -0,0 +1,229 @@
+//! accounts.json 令牌加密（信封加密，AES-256-GCM）。
+//!
+//! - 主密钥（KEK）来自 `ACCOUNTS_ENCRYPTION_KEY` 或 `ACCOUNTS_ENCRYPTION_KEY_FILE`（32 字节 base64）
+//! - 每次写盘生成随机数据密钥（DEK）加密各账号的 access_token / refresh_token，
+//!   DEK 由主密钥加密后与文件一同保存；令牌密文绑定账号 ID 与字段名，防止互换
+//! - 主密钥通过 ID（SHA-256 前缀）识别，便于轮换时给出明确错误
+
+use anyhow::{Context, anyhow, bail};
+use aws_lc_rs::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use aws_lc_rs::rand::{SecureRandom, SystemRandom};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// 加密算法标识。
+pub const ALGORITHM: &str = "AES-256-GCM";
+/// 令牌密文前缀。
+const CIPHERTEXT_PREFIX: &str = "enc:";
+const KEY_LEN: usize = 32;
+
+/// 文件中保存的信封：被主密钥加密的数据密钥。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub alg: String,
+    pub key_id: String,
+    pub wrapped_key: String,
+}
+
+/// 主密钥（KEK）。
+#[derive(Clone)]
+pub struct MasterKey {
+    bytes: [u8; KEY_LEN],
+    id: String,
+}
+
+impl std::fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MasterKey").field("id", &self.id).finish()
+    }
+}
+
+impl MasterKey {
+    /// 从配置读取主密钥；未配置时返回 None。
+    pub fn from_config(cfg: &Config) -> anyhow::Result<Option<Self>> {
+        let inline = cfg.accounts_encryption_key.trim();
+        if !inline.is_empty() {
+            return Self::parse(inline).map(Some);
+        }
+        let path = cfg.accounts_encryption_key_file.trim();
+        if path.is_empty() {
+            return Ok(None);
+        }
+        Self::from_file(path).map(Some)
+    }
+
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("读取密钥文件 {path} 失败"))?;
+        Self::parse(&text)
+    }
+
+    /// 解析 base64 编码的 32 字节密钥。
+    pub fn parse(encoded: &str) -> anyhow::Result<Self> {
+        let encoded = encoded.trim();
+        let bytes = STANDARD
+            .decode(encoded)
+            .or_else(|_| base64::engine::general_purpose::URL_SAFE.decode(encoded))
+            .map_err(|_| anyhow!("加密密钥不是有效的 base64"))?;
+        let bytes: [u8; KEY_LEN] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("加密密钥长度必须为 {KEY_LEN} 字节"))?;
+        Ok(Self::from_bytes(bytes))
+    }
+
+    /// 生成随机主密钥。
+    pub fn generate() -> anyhow::Result<Self> {
+        Ok(Self::from_bytes(random_bytes()?))
+    }
+
+    fn from_bytes(bytes: [u8; KEY_LEN]) -> Self {
+        let digest = aws_lc_rs::digest::digest(&aws_lc_rs::digest::SHA256, &bytes);
+        let id = digest.as_ref()[..8]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        Self { bytes, id }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn encode(&self) -> String {
+        STANDARD.encode(self.bytes)
+    }
+
+    /// 生成新的数据密钥，返回（数据密钥, 信封）。
+    pub fn new_data_key(&self) -> anyhow::Result<(DataKey, Envelope)> {
+        let dek = random_bytes()?;
+        let wrapped = seal(&self.bytes, &dek, self.id.as_bytes())?;
+        let envelope = Envelope {
+            alg: ALGORITHM.to_string(),
+            key_id: self.id.clone(),
+            wrapped_key: wrapped,
+        };
+        Ok((DataKey(dek), envelope))
+    }
+
+    /// 解开信封中的数据密钥。
+    pub fn open_envelope(&self, envelope: &Envelope) -> anyhow::Result<DataKey> {
+        if envelope.alg != ALGORITHM {
+            bail!("不支持的加密算法: {}", envelope.alg);
+        }
+        if envelope.key_id != self.id {
+            bail!(
+                "accounts.json 使用密钥 {} 加密，与当前密钥 {} 不匹配",
+                envelope.key_id,
+                self.id
+            );
+        }
+        let dek = open(&self.bytes, &envelope.wrapped_key, self.id.as_bytes())?;
+        let dek: [u8; KEY_LEN] = dek.try_into().map_err(|_| anyhow!("数据密钥长度无效"))?;
+        Ok(DataKey(dek))
+    }
+}
+
+/// 数据密钥（DEK），仅在内存中短暂存在。
+pub struct DataKey([u8; KEY_LEN]);
+
+impl DataKey {
+    /// 加密单个字段；`aad` 用于绑定上下文（账号 ID + 字段名）。
+    pub fn encrypt(&self, plaintext: &str, aad: &str) -> anyhow::Result<String> {
+        if plaintext.is_empty() {
+            return Ok(String::new());
+        }
+        let sealed = seal(&self.0, plaintext.as_bytes(), aad.as_bytes())?;
+        Ok(format!("{CIPHERTEXT_PREFIX}{sealed}"))
+    }
+
+    pub fn decrypt(&self, ciphertext: &str, aad: &str) -> anyhow::Result<String> {
+        if ciphertext.is_empty() {
+            return Ok(String::new());
+        }
+        let sealed = ciphertext
+            .strip_prefix(CIPHERTEXT_PREFIX)
+            .ok_or_else(|| anyhow!("字段未加密"))?;
+        let plain = open(&self.0, sealed, aad.as_bytes())?;
+        String::from_utf8(plain).map_err(|_| anyhow!("解密结果不是有效的 UTF-8"))
+    }
+}
+
+fn random_bytes<const N: usize>() -> anyhow::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    SystemRandom::new()
+        .fill(&mut buf)
+        .map_err(|_| anyhow!("生成随机数失败"))?;
+    Ok(buf)
+}
+
+/// 加密并编码为 base64(nonce || ciphertext || tag)。
+fn seal(key: &[u8; KEY_LEN], plaintext: &[u8], aad: &[u8]) -> anyhow::Result<String> {
+    let key = LessSafeKey::new(
+        UnboundKey::new(&AES_256_GCM, key).map_err(|_| anyhow!("无效的加密密钥"))?,
+    );
+    let nonce: [u8; NONCE_LEN] = random_bytes()?;
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(aad),
+        &mut in_out,
+    )
+    .map_err(|_| anyhow!("加密失败"))?;
+
+    let mut out = Vec::with_capacity(NONCE_LEN + in_out.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&in_out);
+    Ok(STANDARD.encode(out))
+}
+
+fn open(key: &[u8; KEY_LEN], encoded: &str, aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let key = LessSafeKey::new(
+        UnboundKey::new(&AES_256_GCM, key).map_err(|_| anyhow!("无效的加密密钥"))?,
+    );
+    let mut buf = STANDARD
+        .decode(encoded)
+        .map_err(|_| anyhow!("密文不是有效的 base64"))?;
+    if buf.len() < NONCE_LEN {
+        bail!("密文长度无效");
+    }
+    let mut in_out = buf.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&buf).map_err(|_| anyhow!("密文长度无效"))?;
+    let plain = key
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| anyhow!("解密失败：密钥错误或数据已损坏"))?;
+    Ok(plain.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_round_trip_and_key_mismatch() {
+        let master = MasterKey::generate().unwrap();
+        let parsed = MasterKey::parse(&master.encode()).unwrap();
+        assert_eq!(parsed.id(), master.id());
+        assert!(MasterKey::parse("c2hvcnQ=").is_err());
+
+        let (dek, envelope) = master.new_data_key().unwrap();
+        let ct = dek.encrypt("1//refresh", "acc_1:refresh_token").unwrap();
+        assert!(ct.starts_with(CIPHERTEXT_PREFIX));
+        assert!(!ct.contains("refresh"));
+
+        let dek = parsed.open_envelope(&envelope).unwrap();
+        assert_eq!(
+            dek.decrypt(&ct, "acc_1:refresh_token").unwrap(),
+            "1//refresh"
+        );
+        // 绑定的上下文不同则无法解密（防止密文在账号/字段间互换）。
+        assert!(dek.decrypt(&ct, "acc_2:refresh_token").is_err());
+        assert_eq!(dek.encrypt("", "x").unwrap(), "");
+
+        let other = MasterKey::generate().unwrap();
+        assert!(other.open_envelope(&envelope).is_err());
+    }
+}
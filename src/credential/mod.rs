@@ -1,3 +1,5 @@
+pub mod affinity;
+pub mod crypto;
 pub mod oauth;
 pub mod refresh_task;
 pub mod store;
@@ -1,6 +1,7 @@
 use crate::config::Config;
 use crate::credential::types::Account;
 use crate::runtime_config;
+use crate::util::proxy::ProxyClientCache;
 use anyhow::{anyhow, bail};
 use base64::Engine;
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HOST, USER_AGENT};
@@ -113,7 +114,7 @@ pub async fn refresh_token(cfg: &Config, account: &mut Account) -> anyhow::Resul
         bail!("缺少 refresh_token");
     }
 
-    let client = oauth_http_client(cfg)?;
+    let client = oauth_http_client_for(cfg, &account.proxy)?;
     let resp = client
         .post("https://oauth2.googleapis.com/token")
         .header(HOST, "oauth2.googleapis.com")
@@ -205,15 +206,21 @@ pub fn parse_oauth_url(oauth_url: &str) -> anyhow::Result<(String, String)> {
     Ok((code, state))
 }
 
-pub async fn fetch_project_id(cfg: &Config, access_token: &str) -> anyhow::Result<String> {
-    if let Ok(pid) = fetch_project_id_from_load_code_assist(cfg, access_token).await {
+/// 获取账号的 projectId；`proxy` 为账号出口代理（为空时使用全局代理）。
+pub async fn fetch_project_id(
+    cfg: &Config,
+    access_token: &str,
+    proxy: &str,
+) -> anyhow::Result<String> {
+    let client = oauth_http_client_for(cfg, proxy)?;
+    if let Ok(pid) = fetch_project_id_from_load_code_assist(cfg, &client, access_token).await {
         let pid = pid.trim().to_string();
         if !pid.is_empty() {
             return Ok(pid);
         }
     }
 
-    match fetch_project_id_from_resource_manager(cfg, access_token).await {
+    match fetch_project_id_from_resource_manager(cfg, &client, access_token).await {
         Ok(pid) if !pid.trim().is_empty() => Ok(pid.trim().to_string()),
         Ok(_) => Err(anyhow!("未能获取 projectId")),
         Err(e) => Err(e),
@@ -228,6 +235,7 @@ struct LoadCodeAssistResponse {
 
 async fn fetch_project_id_from_load_code_assist(
     cfg: &Config,
+    client: &reqwest::Client,
     access_token: &str,
 ) -> anyhow::Result<String> {
     let access_token = access_token.trim();
@@ -235,7 +243,6 @@ async fn fetch_project_id_from_load_code_assist(
         bail!("缺少 access_token");
     }
 
-    let client = oauth_http_client(cfg)?;
     let host = runtime_config::current_endpoint_host();
     let url = format!("https://{host}/v1internal:loadCodeAssist");
     let resp = client
@@ -283,6 +290,7 @@ struct ResourceManagerProject {
 
 async fn fetch_project_id_from_resource_manager(
     cfg: &Config,
+    client: &reqwest::Client,
     access_token: &str,
 ) -> anyhow::Result<String> {
     let access_token = access_token.trim();
@@ -290,7 +298,6 @@ async fn fetch_project_id_from_resource_manager(
         bail!("缺少 access_token");
     }
 
-    let client = oauth_http_client(cfg)?;
     let mut page_token = String::new();
 
     for _ in 0..5 {
@@ -423,14 +430,27 @@ fn oauth_http_client(cfg: &Config) -> anyhow::Result<&'static reqwest::Client> {
         return Ok(c);
     }
 
-    let client = build_oauth_http_client(cfg)?;
+    let client = build_oauth_http_client(cfg, cfg.proxy.trim())?;
     let _ = OAUTH_HTTP_CLIENT.set(client);
     OAUTH_HTTP_CLIENT
         .get()
         .ok_or_else(|| anyhow!("初始化 OAuth HTTP client 失败"))
 }
 
-fn build_oauth_http_client(cfg: &Config) -> anyhow::Result<reqwest::Client> {
+static OAUTH_PROXIED_CLIENTS: OnceLock<ProxyClientCache<reqwest::Client>> = OnceLock::new();
+
+/// 账号代理对应的 OAuth client；代理为空时使用全局 client。
+fn oauth_http_client_for(cfg: &Config, proxy: &str) -> anyhow::Result<reqwest::Client> {
+    let proxy = proxy.trim();
+    if proxy.is_empty() {
+        return oauth_http_client(cfg).cloned();
+    }
+    OAUTH_PROXIED_CLIENTS
+        .get_or_init(ProxyClientCache::new)
+        .get_or_build(proxy, |p| build_oauth_http_client(cfg, p))
+}
+
+fn build_oauth_http_client(cfg: &Config, proxy: &str) -> anyhow::Result<reqwest::Client> {
     let mut builder = reqwest::Client::builder()
         .pool_max_idle_per_host(10)
         .pool_idle_timeout(Duration::from_secs(90))
@@ -442,8 +462,8 @@ fn build_oauth_http_client(cfg: &Config) -> anyhow::Result<reqwest::Client> {
         builder = builder.timeout(Duration::from_millis(cfg.timeout_ms));
     }
 
-    if !cfg.proxy.trim().is_empty() {
-        builder = builder.proxy(reqwest::Proxy::all(cfg.proxy.trim())?);
+    if !proxy.is_empty() {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
     }
 
     Ok(builder.build()?)
@@ -1,18 +1,32 @@
 use crate::config::Config;
+use crate::credential::affinity::AccountAffinity;
+use crate::credential::crypto::{Envelope, MasterKey};
 use crate::credential::oauth;
 use crate::credential::types::Account;
 use crate::quota_pool::QuotaPoolManager;
 use crate::quota_pool::group_quota_key;
 use crate::util::id;
-use anyhow::{Context, anyhow};
+use crate::util::proxy::normalize_proxy_url;
+use anyhow::{Context, anyhow, bail};
 use chrono::{Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use sonic_rs::JsonValueTrait;
 use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::{Mutex, RwLock};
 
 /// 连续刷新失败上限：达到后自动禁用账号（内存计数，重启清零）。
 const MAX_REFRESH_FAILURES: u32 = 5;
+/// accounts.json 格式版本（v1 为裸数组，v2 起为带版本号的对象，v3 起支持令牌加密）。
+const ACCOUNTS_FILE_VERSION: u32 = 3;
+/// 轮换备份数量（`accounts.json.bak.1` 最新）。
+const BACKUP_COUNT: usize = 3;
+/// 检查 accounts.json 外部修改的间隔。
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum RefreshSessionOutcome {
@@ -32,7 +46,10 @@ pub struct Store {
     state: RwLock<State>,
     cfg: Config,
     refreshing_sessions: Arc<Mutex<HashSet<String>>>,
-    save_lock: Arc<Mutex<()>>,
+    // 令牌加密主密钥（配置有误时保存错误信息，读写均拒绝，避免以明文覆盖密文）
+    key: Result<Option<MasterKey>, String>,
+    // 串行化磁盘读写，并记录最近一次读写时的文件状态
+    disk: Mutex<DiskState>,
     // 刷新失败计数（仅内存，服务重启后清零）
     refresh_failures: Arc<Mutex<HashMap<String, u32>>>,
 }
@@ -43,59 +60,270 @@ struct State {
     current_index: usize,
 }
 
+/// 文件修改时间与大小，用于识别外部修改。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    fn of(meta: &std::fs::Metadata) -> Self {
+        Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DiskState {
+    stamp: Option<FileStamp>,
+    /// 最近一次读写内容（明文账号）的哈希；内容未变时跳过写盘。
+    content_hash: u64,
+    /// 磁盘上的主文件是否已加密。
+    encrypted: bool,
+    /// 文件无法安全读取（版本过高 / 无法解密）时暂停写入，避免覆盖原数据。
+    write_blocked: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AccountsFileOut<'a> {
+    version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encryption: Option<Envelope>,
+    accounts: &'a [Account],
+}
+
+/// 文件版本高于当前程序支持的版本（不回退到备份，避免覆盖新版数据）。
+#[derive(Debug, thiserror::Error)]
+#[error("accounts.json 版本 {0} 高于当前支持的版本 {ACCOUNTS_FILE_VERSION}")]
+struct UnsupportedVersion(u32);
+
+/// 文件已加密但无法解密（未配置密钥或密钥不匹配）。
+#[derive(Debug, thiserror::Error)]
+#[error("accounts.json 解密失败: {0}")]
+struct DecryptError(String);
+
+/// 该错误表示文件本身完好但当前无法读取：不回退备份，并暂停写入。
+fn is_unreadable(e: &anyhow::Error) -> bool {
+    e.chain()
+        .any(|c| c.is::<UnsupportedVersion>() || c.is::<DecryptError>())
+}
+
+#[derive(Deserialize)]
+struct AccountsFileIn {
+    #[serde(default)]
+    version: u32,
+    #[serde(default)]
+    encryption: Option<Envelope>,
+    #[serde(default)]
+    accounts: Vec<Account>,
+}
+
+/// 已解析的账号文件。
+struct LoadedFile {
+    accounts: Vec<Account>,
+    stamp: FileStamp,
+    hash: u64,
+    encrypted: bool,
+}
+
+/// 写盘时的备份处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BackupMode {
+    /// 轮换备份（常规写入）。
+    Rotate,
+    /// 删除已有备份（明文迁移为密文时，避免令牌继续以明文留存）。
+    Discard,
+    /// 不处理备份（密钥轮换时逐个重写文件）。
+    Keep,
+}
+
 impl Store {
     pub fn new(cfg: Config) -> Self {
         let file_path = PathBuf::from(&cfg.data_dir).join("accounts.json");
+        let key = MasterKey::from_config(&cfg).map_err(|e| format!("{e:#}"));
         Self {
             file_path,
             state: RwLock::new(State::default()),
             cfg,
             refreshing_sessions: Arc::new(Mutex::new(HashSet::new())),
-            save_lock: Arc::new(Mutex::new(())),
+            key,
+            disk: Mutex::new(DiskState::default()),
             refresh_failures: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
+    /// 加载 accounts.json；主文件损坏时依次尝试轮换备份。
+    /// 缺少持久化 ID 的账号（旧版文件）会补齐 ID，配置了加密密钥时明文令牌会被加密，并立即写回。
     pub async fn load(&self) -> anyhow::Result<()> {
         ensure_parent_dir(&self.file_path).await?;
+        let key = self.master_key()?;
+        let mut disk = self.disk.lock().await;
 
-        let data = match tokio::fs::read(&self.file_path).await {
-            Ok(v) => v,
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        let loaded = match read_accounts_file(&self.file_path, key).await {
+            Ok(Some(v)) => v,
+            Ok(None) => {
                 let mut state = self.state.write().await;
                 state.accounts.clear();
                 state.current_index = 0;
+                *disk = DiskState::default();
                 return Ok(());
             }
-            Err(e) => return Err(e).context("读取 accounts.json 失败"),
-        };
-
-        let mut accounts: Vec<Account> = match sonic_rs::from_slice(&data) {
-            Ok(v) => v,
-            Err(e) => {
+            Err(e) if is_unreadable(&e) => {
+                disk.write_blocked = Some(format!("{e:#}"));
                 let mut state = self.state.write().await;
                 state.accounts.clear();
                 state.current_index = 0;
-                return Err(anyhow!(e)).context("解析 accounts.json 失败");
+                return Err(e);
+            }
+            Err(e) => {
+                let mut restored = None;
+                for n in 1..=BACKUP_COUNT {
+                    let path = backup_path(&self.file_path, n);
+                    if let Ok(Some(v)) = read_accounts_file(&path, key).await {
+                        tracing::warn!(
+                            "accounts.json 无法解析（{e:#}），已从备份 {} 恢复",
+                            path.display()
+                        );
+                        restored = Some(v);
+                        break;
+                    }
+                }
+                let Some(loaded) = restored else {
+                    let mut state = self.state.write().await;
+                    state.accounts.clear();
+                    state.current_index = 0;
+                    return Err(e);
+                };
+                // 备份内容写回主文件（损坏的主文件会被轮换进备份保留）。
+                self.install(LoadedFile { hash: 0, ..loaded }, &mut disk)
+                    .await;
+                disk.stamp = None;
+                let snapshot = { self.state.read().await.accounts.clone() };
+                return self.write_locked(&mut disk, &snapshot).await;
             }
         };
 
-        for a in &mut accounts {
-            a.session_id = id::session_id();
+        let migrate = key.is_some() && !loaded.encrypted;
+        let missing_ids = self.install(loaded, &mut disk).await;
+        if missing_ids || migrate {
+            let snapshot = { self.state.read().await.accounts.clone() };
+            self.write_locked(&mut disk, &snapshot).await?;
+            if migrate {
+                tracing::info!("已将 accounts.json 中的令牌加密保存");
+            }
         }
+        Ok(())
+    }
 
+    fn master_key(&self) -> anyhow::Result<Option<&MasterKey>> {
+        self.key
+            .as_ref()
+            .map(Option::as_ref)
+            .map_err(|e| anyhow!("加载 accounts.json 加密密钥失败: {e}"))
+    }
+
+    /// 若 accounts.json 被外部修改（修改时间或大小变化），重新加载。
+    /// 返回是否发生了重新加载；文件无法解析时保留内存中的账号。
+    pub async fn reload_if_changed(&self) -> anyhow::Result<bool> {
+        let mut disk = self.disk.lock().await;
+        self.reload_locked(&mut disk).await
+    }
+
+    async fn reload_locked(&self, disk: &mut DiskState) -> anyhow::Result<bool> {
+        let stamp = match tokio::fs::metadata(&self.file_path).await {
+            Ok(meta) => FileStamp::of(&meta),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e).context("读取 accounts.json 状态失败"),
+        };
+        if disk.stamp == Some(stamp) {
+            return Ok(false);
+        }
+
+        match read_accounts_file(&self.file_path, self.master_key()?).await {
+            Ok(Some(loaded)) => {
+                if loaded.hash == disk.content_hash && loaded.encrypted == disk.encrypted {
+                    disk.stamp = Some(loaded.stamp);
+                    return Ok(false);
+                }
+                let missing_ids = self.install(loaded, disk).await;
+                if missing_ids {
+                    let snapshot = { self.state.read().await.accounts.clone() };
+                    self.write_locked(disk, &snapshot).await?;
+                }
+                tracing::info!("检测到 accounts.json 外部修改，已重新加载");
+                Ok(true)
+            }
+            Ok(None) => Ok(false),
+            Err(e) => {
+                // 记录状态，避免对同一份损坏内容反复告警。
+                disk.stamp = Some(stamp);
+                if is_unreadable(&e) {
+                    disk.write_blocked = Some(format!("{e:#}"));
+                }
+                tracing::warn!("accounts.json 外部修改后无法解析，保留内存中的账号: {e:#}");
+                Ok(false)
+            }
+        }
+    }
+
+    /// 用磁盘内容替换内存账号：沿用同 ID 账号的 session_id（配额池/粘性路由不受影响），
+    /// 为缺少 ID 的账号补齐 ID。返回是否补齐过 ID。
+    async fn install(&self, loaded: LoadedFile, disk: &mut DiskState) -> bool {
+        let mut accounts = loaded.accounts;
         let mut state = self.state.write().await;
-        state.accounts = accounts;
-        state.current_index = 0;
+        let sessions: HashMap<&str, &str> = state
+            .accounts
+            .iter()
+            .map(|a| (a.id.as_str(), a.session_id.as_str()))
+            .collect();
 
-        Ok(())
+        let mut missing_ids = false;
+        for a in &mut accounts {
+            if a.id.trim().is_empty() {
+                a.id = id::account_id();
+                missing_ids = true;
+            }
+            a.session_id = match sessions.get(a.id.as_str()) {
+                Some(sid) if !sid.is_empty() => sid.to_string(),
+                _ => id::session_id(),
+            };
+        }
+
+        state.accounts = accounts;
+        if state.current_index >= state.accounts.len() {
+            state.current_index = 0;
+        }
+        disk.stamp = Some(loaded.stamp);
+        disk.content_hash = loaded.hash;
+        disk.encrypted = loaded.encrypted;
+        disk.write_blocked = None;
+        missing_ids
     }
 
     pub async fn save(&self) -> anyhow::Result<()> {
         // 串行化 accounts.json 的写入，避免并发写导致“后写覆盖先写”而丢失更新。
-        let _guard = self.save_lock.lock().await;
+        let mut disk = self.disk.lock().await;
         let snapshot = { self.state.read().await.accounts.clone() };
-        self.write_accounts(&snapshot).await
+        self.write_locked(&mut disk, &snapshot).await
+    }
+
+    /// 修改账号并落盘：先合并外部修改，再在写锁内执行 `f`，最后原子写入。
+    async fn mutate<T>(
+        &self,
+        f: impl FnOnce(&mut State) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut disk = self.disk.lock().await;
+        self.reload_locked(&mut disk).await?;
+        let (out, snapshot) = {
+            let mut state = self.state.write().await;
+            let out = f(&mut state)?;
+            (out, state.accounts.clone())
+        };
+        self.write_locked(&mut disk, &snapshot).await?;
+        Ok(out)
     }
 
     pub async fn get_token(&self) -> anyhow::Result<Account> {
@@ -161,7 +389,7 @@ impl Store {
                 break;
             };
 
-            let Some((_idx, account)) = self.find_by_session_id(&session_id).await else {
+            let Some(account) = self.find_by_session_id(&session_id).await else {
                 tracing::warn!(
                     session_id = session_id,
                     "配额池命中但 Store 未找到账号，已清理"
@@ -191,10 +419,56 @@ impl Store {
             return Ok(account);
         }
 
-        // 没有配额池数据或无法使用：退化为原有轮询策略（同时尊重 exclude）。
+        // 没有配额池数据或无法使用：退化为原有轮询策略（同时尊重 exclude），
+        // 优先跳过限流冷却中的账号；全部冷却时仍按原策略兜底。
+        let limited = pool_mgr.rate_limited_sessions(pool_name).await;
+        if !limited.is_empty() {
+            let mut skip = exclude.clone();
+            skip.extend(limited);
+            if let Ok(account) = self.get_token_excluding(&skip).await {
+                return Ok(account);
+            }
+        }
         self.get_token_excluding(exclude).await
     }
 
+    /// 带会话粘性的选号：对话已固定的账号仍可用（未排除、未冷却、未禁用）时直接复用，
+    /// 否则走 [`Self::get_token_for_model_excluding`] 并把对话重新固定到新账号。
+    pub async fn get_token_for_conversation(
+        &self,
+        model: &str,
+        pool_mgr: &QuotaPoolManager,
+        exclude: &HashSet<String>,
+        affinity: &AccountAffinity,
+        conversation: Option<&str>,
+    ) -> anyhow::Result<Account> {
+        let Some(conversation) = conversation.filter(|_| affinity.enabled()) else {
+            return self
+                .get_token_for_model_excluding(model, pool_mgr, exclude)
+                .await;
+        };
+
+        // 不同配额分组各自固定，避免跨模型切换时命中冷却账号。
+        let pool_name = group_quota_key(model.trim());
+        let key = format!("{pool_name}|{conversation}");
+
+        if let Some(session_id) = affinity.get(&key)
+            && !exclude.contains(&session_id)
+            && !pool_mgr.is_cooling(pool_name, &session_id).await
+            && let Some(account) = self.find_by_session_id(&session_id).await
+            && account.enable
+        {
+            affinity.pin(&key, &session_id);
+            return Ok(account);
+        }
+
+        let account = self
+            .get_token_for_model_excluding(model, pool_mgr, exclude)
+            .await?;
+        affinity.pin(&key, &account.session_id);
+        Ok(account)
+    }
+
     /// 轮询挑选账号，但会跳过 `exclude` 中的 sessionId。
     pub async fn get_token_excluding(&self, exclude: &HashSet<String>) -> anyhow::Result<Account> {
         let len = { self.state.read().await.accounts.len() };
@@ -306,27 +580,11 @@ impl Store {
 
     /// 通过 session_id 禁用账号（用于连续失败后熔断）。
     pub async fn disable_by_session_id(&self, session_id: &str) -> anyhow::Result<()> {
-        let session_id = session_id.trim();
-        if session_id.is_empty() {
+        let Some(account) = self.find_by_session_id(session_id).await else {
             return Ok(());
-        }
-
-        let mut changed = false;
-        {
-            let mut state = self.state.write().await;
-            for acc in &mut state.accounts {
-                if acc.session_id == session_id {
-                    if acc.enable {
-                        acc.enable = false;
-                        changed = true;
-                    }
-                    break;
-                }
-            }
-        }
-
-        if changed {
-            self.save().await?;
+        };
+        if account.enable {
+            self.set_enable(&account.id, false).await?;
         }
         Ok(())
     }
@@ -354,7 +612,7 @@ impl Store {
         }
 
         let result = async {
-            let Some((idx, mut account)) = self.find_by_session_id(&session_id).await else {
+            let Some(mut account) = self.find_by_session_id(&session_id).await else {
                 return Err(anyhow!("未找到指定 session_id 对应的账号"));
             };
 
@@ -365,12 +623,12 @@ impl Store {
 
             match oauth::refresh_token(&cfg, &mut account).await {
                 Ok(()) => {
-                    self.replace_account(idx, &account).await?;
-                    self.save().await?;
+                    self.apply_refreshed(&account).await?;
                     self.clear_refresh_failure(&session_id).await;
                     Ok(RefreshSessionOutcome::Refreshed)
                 }
                 Err(e) => {
+                    crate::metrics::record_refresh_failure(&account.id);
                     let failures = self.record_refresh_failure(&session_id).await;
                     tracing::warn!(
                         session_id = %session_id,
@@ -426,161 +684,165 @@ impl Store {
     }
 
     pub async fn clear(&self) -> anyhow::Result<()> {
-        {
-            let mut state = self.state.write().await;
+        self.mutate(|state| {
             state.accounts.clear();
             state.current_index = 0;
-        }
-        self.save().await
+            Ok(())
+        })
+        .await
     }
 
+    /// 添加账号；邮箱或 refresh_token 相同的已有账号会被替换（沿用其 ID）。
     pub async fn add(&self, mut account: Account) -> anyhow::Result<()> {
         account.session_id = id::session_id();
         if account.created_at.year() == 1 {
             account.created_at = Utc::now();
         }
 
-        {
-            let mut state = self.state.write().await;
-            let mut replaced = false;
-            for existing in &mut state.accounts {
-                if (!account.email.is_empty() && existing.email == account.email)
+        self.mutate(|state| {
+            let existing = state.accounts.iter_mut().find(|existing| {
+                (!account.email.is_empty() && existing.email == account.email)
                     || (!account.refresh_token.is_empty()
                         && existing.refresh_token == account.refresh_token)
-                {
-                    // 保留原始 created_at
+            });
+            match existing {
+                Some(existing) => {
+                    // 保留原始 ID 与 created_at；重新登录时沿用已配置的代理
+                    account.id = existing.id.clone();
                     account.created_at = existing.created_at;
-                    *existing = account.clone();
-                    replaced = true;
-                    break;
+                    if account.proxy.is_empty() {
+                        account.proxy = existing.proxy.clone();
+                    }
+                    *existing = account;
+                }
+                None => {
+                    if account.id.trim().is_empty() {
+                        account.id = id::account_id();
+                    }
+                    state.accounts.push(account);
                 }
             }
-
-            if !replaced {
-                state.accounts.push(account);
-            }
-        }
-
-        self.save().await
+            Ok(())
+        })
+        .await
     }
 
-    pub async fn delete(&self, index: usize) -> anyhow::Result<()> {
-        {
-            let mut state = self.state.write().await;
-            if index >= state.accounts.len() {
-                return Err(anyhow!("索引超出范围"));
-            }
-            state.accounts.remove(index);
+    /// 删除账号，返回被删除的账号。
+    pub async fn delete(&self, account_id: &str) -> anyhow::Result<Account> {
+        self.mutate(|state| {
+            let idx = position_by_id(&state.accounts, account_id)?;
+            let removed = state.accounts.remove(idx);
             if state.current_index >= state.accounts.len() {
                 state.current_index = 0;
             }
-        }
-        self.save().await
+            Ok(removed)
+        })
+        .await
     }
 
-    pub async fn set_enable(&self, index: usize, enable: bool) -> anyhow::Result<()> {
-        {
-            let mut state = self.state.write().await;
-            if index >= state.accounts.len() {
-                return Err(anyhow!("索引超出范围"));
-            }
-            state.accounts[index].enable = enable;
-        }
-        self.save().await
+    /// 启用/禁用账号，返回更新后的账号。
+    pub async fn set_enable(&self, account_id: &str, enable: bool) -> anyhow::Result<Account> {
+        self.update_by_id(account_id, |acc| acc.enable = enable)
+            .await
+    }
+
+    /// 设置账号出口代理（空字符串表示使用全局代理），返回更新后的账号。
+    pub async fn set_proxy(&self, account_id: &str, proxy: &str) -> anyhow::Result<Account> {
+        let proxy = normalize_proxy_url(proxy)?;
+        self.update_by_id(account_id, |acc| acc.proxy = proxy).await
     }
 
-    /// 按 session_id 更新账号的 project_id（不改变 session_id）。
+    /// 更新账号的 project_id（不改变 session_id）。
     /// 返回值表示是否发生了修改并落盘。
-    pub async fn update_project_id_by_session_id(
+    pub async fn update_project_id(
         &self,
-        session_id: &str,
+        account_id: &str,
         project_id: &str,
     ) -> anyhow::Result<bool> {
-        let session_id = session_id.trim();
         let project_id = project_id.trim();
-        if session_id.is_empty() || project_id.is_empty() {
+        if project_id.is_empty() {
             return Ok(false);
         }
-
-        let mut changed = false;
-        {
-            let mut state = self.state.write().await;
-            let Some(acc) = state
-                .accounts
-                .iter_mut()
-                .find(|a| a.session_id == session_id)
-            else {
-                return Ok(false);
-            };
-
-            if acc.project_id != project_id {
-                acc.project_id = project_id.to_string();
-                changed = true;
-            }
-        }
-
-        if changed {
-            self.save().await?;
+        let Some(account) = self.get_by_id(account_id).await else {
+            return Ok(false);
+        };
+        if account.project_id == project_id {
+            return Ok(false);
         }
-        Ok(changed)
+        self.update_by_id(account_id, |acc| acc.project_id = project_id.to_string())
+            .await?;
+        Ok(true)
     }
 
-    pub async fn refresh_account(&self, index: usize) -> anyhow::Result<()> {
-        let mut account = {
-            let state = self.state.read().await;
-            if index >= state.accounts.len() {
-                return Err(anyhow!("索引超出范围"));
-            }
-            state.accounts[index].clone()
-        };
+    pub async fn refresh_account(&self, account_id: &str) -> anyhow::Result<()> {
+        let mut account = self
+            .get_by_id(account_id)
+            .await
+            .ok_or_else(|| anyhow!("未找到账号"))?;
 
         oauth::refresh_token(&self.cfg, &mut account).await?;
-        self.replace_account(index, &account).await?;
-        self.save().await?;
+        self.apply_refreshed(&account).await?;
         self.clear_refresh_failure(&account.session_id).await;
         Ok(())
     }
 
     pub async fn refresh_all(&self) -> anyhow::Result<(usize, usize)> {
         let accounts = self.get_all().await;
-        let mut updated = Vec::with_capacity(accounts.len());
+        let mut refreshed = Vec::with_capacity(accounts.len());
         let mut success = 0usize;
         let mut failed = 0usize;
 
         for mut a in accounts {
-            let sid = a.session_id.clone();
             match oauth::refresh_token(&self.cfg, &mut a).await {
                 Ok(()) => {
                     success += 1;
-                    self.clear_refresh_failure(&sid).await;
-                    updated.push(a);
-                }
-                Err(_) => {
-                    failed += 1;
-                    updated.push(a);
+                    self.clear_refresh_failure(&a.session_id).await;
+                    refreshed.push(a);
                 }
+                Err(_) => failed += 1,
             }
         }
 
-        {
-            let mut state = self.state.write().await;
-            state.accounts = updated;
+        // 仅合并刷新得到的凭证字段，刷新期间的其他修改（启用状态、删除等）不受影响。
+        self.mutate(|state| {
+            for r in &refreshed {
+                if let Some(acc) = state.accounts.iter_mut().find(|a| a.id == r.id) {
+                    copy_tokens(acc, r);
+                }
+            }
             state.current_index = 0;
-        }
-        self.save().await?;
+            Ok(())
+        })
+        .await?;
         Ok((success, failed))
     }
 
-    async fn replace_account(&self, index: usize, account: &Account) -> anyhow::Result<()> {
-        let mut state = self.state.write().await;
-        if index >= state.accounts.len() {
-            return Err(anyhow!("索引超出范围"));
-        }
-        state.accounts[index] = account.clone();
-        Ok(())
+    /// 写回刷新得到的凭证字段（按 ID 定位；账号已被删除时报错）。
+    async fn apply_refreshed(&self, refreshed: &Account) -> anyhow::Result<()> {
+        self.update_by_id(&refreshed.id, |acc| copy_tokens(acc, refreshed))
+            .await
+            .map(|_| ())
     }
 
-    async fn find_by_session_id(&self, session_id: &str) -> Option<(usize, Account)> {
+    async fn update_by_id(
+        &self,
+        account_id: &str,
+        f: impl FnOnce(&mut Account),
+    ) -> anyhow::Result<Account> {
+        self.mutate(|state| {
+            let idx = position_by_id(&state.accounts, account_id)?;
+            f(&mut state.accounts[idx]);
+            Ok(state.accounts[idx].clone())
+        })
+        .await
+    }
+
+    pub async fn get_by_id(&self, account_id: &str) -> Option<Account> {
+        let state = self.state.read().await;
+        state.accounts.iter().find(|a| a.id == account_id).cloned()
+    }
+
+    async fn find_by_session_id(&self, session_id: &str) -> Option<Account> {
         let session_id = session_id.trim();
         if session_id.is_empty() {
             return None;
@@ -589,20 +851,252 @@ impl Store {
         state
             .accounts
             .iter()
-            .enumerate()
-            .find(|(_, a)| a.session_id == session_id)
-            .map(|(i, a)| (i, a.clone()))
+            .find(|a| a.session_id == session_id)
+            .cloned()
     }
 
-    async fn write_accounts(&self, accounts: &[Account]) -> anyhow::Result<()> {
+    /// 原子写入：临时文件 fsync 后轮换备份并 rename 覆盖主文件。
+    async fn write_locked(&self, disk: &mut DiskState, accounts: &[Account]) -> anyhow::Result<()> {
+        if let Some(reason) = &disk.write_blocked {
+            bail!("accounts.json 已暂停写入：{reason}");
+        }
+        let key = self.master_key()?;
+        let hash = accounts_hash(accounts)?;
+        if disk.stamp.is_some() && hash == disk.content_hash && disk.encrypted == key.is_some() {
+            return Ok(());
+        }
+
+        let data = encode_accounts(accounts, key)?;
+        let mode = if key.is_some() && !disk.encrypted {
+            BackupMode::Discard
+        } else {
+            BackupMode::Rotate
+        };
         ensure_parent_dir(&self.file_path).await?;
-        let data = sonic_rs::to_vec_pretty(accounts).context("序列化 accounts.json 失败")?;
-        tokio::fs::write(&self.file_path, data)
+        let path = self.file_path.clone();
+        let stamp = tokio::task::spawn_blocking(move || write_atomic(&path, &data, mode))
             .await
-            .context("写入 accounts.json 失败")
+            .context("写入 accounts.json 失败")?
+            .context("写入 accounts.json 失败")?;
+        disk.stamp = Some(stamp);
+        disk.content_hash = hash;
+        disk.encrypted = key.is_some();
+        Ok(())
     }
 }
 
+/// 轮换 accounts.json（含备份）的加密主密钥：用当前配置的密钥解密后以 `new_key` 重新加密，
+/// `new_key` 为 None 时写回明文。应在服务停止时执行；返回主文件中的账号数。
+pub async fn rotate_encryption_key(
+    cfg: &Config,
+    new_key: Option<&MasterKey>,
+) -> anyhow::Result<usize> {
+    let old_key = MasterKey::from_config(cfg)?;
+    let path = PathBuf::from(&cfg.data_dir).join("accounts.json");
+
+    // 先完成全部解密，主文件失败时不改动任何文件。
+    let Some(main) = read_accounts_file(&path, old_key.as_ref()).await? else {
+        bail!("未找到 {}", path.display());
+    };
+    let mut files = vec![(path.clone(), main.accounts)];
+    for n in 1..=BACKUP_COUNT {
+        let backup = backup_path(&path, n);
+        match read_accounts_file(&backup, old_key.as_ref()).await {
+            Ok(Some(loaded)) => files.push((backup, loaded.accounts)),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("跳过无法解密的备份 {}: {e:#}", backup.display()),
+        }
+    }
+
+    let count = files[0].1.len();
+    for (file, mut accounts) in files {
+        for a in &mut accounts {
+            if a.id.trim().is_empty() {
+                a.id = id::account_id();
+            }
+        }
+        let data = encode_accounts(&accounts, new_key)?;
+        tokio::task::spawn_blocking(move || write_atomic(&file, &data, BackupMode::Keep))
+            .await
+            .context("写入 accounts.json 失败")??;
+    }
+    Ok(count)
+}
+
+/// 定期检查 accounts.json 的外部修改并重新加载。
+pub fn spawn_reload_task(store: Arc<Store>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RELOAD_CHECK_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = store.reload_if_changed().await {
+                tracing::warn!("检查 accounts.json 修改失败: {e:#}");
+            }
+        }
+    });
+}
+
+fn position_by_id(accounts: &[Account], account_id: &str) -> anyhow::Result<usize> {
+    let account_id = account_id.trim();
+    accounts
+        .iter()
+        .position(|a| !account_id.is_empty() && a.id == account_id)
+        .ok_or_else(|| anyhow!("未找到账号"))
+}
+
+fn copy_tokens(dst: &mut Account, src: &Account) {
+    dst.access_token = src.access_token.clone();
+    dst.refresh_token = src.refresh_token.clone();
+    dst.expires_in = src.expires_in;
+    dst.timestamp = src.timestamp;
+}
+
+fn content_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 解析账号文件（兼容 v1 裸数组）；文件不存在时返回 None。
+async fn read_accounts_file(
+    path: &Path,
+    key: Option<&MasterKey>,
+) -> anyhow::Result<Option<LoadedFile>> {
+    let data = match tokio::fs::read(path).await {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("读取 {} 失败", path.display())),
+    };
+    let meta = tokio::fs::metadata(path)
+        .await
+        .with_context(|| format!("读取 {} 失败", path.display()))?;
+    let (accounts, encrypted) =
+        parse_accounts(&data, key).with_context(|| format!("解析 {} 失败", path.display()))?;
+    Ok(Some(LoadedFile {
+        hash: accounts_hash(&accounts)?,
+        accounts,
+        stamp: FileStamp::of(&meta),
+        encrypted,
+    }))
+}
+
+/// 解析账号文件，返回（明文账号, 是否加密）。
+fn parse_accounts(data: &[u8], key: Option<&MasterKey>) -> anyhow::Result<(Vec<Account>, bool)> {
+    let value: sonic_rs::Value = sonic_rs::from_slice(data)?;
+    if value.is_array() {
+        return Ok((sonic_rs::from_value(&value)?, false));
+    }
+    let file: AccountsFileIn = sonic_rs::from_value(&value)?;
+    if file.version > ACCOUNTS_FILE_VERSION {
+        return Err(UnsupportedVersion(file.version).into());
+    }
+    let Some(envelope) = file.encryption else {
+        return Ok((file.accounts, false));
+    };
+
+    let key = key.ok_or_else(|| {
+        DecryptError(
+            "文件已加密，但未配置 ACCOUNTS_ENCRYPTION_KEY / ACCOUNTS_ENCRYPTION_KEY_FILE"
+                .to_string(),
+        )
+    })?;
+    let dek = key
+        .open_envelope(&envelope)
+        .map_err(|e| DecryptError(format!("{e:#}")))?;
+    let mut accounts = file.accounts;
+    for a in &mut accounts {
+        let decrypt = |value: &str, field: &str| {
+            dek.decrypt(value, &token_aad(&a.id, field))
+                .map_err(|e| DecryptError(format!("账号 {} 的 {field}: {e:#}", a.id)))
+        };
+        let access_token = decrypt(&a.access_token, "access_token")?;
+        let refresh_token = decrypt(&a.refresh_token, "refresh_token")?;
+        a.access_token = access_token;
+        a.refresh_token = refresh_token;
+    }
+    Ok((accounts, true))
+}
+
+/// 序列化账号文件；提供密钥时加密各账号令牌。
+fn encode_accounts(accounts: &[Account], key: Option<&MasterKey>) -> anyhow::Result<Vec<u8>> {
+    let Some(key) = key else {
+        return sonic_rs::to_vec_pretty(&AccountsFileOut {
+            version: ACCOUNTS_FILE_VERSION,
+            encryption: None,
+            accounts,
+        })
+        .context("序列化 accounts.json 失败");
+    };
+
+    let (dek, envelope) = key.new_data_key()?;
+    let mut sealed = accounts.to_vec();
+    for a in &mut sealed {
+        a.access_token = dek.encrypt(&a.access_token, &token_aad(&a.id, "access_token"))?;
+        a.refresh_token = dek.encrypt(&a.refresh_token, &token_aad(&a.id, "refresh_token"))?;
+    }
+    sonic_rs::to_vec_pretty(&AccountsFileOut {
+        version: ACCOUNTS_FILE_VERSION,
+        encryption: Some(envelope),
+        accounts: &sealed,
+    })
+    .context("序列化 accounts.json 失败")
+}
+
+fn token_aad(account_id: &str, field: &str) -> String {
+    format!("{account_id}:{field}")
+}
+
+fn accounts_hash(accounts: &[Account]) -> anyhow::Result<u64> {
+    let data = sonic_rs::to_vec(accounts).context("序列化 accounts.json 失败")?;
+    Ok(content_hash(&data))
+}
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".bak.{n}"));
+    PathBuf::from(name)
+}
+
+fn write_atomic(path: &Path, data: &[u8], mode: BackupMode) -> std::io::Result<FileStamp> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut f = std::fs::File::create(&tmp)?;
+        f.write_all(data)?;
+        f.sync_all()?;
+    }
+
+    // 轮换备份：bak.1 -> bak.2 -> ...，当前主文件复制为 bak.1（主文件在 rename 前始终完整）。
+    if mode == BackupMode::Rotate && path.exists() {
+        for n in (1..BACKUP_COUNT).rev() {
+            let from = backup_path(path, n);
+            if from.exists() {
+                std::fs::rename(&from, backup_path(path, n + 1))?;
+            }
+        }
+        std::fs::copy(path, backup_path(path, 1))?;
+    }
+
+    std::fs::rename(&tmp, path)?;
+    if mode == BackupMode::Discard {
+        for n in 1..=BACKUP_COUNT {
+            match std::fs::remove_file(backup_path(path, n)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+    }
+    #[cfg(unix)]
+    if let Some(dir) = path.parent()
+        && let Ok(d) = std::fs::File::open(dir)
+    {
+        let _ = d.sync_all();
+    }
+    Ok(FileStamp::of(&std::fs::metadata(path)?))
+}
+
 async fn ensure_parent_dir(path: &Path) -> anyhow::Result<()> {
     let Some(dir) = path.parent() else {
         return Ok(());
@@ -620,22 +1114,10 @@ mod tests {
 
     fn test_cfg(data_dir: String) -> Config {
         Config {
-            host: "127.0.0.1".to_string(),
-            port: 0,
-            api_user_agent: "ant2api-test".to_string(),
-            timeout_ms: 1_000,
-            proxy: String::new(),
-            api_key: String::new(),
             retry_status_codes: vec![429, 500],
             retry_max_attempts: 3,
-            debug: "off".to_string(),
-            endpoint_mode: "production".to_string(),
-            google_client_id: String::new(),
-            google_client_secret: String::new(),
             data_dir,
-            webui_password: String::new(),
-            gemini3_media_resolution: String::new(),
-            cache_retention_days: 7,
+            ..Config::for_test()
         }
     }
 
@@ -647,6 +1129,7 @@ mod tests {
 
     fn expired_account(project_id: &str) -> Account {
         Account {
+            id: String::new(),
             access_token: "expired".to_string(),
             refresh_token: String::new(),
             expires_in: 0,
@@ -655,6 +1138,7 @@ mod tests {
             email: "test@example.com".to_string(),
             enable: true,
             created_at: Utc::now(),
+            proxy: String::new(),
             session_id: String::new(),
         }
     }
@@ -713,6 +1197,61 @@ mod tests {
         let _ = tokio::fs::remove_dir_all(&data_dir).await;
     }
 
+    #[tokio::test]
+    async fn conversation_sticks_to_account_until_unavailable() {
+        let data_dir = temp_data_dir();
+        let store = Store::new(test_cfg(data_dir.clone()));
+        store.add(expired_account("p1")).await.unwrap();
+        store
+            .add(Account {
+                email: "other@example.com".to_string(),
+                ..expired_account("p2")
+            })
+            .await
+            .unwrap();
+
+        let pool = QuotaPoolManager::new();
+        let affinity = AccountAffinity::new(std::time::Duration::from_secs(60));
+        let none = HashSet::new();
+        let pick = |exclude: HashSet<String>| {
+            let (store, pool, affinity) = (&store, &pool, &affinity);
+            async move {
+                store
+                    .get_token_for_conversation(
+                        "gemini-3-flash",
+                        pool,
+                        &exclude,
+                        affinity,
+                        Some("c"),
+                    )
+                    .await
+                    .unwrap()
+                    .session_id
+            }
+        };
+
+        let first = pick(none.clone()).await;
+        for _ in 0..5 {
+            assert_eq!(pick(none.clone()).await, first);
+        }
+
+        // 被排除（重试换号）时改用其他账号，并重新固定。
+        let second = pick(HashSet::from([first.clone()])).await;
+        assert_ne!(second, first);
+        assert_eq!(pick(none.clone()).await, second);
+
+        // 固定账号进入限流冷却时不再复用。
+        pool.mark_rate_limited(
+            &second,
+            group_quota_key("gemini-3-flash"),
+            std::time::Duration::from_secs(60),
+        )
+        .await;
+        assert_eq!(pick(none.clone()).await, first);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
     #[tokio::test]
     async fn refresh_session_disables_after_five_failures() {
         let data_dir = temp_data_dir();
@@ -755,7 +1294,7 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn update_project_id_by_session_id_updates_without_rotating_session() {
+    async fn update_project_id_updates_without_rotating_session() {
         let data_dir = temp_data_dir();
         let store = Store::new(test_cfg(data_dir.clone()));
         store.add(expired_account("old")).await.unwrap();
@@ -764,7 +1303,7 @@ mod tests {
         let sid = acc.session_id.clone();
 
         let changed = store
-            .update_project_id_by_session_id(&sid, "new-project")
+            .update_project_id(&acc.id, "new-project")
             .await
             .unwrap();
         assert!(changed);
@@ -774,4 +1313,181 @@ mod tests {
 
         let _ = tokio::fs::remove_dir_all(&data_dir).await;
     }
+
+    #[tokio::test]
+    async fn proxy_is_validated_and_kept_on_relogin() {
+        let data_dir = temp_data_dir();
+        let store = Store::new(test_cfg(data_dir.clone()));
+        store.add(expired_account("p1")).await.unwrap();
+        let id = store.get_token().await.unwrap().id;
+
+        assert!(store.set_proxy(&id, "ftp://127.0.0.1").await.is_err());
+        store
+            .set_proxy(&id, " socks5://127.0.0.1:1080 ")
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_token().await.unwrap().proxy,
+            "socks5://127.0.0.1:1080"
+        );
+
+        // 重新登录同一账号（新凭证不带代理）时沿用原代理。
+        store.add(expired_account("p1")).await.unwrap();
+        assert_eq!(
+            store.get_token().await.unwrap().proxy,
+            "socks5://127.0.0.1:1080"
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn legacy_file_is_upgraded_and_recovered_from_backup() {
+        let data_dir = temp_data_dir();
+        let cfg = test_cfg(data_dir.clone());
+        let path = PathBuf::from(&data_dir).join("accounts.json");
+        tokio::fs::create_dir_all(&data_dir).await.unwrap();
+        let legacy = sonic_rs::to_vec(&vec![expired_account("p1")]).unwrap();
+        tokio::fs::write(&path, legacy).await.unwrap();
+
+        let store = Store::new(cfg.clone());
+        store.load().await.unwrap();
+        let id = store.get_token().await.unwrap().id;
+        assert!(id.starts_with("acc_"));
+
+        // 补齐的 ID 已写回，文件升级为带版本号的格式，旧内容进入备份。
+        let data = tokio::fs::read(&path).await.unwrap();
+        let v: sonic_rs::Value = sonic_rs::from_slice(&data).unwrap();
+        assert_eq!(
+            v.get("version").and_then(|v| v.as_u64()),
+            Some(ACCOUNTS_FILE_VERSION as u64)
+        );
+        assert!(backup_path(&path, 1).exists());
+
+        store.set_enable(&id, false).await.unwrap();
+        assert!(
+            !tokio::fs::try_exists(path.with_extension("json.tmp"))
+                .await
+                .unwrap()
+        );
+
+        // 主文件损坏（如写入中途崩溃）时从最新备份恢复。
+        tokio::fs::write(&path, b"{\"version\":2,\"acc")
+            .await
+            .unwrap();
+        let reloaded = Store::new(cfg);
+        reloaded.load().await.unwrap();
+        let acc = reloaded.get_by_id(&id).await.unwrap();
+        assert_eq!(acc.project_id, "p1");
+        assert!(acc.enable);
+        assert!(parse_accounts(&tokio::fs::read(&path).await.unwrap(), None).is_ok());
+
+        // 更高版本的文件不回退备份，直接报错。
+        tokio::fs::write(&path, br#"{"version":99,"accounts":[]}"#)
+            .await
+            .unwrap();
+        assert!(Store::new(test_cfg(data_dir.clone())).load().await.is_err());
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn external_edits_are_reloaded_and_mutations_use_ids() {
+        let data_dir = temp_data_dir();
+        let store = Store::new(test_cfg(data_dir.clone()));
+        store.add(expired_account("p1")).await.unwrap();
+        store
+            .add(Account {
+                email: "other@example.com".to_string(),
+                ..expired_account("p2")
+            })
+            .await
+            .unwrap();
+        let all = store.get_all().await;
+        let (first, second) = (all[0].clone(), all[1].clone());
+        assert!(!store.reload_if_changed().await.unwrap());
+
+        // 外部删除第一个账号并修改第二个账号的 projectId。
+        let mut edited = second.clone();
+        edited.project_id = "edited".to_string();
+        let data = sonic_rs::to_vec_pretty(&AccountsFileOut {
+            version: ACCOUNTS_FILE_VERSION,
+            encryption: None,
+            accounts: &[edited],
+        })
+        .unwrap();
+        tokio::fs::write(PathBuf::from(&data_dir).join("accounts.json"), data)
+            .await
+            .unwrap();
+
+        // 变更前先合并外部修改：已删除的账号按 ID 找不到，剩余账号保留 session_id。
+        assert!(store.delete(&first.id).await.is_err());
+        let acc = store.get_by_id(&second.id).await.unwrap();
+        assert_eq!(acc.project_id, "edited");
+        assert_eq!(acc.session_id, second.session_id);
+
+        let removed = store.delete(&second.id).await.unwrap();
+        assert_eq!(removed.session_id, second.session_id);
+        assert_eq!(store.count().await, 0);
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
+
+    #[tokio::test]
+    async fn plaintext_file_is_encrypted_and_key_can_be_rotated() {
+        let data_dir = temp_data_dir();
+        let path = PathBuf::from(&data_dir).join("accounts.json");
+        let plain_store = Store::new(test_cfg(data_dir.clone()));
+        plain_store
+            .add(Account {
+                refresh_token: "1//secret-refresh".to_string(),
+                ..expired_account("p1")
+            })
+            .await
+            .unwrap();
+        plain_store
+            .set_enable(&plain_store.get_all().await[0].id, false)
+            .await
+            .unwrap();
+        assert!(backup_path(&path, 1).exists());
+
+        let key = MasterKey::generate().unwrap();
+        let mut cfg = test_cfg(data_dir.clone());
+        cfg.accounts_encryption_key = key.encode();
+
+        // 配置密钥后加载即迁移为密文，明文备份一并删除。
+        let store = Store::new(cfg.clone());
+        store.load().await.unwrap();
+        let data = String::from_utf8(tokio::fs::read(&path).await.unwrap()).unwrap();
+        assert!(!data.contains("secret-refresh"));
+        assert!(!data.contains("\"expired\""));
+        assert!(!backup_path(&path, 1).exists());
+        assert_eq!(store.get_all().await[0].refresh_token, "1//secret-refresh");
+
+        // 缺少密钥或密钥错误时加载失败，且不会覆盖密文。
+        let no_key = Store::new(test_cfg(data_dir.clone()));
+        assert!(no_key.load().await.is_err());
+        assert!(no_key.add(expired_account("p2")).await.is_err());
+        let mut wrong = test_cfg(data_dir.clone());
+        wrong.accounts_encryption_key = MasterKey::generate().unwrap().encode();
+        assert!(Store::new(wrong).load().await.is_err());
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), data);
+
+        // 轮换密钥后旧密钥失效，新密钥可正常加载。
+        let new_key = MasterKey::generate().unwrap();
+        assert_eq!(
+            rotate_encryption_key(&cfg, Some(&new_key)).await.unwrap(),
+            1
+        );
+        assert!(Store::new(cfg.clone()).load().await.is_err());
+        cfg.accounts_encryption_key = new_key.encode();
+        let rotated = Store::new(cfg);
+        rotated.load().await.unwrap();
+        assert_eq!(
+            rotated.get_all().await[0].refresh_token,
+            "1//secret-refresh"
+        );
+
+        let _ = tokio::fs::remove_dir_all(&data_dir).await;
+    }
 }
@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Account {
+    /// 持久化的账号 ID（跨重启稳定），管理操作按此定位账号。
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub id: String,
     pub access_token: String,
     pub refresh_token: String,
     pub expires_in: i32,
@@ -18,6 +21,9 @@ pub struct Account {
     pub enable: bool,
     #[serde(default = "default_created_at")]
     pub created_at: DateTime<Utc>,
+    /// 账号专用出口代理（http/https/socks5），为空时使用全局 `PROXY`。
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub proxy: String,
     #[serde(skip)]
     pub session_id: String,
 }
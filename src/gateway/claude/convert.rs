@@ -1,14 +1,14 @@
 use crate::config::Config;
 use crate::gateway::common::extract::{extract_claude_system_text, extract_text_from_content};
+use crate::gateway::common::media;
+use crate::gateway::common::output_limit;
 use crate::gateway::common::{AccountContext, find_function_name};
 use crate::signature::manager::Manager as SignatureManager;
-use crate::util::{id, model as modelutil};
-use crate::vertex::sanitize::{
-    inject_agent_system_prompt, sanitize_contents, sanitize_function_parameters_schema,
-};
+use crate::util::{id, model as modelutil, system_prompt, virtual_model};
+use crate::vertex::sanitize::{sanitize_contents, sanitize_function_parameters_schema};
 use crate::vertex::types::{
     Content, FunctionCall as VFunctionCall, FunctionCallingConfig, FunctionDeclaration,
-    FunctionResponse, GenerationConfig, ImageConfig, InnerReq, Part, Request, SystemInstruction,
+    FunctionResponse, GenerationConfig, InlineData, InnerReq, Part, Request, SystemInstruction,
     Tool as VTool, ToolConfig,
 };
 use sonic_rs::prelude::*;
@@ -29,8 +29,7 @@ pub async fn to_vertex_request(
     let model_name = req.model.clone();
     let model = req.model.trim();
     let is_claude_model = modelutil::is_claude(model);
-    let is_image_model = modelutil::is_image_model(model);
-    let is_gemini3_flash = modelutil::is_gemini3_flash(model);
+    let is_gemini_pro_image = modelutil::is_gemini_pro_image(model);
 
     let request_id = id::request_id();
     let vertex_model = modelutil::backend_model_id(&model_name);
@@ -47,6 +46,7 @@ pub async fn to_vertex_request(
             generation_config: None,
             tools: Vec::new(),
             tool_config: None,
+            safety_settings: Vec::new(),
             session_id: account.session_id.clone(),
         },
     };
@@ -66,31 +66,31 @@ pub async fn to_vertex_request(
 
     if !req.tools.is_empty() {
         vreq.request.tools = to_vertex_tools(&req.tools);
-        vreq.request.tool_config = Some(ToolConfig {
-            function_calling_config: Some(FunctionCallingConfig {
-                mode: "AUTO".to_string(),
-                allowed_function_names: Vec::new(),
-            }),
-        });
+        vreq.request.tool_config = Some(to_tool_config(req.tool_choice.as_ref()));
     }
 
     vreq.request.generation_config = Some(build_generation_config(cfg, req));
-    let contents = to_vertex_contents(sig_mgr, &req.messages, is_claude_model).await?;
+    let contents = to_vertex_contents(
+        cfg,
+        sig_mgr,
+        &req.messages,
+        is_claude_model,
+        is_gemini_pro_image,
+    )
+    .await?;
     vreq.request.contents = sanitize_contents(contents);
 
-    let should_skip_system_prompt = is_image_model || is_gemini3_flash;
-    if !should_skip_system_prompt {
-        let sys = vreq.request.system_instruction.take();
-        vreq.request.system_instruction = Some(inject_agent_system_prompt(sys));
-    }
+    system_prompt::apply(
+        model,
+        account.api_key_name.as_deref(),
+        &mut vreq.request.system_instruction,
+    );
 
     Ok((vreq, request_id))
 }
 
 fn build_generation_config(cfg: &Config, req: &MessagesRequest) -> GenerationConfig {
     let model = req.model.trim();
-    let is_claude = modelutil::is_claude(model);
-    let is_gemini = modelutil::is_gemini(model);
     let is_image_model = modelutil::is_image_model(model);
 
     let mut out = GenerationConfig {
@@ -103,16 +103,13 @@ fn build_generation_config(cfg: &Config, req: &MessagesRequest) -> GenerationCon
         thinking_config: None,
         image_config: None,
         media_resolution: String::new(),
+        response_mime_type: String::new(),
+        response_schema: None,
     };
 
-    // Claude：maxOutputTokens 固定为 64000；Gemini：固定为 65535。
-    if is_claude {
-        out.max_output_tokens = modelutil::CLAUDE_MAX_OUTPUT_TOKENS;
-    } else if is_gemini {
-        out.max_output_tokens = modelutil::GEMINI_MAX_OUTPUT_TOKENS;
-    } else if req.max_tokens > 0 {
-        out.max_output_tokens = req.max_tokens;
-    } else {
+    // maxOutputTokens：按 MAX_TOKENS_POLICY 处理客户端 max_tokens；未知模型未指定时默认 8192。
+    out.max_output_tokens = output_limit::resolve_max_output_tokens(cfg, model, req.max_tokens);
+    if out.max_output_tokens <= 0 {
         out.max_output_tokens = 8192;
     }
 
@@ -137,41 +134,8 @@ fn build_generation_config(cfg: &Config, req: &MessagesRequest) -> GenerationCon
         out.thinking_config = modelutil::forced_thinking_config(model);
     }
 
-    // 当使用 thinkingBudget 时，确保与 maxOutputTokens 兼容（与 Go 行为一致）。
-    if let Some(tc) = out.thinking_config.as_mut()
-        && tc.thinking_budget > 0
-    {
-        if out.max_output_tokens <= 0 {
-            out.max_output_tokens =
-                tc.thinking_budget + modelutil::THINKING_MAX_OUTPUT_TOKENS_OVERHEAD_TOKENS;
-        }
-        if is_claude {
-            let mut max_budget = out.max_output_tokens - modelutil::THINKING_BUDGET_HEADROOM_TOKENS;
-            if max_budget < modelutil::THINKING_BUDGET_MIN_TOKENS {
-                max_budget = modelutil::THINKING_BUDGET_MIN_TOKENS;
-            }
-            if tc.thinking_budget > max_budget {
-                tc.thinking_budget = max_budget;
-            }
-        } else if is_gemini && out.max_output_tokens <= tc.thinking_budget {
-            let mut max_budget = out.max_output_tokens - modelutil::THINKING_BUDGET_HEADROOM_TOKENS;
-            if max_budget < modelutil::THINKING_BUDGET_MIN_TOKENS {
-                max_budget = modelutil::THINKING_BUDGET_MIN_TOKENS;
-            }
-            tc.thinking_budget = max_budget;
-        } else if out.max_output_tokens <= tc.thinking_budget {
-            out.max_output_tokens =
-                tc.thinking_budget + modelutil::THINKING_MAX_OUTPUT_TOKENS_OVERHEAD_TOKENS;
-        }
-    }
-
-    // Gemini image size 虚拟模型：由 modelName 强制 imageConfig.imageSize。
-    if let Some((image_size, _backend)) = modelutil::gemini_pro_image_size_config(model) {
-        out.image_config = Some(ImageConfig {
-            aspect_ratio: String::new(),
-            image_size,
-        });
-    }
+    // thinkingBudget 需小于 maxOutputTokens。
+    output_limit::fit_thinking_budget(&mut out, model);
 
     // Gemini 3：应用全局 mediaResolution（非 image 模型）。
     if modelutil::is_gemini3(model)
@@ -182,9 +146,50 @@ fn build_generation_config(cfg: &Config, req: &MessagesRequest) -> GenerationCon
         out.media_resolution = v;
     }
 
+    // 虚拟模型：强制图片尺寸/宽高比、mediaResolution、temperature。
+    virtual_model::apply_generation_overrides(model, &mut out);
+
     out
 }
 
+/// Claude tool_choice -> functionCallingConfig：
+/// - {"type":"auto"} / {"type":"any"} / {"type":"none"} -> AUTO / ANY / NONE
+/// - {"type":"tool","name":..} -> ANY + allowedFunctionNames
+/// - 缺省或无法识别时为 AUTO
+fn to_tool_config(tool_choice: Option<&sonic_rs::Value>) -> ToolConfig {
+    let typ = tool_choice
+        .and_then(|tc| tc.get("type"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("auto");
+    let name = tool_choice
+        .and_then(|tc| tc.get("name"))
+        .and_then(|n| n.as_str())
+        .map(str::trim)
+        .unwrap_or("");
+
+    let (mode, allowed_function_names) = match typ {
+        "none" => ("NONE", Vec::new()),
+        "any" => ("ANY", Vec::new()),
+        "tool" if !name.is_empty() => ("ANY", vec![name.to_string()]),
+        _ => ("AUTO", Vec::new()),
+    };
+    ToolConfig {
+        function_calling_config: Some(FunctionCallingConfig {
+            mode: mode.to_string(),
+            allowed_function_names,
+        }),
+    }
+}
+
+/// tool_choice.disable_parallel_tool_use：为 true 时响应中最多保留一个 tool_use。
+/// 后端没有对应开关，由响应转换侧截断。
+pub(crate) fn parallel_tool_use_disabled(tool_choice: Option<&sonic_rs::Value>) -> bool {
+    tool_choice
+        .and_then(|tc| tc.get("disable_parallel_tool_use"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
 fn to_vertex_tools(tools: &[Tool]) -> Vec<VTool> {
     let mut out: Vec<VTool> = Vec::with_capacity(tools.len());
     for t in tools {
@@ -208,17 +213,26 @@ fn to_vertex_tools(tools: &[Tool]) -> Vec<VTool> {
 }
 
 async fn to_vertex_contents(
+    cfg: &Config,
     sig_mgr: &SignatureManager,
     messages: &[Message],
     is_claude_model: bool,
+    is_gemini_pro_image: bool,
 ) -> anyhow::Result<Vec<Content>> {
     let mut out: Vec<Content> = Vec::new();
 
     for m in messages {
         match m.role.as_str() {
             "user" => {
-                let parts =
-                    extract_content_parts(sig_mgr, &m.content, &out, is_claude_model).await?;
+                let parts = extract_content_parts(
+                    cfg,
+                    sig_mgr,
+                    &m.content,
+                    &out,
+                    is_claude_model,
+                    is_gemini_pro_image,
+                )
+                .await?;
                 if !parts.is_empty() {
                     out.push(Content {
                         role: "user".to_string(),
@@ -227,8 +241,15 @@ async fn to_vertex_contents(
                 }
             }
             "assistant" => {
-                let parts =
-                    extract_content_parts(sig_mgr, &m.content, &out, is_claude_model).await?;
+                let parts = extract_content_parts(
+                    cfg,
+                    sig_mgr,
+                    &m.content,
+                    &out,
+                    is_claude_model,
+                    is_gemini_pro_image,
+                )
+                .await?;
                 if !parts.is_empty() {
                     out.push(Content {
                         role: "model".to_string(),
@@ -244,10 +265,12 @@ async fn to_vertex_contents(
 }
 
 async fn extract_content_parts(
+    cfg: &Config,
     sig_mgr: &SignatureManager,
     content: &sonic_rs::Value,
     contents_so_far: &[Content],
     is_claude_model: bool,
+    is_gemini_pro_image: bool,
 ) -> anyhow::Result<Vec<Part>> {
     let mut out: Vec<Part> = Vec::new();
 
@@ -280,6 +303,11 @@ async fn extract_content_parts(
                     });
                 }
             }
+            "image" | "document" => {
+                if let Some(part) = media_part(cfg, sig_mgr, obj, is_gemini_pro_image).await? {
+                    out.push(part);
+                }
+            }
             "thinking" => {
                 let mut thinking = obj
                     .get(&"thinking")
@@ -295,14 +323,11 @@ async fn extract_content_parts(
 
                 if is_claude_model {
                     // 部分客户端不会持久化 signature，尝试用后续 tool_use 的 id 从缓存中恢复。
-                    if let Some(tool_use_id) = lookahead_tool_use_id(arr, i + 1) {
-                        if signature.is_empty() || signature.len() <= 50 {
-                            if let Some(e) =
-                                sig_mgr.lookup_by_tool_call_id_strict(&tool_use_id).await
-                            {
-                                signature = e.signature.trim().to_string();
-                            }
-                        }
+                    if let Some(tool_use_id) = lookahead_tool_use_id(arr, i + 1)
+                        && (signature.is_empty() || signature.len() <= 50)
+                        && let Some(e) = sig_mgr.lookup_by_tool_call_id_strict(&tool_use_id).await
+                    {
+                        signature = e.signature.trim().to_string();
                     }
 
                     // Claude thinking block 在回传时必须带 signature；若无法恢复则丢弃该块，
@@ -343,14 +368,11 @@ async fn extract_content_parts(
 
                 if is_claude_model {
                     // 部分客户端会丢失 opaque payload，尝试用后续 tool_use 的 id 从缓存中恢复。
-                    if let Some(tool_use_id) = lookahead_tool_use_id(arr, i + 1) {
-                        if data.is_empty() || data.len() <= 50 {
-                            if let Some(e) =
-                                sig_mgr.lookup_by_tool_call_id_strict(&tool_use_id).await
-                            {
-                                data = e.signature.trim().to_string();
-                            }
-                        }
+                    if let Some(tool_use_id) = lookahead_tool_use_id(arr, i + 1)
+                        && (data.is_empty() || data.len() <= 50)
+                        && let Some(e) = sig_mgr.lookup_by_tool_call_id_strict(&tool_use_id).await
+                    {
+                        data = e.signature.trim().to_string();
                     }
                     if data.is_empty() || data.len() <= 50 {
                         continue;
@@ -443,6 +465,20 @@ async fn extract_content_parts(
                     }),
                     ..Part::default()
                 });
+
+                // tool_result 内的图片/文档（如截图）紧跟在 functionResponse 之后。
+                for block in content_value.as_array().into_iter().flat_map(|a| a.iter()) {
+                    let Some(block) = block.as_object() else {
+                        continue;
+                    };
+                    let typ = block.get(&"type").and_then(|v| v.as_str());
+                    if matches!(typ, Some("image" | "document"))
+                        && let Some(part) =
+                            media_part(cfg, sig_mgr, block, is_gemini_pro_image).await?
+                    {
+                        out.push(part);
+                    }
+                }
             }
             _ => {}
         }
@@ -451,6 +487,77 @@ async fn extract_content_parts(
     Ok(out)
 }
 
+/// 将 image / document 块转为 Part：
+/// - source.type=base64 / url：转为 InlineData（远程 URL 会被下载）
+/// - document 的 source.type=text / content：转为文本 Part
+async fn media_part(
+    cfg: &Config,
+    sig_mgr: &SignatureManager,
+    block: &sonic_rs::Object,
+    is_gemini_pro_image: bool,
+) -> anyhow::Result<Option<Part>> {
+    let typ = block.get(&"type").and_then(|v| v.as_str()).unwrap_or("");
+    let Some(source) = block.get(&"source").and_then(|v| v.as_object()) else {
+        return Ok(None);
+    };
+    let field = |k: &str| source.get(&k).and_then(|v| v.as_str()).unwrap_or("");
+
+    let inline = match field("type") {
+        "base64" => {
+            let (mime_type, data) = (field("media_type"), field("data"));
+            if mime_type.is_empty() || data.is_empty() {
+                return Ok(None);
+            }
+            InlineData::new(media::normalize_mime(mime_type)?, data)
+        }
+        "url" => {
+            let url = field("url").trim();
+            if url.is_empty() {
+                return Ok(None);
+            }
+            media::resolve_url(cfg, url).await?
+        }
+        "text" if typ == "document" => {
+            return Ok(document_text_part(block, field("data")));
+        }
+        "content" if typ == "document" => {
+            let content = source.get(&"content").cloned().unwrap_or_default();
+            let text = extract_text_from_content(&content, "\n", true);
+            return Ok(document_text_part(block, &text));
+        }
+        "file" => anyhow::bail!("不支持通过 file_id 引用的 {typ}，请改用 base64 或 url"),
+        _ => return Ok(None),
+    };
+
+    // 与 OpenAI image_url 一致：回传图片时带上缓存的 thoughtSignature。
+    let thought_signature = if typ == "image" {
+        media::image_signature(sig_mgr, &inline, is_gemini_pro_image).await
+    } else {
+        String::new()
+    };
+    Ok(Some(Part {
+        inline_data: Some(inline),
+        thought_signature,
+        ..Part::default()
+    }))
+}
+
+fn document_text_part(block: &sonic_rs::Object, text: &str) -> Option<Part> {
+    if text.is_empty() {
+        return None;
+    }
+    let title = block.get(&"title").and_then(|v| v.as_str()).unwrap_or("");
+    let text = if title.is_empty() {
+        text.to_string()
+    } else {
+        format!("{title}\n\n{text}")
+    };
+    Some(Part {
+        text,
+        ..Part::default()
+    })
+}
+
 fn lookahead_tool_use_id(blocks: &[sonic_rs::Value], start: usize) -> Option<String> {
     for block in blocks.iter().skip(start) {
         let Some(obj) = block.as_object() else {
@@ -466,3 +573,111 @@ fn lookahead_tool_use_id(blocks: &[sonic_rs::Value], start: usize) -> Option<Str
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(tool_choice: &str) -> sonic_rs::Value {
+        sonic_rs::from_str(tool_choice).unwrap()
+    }
+
+    fn mode_of(tool_choice: Option<&str>) -> (String, Vec<String>) {
+        let v = tool_choice.map(parse);
+        let fc = to_tool_config(v.as_ref()).function_calling_config.unwrap();
+        (fc.mode, fc.allowed_function_names)
+    }
+
+    #[test]
+    fn tool_choice_maps_to_function_calling_mode() {
+        assert_eq!(mode_of(None).0, "AUTO");
+        assert_eq!(mode_of(Some(r#"{"type":"auto"}"#)).0, "AUTO");
+        assert_eq!(mode_of(Some(r#"{"type":"any"}"#)).0, "ANY");
+        assert_eq!(mode_of(Some(r#"{"type":"none"}"#)).0, "NONE");
+
+        let (mode, names) = mode_of(Some(r#"{"type":"tool","name":"get_weather"}"#));
+        assert_eq!(mode, "ANY");
+        assert_eq!(names, vec!["get_weather".to_string()]);
+    }
+
+    #[test]
+    fn stop_sequences_are_forwarded_upstream() {
+        let req: MessagesRequest = sonic_rs::from_str(
+            r#"{"model":"claude-sonnet-4-5","max_tokens":64,"stop_sequences":["\nUser:"],"messages":[]}"#,
+        )
+        .unwrap();
+        let gc = build_generation_config(&Config::for_test(), &req);
+        assert_eq!(gc.stop_sequences, vec!["\nUser:".to_string()]);
+    }
+
+    #[test]
+    fn disable_parallel_tool_use_is_detected() {
+        assert!(!parallel_tool_use_disabled(None));
+        assert!(!parallel_tool_use_disabled(Some(&parse(
+            r#"{"type":"auto"}"#
+        ))));
+        assert!(parallel_tool_use_disabled(Some(&parse(
+            r#"{"type":"any","disable_parallel_tool_use":true}"#
+        ))));
+    }
+
+    #[tokio::test]
+    async fn image_and_document_blocks_become_parts() {
+        let dir =
+            std::env::temp_dir().join(format!("ant2api-claude-media-{}", uuid::Uuid::new_v4()));
+        let sig_mgr = SignatureManager::new(&dir.to_string_lossy()).await.unwrap();
+        let content = parse(
+            r#"[
+                {"type":"image","source":{"type":"base64","media_type":"image/png","data":"iVBORw0KGgo="}},
+                {"type":"image","source":{"type":"url","url":"data:image/jpeg;base64,/9j/4AAQ"}},
+                {"type":"document","title":"notes","source":{"type":"text","media_type":"text/plain","data":"hello"}},
+                {"type":"document","source":{"type":"base64","media_type":"application/pdf","data":"JVBERi0="}},
+                {"type":"tool_result","tool_use_id":"toolu_1","content":[
+                    {"type":"text","text":"screenshot taken"},
+                    {"type":"image","source":{"type":"base64","media_type":"image/webp","data":"UklGRg=="}}
+                ]}
+            ]"#,
+        );
+
+        let history = vec![Content {
+            role: "model".to_string(),
+            parts: vec![Part {
+                function_call: Some(VFunctionCall {
+                    id: "toolu_1".to_string(),
+                    name: "screenshot".to_string(),
+                    args: HashMap::new(),
+                }),
+                ..Part::default()
+            }],
+        }];
+        let parts = extract_content_parts(
+            &Config::for_test(),
+            &sig_mgr,
+            &content,
+            &history,
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+        let mimes: Vec<&str> = parts
+            .iter()
+            .filter_map(|p| p.inline_data.as_ref().map(|d| d.mime_type.as_str()))
+            .collect();
+        assert_eq!(
+            mimes,
+            vec!["image/png", "image/jpeg", "application/pdf", "image/webp"]
+        );
+        assert!(parts.iter().any(|p| p.text == "notes\n\nhello"));
+        assert!(parts[4].function_response.is_some());
+        assert!(parts[5].inline_data.is_some());
+
+        let file_ref = parse(r#"[{"type":"image","source":{"type":"file","file_id":"file_1"}}]"#);
+        assert!(
+            extract_content_parts(&Config::for_test(), &sig_mgr, &file_ref, &[], false, false)
+                .await
+                .is_err()
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,23 +1,34 @@
-use super::convert::to_vertex_request;
+use super::convert::{parallel_tool_use_disabled, to_vertex_request};
 use super::response::to_messages_response;
 use super::stream::{ClaudeStreamWriter, sse_error_events};
-use super::types::MessagesRequest;
+use super::types::{MessagesRequest, ModelInfoResponse, ModelListResponse};
+use crate::capture::{self, CaptureProvider, CaptureRecorder};
+use crate::credential::affinity::AccountAffinity;
 use crate::credential::store::Store as CredentialStore;
 use crate::gateway::common::AccountContext;
+use crate::gateway::common::api_auth::resolve_key_name;
 use crate::gateway::common::auth_retry::is_auth_failure;
+use crate::gateway::common::conversation::conversation_key;
+use crate::gateway::common::model_catalog::{self, ModelInfo};
 use crate::gateway::common::retry::{
     MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE, MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES,
     should_retry_with_next_token,
 };
+use crate::gateway::common::token_count;
+use crate::gateway::common::{json_response_logged, log_model_request};
+use crate::key_quota::KeyQuotaManager;
+use crate::ledger::{LedgerEntry, UsageLedger};
 use crate::logging;
+use crate::metrics;
 use crate::quota_pool::QuotaPoolManager;
 use crate::runtime_config;
 use crate::signature::manager::Manager as SignatureManager;
-use crate::util::{id, model as modelutil};
+use crate::util::id;
 use crate::vertex::client::{ApiError, VertexClient};
 use axum::Json;
 use axum::body::Bytes;
 use axum::extract::OriginalUri;
+use axum::extract::Path;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::http::{HeaderMap, Method};
@@ -38,6 +49,10 @@ pub struct ClaudeState {
     pub store: Arc<CredentialStore>,
     pub quota_pool: Arc<QuotaPoolManager>,
     pub sig_mgr: SignatureManager,
+    pub key_quota: Arc<KeyQuotaManager>,
+    pub responses: Arc<crate::gateway::openai::responses::ResponseStore>,
+    pub ledger: Arc<UsageLedger>,
+    pub affinity: Arc<AccountAffinity>,
 }
 
 pub async fn handle_list_models(
@@ -48,135 +63,63 @@ pub async fn handle_list_models(
 ) -> Response {
     let start = Instant::now();
     let log_level = state.cfg.log_level();
-    if log_level.client_enabled() {
-        if log_level.raw_enabled() {
-            logging::client_request_raw(method.as_str(), uri.0.path(), &headers, &[]);
-        } else {
-            logging::client_request(method.as_str(), uri.0.path(), &headers, &[]);
-        }
-    }
-
-    let endpoint = runtime_config::current_endpoint();
-    let mut attempts = state.store.enabled_count().await;
-    if attempts < 1 {
-        attempts = 1;
-    }
+    log_model_request(log_level, &method, &uri, &headers);
 
-    let mut last_err: Option<ApiError> = None;
-    let mut models = None;
-
-    for _ in 0..attempts {
-        let acc = match state.store.get_token().await {
-            Ok(v) => v,
-            Err(e) => {
-                let status = StatusCode::SERVICE_UNAVAILABLE;
-                if log_level.client_enabled() {
-                    if log_level.raw_enabled() {
-                        let body = claude_error_body(&e.to_string());
-                        logging::client_response_raw(
-                            status.as_u16(),
-                            start.elapsed(),
-                            body.as_bytes(),
-                        );
-                    } else {
-                        let err = claude_error_value(&e.to_string());
-                        logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
-                    }
-                }
-                return claude_error(status, &e.to_string());
-            }
-        };
-
-        let project_id = if acc.project_id.is_empty() {
-            id::project_id()
-        } else {
-            acc.project_id.clone()
-        };
-
-        match state
-            .vertex
-            .fetch_available_models(&endpoint, &project_id, &acc.access_token, &acc.email)
-            .await
-        {
-            Ok(v) => {
-                models = Some(v.models);
-                last_err = None;
-                break;
-            }
-            Err(e) => {
-                tracing::warn!(error = ?e, "fetchAvailableModels 失败");
-                // 认证失败：立即切换到下一个凭证，同时后台触发刷新（不阻塞请求路径）。
-                if is_auth_failure(&e) {
-                    state
-                        .store
-                        .trigger_background_refresh(acc.session_id.clone(), state.cfg.clone());
-                }
-                let retry = should_retry_with_next_token(&e);
-                last_err = Some(e);
-                if !retry {
-                    break;
-                }
-            }
-        }
-    }
+    let models = match model_catalog::load_or_fetch(&state).await {
+        Ok(v) => v,
+        Err((status, msg)) => return claude_error_logged(log_level, start, status, &msg),
+    };
 
-    let Some(models) = models else {
-        let status = last_err
-            .as_ref()
-            .and_then(|e| e.status())
-            .and_then(|s| StatusCode::from_u16(s).ok())
-            .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
-        let msg = last_err
-            .as_ref()
-            .map(|e| e.to_string())
-            .unwrap_or_else(|| "后端请求失败".to_string());
-        if log_level.client_enabled() {
-            if log_level.raw_enabled() {
-                let body = claude_error_body(&msg);
-                logging::client_response_raw(status.as_u16(), start.elapsed(), body.as_bytes());
-            } else {
-                let err = claude_error_value(&msg);
-                logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
-            }
-        }
-        return claude_error(status, &msg);
+    let data: Vec<ModelInfoResponse> = models.iter().map(to_model_info).collect();
+    let out = ModelListResponse {
+        has_more: false,
+        first_id: data.first().map(|m| m.id.clone()),
+        last_id: data.last().map(|m| m.id.clone()),
+        data,
     };
+    json_response_logged(log_level, start, &out)
+}
 
-    #[derive(Serialize)]
-    struct ModelItem {
-        id: String,
-        #[serde(rename = "type")]
-        typ: String,
-        #[serde(skip_serializing_if = "String::is_empty", default)]
-        display_name: String,
-    }
+/// GET /v1/models/{id}（Claude 格式）。
+pub async fn handle_get_model(
+    State(state): State<Arc<ClaudeState>>,
+    Path(model): Path<String>,
+    method: Method,
+    uri: OriginalUri,
+    headers: HeaderMap,
+) -> Response {
+    let start = Instant::now();
+    let log_level = state.cfg.log_level();
+    log_model_request(log_level, &method, &uri, &headers);
 
-    #[derive(Serialize)]
-    struct ModelListResponse {
-        data: Vec<ModelItem>,
+    if let Err((status, msg)) = model_catalog::load_or_fetch(&state).await {
+        return claude_error_logged(log_level, start, status, &msg);
     }
+    let Some(info) = model_catalog::catalog().get(&runtime_config::map_client_model_id(&model))
+    else {
+        let msg = format!("模型不存在：{model}");
+        return claude_error_logged(log_level, start, StatusCode::NOT_FOUND, &msg);
+    };
 
-    let ids = modelutil::build_sorted_model_ids(&models);
-    let mut items: Vec<ModelItem> = Vec::with_capacity(ids.len());
-    for mid in ids {
-        items.push(ModelItem {
-            display_name: mid.clone(),
-            id: mid,
-            typ: "model".to_string(),
-        });
-    }
+    let mut out = to_model_info(&info);
+    out.id = model;
+    json_response_logged(log_level, start, &out)
+}
 
-    let out = ModelListResponse { data: items };
-    if log_level.client_enabled() {
-        if log_level.raw_enabled() {
-            if let Ok(bytes) = serde_json::to_vec(&out) {
-                logging::client_response_raw(StatusCode::OK.as_u16(), start.elapsed(), &bytes);
-            }
-        } else if let Ok(v) = sonic_rs::to_value(&out) {
-            logging::client_response(StatusCode::OK.as_u16(), start.elapsed(), Some(&v));
-        }
+fn to_model_info(m: &ModelInfo) -> ModelInfoResponse {
+    let created_at = model_catalog::catalog()
+        .updated_at()
+        .unwrap_or_else(chrono::Utc::now);
+    ModelInfoResponse {
+        typ: "model".to_string(),
+        id: m.id.clone(),
+        display_name: m.display_name.clone(),
+        created_at: created_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        context_window: m.context_window,
+        max_output_tokens: m.max_output_tokens,
+        thinking: m.thinking,
+        vision: m.vision,
     }
-    (StatusCode::OK, Json(out)).into_response()
 }
 
 pub async fn handle_messages(
@@ -237,13 +180,37 @@ pub(crate) async fn handle_messages_inner(
     };
 
     // 模型 ID 映射：允许客户端使用自定义模型名，后端自动替换为原始模型名。
+    let client_model = req.model.clone();
     req.model = runtime_config::map_client_model_id(&req.model);
 
+    // API Key 配额：模型白名单 + RPM + 每日 token。
+    let key_name = resolve_key_name(&headers, &uri.0);
+    if let Err(rejection) = state
+        .key_quota
+        .check_and_record(key_name.as_deref(), &req.model)
+    {
+        let status = rejection.status();
+        let msg = rejection.to_string();
+        if log_level.client_enabled() {
+            if log_level.raw_enabled() {
+                let body = claude_error_body(&msg);
+                logging::client_response_raw(status.as_u16(), start.elapsed(), body.as_bytes());
+            } else {
+                let err = claude_error_value(&msg);
+                logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
+            }
+        }
+        return claude_error(status, &msg);
+    }
+
+    metrics::set_request_model(&req.model);
+
     let placeholder = AccountContext {
         project_id: id::project_id(),
         session_id: id::session_id(),
         access_token: String::new(),
         email: String::new(),
+        api_key_name: key_name.clone(),
     };
 
     let (mut vreq, request_id) =
@@ -273,7 +240,32 @@ pub(crate) async fn handle_messages_inner(
 
     let model = req.model.clone();
     let is_stream = req.stream;
+    let single_tool_use = parallel_tool_use_disabled(req.tool_choice.as_ref());
+    let stop_sequences = std::mem::take(&mut req.stop_sequences);
+    let conversation = conversation_key(
+        &headers,
+        req.metadata.as_ref().and_then(|m| m.user_id.as_deref()),
+        key_name.as_deref(),
+        &vreq.request,
+    );
     drop(req);
+    let capture = capture::begin(
+        &state.cfg,
+        CaptureProvider::Claude,
+        uri.0.path(),
+        &model,
+        key_name.as_deref(),
+        is_stream,
+        body.as_ref(),
+        &vreq,
+    );
+    let mut ledger = state.ledger.begin(
+        start,
+        key_name.as_deref(),
+        &client_model,
+        &vreq.model,
+        is_stream,
+    );
 
     let mut attempts = state.store.enabled_count().await;
     if attempts < 1 {
@@ -281,7 +273,21 @@ pub(crate) async fn handle_messages_inner(
     }
 
     if is_stream {
-        return handle_stream_with_retry(state, vreq, request_id, model, attempts, start).await;
+        return handle_stream_with_retry(
+            state,
+            vreq,
+            request_id,
+            model,
+            key_name,
+            conversation,
+            single_tool_use,
+            stop_sequences,
+            capture,
+            ledger,
+            attempts,
+            start,
+        )
+        .await;
     }
 
     let mut last_err: Option<ApiError> = None;
@@ -292,11 +298,20 @@ pub(crate) async fn handle_messages_inner(
     for _ in 0..attempts {
         let acc = match state
             .store
-            .get_token_for_model_excluding(&model, &state.quota_pool, &used_sessions)
+            .get_token_for_conversation(
+                &model,
+                &state.quota_pool,
+                &used_sessions,
+                &state.affinity,
+                conversation.as_deref(),
+            )
             .await
         {
             Ok(v) => v,
             Err(e) => {
+                ledger
+                    .finish(StatusCode::SERVICE_UNAVAILABLE.as_u16(), None)
+                    .await;
                 if log_level.client_enabled() {
                     if log_level.raw_enabled() {
                         let body = claude_error_body(&e.to_string());
@@ -319,6 +334,7 @@ pub(crate) async fn handle_messages_inner(
         };
         let session_id = acc.session_id.clone();
         used_sessions.insert(session_id.clone());
+        ledger.attempt(&acc.email);
         let project_id = if acc.project_id.is_empty() {
             id::project_id()
         } else {
@@ -330,6 +346,7 @@ pub(crate) async fn handle_messages_inner(
 
         match state
             .vertex
+            .for_proxy(&acc.proxy)
             .generate_content(&endpoint, &acc.access_token, &vreq, &acc.email)
             .await
         {
@@ -345,6 +362,11 @@ pub(crate) async fn handle_messages_inner(
                         .store
                         .trigger_background_refresh(session_id.clone(), state.cfg.clone());
                 }
+                // 429/容量不足：在模型所属配额池中冷却该账号，避免被其他请求继续选中。
+                state
+                    .quota_pool
+                    .cooldown_on_error(&session_id, &model, &e, state.cfg.rate_limit_cooldown())
+                    .await;
                 if e.is_model_capacity_exhausted() {
                     model_capacity_failures += 1;
                 } else {
@@ -363,6 +385,7 @@ pub(crate) async fn handle_messages_inner(
     }
 
     let Some(vresp) = vresp else {
+        ledger.finish_error(last_err.as_ref()).await;
         let status = last_err
             .as_ref()
             .and_then(|e| e.status())
@@ -379,6 +402,10 @@ pub(crate) async fn handle_messages_inner(
         {
             msg = MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE.to_string();
         }
+        if let Some(mut capture) = capture {
+            capture.set_upstream_error(&msg);
+            capture.finish().await;
+        }
         if log_level.client_enabled() {
             if log_level.raw_enabled() {
                 let body = claude_error_body(&msg);
@@ -391,7 +418,29 @@ pub(crate) async fn handle_messages_inner(
         return claude_error(status, &msg);
     };
 
-    let out = to_messages_response(&vresp, &request_id, &model, &state.sig_mgr).await;
+    state
+        .key_quota
+        .record_usage(key_name.as_deref(), vresp.response.usage_metadata.as_ref());
+    metrics::record_usage(&model, vresp.response.usage_metadata.as_ref());
+    ledger
+        .finish(
+            StatusCode::OK.as_u16(),
+            vresp.response.usage_metadata.as_ref(),
+        )
+        .await;
+    if let Some(mut capture) = capture {
+        capture.push_response(&vresp);
+        capture.finish().await;
+    }
+    let out = to_messages_response(
+        &vresp,
+        &request_id,
+        &model,
+        &state.sig_mgr,
+        single_tool_use,
+        &stop_sequences,
+    )
+    .await;
     if log_level.client_enabled() {
         if log_level.raw_enabled() {
             if let Ok(bytes) = serde_json::to_vec(&out) {
@@ -404,11 +453,18 @@ pub(crate) async fn handle_messages_inner(
     (StatusCode::OK, Json(out)).into_response()
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_stream_with_retry(
     state: Arc<ClaudeState>,
     mut vreq: crate::vertex::types::Request,
     request_id: String,
     model: String,
+    key_name: Option<String>,
+    conversation: Option<String>,
+    single_tool_use: bool,
+    stop_sequences: Vec<String>,
+    mut capture: Option<CaptureRecorder>,
+    mut ledger: LedgerEntry,
     attempts: usize,
     started_at: Instant,
 ) -> Response {
@@ -433,11 +489,20 @@ async fn handle_stream_with_retry(
         for _ in 0..attempts {
             let acc = match state
                 .store
-                .get_token_for_model_excluding(&model, &state.quota_pool, &used_sessions)
+                .get_token_for_conversation(
+                    &model,
+                    &state.quota_pool,
+                    &used_sessions,
+                    &state.affinity,
+                    conversation.as_deref(),
+                )
                 .await
             {
                 Ok(v) => v,
                 Err(e) => {
+                    ledger
+                        .finish(StatusCode::SERVICE_UNAVAILABLE.as_u16(), None)
+                        .await;
                     if client_log && !raw_log {
                         let err = claude_error_value(&e.to_string());
                         logging::client_stream_response(
@@ -458,6 +523,7 @@ async fn handle_stream_with_retry(
             };
             let session_id = acc.session_id.clone();
             used_sessions.insert(session_id.clone());
+            ledger.attempt(&acc.email);
 
             let project_id = if acc.project_id.is_empty() {
                 id::project_id()
@@ -469,6 +535,7 @@ async fn handle_stream_with_retry(
 
             match state
                 .vertex
+                .for_proxy(&acc.proxy)
                 .generate_content_stream(&endpoint, &acc.access_token, &vreq, &acc.email)
                 .await
             {
@@ -484,6 +551,11 @@ async fn handle_stream_with_retry(
                             .store
                             .trigger_background_refresh(session_id.clone(), state.cfg.clone());
                     }
+                    // 429/容量不足：在模型所属配额池中冷却该账号，避免被其他请求继续选中。
+                    state
+                        .quota_pool
+                        .cooldown_on_error(&session_id, &model, &e, state.cfg.rate_limit_cooldown())
+                        .await;
                     if e.is_model_capacity_exhausted() {
                         model_capacity_failures += 1;
                     } else {
@@ -502,6 +574,7 @@ async fn handle_stream_with_retry(
         }
 
         let Some(resp) = resp else {
+            ledger.finish_error(last_err.as_ref()).await;
             let mut msg = last_err
                 .as_ref()
                 .map(|e| e.to_string())
@@ -513,6 +586,10 @@ async fn handle_stream_with_retry(
             {
                 msg = MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE.to_string();
             }
+            if let Some(mut capture) = capture {
+                capture.set_upstream_error(&msg);
+                capture.finish().await;
+            }
             if client_log && !raw_log {
                 let err = claude_error_value(&msg);
                 logging::client_stream_response(
@@ -527,27 +604,18 @@ async fn handle_stream_with_retry(
 
         let mut writer = ClaudeStreamWriter::new(request_id.clone(), model.clone());
         writer.set_log_enabled(client_log && !raw_log);
+        writer.set_single_tool_use(single_tool_use);
+        writer.set_stop_sequences(&stop_sequences);
 
         let backend_raw = backend_log && raw_log;
         let build_merged = backend_log && !raw_log;
         let parse_res = crate::vertex::stream::parse_stream_with_result(
             resp,
             |data| {
-                let mut events: Vec<(&'static str, String)> = Vec::new();
-                let mut saves: Vec<super::stream::SignatureSave> = Vec::new();
-
-                if let Some(usage) = data.response.usage_metadata.as_ref() {
-                    writer.set_input_tokens(usage.prompt_token_count);
-                }
-
-                if let Some(cand) = data.response.candidates.first() {
-                    for p in &cand.content.parts {
-                        let (ev, sv) = writer.process_part(p);
-                        events.extend(ev);
-                        saves.extend(sv);
-                    }
-                }
+                let (events, saves) = writer.process_chunk(data);
 
+                // 命中 stop sequence 后不再读取上游（其后的输出都会被丢弃）。
+                let stopped = writer.stop_sequence().is_some();
                 let tx = tx.clone();
                 let sig_mgr = state.sig_mgr.clone();
                 let raw_section = raw_section.clone();
@@ -591,13 +659,20 @@ async fn handle_stream_with_retry(
                                 .await;
                         }
                     }
+                    if stopped {
+                        anyhow::bail!("已命中 stop sequence");
+                    }
                     Ok(())
                 }
             },
             build_merged,
             {
                 let raw_section = raw_section.clone();
+                let mut capture = capture.as_mut();
                 move |line| {
+                    if let Some(capture) = capture.as_mut() {
+                        capture.push_line(line);
+                    }
                     if !backend_raw {
                         return;
                     }
@@ -616,21 +691,19 @@ async fn handle_stream_with_retry(
             Ok(r) => r,
             Err(e) => e.result,
         };
-
-        let output_tokens = stream_result
-            .usage
-            .as_ref()
-            .map(|u| u.candidates_token_count)
-            .unwrap_or(0);
-
-        let stop_reason = if !stream_result.tool_calls.is_empty() {
-            "tool_use"
-        } else {
-            "end_turn"
-        };
+        state
+            .key_quota
+            .record_usage(key_name.as_deref(), stream_result.usage.as_ref());
+        metrics::record_usage(&model, stream_result.usage.as_ref());
+        ledger
+            .finish(StatusCode::OK.as_u16(), stream_result.usage.as_ref())
+            .await;
+        if let Some(capture) = capture {
+            capture.finish().await;
+        }
 
         let mut client_disconnected = false;
-        for (event_name, ev) in writer.finish(output_tokens, stop_reason) {
+        for (event_name, ev) in writer.finish_stream(&stream_result) {
             if client_disconnected {
                 continue;
             }
@@ -687,6 +760,122 @@ async fn send_sse_error(
     }
 }
 
+/// POST /v1/messages/count_tokens
+///
+/// 优先使用后端 countTokens；无可用账号或后端失败时回退到本地估算。
+pub async fn handle_count_tokens(
+    State(state): State<Arc<ClaudeState>>,
+    method: Method,
+    uri: OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let start = Instant::now();
+    let log_level = state.cfg.log_level();
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            logging::client_request_raw(method.as_str(), uri.0.path(), &headers, body.as_ref());
+        } else {
+            logging::client_request(method.as_str(), uri.0.path(), &headers, body.as_ref());
+        }
+    }
+
+    let mut req: MessagesRequest = match sonic_rs::from_slice(body.as_ref()) {
+        Ok(v) => v,
+        Err(_) => {
+            return claude_error_logged(
+                log_level,
+                start,
+                StatusCode::BAD_REQUEST,
+                "请求 JSON 解析失败，请检查请求体格式。",
+            );
+        }
+    };
+    req.model = runtime_config::map_client_model_id(&req.model);
+
+    // 计数不占用 RPM，只校验模型白名单。
+    let key_name = resolve_key_name(&headers, &uri.0);
+    if let Err(rejection) = state.key_quota.check_model(key_name.as_deref(), &req.model) {
+        return claude_error_logged(log_level, start, rejection.status(), &rejection.to_string());
+    }
+
+    metrics::set_request_model(&req.model);
+
+    let placeholder = AccountContext {
+        project_id: id::project_id(),
+        session_id: id::session_id(),
+        access_token: String::new(),
+        email: String::new(),
+        api_key_name: key_name.clone(),
+    };
+    let vreq = match to_vertex_request(&state.cfg, &state.sig_mgr, &req, &placeholder).await {
+        Ok((v, _)) => v,
+        Err(e) => {
+            return claude_error_logged(log_level, start, StatusCode::BAD_REQUEST, &e.to_string());
+        }
+    };
+
+    let mut input_tokens = None;
+    if let Ok(acc) = state
+        .store
+        .get_token_for_model_excluding(&req.model, &state.quota_pool, &HashSet::new())
+        .await
+    {
+        let endpoint = runtime_config::current_endpoint();
+        let contents = token_count::contents_for_count(&vreq.request);
+        match state
+            .vertex
+            .for_proxy(&acc.proxy)
+            .count_tokens(&endpoint, &acc.access_token, &vreq.model, &contents)
+            .await
+        {
+            Ok(v) if v.total_tokens > 0 => input_tokens = Some(v.total_tokens),
+            Ok(_) => {}
+            Err(e) => {
+                if is_auth_failure(&e) {
+                    state
+                        .store
+                        .trigger_background_refresh(acc.session_id.clone(), state.cfg.clone());
+                }
+                tracing::debug!("countTokens 失败，回退本地估算: {e}");
+            }
+        }
+    }
+    let input_tokens = input_tokens
+        .unwrap_or_else(|| token_count::estimate_input_tokens(&req.model, &vreq.request));
+
+    let out = sonic_rs::json!({ "input_tokens": input_tokens });
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            if let Ok(bytes) = sonic_rs::to_vec(&out) {
+                logging::client_response_raw(StatusCode::OK.as_u16(), start.elapsed(), &bytes);
+            }
+        } else {
+            logging::client_response(StatusCode::OK.as_u16(), start.elapsed(), Some(&out));
+        }
+    }
+    (StatusCode::OK, Json(out)).into_response()
+}
+
+/// 记录客户端日志并返回 Claude 格式错误。
+fn claude_error_logged(
+    log_level: logging::LogLevel,
+    start: Instant,
+    status: StatusCode,
+    msg: &str,
+) -> Response {
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            let body = claude_error_body(msg);
+            logging::client_response_raw(status.as_u16(), start.elapsed(), body.as_bytes());
+        } else {
+            let err = claude_error_value(msg);
+            logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
+        }
+    }
+    claude_error(status, msg)
+}
+
 fn claude_error(status: StatusCode, msg: &str) -> Response {
     let body = claude_error_body(msg);
     (
@@ -4,6 +4,11 @@ mod response;
 mod stream;
 mod types;
 
-pub use handler::{ClaudeState, handle_list_models, handle_messages};
+pub use handler::{
+    ClaudeState, handle_count_tokens, handle_get_model, handle_list_models, handle_messages,
+};
+pub(crate) use convert::{parallel_tool_use_disabled, to_vertex_request};
 pub(crate) use handler::handle_messages_inner;
+pub(crate) use response::to_messages_response;
+pub(crate) use stream::ClaudeStreamWriter;
 pub use types::*;
@@ -1,3 +1,5 @@
+use crate::gateway::common::stop_sequence::StopSequenceScanner;
+use crate::gateway::common::token_count;
 use crate::signature::manager::Manager as SignatureManager;
 use crate::util::{id, model as modelutil};
 use crate::vertex;
@@ -9,6 +11,8 @@ pub async fn to_messages_response(
     request_id: &str,
     model: &str,
     sig_mgr: &SignatureManager,
+    single_tool_use: bool,
+    stop_sequences: &[String],
 ) -> MessagesResponse {
     let input_tokens = resp
         .response
@@ -62,6 +66,10 @@ pub async fn to_messages_response(
         let Some(fc) = &p.function_call else {
             continue;
         };
+        // disable_parallel_tool_use：仅保留第一个 tool_use。
+        if single_tool_use && !tool_uses.is_empty() {
+            continue;
+        }
 
         let tool_id = if fc.id.trim().is_empty() {
             format!("toolu_{}", id::request_id())
@@ -96,6 +104,11 @@ pub async fn to_messages_response(
         out.stop_reason = "tool_use".to_string();
     }
 
+    // 输出被 maxOutputTokens 截断。
+    if tool_uses.is_empty() && cand.finish_reason == "MAX_TOKENS" {
+        out.stop_reason = "max_tokens".to_string();
+    }
+
     // Edge case：只有签名没有 thinking 文本时注入占位符。
     if !thinking_signature.is_empty() && thinking.trim().is_empty() {
         thinking = "[missing thought text]".to_string();
@@ -137,6 +150,120 @@ pub async fn to_messages_response(
     if let Some(u) = resp.response.usage_metadata.as_ref() {
         out.usage.output_tokens = u.candidates_token_count.max(0);
     }
+    apply_stop_sequences(&mut out, stop_sequences);
 
     out
 }
+
+/// 按请求的 stop_sequences 依次扫描各 text 块，在首个命中处截断：stop_reason 改为 stop_sequence，
+/// 丢弃其后的内容块，并从 output_tokens 中扣除被丢弃部分的估算 token。
+fn apply_stop_sequences(out: &mut MessagesResponse, sequences: &[String]) {
+    if !StopSequenceScanner::new(sequences).is_enabled() {
+        return;
+    }
+    for pos in 0..out.content.len() {
+        if out.content[pos].typ != "text" {
+            continue;
+        }
+        let mut scanner = StopSequenceScanner::new(sequences);
+        let text = out.content[pos].text.take().unwrap_or_default();
+        let mut kept = scanner.push(&text);
+        kept.push_str(&scanner.flush());
+
+        let Some(seq) = scanner.hit().map(str::to_string) else {
+            out.content[pos].text = Some(text);
+            continue;
+        };
+        let mut discarded = token_count::estimate_text_tokens(&out.model, &text[kept.len()..]);
+        for b in out.content.drain(pos + 1..) {
+            discarded +=
+                token_count::estimate_text_tokens(&out.model, b.text.as_deref().unwrap_or(""));
+            if let Some(input) = b.input.as_ref() {
+                let args = sonic_rs::to_string(input).unwrap_or_default();
+                discarded += token_count::estimate_text_tokens(&out.model, &args);
+            }
+        }
+        out.content[pos].text = Some(kept);
+        let discarded = i32::try_from(discarded).unwrap_or(i32::MAX);
+        out.usage.output_tokens = out.usage.output_tokens.saturating_sub(discarded).max(0);
+        out.stop_reason = "stop_sequence".to_string();
+        out.stop_sequence = Some(seq);
+        return;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(typ: &str, text: Option<&str>) -> ResponseContentBlock {
+        ResponseContentBlock {
+            typ: typ.to_string(),
+            text: text.map(str::to_string),
+            thinking: None,
+            signature: None,
+            id: None,
+            name: None,
+            input: None,
+        }
+    }
+
+    #[test]
+    fn stop_sequence_truncates_text_and_sets_reason() {
+        let mut out = MessagesResponse {
+            id: "msg_test".to_string(),
+            typ: "message".to_string(),
+            role: "assistant".to_string(),
+            model: "claude-sonnet-4-5".to_string(),
+            content: vec![
+                block("text", Some("answer: 42\nUser: next")),
+                block("tool_use", None),
+            ],
+            stop_reason: "tool_use".to_string(),
+            stop_sequence: None,
+            usage: Usage {
+                input_tokens: 1,
+                output_tokens: 1,
+            },
+        };
+
+        apply_stop_sequences(&mut out, &["\nUser:".to_string()]);
+        assert_eq!(out.stop_reason, "stop_sequence");
+        assert_eq!(out.stop_sequence.as_deref(), Some("\nUser:"));
+        assert_eq!(out.content.len(), 1);
+        assert_eq!(out.content[0].text.as_deref(), Some("answer: 42"));
+    }
+
+    #[test]
+    fn stop_sequence_in_later_text_block_discards_tail_tokens() {
+        let mut out = MessagesResponse {
+            id: "msg_test".to_string(),
+            typ: "message".to_string(),
+            role: "assistant".to_string(),
+            model: "claude-sonnet-4-5".to_string(),
+            content: vec![
+                block("thinking", None),
+                block("text", Some("first block")),
+                block("text", Some("second END and a long discarded tail")),
+                block("text", Some("third block")),
+            ],
+            stop_reason: "end_turn".to_string(),
+            stop_sequence: None,
+            usage: Usage {
+                input_tokens: 1,
+                output_tokens: 20,
+            },
+        };
+
+        apply_stop_sequences(&mut out, &["END".to_string()]);
+        assert_eq!(out.stop_reason, "stop_sequence");
+        assert_eq!(out.stop_sequence.as_deref(), Some("END"));
+        assert_eq!(out.content.len(), 3);
+        assert_eq!(out.content[1].text.as_deref(), Some("first block"));
+        assert_eq!(out.content[2].text.as_deref(), Some("second "));
+        let discarded =
+            token_count::estimate_text_tokens("claude-sonnet-4-5", "END and a long discarded tail")
+                + token_count::estimate_text_tokens("claude-sonnet-4-5", "third block");
+        assert_eq!(i64::from(out.usage.output_tokens), 20 - discarded);
+    }
+}
@@ -1,5 +1,6 @@
+use crate::gateway::common::stop_sequence::StopSequenceScanner;
 use crate::util::{id, model as modelutil};
-use crate::vertex::types::StreamDataPart;
+use crate::vertex::types::{StreamData, StreamDataPart, StreamResult};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -31,7 +32,7 @@ struct MessageStartEvent<'a> {
 #[derive(Serialize)]
 struct MessageDeltaDelta<'a> {
     stop_reason: &'a str,
-    stop_sequence: Option<()>,
+    stop_sequence: Option<&'a str>,
 }
 
 #[derive(Serialize)]
@@ -141,6 +142,9 @@ pub struct ClaudeStreamWriter {
     signature_emitted: bool,
     enable_signature: bool, // true for Claude models only
     is_gemini_pro_image: bool,
+    single_tool_use: bool,
+    tool_use_emitted: bool,
+    stop_scanner: StopSequenceScanner,
 
     // 仅用于客户端流式日志（合并连续 delta，避免刷屏）
     log_enabled: bool,
@@ -165,6 +169,9 @@ impl ClaudeStreamWriter {
             signature_emitted: false,
             enable_signature: modelutil::is_claude(&model),
             is_gemini_pro_image: modelutil::is_gemini_pro_image(&model),
+            single_tool_use: false,
+            tool_use_emitted: false,
+            stop_scanner: StopSequenceScanner::default(),
 
             log_enabled: false,
             log_events: Vec::new(),
@@ -187,6 +194,34 @@ impl ClaudeStreamWriter {
         self.input_tokens = v;
     }
 
+    /// disable_parallel_tool_use：只输出第一个 tool_use，其余丢弃。
+    pub fn set_single_tool_use(&mut self, enabled: bool) {
+        self.single_tool_use = enabled;
+    }
+
+    /// 请求的 stop_sequences：命中后截断输出并在 message_delta 中回报。
+    pub fn set_stop_sequences(&mut self, sequences: &[String]) {
+        self.stop_scanner = StopSequenceScanner::new(sequences);
+    }
+
+    /// 命中的 stop sequence（未命中时为 None）。
+    pub fn stop_sequence(&self) -> Option<&str> {
+        self.stop_scanner.hit()
+    }
+
+    /// 流结束时的 stop_reason：stop_sequence > tool_use > max_tokens > end_turn。
+    pub fn stop_reason(&self, result: &StreamResult) -> &'static str {
+        if self.stop_sequence().is_some() {
+            "stop_sequence"
+        } else if !result.tool_calls.is_empty() {
+            "tool_use"
+        } else if result.finish_reason == "MAX_TOKENS" {
+            "max_tokens"
+        } else {
+            "end_turn"
+        }
+    }
+
     pub fn set_log_enabled(&mut self, enabled: bool) {
         self.log_enabled = enabled;
     }
@@ -199,6 +234,26 @@ impl ClaudeStreamWriter {
         std::mem::take(&mut self.log_events)
     }
 
+    /// 处理上游一个 SSE 数据块：记录输入 token，并逐个处理首个候选的 Part。
+    pub fn process_chunk(
+        &mut self,
+        data: &StreamData,
+    ) -> (Vec<(&'static str, String)>, Vec<SignatureSave>) {
+        let mut events = Vec::new();
+        let mut saves = Vec::new();
+        if let Some(usage) = data.response.usage_metadata.as_ref() {
+            self.set_input_tokens(usage.prompt_token_count);
+        }
+        if let Some(cand) = data.response.candidates.first() {
+            for p in &cand.content.parts {
+                let (ev, sv) = self.process_part(p);
+                events.extend(ev);
+                saves.extend(sv);
+            }
+        }
+        (events, saves)
+    }
+
     /// 处理一个 Part，立即返回要发送的 SSE 事件。
     pub fn process_part(
         &mut self,
@@ -213,6 +268,16 @@ impl ClaudeStreamWriter {
             self.started = true;
         }
 
+        // 已命中 stop sequence：丢弃后续所有输出。
+        if self.stop_scanner.hit().is_some() {
+            return (events, saves);
+        }
+        // 非文本 part：先下发 stop sequence 检测暂存的文本，保证顺序。
+        if part.thought || part.text.is_empty() {
+            let tail = self.stop_scanner.flush();
+            events.extend(self.write_text(&tail));
+        }
+
         // 缓存 thinking 签名（后续绑定到 tool_use）
         if self.enable_signature && part.thought && !part.thought_signature.trim().is_empty() {
             self.pending_signature = part.thought_signature.trim().to_string();
@@ -230,13 +295,8 @@ impl ClaudeStreamWriter {
                 self.pending_thinking_text.push_str(&part.text);
             }
         } else if !part.text.is_empty() {
-            // 切换到 text 块时，先刷新 signature 到 thinking 块
-            if self.current_block.map(|(_, t)| t) != Some(BlockType::Text) {
-                events.extend(self.flush_signature_to_current_block());
-                events.extend(self.close_current_block());
-                events.push(self.open_block(BlockType::Text));
-            }
-            events.push(self.emit_text_delta(&part.text));
+            let text = self.stop_scanner.push(&part.text);
+            events.extend(self.write_text(&text));
         } else if let Some(inline) = &part.inline_data {
             if self.current_block.map(|(_, t)| t) != Some(BlockType::Text) {
                 events.extend(self.flush_signature_to_current_block());
@@ -270,6 +330,10 @@ impl ClaudeStreamWriter {
             sb.push(')');
             events.push(self.emit_text_delta(&sb));
         } else if let Some(fc) = &part.function_call {
+            if self.single_tool_use && self.tool_use_emitted {
+                return (events, saves);
+            }
+            self.tool_use_emitted = true;
             // tool_use：关闭当前块，按 Claude SSE 规范输出 tool_use，并通过 input_json_delta 传输 input
             events.extend(self.close_current_block());
             let (tool_events, save) = self.emit_tool_use(fc, &part.thought_signature);
@@ -282,10 +346,40 @@ impl ClaudeStreamWriter {
         (events, saves)
     }
 
+    /// 输出一段文本（必要时切换到 text 块）。
+    fn write_text(&mut self, text: &str) -> Vec<(&'static str, String)> {
+        let mut events = Vec::new();
+        if text.is_empty() {
+            return events;
+        }
+        // 切换到 text 块时，先刷新 signature 到 thinking 块
+        if self.current_block.map(|(_, t)| t) != Some(BlockType::Text) {
+            events.extend(self.flush_signature_to_current_block());
+            events.extend(self.close_current_block());
+            events.push(self.open_block(BlockType::Text));
+        }
+        events.push(self.emit_text_delta(text));
+        events
+    }
+
+    /// 上游流结束后调用：按解析结果确定 output_tokens 与 stop_reason 并收尾。
+    pub fn finish_stream(&mut self, result: &StreamResult) -> Vec<(&'static str, String)> {
+        let output_tokens = result
+            .usage
+            .as_ref()
+            .map(|u| u.candidates_token_count)
+            .unwrap_or(0);
+        let stop_reason = self.stop_reason(result);
+        self.finish(output_tokens, stop_reason)
+    }
+
     /// 流结束时调用。
     pub fn finish(&mut self, output_tokens: i32, stop_reason: &str) -> Vec<(&'static str, String)> {
         let mut events = Vec::new();
 
+        let tail = self.stop_scanner.flush();
+        events.extend(self.write_text(&tail));
+
         // 若 thinking 块仍打开，刷新 signature
         if let Some((_, BlockType::Thinking)) = self.current_block {
             events.extend(self.flush_signature_to_current_block());
@@ -750,10 +844,11 @@ impl ClaudeStreamWriter {
         output_tokens: i32,
         stop_reason: &str,
     ) -> (&'static str, String) {
+        let stop_sequence = self.stop_scanner.hit().map(str::to_string);
         let event = MessageDeltaEvent {
             delta: MessageDeltaDelta {
                 stop_reason,
-                stop_sequence: None,
+                stop_sequence: stop_sequence.as_deref(),
             },
             typ: "message_delta",
             usage: MessageDeltaUsage {
@@ -932,4 +1027,115 @@ mod tests {
         assert!(partial.contains("ls -la"));
         assert!(partial.contains("列出当前目录下的所有文件"));
     }
+
+    #[test]
+    fn single_tool_use_drops_parallel_calls() {
+        use super::ClaudeStreamWriter;
+        use crate::vertex::types::{FunctionCall, StreamDataPart};
+        use std::collections::HashMap;
+
+        let call = |id: &str| StreamDataPart {
+            text: String::new(),
+            function_call: Some(FunctionCall {
+                id: id.to_string(),
+                name: "Bash".to_string(),
+                args: HashMap::new(),
+            }),
+            inline_data: None,
+            thought: false,
+            thought_signature: String::new(),
+        };
+
+        let mut writer =
+            ClaudeStreamWriter::new("req_test".to_string(), "claude-3-opus-20240229".to_string());
+        writer.set_single_tool_use(true);
+        let (first, _) = writer.process_part(&call("toolu_1"));
+        let (second, _) = writer.process_part(&call("toolu_2"));
+
+        assert!(first.iter().any(|(name, _)| *name == "content_block_start"));
+        assert!(second.is_empty());
+    }
+
+    #[test]
+    fn stop_sequence_truncates_text_and_is_reported() {
+        use super::ClaudeStreamWriter;
+        use crate::vertex::types::StreamDataPart;
+
+        let text = |t: &str| StreamDataPart {
+            text: t.to_string(),
+            function_call: None,
+            inline_data: None,
+            thought: false,
+            thought_signature: String::new(),
+        };
+
+        let mut writer =
+            ClaudeStreamWriter::new("req_test".to_string(), "claude-3-opus-20240229".to_string());
+        writer.set_stop_sequences(&["STOP".to_string()]);
+        let (first, _) = writer.process_part(&text("hello ST"));
+        let (second, _) = writer.process_part(&text("OP ignored"));
+        let (third, _) = writer.process_part(&text("also ignored"));
+        assert_eq!(writer.stop_sequence(), Some("STOP"));
+        assert!(third.is_empty());
+
+        let deltas: Vec<String> = first
+            .iter()
+            .chain(second.iter())
+            .filter(|(name, _)| *name == "content_block_delta")
+            .map(|(_, ev)| {
+                let v = serde_json::from_str::<serde_json::Value>(ev).unwrap();
+                v["delta"]["text"].as_str().unwrap_or_default().to_string()
+            })
+            .collect();
+        assert_eq!(deltas.concat(), "hello ");
+
+        let finish = writer.finish(3, "stop_sequence");
+        let delta = finish
+            .iter()
+            .find(|(name, _)| *name == "message_delta")
+            .map(|(_, ev)| serde_json::from_str::<serde_json::Value>(ev).unwrap())
+            .unwrap();
+        assert_eq!(delta["delta"]["stop_reason"], "stop_sequence");
+        assert_eq!(delta["delta"]["stop_sequence"], "STOP");
+    }
+
+    #[test]
+    fn upstream_stop_without_sequence_is_end_turn() {
+        use super::ClaudeStreamWriter;
+        use crate::vertex::types::{StreamDataPart, StreamResult};
+
+        // 上游自然结束（输出中没有请求的序列）：不回报 stop_sequence。
+        let mut writer =
+            ClaudeStreamWriter::new("req_test".to_string(), "claude-sonnet-4-5".to_string());
+        writer.set_stop_sequences(&["\nUser:".to_string()]);
+        writer.process_part(&StreamDataPart {
+            text: "answer\nUser".to_string(),
+            function_call: None,
+            inline_data: None,
+            thought: false,
+            thought_signature: String::new(),
+        });
+        let result = StreamResult {
+            finish_reason: "STOP".to_string(),
+            ..StreamResult::default()
+        };
+        let stop_reason = writer.stop_reason(&result);
+        assert_eq!(stop_reason, "end_turn");
+
+        let finish = writer.finish(2, stop_reason);
+        let text: String = finish
+            .iter()
+            .filter(|(name, _)| *name == "content_block_delta")
+            .map(|(_, ev)| serde_json::from_str::<serde_json::Value>(ev).unwrap())
+            .map(|v| v["delta"]["text"].as_str().unwrap_or_default().to_string())
+            .collect();
+        assert_eq!(text, "\nUser");
+        let delta = finish
+            .iter()
+            .find(|(name, _)| *name == "message_delta")
+            .map(|(_, ev)| serde_json::from_str::<serde_json::Value>(ev).unwrap())
+            .unwrap();
+        assert_eq!(delta["delta"]["stop_reason"], "end_turn");
+        assert!(delta["delta"]["stop_sequence"].is_null());
+    }
 }
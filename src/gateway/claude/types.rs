@@ -22,11 +22,20 @@ pub struct MessagesRequest {
     pub stop_sequences: Vec<String>,
     #[serde(default)]
     pub tools: Vec<Tool>,
-    /// 解析但不使用（保持与 Go 版一致）。
+    /// auto / any / tool / none，可带 disable_parallel_tool_use。
     #[serde(rename = "tool_choice", default)]
     pub tool_choice: Option<sonic_rs::Value>,
     #[serde(default)]
     pub thinking: Option<Thinking>,
+    #[serde(default)]
+    pub metadata: Option<Metadata>,
+}
+
+/// 请求元数据（仅用于账号粘性路由）。
+#[derive(Debug, Clone, Deserialize)]
+pub struct Metadata {
+    #[serde(default)]
+    pub user_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -115,3 +124,25 @@ pub struct Usage {
     pub input_tokens: i32,
     pub output_tokens: i32,
 }
+
+/// Claude /v1/models 列表。
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelListResponse {
+    pub data: Vec<ModelInfoResponse>,
+    pub has_more: bool,
+    pub first_id: Option<String>,
+    pub last_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfoResponse {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub id: String,
+    pub display_name: String,
+    pub created_at: String,
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+    pub thinking: bool,
+    pub vision: bool,
+}
@@ -0,0 +1,159 @@
+//! /v1 接口的 API Key 鉴权中间件。
+//!
+//! 支持 `Authorization: Bearer <key>`、`x-api-key: <key>`，
+//! 以及 Gemini 风格的 `x-goog-api-key: <key>` / `?key=<key>`；
+//! 密钥来自运行时配置（`API_KEY` + data_dir/api_keys.json），WebUI 修改后立即生效。
+
+use std::collections::HashMap;
+
+use axum::extract::{Query, Request};
+use axum::http::{HeaderMap, StatusCode, Uri, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::runtime_config;
+
+/// 提取客户端提供的 API Key（优先 Authorization: Bearer，其次各类请求头，最后 `?key=`）。
+pub fn extract_api_key(headers: &HeaderMap, uri: &Uri) -> String {
+    if let Some(v) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+    {
+        let v = v.trim();
+        let token = v
+            .get(..7)
+            .filter(|p| p.eq_ignore_ascii_case("bearer "))
+            .map(|_| v[7..].trim())
+            .unwrap_or("");
+        if !token.is_empty() {
+            return token.to_string();
+        }
+    }
+    for name in ["x-api-key", "x-goog-api-key"] {
+        let v = headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .unwrap_or("");
+        if !v.is_empty() {
+            return v.to_string();
+        }
+    }
+    Query::<HashMap<String, String>>::try_from_uri(uri)
+        .ok()
+        .and_then(|Query(q)| q.get("key").map(|v| v.trim().to_string()))
+        .unwrap_or_default()
+}
+
+/// 解析请求所用密钥的名称（未启用鉴权或密钥无效时为 None）。
+pub fn resolve_key_name(headers: &HeaderMap, uri: &Uri) -> Option<String> {
+    runtime_config::authenticate_api_key(&extract_api_key(headers, uri))
+}
+
+/// 未配置任何密钥时放行；否则要求命中默认密钥或任一命名密钥。
+pub async fn api_key_auth_middleware(request: Request, next: Next) -> Response {
+    if !runtime_config::api_auth_enabled() {
+        return next.run(request).await;
+    }
+
+    let presented = extract_api_key(request.headers(), request.uri());
+    if runtime_config::authenticate_api_key(&presented).is_some() {
+        return next.run(request).await;
+    }
+
+    let msg = if presented.is_empty() {
+        "缺少 API Key，请通过 Authorization: Bearer、x-api-key / x-goog-api-key 请求头或 key 查询参数提供。"
+    } else {
+        "API Key 无效。"
+    };
+    unauthorized(request.uri().path(), msg)
+}
+
+/// 按路由协议返回 401：/v1/messages* 使用 Claude 错误格式，/v1beta/* 使用 Gemini 格式，
+/// 其余使用 OpenAI 格式。
+fn unauthorized(path: &str, msg: &str) -> Response {
+    let body = if is_gemini_path(path) {
+        sonic_rs::json!({
+            "error": { "code": 401, "message": msg, "status": "UNAUTHENTICATED" }
+        })
+    } else if is_claude_path(path) {
+        sonic_rs::json!({
+            "type": "error",
+            "error": { "type": "authentication_error", "message": msg }
+        })
+    } else {
+        sonic_rs::json!({
+            "error": {
+                "message": msg,
+                "type": "invalid_request_error",
+                "code": "invalid_api_key"
+            }
+        })
+    };
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::CONTENT_TYPE, "application/json")],
+        sonic_rs::to_string(&body).unwrap_or_default(),
+    )
+        .into_response()
+}
+
+fn is_claude_path(path: &str) -> bool {
+    path == "/v1/messages" || path.starts_with("/v1/messages/")
+}
+
+fn is_gemini_path(path: &str) -> bool {
+    path.starts_with("/v1beta/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_extract_api_key() {
+        let uri = Uri::from_static("/v1/messages");
+        let mut headers = HeaderMap::new();
+        assert_eq!(extract_api_key(&headers, &uri), "");
+
+        headers.insert("x-api-key", HeaderValue::from_static(" sk-claude "));
+        assert_eq!(extract_api_key(&headers, &uri), "sk-claude");
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("bearer sk-openai"),
+        );
+        assert_eq!(extract_api_key(&headers, &uri), "sk-openai");
+
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Basic xxx"));
+        assert_eq!(extract_api_key(&headers, &uri), "sk-claude");
+    }
+
+    #[test]
+    fn test_extract_gemini_api_key() {
+        let mut headers = HeaderMap::new();
+        let uri = Uri::from_static(
+            "/v1beta/models/gemini-3-flash:streamGenerateContent?alt=sse&key=sk-query",
+        );
+        assert_eq!(extract_api_key(&headers, &uri), "sk-query");
+
+        headers.insert("x-goog-api-key", HeaderValue::from_static("sk-goog"));
+        assert_eq!(extract_api_key(&headers, &uri), "sk-goog");
+
+        let no_key = Uri::from_static("/v1beta/models/gemini-3-flash:generateContent?alt=sse");
+        assert_eq!(extract_api_key(&HeaderMap::new(), &no_key), "");
+    }
+
+    #[test]
+    fn test_is_claude_path() {
+        assert!(is_claude_path("/v1/messages"));
+        assert!(is_claude_path("/v1/messages/"));
+        assert!(!is_claude_path("/v1/messagesx"));
+        assert!(!is_claude_path("/v1/chat/completions"));
+        assert!(is_gemini_path(
+            "/v1beta/models/gemini-3-flash:generateContent"
+        ));
+        assert!(!is_gemini_path("/v1/models"));
+    }
+}
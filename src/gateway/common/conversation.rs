@@ -0,0 +1,115 @@
+//! 对话键推导（用于账号粘性路由，见 `credential::affinity`）。
+
+use axum::http::HeaderMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::vertex::types::InnerReq;
+
+/// 客户端显式指定对话 ID 的请求头。
+pub const CONVERSATION_HEADER: &str = "x-conversation-id";
+
+/// 推导对话键，依次尝试：
+/// 1. `X-Conversation-Id` 请求头
+/// 2. 客户端提供的会话/用户标识（如 Claude `metadata.user_id`）
+/// 3. system 与首条消息的哈希（仅多轮请求；单轮请求不固定，避免相同提示词集中到同一账号）
+///
+/// 结果带 API Key 名称前缀，不同调用方互不影响。
+pub fn conversation_key(
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    key_name: Option<&str>,
+    req: &InnerReq,
+) -> Option<String> {
+    let scope = key_name.unwrap_or_default();
+
+    let header = headers
+        .get(CONVERSATION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty());
+    if let Some(v) = header {
+        return Some(format!("{scope}|h:{v}"));
+    }
+
+    if let Some(v) = client_id.map(str::trim).filter(|v| !v.is_empty()) {
+        return Some(format!("{scope}|u:{v}"));
+    }
+
+    if req.contents.len() < 2 {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    if let Some(sys) = req.system_instruction.as_ref() {
+        for p in &sys.parts {
+            p.text.hash(&mut hasher);
+        }
+    }
+    let first = &req.contents[0];
+    first.role.hash(&mut hasher);
+    for p in &first.parts {
+        p.text.hash(&mut hasher);
+        if let Some(d) = p.inline_data.as_ref() {
+            d.signature_key().hash(&mut hasher);
+        }
+    }
+    Some(format!("{scope}|m:{:016x}", hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::types::{Content, Part};
+
+    fn req(turns: &[&str]) -> InnerReq {
+        InnerReq {
+            contents: turns
+                .iter()
+                .enumerate()
+                .map(|(i, t)| Content {
+                    role: if i % 2 == 0 { "user" } else { "model" }.to_string(),
+                    parts: vec![Part {
+                        text: t.to_string(),
+                        ..Default::default()
+                    }],
+                })
+                .collect(),
+            system_instruction: None,
+            generation_config: None,
+            tools: Vec::new(),
+            tool_config: None,
+            safety_settings: Vec::new(),
+            session_id: String::new(),
+        }
+    }
+
+    #[test]
+    fn key_prefers_header_then_client_id_then_first_message() {
+        let mut headers = HeaderMap::new();
+        let r = req(&["hi", "hello", "next"]);
+
+        let by_hash = conversation_key(&headers, None, Some("team"), &r).unwrap();
+        let later = conversation_key(
+            &headers,
+            None,
+            Some("team"),
+            &req(&["hi", "x", "y", "z", "w"]),
+        );
+        assert_eq!(Some(by_hash.clone()), later);
+        assert_ne!(
+            conversation_key(&headers, None, Some("other"), &r),
+            Some(by_hash)
+        );
+        assert!(conversation_key(&headers, None, None, &req(&["hi"])).is_none());
+
+        assert_eq!(
+            conversation_key(&headers, Some("user_1"), None, &req(&["hi"])).as_deref(),
+            Some("|u:user_1")
+        );
+
+        headers.insert(CONVERSATION_HEADER, "conv-1".parse().unwrap());
+        assert_eq!(
+            conversation_key(&headers, Some("user_1"), Some("team"), &r).as_deref(),
+            Some("team|h:conv-1")
+        );
+    }
+}
@@ -0,0 +1,453 @@
+//! 多模态输入（openai/claude 共用）：data URL 解析、远程图片/文档下载，以及图片签名查找。
+
+use anyhow::{Context, anyhow, bail};
+use base64::Engine as _;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::signature::manager::Manager as SignatureManager;
+use crate::signature::types::FALLBACK_SIGNATURE;
+use crate::vertex::types::InlineData;
+
+/// 远程媒体的最大字节数。
+const MAX_REMOTE_MEDIA_BYTES: usize = 20 * 1024 * 1024;
+/// 远程媒体下载超时。
+const REMOTE_MEDIA_TIMEOUT: Duration = Duration::from_secs(30);
+/// 远程媒体最多跟随的重定向次数（每一跳都重新校验目标地址）。
+const MAX_REMOTE_MEDIA_REDIRECTS: usize = 3;
+
+/// 允许转发给后端的 MIME 类型（图片 / PDF / 纯文本 / 音频）。
+const ALLOWED_MIME_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/heic",
+    "image/heif",
+    "application/pdf",
+    "text/plain",
+    "audio/wav",
+    "audio/mp3",
+    "audio/mpeg",
+    "audio/aac",
+    "audio/ogg",
+    "audio/flac",
+    "audio/aiff",
+];
+
+/// 归一化 MIME（小写、去参数、常见别名），不在允许列表内时报错。
+pub fn normalize_mime(mime: &str) -> anyhow::Result<String> {
+    let mime = mime
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let mime = match mime.as_str() {
+        "image/jpg" => "image/jpeg".to_string(),
+        "audio/x-wav" | "audio/wave" => "audio/wav".to_string(),
+        "audio/x-flac" => "audio/flac".to_string(),
+        _ => mime,
+    };
+    if !ALLOWED_MIME_TYPES.contains(&mime.as_str()) {
+        bail!("不支持的媒体类型: {mime}");
+    }
+    Ok(mime)
+}
+
+/// 将 data URL 或 http(s) URL 转为 InlineData（MIME 经允许列表校验）。
+pub async fn resolve_url(cfg: &Config, url: &str) -> anyhow::Result<InlineData> {
+    let url = url.trim();
+    if url.starts_with("data:") {
+        let inline = parse_data_url(url).ok_or_else(|| anyhow!("无效的 data URL"))?;
+        return Ok(InlineData::new(
+            normalize_mime(&inline.mime_type)?,
+            inline.data.as_str(),
+        ));
+    }
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return fetch_remote(cfg, url).await;
+    }
+    bail!("无法解析媒体 URL（仅支持 data: 与 http(s)://）")
+}
+
+/// 解析 `data:<mime>;base64,<data>`。
+pub fn parse_data_url(url: &str) -> Option<InlineData> {
+    let rest = url.strip_prefix("data:")?;
+    let (mime_type, data) = rest.split_once(";base64,")?;
+    if mime_type.is_empty() || data.is_empty() {
+        return None;
+    }
+    Some(InlineData::new(mime_type, data))
+}
+
+/// 图片签名缓存键（Gemini Pro Image 使用更长的前缀，与响应侧保持一致）。
+pub fn image_signature_key(inline: &InlineData, is_gemini_pro_image: bool) -> String {
+    if !is_gemini_pro_image {
+        return inline.signature_key();
+    }
+
+    let s = inline.data.as_str();
+    if s.is_empty() {
+        return String::new();
+    }
+    let n = s.len().min(100);
+    s[..n].to_string()
+}
+
+/// 查找回传图片对应的 thoughtSignature；Gemini Pro Image 缺失时使用兜底签名。
+pub async fn image_signature(
+    sig_mgr: &SignatureManager,
+    inline: &InlineData,
+    is_gemini_pro_image: bool,
+) -> String {
+    let image_key = image_signature_key(inline, is_gemini_pro_image);
+    if image_key.is_empty() {
+        return String::new();
+    }
+    sig_mgr
+        .lookup_by_image_key_strict(&image_key)
+        .await
+        .map(|e| e.signature)
+        .unwrap_or_else(|| {
+            if is_gemini_pro_image {
+                FALLBACK_SIGNATURE.to_string()
+            } else {
+                String::new()
+            }
+        })
+}
+
+static MEDIA_HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn media_http_client(cfg: &Config) -> anyhow::Result<&'static reqwest::Client> {
+    if let Some(c) = MEDIA_HTTP_CLIENT.get() {
+        return Ok(c);
+    }
+
+    // 重定向由 fetch_remote 手动跟随，以便逐跳校验目标地址。
+    let mut builder = reqwest::Client::builder()
+        .timeout(REMOTE_MEDIA_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none());
+    let proxy = cfg.proxy.trim();
+    let mut proxy_host = None;
+    if !proxy.is_empty() {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        proxy_host = reqwest::Url::parse(proxy)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string));
+    }
+    builder = builder.dns_resolver(PublicOnlyResolver { proxy_host });
+    let _ = MEDIA_HTTP_CLIENT.set(builder.build()?);
+    MEDIA_HTTP_CLIENT
+        .get()
+        .ok_or_else(|| anyhow!("初始化媒体下载 HTTP client 失败"))
+}
+
+/// 仅返回公网地址的 DNS 解析：域名解析到本机/内网地址时在建立连接前拒绝。
+/// 代理地址本身不受限制（代理通常部署在内网）。
+struct PublicOnlyResolver {
+    proxy_host: Option<String>,
+}
+
+impl reqwest::dns::Resolve for PublicOnlyResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        let is_proxy = self
+            .proxy_host
+            .as_deref()
+            .is_some_and(|p| p.eq_ignore_ascii_case(&host));
+        Box::pin(async move {
+            let addrs = if is_proxy {
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect()
+            } else {
+                resolve_public(&host).await?
+            };
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// 解析域名，任一地址为本机/内网地址时报错。
+async fn resolve_public(host: &str) -> anyhow::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+        .await
+        .with_context(|| format!("解析媒体地址失败: {host}"))?
+        .collect();
+    if let Some(addr) = addrs.iter().find(|a| is_internal_ip(a.ip())) {
+        bail!("不允许访问内网媒体地址: {host} -> {}", addr.ip());
+    }
+    Ok(addrs)
+}
+
+/// 校验媒体 URL：仅 http/https，且主机不是本机/内网地址字面量。
+fn check_media_url(url: &reqwest::Url) -> anyhow::Result<()> {
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("不支持的媒体 URL 协议: {}", url.scheme());
+    }
+    if is_internal_host(url.host_str().unwrap_or_default()) {
+        bail!("不允许访问内网媒体地址: {url}");
+    }
+    Ok(())
+}
+
+/// 计算重定向目标并重新校验。
+fn redirect_target(base: &reqwest::Url, location: &str) -> anyhow::Result<reqwest::Url> {
+    let next = base
+        .join(location)
+        .with_context(|| format!("无效的重定向地址: {location}"))?;
+    check_media_url(&next)?;
+    Ok(next)
+}
+
+/// 下载远程图片/文档并转为 InlineData（仅 http/https；每一跳都拒绝本机与内网地址，
+/// 包括解析到内网的域名）。
+pub async fn fetch_remote(cfg: &Config, url: &str) -> anyhow::Result<InlineData> {
+    let client = media_http_client(cfg)?;
+    let mut target = reqwest::Url::parse(url).with_context(|| format!("无效的媒体 URL: {url}"))?;
+    check_media_url(&target)?;
+
+    let mut redirects = 0;
+    let mut resp = loop {
+        // 经代理访问时由代理解析域名，这里预先校验解析结果。
+        if !cfg.proxy.trim().is_empty()
+            && let Some(host) = target.domain()
+        {
+            resolve_public(host).await?;
+        }
+        let resp = client
+            .get(target.clone())
+            .send()
+            .await
+            .with_context(|| format!("下载媒体失败: {url}"))?;
+        if !resp.status().is_redirection() {
+            break resp
+                .error_for_status()
+                .with_context(|| format!("下载媒体失败: {url}"))?;
+        }
+        if redirects >= MAX_REMOTE_MEDIA_REDIRECTS {
+            bail!("媒体重定向次数过多: {url}");
+        }
+        redirects += 1;
+        let location = resp
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| anyhow!("媒体重定向缺少 Location: {url}"))?;
+        target = redirect_target(&target, location)?;
+    };
+
+    if resp
+        .content_length()
+        .is_some_and(|n| n as usize > MAX_REMOTE_MEDIA_BYTES)
+    {
+        bail!(
+            "媒体超过大小上限 {} MB: {url}",
+            MAX_REMOTE_MEDIA_BYTES >> 20
+        );
+    }
+    let header_mime = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let mut buf: Vec<u8> = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        if buf.len() + chunk.len() > MAX_REMOTE_MEDIA_BYTES {
+            bail!(
+                "媒体超过大小上限 {} MB: {url}",
+                MAX_REMOTE_MEDIA_BYTES >> 20
+            );
+        }
+        buf.extend_from_slice(&chunk);
+    }
+
+    // 优先按内容魔数识别，其次 Content-Type，最后按扩展名推断。
+    let mime_type = match sniff_mime(&buf) {
+        Some(m) => m.to_string(),
+        None if !header_mime.is_empty() && header_mime != "application/octet-stream" => header_mime,
+        None => mime_from_path(target.path())
+            .ok_or_else(|| anyhow!("无法识别媒体类型: {url}"))?
+            .to_string(),
+    };
+    let mime_type = normalize_mime(&mime_type)?;
+
+    let data = base64::engine::general_purpose::STANDARD.encode(&buf);
+    Ok(InlineData::new(mime_type, data))
+}
+
+/// 按文件头魔数识别常见媒体类型。
+fn sniff_mime(buf: &[u8]) -> Option<&'static str> {
+    let riff_kind = (buf.len() >= 12 && &buf[..4] == b"RIFF").then(|| &buf[8..12]);
+    Some(match buf {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'%', b'P', b'D', b'F', b'-', ..] => "application/pdf",
+        [b'O', b'g', b'g', b'S', ..] => "audio/ogg",
+        [b'f', b'L', b'a', b'C', ..] => "audio/flac",
+        [b'I', b'D', b'3', ..] => "audio/mp3",
+        _ if riff_kind == Some(b"WEBP") => "image/webp",
+        _ if riff_kind == Some(b"WAVE") => "audio/wav",
+        _ => return None,
+    })
+}
+
+/// 按文件名/路径扩展名推断 MIME。
+pub fn mime_from_path(path: &str) -> Option<&'static str> {
+    let ext = path.rsplit_once('.')?.1.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        "wav" => "audio/wav",
+        "mp3" => "audio/mp3",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        _ => return None,
+    })
+}
+
+fn is_internal_host(host: &str) -> bool {
+    let host = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.');
+    if host.eq_ignore_ascii_case("localhost") || host.ends_with(".localhost") {
+        return true;
+    }
+    host.parse::<IpAddr>().is_ok_and(is_internal_ip)
+}
+
+/// 本机 / 私有 / 链路本地 / CGNAT / 保留段等不应由网关代为访问的地址；
+/// 内嵌 IPv4 的 IPv6 地址（映射、兼容、NAT64、6to4）按其 IPv4 判断。
+fn is_internal_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || a == 0
+                // 100.64.0.0/10（CGNAT）
+                || (a == 100 && (b & 0xC0) == 64)
+                // 192.0.0.0/24（IETF 协议分配）
+                || (a == 192 && b == 0 && c == 0)
+                // 198.18.0.0/15（基准测试）
+                || (a == 198 && (b & 0xFE) == 18)
+                // 240.0.0.0/4（保留）
+                || a >= 240
+        }
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(v4) => is_internal_ip(IpAddr::V4(v4)),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+            }
+        },
+    }
+}
+
+/// 提取 IPv6 地址中内嵌的 IPv4：::ffff:a.b.c.d、::a.b.c.d、64:ff9b::a.b.c.d（NAT64）、2002:AABB:CCDD::（6to4）。
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return Some(v4);
+    }
+    let seg = ip.segments();
+    let tail = |hi: u16, lo: u16| Ipv4Addr::from((u32::from(hi) << 16) | u32::from(lo));
+    match seg {
+        [0, 0, 0, 0, 0, 0, hi, lo] => Some(tail(hi, lo)),
+        [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => Some(tail(hi, lo)),
+        [0x2002, hi, lo, ..] => Some(tail(hi, lo)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_url_and_host_checks() {
+        let inline = parse_data_url("data:application/pdf;base64,JVBERi0=").unwrap();
+        assert_eq!(inline.mime_type, "application/pdf");
+        assert_eq!(inline.data.as_str(), "JVBERi0=");
+        assert!(parse_data_url("data:image/png,raw").is_none());
+        assert!(parse_data_url("https://example.com/a.png").is_none());
+
+        assert!(is_internal_host("127.0.0.1"));
+        assert!(is_internal_host("169.254.169.254"));
+        assert!(is_internal_host("[::1]"));
+        assert!(is_internal_host("localhost"));
+        assert!(is_internal_host("LOCALHOST."));
+        assert!(is_internal_host("[::ffff:127.0.0.1]"));
+        assert!(is_internal_host("[::ffff:a9fe:a9fe]"));
+        assert!(is_internal_host("100.64.0.1"));
+        assert!(!is_internal_host("100.128.0.1"));
+        assert!(is_internal_host("198.18.0.1"));
+        assert!(is_internal_host("198.19.255.1"));
+        assert!(!is_internal_host("198.20.0.1"));
+        assert!(is_internal_host("192.0.0.8"));
+        assert!(is_internal_host("240.0.0.1"));
+        // 内嵌 IPv4：兼容地址、NAT64、6to4。
+        assert!(is_internal_host("[::127.0.0.1]"));
+        assert!(is_internal_host("[64:ff9b::a9fe:a9fe]"));
+        assert!(is_internal_host("[64:ff9b::10.0.0.1]"));
+        assert!(is_internal_host("[2002:7f00:1::]"));
+        assert!(is_internal_host("[2002:c0a8:101::1]"));
+        assert!(!is_internal_host("[2002:808:808::]"));
+        assert!(!is_internal_host("[64:ff9b::8.8.8.8]"));
+        assert!(!is_internal_host("[2606:4700::1111]"));
+        assert!(!is_internal_host("example.com"));
+        assert_eq!(mime_from_path("/a/B.JPG"), Some("image/jpeg"));
+    }
+
+    #[tokio::test]
+    async fn redirects_and_resolved_addresses_are_checked() {
+        let base = reqwest::Url::parse("https://example.com/a/b.png").unwrap();
+        assert!(redirect_target(&base, "http://169.254.169.254/latest/meta-data").is_err());
+        assert!(redirect_target(&base, "http://[::ffff:127.0.0.1]:8080/").is_err());
+        assert!(redirect_target(&base, "file:///etc/passwd").is_err());
+        assert_eq!(
+            redirect_target(&base, "../c.png").unwrap().as_str(),
+            "https://example.com/c.png"
+        );
+
+        // 域名解析到本机地址时拒绝。
+        assert!(resolve_public("localhost").await.is_err());
+        assert!(
+            fetch_remote(&Config::for_test(), "http://[::ffff:7f00:1]/a.png")
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn mime_is_sniffed_and_allowlisted() {
+        assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\n"), Some("image/png"));
+        assert_eq!(sniff_mime(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_mime(b"RIFF\0\0\0\0WAVEfmt "), Some("audio/wav"));
+        assert_eq!(sniff_mime(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(sniff_mime(b"<html>"), None);
+
+        assert_eq!(normalize_mime("Image/JPG").unwrap(), "image/jpeg");
+        assert_eq!(
+            normalize_mime("text/plain; charset=utf-8").unwrap(),
+            "text/plain"
+        );
+        assert!(normalize_mime("text/html").is_err());
+        assert!(normalize_mime("application/zip").is_err());
+    }
+}
@@ -1,6 +1,21 @@
+pub mod api_auth;
 pub mod auth_retry;
+pub mod conversation;
 pub mod extract;
+pub mod media;
+pub mod model_catalog;
+pub mod output_limit;
 pub mod retry;
+pub mod stop_sequence;
+pub mod token_count;
+
+use crate::logging::{self, LogLevel};
+use axum::Json;
+use axum::extract::OriginalUri;
+use axum::http::{HeaderMap, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use std::time::Instant;
 
 /// 一次转发到后端所需的账号上下文（providers 共享）。
 #[derive(Debug, Clone, Default)]
@@ -9,6 +24,42 @@ pub struct AccountContext {
     pub session_id: String,
     pub access_token: String,
     pub email: String,
+    /// 发起请求的 API Key 名称（用于按 Key 的策略）。
+    pub api_key_name: Option<String>,
+}
+
+/// 记录模型列表等无请求体接口的客户端请求日志。
+pub fn log_model_request(
+    log_level: LogLevel,
+    method: &Method,
+    uri: &OriginalUri,
+    headers: &HeaderMap,
+) {
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            logging::client_request_raw(method.as_str(), uri.0.path(), headers, &[]);
+        } else {
+            logging::client_request(method.as_str(), uri.0.path(), headers, &[]);
+        }
+    }
+}
+
+/// 记录客户端响应日志并返回 200 JSON。
+pub fn json_response_logged<T: Serialize>(
+    log_level: LogLevel,
+    start: Instant,
+    out: &T,
+) -> Response {
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            if let Ok(bytes) = serde_json::to_vec(out) {
+                logging::client_response_raw(StatusCode::OK.as_u16(), start.elapsed(), &bytes);
+            }
+        } else if let Ok(v) = sonic_rs::to_value(out) {
+            logging::client_response(StatusCode::OK.as_u16(), start.elapsed(), Some(&v));
+        }
+    }
+    (StatusCode::OK, Json(out)).into_response()
 }
 
 pub fn find_function_name(
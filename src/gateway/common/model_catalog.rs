@@ -0,0 +1,325 @@
+//! 模型目录：由后台配额刷新任务拉取的 fetchAvailableModels 结果构建并缓存在内存中，
+//! 模型列表接口（OpenAI / Claude / Gemini 格式）直接读取，不再每次请求都访问后端。
+//!
+//! 目录为空（刚启动、后台尚未刷新成功）时，由首个列表请求同步拉取一次并填充。
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use sonic_rs::JsonValueTrait;
+
+use crate::gateway::claude::ClaudeState;
+use crate::gateway::common::auth_retry::is_auth_failure;
+use crate::gateway::common::retry::should_retry_with_next_token;
+use crate::quota_pool::group_quota_key;
+use crate::runtime_config;
+use crate::util::id;
+use crate::util::model as modelutil;
+use crate::util::virtual_model;
+use crate::vertex::client::ApiError;
+
+/// 后端未返回上下文窗口时的默认值。
+const CLAUDE_CONTEXT_WINDOW: u32 = 200_000;
+const GEMINI_CONTEXT_WINDOW: u32 = 1_048_576;
+
+/// 目录中的单个模型。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: String,
+    pub owned_by: &'static str,
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+    pub thinking: bool,
+    pub vision: bool,
+    pub quota_group: &'static str,
+    /// 网关注入的虚拟模型（后端不直接提供）。
+    pub is_virtual: bool,
+}
+
+#[derive(Debug, Default)]
+struct Snapshot {
+    /// 最近一次 fetchAvailableModels 的原始数据，虚拟模型定义变更后据此重建。
+    raw: HashMap<String, sonic_rs::Value>,
+    models: Arc<Vec<ModelInfo>>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+pub struct ModelCatalog {
+    inner: RwLock<Snapshot>,
+}
+
+impl ModelCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 用 fetchAvailableModels 的 `models` 字段重建目录；为空时保留原目录。
+    pub fn replace(&self, models: &HashMap<String, sonic_rs::Value>) {
+        let built = build_catalog(models);
+        if built.is_empty() {
+            return;
+        }
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        inner.raw = models.clone();
+        inner.models = Arc::new(built);
+        inner.updated_at = Some(Utc::now());
+    }
+
+    /// 按当前虚拟模型定义重建目录（不访问后端）。
+    pub fn rebuild(&self) {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        if inner.raw.is_empty() {
+            return;
+        }
+        inner.models = Arc::new(build_catalog(&inner.raw));
+    }
+
+    pub fn models(&self) -> Arc<Vec<ModelInfo>> {
+        self.inner
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .models
+            .clone()
+    }
+
+    pub fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.inner
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .updated_at
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models().is_empty()
+    }
+
+    /// 按模型 ID 查找（忽略大小写与 `models/` 前缀）。
+    pub fn get(&self, model: &str) -> Option<ModelInfo> {
+        let key = modelutil::canonical_model_id(model);
+        self.models()
+            .iter()
+            .find(|m| m.id.eq_ignore_ascii_case(&key))
+            .cloned()
+    }
+}
+
+static CATALOG: OnceLock<ModelCatalog> = OnceLock::new();
+
+/// 全局模型目录。
+pub fn catalog() -> &'static ModelCatalog {
+    CATALOG.get_or_init(ModelCatalog::new)
+}
+
+/// 返回全局目录；为空时轮换账号同步拉取一次。失败时返回（状态码, 错误信息）。
+pub async fn load_or_fetch(
+    state: &ClaudeState,
+) -> Result<Arc<Vec<ModelInfo>>, (StatusCode, String)> {
+    let models = catalog().models();
+    if !models.is_empty() {
+        return Ok(models);
+    }
+
+    let endpoint = runtime_config::current_endpoint();
+    let attempts = state.store.enabled_count().await.max(1);
+    let mut last_err: Option<ApiError> = None;
+
+    for _ in 0..attempts {
+        let acc = state
+            .store
+            .get_token()
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e.to_string()))?;
+        let project_id = if acc.project_id.is_empty() {
+            id::project_id()
+        } else {
+            acc.project_id.clone()
+        };
+
+        match state
+            .vertex
+            .for_proxy(&acc.proxy)
+            .fetch_available_models(&endpoint, &project_id, &acc.access_token, &acc.email)
+            .await
+        {
+            Ok(v) => {
+                catalog().replace(&v.models);
+                return Ok(catalog().models());
+            }
+            Err(e) => {
+                tracing::warn!(error = ?e, "fetchAvailableModels 失败");
+                // 认证失败：立即切换到下一个凭证，同时后台触发刷新（不阻塞请求路径）。
+                if is_auth_failure(&e) {
+                    state
+                        .store
+                        .trigger_background_refresh(acc.session_id.clone(), state.cfg.clone());
+                }
+                let retry = should_retry_with_next_token(&e);
+                last_err = Some(e);
+                if !retry {
+                    break;
+                }
+            }
+        }
+    }
+
+    let status = last_err
+        .as_ref()
+        .and_then(|e| e.status())
+        .and_then(|s| StatusCode::from_u16(s).ok())
+        .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+    let msg = last_err
+        .as_ref()
+        .map(|e| e.to_string())
+        .unwrap_or_else(|| "后端请求失败".to_string());
+    Err((status, msg))
+}
+
+/// 构建目录：包含后端模型与网关注入的虚拟模型，按 ID 排序。
+pub fn build_catalog(models: &HashMap<String, sonic_rs::Value>) -> Vec<ModelInfo> {
+    let by_lower: HashMap<String, &sonic_rs::Value> = models
+        .iter()
+        .map(|(k, v)| (k.trim().to_lowercase(), v))
+        .collect();
+
+    modelutil::build_sorted_model_ids(models)
+        .into_iter()
+        .map(|id| {
+            let own = by_lower.get(&id.to_lowercase()).copied();
+            let raw = own.or_else(|| {
+                by_lower
+                    .get(&modelutil::backend_model_id(&id).to_lowercase())
+                    .copied()
+            });
+            model_info(id, raw, own.is_none())
+        })
+        .collect()
+}
+
+fn model_info(id: String, raw: Option<&sonic_rs::Value>, is_virtual: bool) -> ModelInfo {
+    let is_claude = modelutil::is_claude(&id);
+    let is_gemini = modelutil::is_gemini(&id);
+    let field_u32 = |key: &str| {
+        raw.and_then(|v| v.get(key))
+            .and_then(|v| {
+                v.as_u64()
+                    .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+            })
+            .map(|n| n.min(u32::MAX as u64) as u32)
+            .filter(|n| *n > 0)
+    };
+    let field_bool = |key: &str| raw.and_then(|v| v.get(key)).and_then(|v| v.as_bool());
+
+    let context_window = field_u32("maxTokens").unwrap_or(if is_claude {
+        CLAUDE_CONTEXT_WINDOW
+    } else if is_gemini {
+        GEMINI_CONTEXT_WINDOW
+    } else {
+        0
+    });
+    let max_output_tokens = field_u32("maxOutputTokens").unwrap_or(if is_claude {
+        modelutil::CLAUDE_MAX_OUTPUT_TOKENS as u32
+    } else if is_gemini {
+        modelutil::GEMINI_MAX_OUTPUT_TOKENS as u32
+    } else {
+        0
+    });
+
+    // 虚拟模型的思考能力由其定义决定（如 claude-opus-4-5 关闭思考、gemini-3-flash-thinking 开启）。
+    let thinking = match virtual_model::lookup(&id).filter(|_| is_virtual) {
+        Some(vm) if vm.thinking_disabled() => false,
+        Some(vm) if vm.thinking_config().is_some() => true,
+        _ => field_bool("supportsThinking").unwrap_or_else(|| {
+            modelutil::is_claude_thinking(&id) || (is_gemini && !modelutil::is_image_model(&id))
+        }),
+    };
+    let vision = field_bool("supportsImages").unwrap_or(is_claude || is_gemini);
+
+    let display_name = raw
+        .filter(|_| !is_virtual)
+        .and_then(|v| v.get("displayName"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| id.clone());
+
+    ModelInfo {
+        owned_by: owned_by(&id),
+        quota_group: group_quota_key(&id),
+        display_name,
+        context_window,
+        max_output_tokens,
+        thinking,
+        vision,
+        is_virtual,
+        id,
+    }
+}
+
+/// 模型提供方（OpenAI 格式的 owned_by）。
+pub fn owned_by(model: &str) -> &'static str {
+    if model.starts_with("claude-") {
+        "anthropic"
+    } else if model.starts_with("gpt-") {
+        "openai"
+    } else {
+        "google"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_is_enriched_from_backend_fields_and_virtual_models() {
+        let models: HashMap<String, sonic_rs::Value> = sonic_rs::from_str(
+            r#"{
+                "gemini-3-flash": {"displayName": "Gemini 3 Flash", "maxTokens": 1048576,
+                    "maxOutputTokens": 65536, "supportsThinking": true, "supportsImages": true,
+                    "quotaInfo": {"remainingFraction": 0.5}},
+                "claude-opus-4-5-thinking": {"maxOutputTokens": 64000},
+                "gemini-3-pro-image": {"supportsImages": true, "supportsThinking": false}
+            }"#,
+        )
+        .unwrap();
+
+        let cat = ModelCatalog::new();
+        assert!(cat.is_empty());
+        cat.replace(&models);
+        let ids: Vec<String> = cat.models().iter().map(|m| m.id.clone()).collect();
+        assert!(ids.contains(&"gemini-3-flash-thinking".to_string()));
+        assert!(ids.contains(&"gemini-3-pro-image-4k".to_string()));
+        assert!(ids.windows(2).all(|w| w[0] <= w[1]));
+
+        let flash = cat.get("models/Gemini-3-Flash").unwrap();
+        assert_eq!(flash.display_name, "Gemini 3 Flash");
+        assert_eq!(flash.context_window, 1_048_576);
+        assert_eq!(flash.max_output_tokens, 65_536);
+        assert_eq!(flash.quota_group, group_quota_key("gemini-3-flash"));
+        assert!(!flash.is_virtual);
+
+        let flash_thinking = cat.get("gemini-3-flash-thinking").unwrap();
+        assert!(flash_thinking.is_virtual && flash_thinking.thinking);
+        assert_eq!(flash_thinking.max_output_tokens, 65_536);
+
+        // claude-opus-4-5 由 thinking 版本派生，但不开启思考。
+        let opus = cat.get("claude-opus-4-5").unwrap();
+        assert!(opus.is_virtual && !opus.thinking);
+        assert_eq!(opus.context_window, CLAUDE_CONTEXT_WINDOW);
+        assert_eq!(opus.owned_by, "anthropic");
+        assert!(cat.get("claude-opus-4-5-thinking").unwrap().thinking);
+
+        let image = cat.get("gemini-3-pro-image-2k").unwrap();
+        assert!(image.vision && !image.thinking);
+
+        // 空响应不会清空已有目录。
+        cat.replace(&HashMap::new());
+        assert!(!cat.is_empty());
+        assert!(cat.get("unknown-model").is_none());
+    }
+}
@@ -0,0 +1,132 @@
+//! maxOutputTokens 策略：按全局 / 按模型的 `MAX_TOKENS_POLICY` 处理客户端 max_tokens，
+//! 并使 thinkingBudget 与最终的 maxOutputTokens 兼容。
+
+use crate::config::{Config, MaxTokensPolicy};
+use crate::gateway::common::model_catalog;
+use crate::util::model as modelutil;
+use crate::vertex::types::GenerationConfig;
+
+/// 模型的输出上限：优先使用模型目录（后端上报），否则按模型系列取默认值；未知模型返回 0。
+pub fn model_output_limit(model: &str) -> i32 {
+    let fallback = if modelutil::is_claude(model) {
+        modelutil::CLAUDE_MAX_OUTPUT_TOKENS
+    } else if modelutil::is_gemini(model) {
+        modelutil::GEMINI_MAX_OUTPUT_TOKENS
+    } else {
+        0
+    };
+    model_catalog::catalog()
+        .get(model)
+        .map(|m| m.max_output_tokens.min(i32::MAX as u32) as i32)
+        .filter(|n| *n > 0)
+        .unwrap_or(fallback)
+}
+
+/// 计算 maxOutputTokens；`requested <= 0` 表示客户端未指定。未知模型且未指定时返回 0。
+pub fn resolve_max_output_tokens(cfg: &Config, model: &str, requested: i32) -> i32 {
+    apply_policy(cfg.max_tokens_policy_for(model), model, requested)
+}
+
+fn apply_policy(policy: MaxTokensPolicy, model: &str, requested: i32) -> i32 {
+    let limit = model_output_limit(model);
+    if limit <= 0 {
+        return requested.max(0);
+    }
+    match policy {
+        MaxTokensPolicy::ModelMax => limit,
+        MaxTokensPolicy::Clamp if requested > 0 => requested.min(limit),
+        MaxTokensPolicy::Clamp => limit,
+    }
+}
+
+/// 使 thinkingBudget 与 maxOutputTokens 兼容：
+/// - Claude / Gemini：预算收缩到 maxOutputTokens 预留余量之内；
+///   maxOutputTokens 连最小预算都容纳不下时，提升到最小预算 + 余量（后端拒绝更小的组合）
+/// - 其他模型：maxOutputTokens 不足时按预算追加输出余量
+pub fn fit_thinking_budget(out: &mut GenerationConfig, model: &str) {
+    let Some(tc) = out.thinking_config.as_mut() else {
+        return;
+    };
+    if tc.thinking_budget <= 0 {
+        return;
+    }
+
+    if out.max_output_tokens <= 0 {
+        out.max_output_tokens =
+            tc.thinking_budget + modelutil::THINKING_MAX_OUTPUT_TOKENS_OVERHEAD_TOKENS;
+    }
+
+    if modelutil::is_claude(model) || modelutil::is_gemini(model) {
+        let min_output =
+            modelutil::THINKING_BUDGET_MIN_TOKENS + modelutil::THINKING_BUDGET_HEADROOM_TOKENS;
+        if out.max_output_tokens < min_output {
+            out.max_output_tokens = min_output;
+        }
+        let max_budget = out.max_output_tokens - modelutil::THINKING_BUDGET_HEADROOM_TOKENS;
+        if tc.thinking_budget > max_budget {
+            tc.thinking_budget = max_budget;
+        }
+    } else if out.max_output_tokens <= tc.thinking_budget {
+        out.max_output_tokens =
+            tc.thinking_budget + modelutil::THINKING_MAX_OUTPUT_TOKENS_OVERHEAD_TOKENS;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::types::ThinkingConfig;
+
+    fn gc(max_output_tokens: i32, thinking_budget: i32) -> GenerationConfig {
+        let mut out: GenerationConfig = serde_json::from_str("{}").unwrap();
+        out.max_output_tokens = max_output_tokens;
+        out.thinking_config = Some(ThinkingConfig {
+            include_thoughts: true,
+            thinking_level: String::new(),
+            thinking_budget,
+        });
+        out
+    }
+
+    #[test]
+    fn client_max_tokens_is_clamped_and_reconciled_with_thinking() {
+        let clamp =
+            |model: &str, requested: i32| apply_policy(MaxTokensPolicy::Clamp, model, requested);
+        assert_eq!(clamp("claude-sonnet-4-5", 256), 256);
+        assert_eq!(
+            clamp("claude-sonnet-4-5", 1_000_000),
+            modelutil::CLAUDE_MAX_OUTPUT_TOKENS
+        );
+        assert_eq!(
+            clamp("gemini-3-pro", 0),
+            modelutil::GEMINI_MAX_OUTPUT_TOKENS
+        );
+        assert_eq!(clamp("other", 0), 0);
+        assert_eq!(clamp("other", 99), 99);
+        assert_eq!(
+            apply_policy(MaxTokensPolicy::ModelMax, "gemini-3-pro", 256),
+            modelutil::GEMINI_MAX_OUTPUT_TOKENS
+        );
+
+        // 预算收缩到 maxOutputTokens - 余量。
+        let mut out = gc(8_192, modelutil::DEFAULT_CLAUDE_THINKING_BUDGET_TOKENS);
+        fit_thinking_budget(&mut out, "claude-sonnet-4-5-thinking");
+        assert_eq!(out.max_output_tokens, 8_192);
+        assert_eq!(
+            out.thinking_config.unwrap().thinking_budget,
+            8_192 - modelutil::THINKING_BUDGET_HEADROOM_TOKENS
+        );
+
+        // 过小的 max_tokens 提升到最小预算 + 余量。
+        let mut out = gc(256, modelutil::DEFAULT_CLAUDE_THINKING_BUDGET_TOKENS);
+        fit_thinking_budget(&mut out, "gemini-2.5-flash");
+        assert_eq!(
+            out.max_output_tokens,
+            modelutil::THINKING_BUDGET_MIN_TOKENS + modelutil::THINKING_BUDGET_HEADROOM_TOKENS
+        );
+        assert_eq!(
+            out.thinking_config.unwrap().thinking_budget,
+            modelutil::THINKING_BUDGET_MIN_TOKENS
+        );
+    }
+}
@@ -4,7 +4,8 @@ pub const MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES: usize = 5;
 pub const MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE: &str = "模型已过载，请稍后再试";
 
 pub fn should_retry_with_next_token(err: &ApiError) -> bool {
-    if err.is_model_capacity_exhausted() {
+    // 容量不足或账号代理不可用：换下一个账号。
+    if err.is_model_capacity_exhausted() || matches!(err, ApiError::Proxy { .. }) {
         return true;
     }
     matches!(err.status(), Some(429 | 401 | 403))
@@ -0,0 +1,124 @@
+//! 网关侧 stop sequence 检测。
+//!
+//! 后端命中 stopSequences 时只返回 finishReason=STOP，不告知命中的是哪一个；
+//! 这里在输出文本上再扫描一遍：命中即截断，并记录命中的序列用于回填 `stop_sequence`。
+//! 流式场景会暂存可能构成某个序列前缀的尾部文本，避免把半个序列先发给客户端。
+
+#[derive(Debug, Default)]
+pub struct StopSequenceScanner {
+    sequences: Vec<String>,
+    pending: String,
+    hit: Option<String>,
+}
+
+impl StopSequenceScanner {
+    pub fn new(sequences: &[String]) -> Self {
+        Self {
+            sequences: sequences
+                .iter()
+                .filter(|s| !s.is_empty())
+                .cloned()
+                .collect(),
+            pending: String::new(),
+            hit: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.sequences.is_empty()
+    }
+
+    /// 命中的 stop sequence（未命中时为 None）。
+    pub fn hit(&self) -> Option<&str> {
+        self.hit.as_deref()
+    }
+
+    /// 追加一段输出文本，返回可以立即下发的部分；命中后的文本全部丢弃。
+    pub fn push(&mut self, text: &str) -> String {
+        if self.hit.is_some() {
+            return String::new();
+        }
+        if !self.is_enabled() {
+            return text.to_string();
+        }
+
+        self.pending.push_str(text);
+        let earliest = self
+            .sequences
+            .iter()
+            .filter_map(|s| self.pending.find(s.as_str()).map(|pos| (pos, s)))
+            .min_by_key(|(pos, _)| *pos);
+        if let Some((pos, seq)) = earliest {
+            self.hit = Some(seq.clone());
+            let mut out = std::mem::take(&mut self.pending);
+            out.truncate(pos);
+            return out;
+        }
+
+        let keep = self.holdback_len();
+        let split = self.pending.len() - keep;
+        let rest = self.pending.split_off(split);
+        std::mem::replace(&mut self.pending, rest)
+    }
+
+    /// 输出结束：返回暂存的尾部文本。
+    pub fn flush(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// pending 尾部可能是某个序列前缀的最长字节数（按字符边界）。
+    fn holdback_len(&self) -> usize {
+        let max = self.sequences.iter().map(|s| s.len()).max().unwrap_or(0);
+        let min_start = self.pending.len().saturating_sub(max.saturating_sub(1));
+        self.pending
+            .char_indices()
+            .map(|(i, _)| i)
+            .filter(|&i| i >= min_start)
+            .find(|&i| {
+                let tail = &self.pending[i..];
+                self.sequences.iter().any(|s| s.starts_with(tail))
+            })
+            .map(|i| self.pending.len() - i)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seqs(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn truncates_at_earliest_sequence() {
+        let mut s = StopSequenceScanner::new(&seqs(&["END", "###"]));
+        assert_eq!(s.push("hello ### world END"), "hello ");
+        assert_eq!(s.hit(), Some("###"));
+        assert_eq!(s.push("more"), "");
+        assert_eq!(s.flush(), "");
+    }
+
+    #[test]
+    fn holds_back_partial_sequence_across_chunks() {
+        let mut s = StopSequenceScanner::new(&seqs(&["</answer>"]));
+        assert_eq!(s.push("42</ans"), "42");
+        assert_eq!(s.push("wer> trailing"), "");
+        assert_eq!(s.hit(), Some("</answer>"));
+
+        let mut s = StopSequenceScanner::new(&seqs(&["</answer>"]));
+        assert_eq!(s.push("a </a"), "a ");
+        assert_eq!(s.push("bc"), "</abc");
+        assert_eq!(s.push("</"), "");
+        assert_eq!(s.flush(), "</");
+        assert_eq!(s.hit(), None);
+    }
+
+    #[test]
+    fn passes_through_when_disabled() {
+        let mut s = StopSequenceScanner::new(&seqs(&[""]));
+        assert!(!s.is_enabled());
+        assert_eq!(s.push("你好"), "你好");
+    }
+}
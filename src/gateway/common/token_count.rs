@@ -0,0 +1,192 @@
+//! 输入 token 统计：后端 countTokens 的请求折算，以及后端不可用时的本地估算。
+
+use crate::util::model as modelutil;
+use crate::vertex::types::{Content, InnerReq, Part};
+
+/// 每条 content 的固定开销（角色/分隔符）。
+const PER_CONTENT_OVERHEAD: i64 = 3;
+
+/// 不同模型家族的估算参数。
+struct Family {
+    /// ASCII 文本平均每 token 字符数。
+    chars_per_token: f64,
+    /// 单张图片/文档的 token 开销。
+    media_tokens: i64,
+}
+
+const CLAUDE_FAMILY: Family = Family {
+    chars_per_token: 3.5,
+    media_tokens: 1600,
+};
+
+const GEMINI_FAMILY: Family = Family {
+    chars_per_token: 4.0,
+    media_tokens: 258,
+};
+
+fn family_for(model: &str) -> &'static Family {
+    if modelutil::is_claude(model) {
+        &CLAUDE_FAMILY
+    } else {
+        &GEMINI_FAMILY
+    }
+}
+
+/// 后端 countTokens 只接受 contents：把 system 与 tools 折算为前置 user content。
+pub fn contents_for_count(req: &InnerReq) -> Vec<Content> {
+    let mut out = Vec::with_capacity(req.contents.len() + 2);
+    if let Some(sys) = req.system_instruction.as_ref()
+        && !sys.parts.is_empty()
+    {
+        out.push(Content {
+            role: "user".to_string(),
+            parts: sys.parts.clone(),
+        });
+    }
+    let tools_text = tools_text(req);
+    if !tools_text.is_empty() {
+        out.push(Content {
+            role: "user".to_string(),
+            parts: vec![Part {
+                text: tools_text,
+                ..Default::default()
+            }],
+        });
+    }
+    out.extend(req.contents.iter().cloned());
+    out
+}
+
+/// 本地估算输入 token（按模型家族取字符/token 比例）。
+pub fn estimate_input_tokens(model: &str, req: &InnerReq) -> i64 {
+    let family = family_for(model);
+    let mut total = 0i64;
+    if let Some(sys) = req.system_instruction.as_ref() {
+        total += estimate_parts(family, &sys.parts) + PER_CONTENT_OVERHEAD;
+    }
+    let tools_text = tools_text(req);
+    if !tools_text.is_empty() {
+        total += estimate_text(family, &tools_text);
+    }
+    for c in &req.contents {
+        total += estimate_parts(family, &c.parts) + PER_CONTENT_OVERHEAD;
+    }
+    total.max(1)
+}
+
+/// 本地估算一段输出文本的 token（用于扣除网关截断丢弃的部分）。
+pub fn estimate_text_tokens(model: &str, text: &str) -> i64 {
+    estimate_text(family_for(model), text)
+}
+
+fn tools_text(req: &InnerReq) -> String {
+    let decls: Vec<_> = req
+        .tools
+        .iter()
+        .flat_map(|t| t.function_declarations.iter())
+        .collect();
+    if decls.is_empty() {
+        return String::new();
+    }
+    sonic_rs::to_string(&decls).unwrap_or_default()
+}
+
+fn estimate_parts(family: &Family, parts: &[Part]) -> i64 {
+    let mut total = 0i64;
+    for p in parts {
+        total += estimate_text(family, &p.text);
+        if let Some(fc) = p.function_call.as_ref() {
+            total += estimate_text(family, &fc.name);
+            total += estimate_text(family, &sonic_rs::to_string(&fc.args).unwrap_or_default());
+        }
+        if let Some(fr) = p.function_response.as_ref() {
+            total += estimate_text(family, &fr.name);
+            total += estimate_text(
+                family,
+                &sonic_rs::to_string(&fr.response).unwrap_or_default(),
+            );
+        }
+        if p.inline_data.is_some() {
+            total += family.media_tokens;
+        }
+    }
+    total
+}
+
+/// ASCII 按比例折算，非 ASCII（CJK 等）按每字符 1 token 计。
+fn estimate_text(family: &Family, text: &str) -> i64 {
+    if text.is_empty() {
+        return 0;
+    }
+    let mut ascii = 0usize;
+    let mut other = 0usize;
+    for c in text.chars() {
+        if c.is_ascii() {
+            ascii += 1;
+        } else {
+            other += 1;
+        }
+    }
+    (ascii as f64 / family.chars_per_token).ceil() as i64 + other as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::types::{FunctionDeclaration, SystemInstruction, Tool};
+
+    fn req(text: &str) -> InnerReq {
+        InnerReq {
+            contents: vec![Content {
+                role: "user".to_string(),
+                parts: vec![Part {
+                    text: text.to_string(),
+                    ..Default::default()
+                }],
+            }],
+            system_instruction: Some(SystemInstruction {
+                role: "user".to_string(),
+                parts: vec![Part {
+                    text: "be brief".to_string(),
+                    ..Default::default()
+                }],
+            }),
+            generation_config: None,
+            tools: vec![Tool {
+                function_declarations: vec![FunctionDeclaration {
+                    name: "get_weather".to_string(),
+                    description: "Get weather".to_string(),
+                    parameters: None,
+                }],
+            }],
+            tool_config: None,
+            safety_settings: Vec::new(),
+            session_id: String::new(),
+        }
+    }
+
+    #[test]
+    fn system_and_tools_are_folded_into_contents() {
+        let contents = contents_for_count(&req("hello"));
+        assert_eq!(contents.len(), 3);
+        assert_eq!(contents[0].parts[0].text, "be brief");
+        assert!(contents[1].parts[0].text.contains("get_weather"));
+        assert_eq!(contents[2].parts[0].text, "hello");
+    }
+
+    #[test]
+    fn estimate_depends_on_model_family() {
+        let r = req(&"word ".repeat(200));
+        let claude = estimate_input_tokens("claude-sonnet-4-5", &r);
+        let gemini = estimate_input_tokens("gemini-3-flash", &r);
+        assert!(claude > gemini);
+        assert!(gemini >= 250);
+
+        let cjk = req("你好世界");
+        let small = req("abcd");
+        assert!(
+            estimate_input_tokens("gemini-3-flash", &cjk)
+                > estimate_input_tokens("gemini-3-flash", &small)
+        );
+    }
+}
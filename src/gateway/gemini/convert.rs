@@ -0,0 +1,329 @@
+use crate::config::Config;
+use crate::gateway::common::AccountContext;
+use crate::gateway::common::model_catalog::ModelInfo;
+use crate::gateway::common::output_limit;
+use crate::signature::types::FALLBACK_SIGNATURE;
+use crate::util::{id, model as modelutil, system_prompt, virtual_model};
+use crate::vertex::sanitize::{sanitize_contents, sanitize_function_parameters_schema};
+use crate::vertex::types::{
+    Candidate, Content, GenerationConfig, InnerReq, Part, Request, StreamData, SystemInstruction,
+    Tool,
+};
+use std::collections::VecDeque;
+
+use super::types::{GenerateContentRequest, GenerateContentResponse, Model};
+
+pub fn to_vertex_request(
+    cfg: &Config,
+    req: GenerateContentRequest,
+    model: &str,
+    account: &AccountContext,
+) -> anyhow::Result<(Request, String)> {
+    if req.contents.is_empty() {
+        anyhow::bail!("contents 不能为空");
+    }
+
+    let request_id = id::request_id();
+    let tools = to_vertex_tools(req.tools);
+    let tool_config = if tools.is_empty() {
+        None
+    } else {
+        req.tool_config
+    };
+
+    let mut vreq = Request {
+        project: account.project_id.clone(),
+        model: modelutil::backend_model_id(model),
+        request_id: request_id.clone(),
+        request_type: "agent".to_string(),
+        user_agent: "antigravity".to_string(),
+        request: InnerReq {
+            contents: sanitize_contents(normalize_contents(req.contents, model)),
+            system_instruction: req.system_instruction.map(|s| SystemInstruction {
+                role: "user".to_string(),
+                parts: s.parts,
+            }),
+            generation_config: Some(build_generation_config(cfg, model, req.generation_config)),
+            tools,
+            tool_config,
+            safety_settings: req.safety_settings,
+            session_id: account.session_id.clone(),
+        },
+    };
+
+    system_prompt::apply(
+        model,
+        account.api_key_name.as_deref(),
+        &mut vreq.request.system_instruction,
+    );
+
+    Ok((vreq, request_id))
+}
+
+/// 规范化客户端 contents：
+/// - 缺省 role 视为 user
+/// - 为缺少 id 的 functionCall 生成 id，并按函数名顺序回填到对应 functionResponse
+/// - Gemini：每轮首个 functionCall 缺签名时注入虚拟签名，避免后端 400
+/// - Claude：丢弃无有效签名的 thought 块（回传时后端要求签名）
+fn normalize_contents(contents: Vec<Content>, model: &str) -> Vec<Content> {
+    let is_gemini = modelutil::is_gemini(model);
+    let is_claude = modelutil::is_claude(model);
+    let mut pending_calls: VecDeque<(String, String)> = VecDeque::new();
+
+    let mut out = Vec::with_capacity(contents.len());
+    for mut c in contents {
+        if c.role.trim().is_empty() {
+            c.role = "user".to_string();
+        }
+
+        let mut first_call = true;
+        let mut parts = Vec::with_capacity(c.parts.len());
+        for mut p in c.parts {
+            if is_claude && p.thought && p.thought_signature.trim().len() <= 50 {
+                continue;
+            }
+            if let Some(fc) = p.function_call.as_mut() {
+                if fc.id.is_empty() {
+                    fc.id = id::tool_call_id();
+                }
+                pending_calls.push_back((fc.name.clone(), fc.id.clone()));
+                if is_gemini && first_call && p.thought_signature.is_empty() {
+                    p.thought_signature = FALLBACK_SIGNATURE.to_string();
+                }
+                first_call = false;
+            }
+            if let Some(fr) = p.function_response.as_mut()
+                && fr.id.is_empty()
+                && let Some(pos) = pending_calls.iter().position(|(name, _)| *name == fr.name)
+                && let Some((_, call_id)) = pending_calls.remove(pos)
+            {
+                fr.id = call_id;
+            }
+            parts.push(p);
+        }
+        c.parts = parts;
+        out.push(c);
+    }
+    out
+}
+
+fn to_vertex_tools(tools: Vec<Tool>) -> Vec<Tool> {
+    let mut out = Vec::with_capacity(tools.len());
+    for mut t in tools {
+        // 仅支持 functionDeclarations；googleSearch 等内置工具会在反序列化时被忽略。
+        if t.function_declarations.is_empty() {
+            continue;
+        }
+        for fd in t.function_declarations.iter_mut() {
+            if let Some(params) = fd.parameters.as_ref() {
+                fd.parameters = Some(sanitize_function_parameters_schema(params));
+            }
+        }
+        out.push(t);
+    }
+    out
+}
+
+fn build_generation_config(
+    cfg: &Config,
+    model: &str,
+    client: Option<GenerationConfig>,
+) -> GenerationConfig {
+    let is_image_model = modelutil::is_image_model(model);
+
+    let mut out = client.unwrap_or(GenerationConfig {
+        candidate_count: 1,
+        stop_sequences: Vec::new(),
+        max_output_tokens: 0,
+        temperature: None,
+        top_p: None,
+        top_k: 0,
+        thinking_config: None,
+        image_config: None,
+        media_resolution: String::new(),
+        response_mime_type: String::new(),
+        response_schema: None,
+    });
+    out.candidate_count = 1;
+
+    // maxOutputTokens：按 MAX_TOKENS_POLICY 处理客户端 maxOutputTokens。
+    out.max_output_tokens =
+        output_limit::resolve_max_output_tokens(cfg, model, out.max_output_tokens);
+
+    // thinkingConfig：按 Gemini 语义解析，并应用虚拟模型的强制配置。
+    let (include_thoughts, budget, level) = match out.thinking_config.take() {
+        Some(tc) => {
+            let include =
+                tc.include_thoughts || tc.thinking_budget > 0 || !tc.thinking_level.is_empty();
+            (include, tc.thinking_budget, tc.thinking_level)
+        }
+        None => (modelutil::is_claude_thinking(model), 0, String::new()),
+    };
+    out.thinking_config =
+        modelutil::thinking_config_from_gemini(model, include_thoughts, budget, &level);
+
+    // thinkingBudget 需小于 maxOutputTokens（与 OpenAI/Claude 转换保持一致）。
+    output_limit::fit_thinking_budget(&mut out, model);
+
+    // Gemini 3：客户端未指定时应用全局 mediaResolution（非 image 模型）。
+    if out.media_resolution.is_empty()
+        && modelutil::is_gemini3(model)
+        && !is_image_model
+        && let Some(v) = modelutil::to_api_media_resolution(&cfg.gemini3_media_resolution)
+        && !v.is_empty()
+    {
+        out.media_resolution = v;
+    }
+
+    // 虚拟模型：强制图片尺寸/宽高比、mediaResolution、temperature。
+    virtual_model::apply_generation_overrides(model, &mut out);
+
+    out
+}
+
+/// v1internal 响应 -> Gemini 公开响应。
+pub fn to_generate_content_response(
+    resp: crate::vertex::types::Response,
+    model: &str,
+    request_id: &str,
+) -> GenerateContentResponse {
+    GenerateContentResponse {
+        candidates: resp.response.candidates,
+        usage_metadata: resp.response.usage_metadata,
+        model_version: model.to_string(),
+        response_id: request_id.to_string(),
+    }
+}
+
+/// 模型目录条目 -> Gemini 模型元数据。
+pub fn to_gemini_model(m: &ModelInfo, id: &str) -> Model {
+    Model {
+        name: format!("models/{id}"),
+        base_model_id: m.id.clone(),
+        version: String::new(),
+        display_name: m.display_name.clone(),
+        input_token_limit: m.context_window,
+        output_token_limit: m.max_output_tokens,
+        supported_generation_methods: vec![
+            "generateContent".to_string(),
+            "streamGenerateContent".to_string(),
+        ],
+        thinking: m.thinking,
+    }
+}
+
+/// v1internal 流式 chunk -> Gemini 公开流式 chunk。
+pub fn stream_chunk_response(
+    data: &StreamData,
+    model: &str,
+    request_id: &str,
+) -> GenerateContentResponse {
+    let candidates = data
+        .response
+        .candidates
+        .iter()
+        .enumerate()
+        .map(|(i, c)| Candidate {
+            content: Content {
+                role: "model".to_string(),
+                parts: c
+                    .content
+                    .parts
+                    .iter()
+                    .map(|p| Part {
+                        text: p.text.clone(),
+                        function_call: p.function_call.clone(),
+                        inline_data: p.inline_data.clone(),
+                        thought: p.thought,
+                        thought_signature: p.thought_signature.clone(),
+                        ..Part::default()
+                    })
+                    .collect(),
+            },
+            finish_reason: c.finish_reason.clone(),
+            index: i as i32,
+        })
+        .collect();
+    GenerateContentResponse {
+        candidates,
+        usage_metadata: data.response.usage_metadata.clone(),
+        model_version: model.to_string(),
+        response_id: request_id.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::types::{FunctionCall, FunctionResponse};
+    use std::collections::HashMap;
+
+    fn account() -> AccountContext {
+        AccountContext {
+            project_id: "proj".to_string(),
+            session_id: "sess".to_string(),
+            access_token: String::new(),
+            email: String::new(),
+            api_key_name: None,
+        }
+    }
+
+    #[test]
+    fn wraps_public_body_into_internal_envelope() {
+        let body = r#"{
+            "contents": [{"parts": [{"text": "hi"}]}],
+            "system_instruction": {"parts": [{"text": "be brief"}]},
+            "generationConfig": {"temperature": 0.2, "maxOutputTokens": 128},
+            "safetySettings": [{"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_NONE"}]
+        }"#;
+        let req: GenerateContentRequest = sonic_rs::from_str(body).unwrap();
+        let (vreq, request_id) =
+            to_vertex_request(&Config::for_test(), req, "gemini-2.5-pro", &account()).unwrap();
+
+        assert_eq!(vreq.project, "proj");
+        assert_eq!(vreq.request.session_id, "sess");
+        assert_eq!(vreq.request_id, request_id);
+        assert_eq!(vreq.request.contents[0].role, "user");
+        assert_eq!(vreq.request.safety_settings.len(), 1);
+        let gc = vreq.request.generation_config.unwrap();
+        assert_eq!(gc.max_output_tokens, 128);
+        assert_eq!(gc.temperature, Some(0.2));
+        let sys = vreq.request.system_instruction.unwrap();
+        assert!(sys.parts[0].text.ends_with("be brief"));
+    }
+
+    #[test]
+    fn pairs_function_call_ids_and_injects_signature() {
+        let contents = vec![
+            Content {
+                role: "model".to_string(),
+                parts: vec![Part {
+                    function_call: Some(FunctionCall {
+                        id: String::new(),
+                        name: "lookup".to_string(),
+                        args: HashMap::new(),
+                    }),
+                    ..Part::default()
+                }],
+            },
+            Content {
+                role: "user".to_string(),
+                parts: vec![Part {
+                    function_response: Some(FunctionResponse {
+                        id: String::new(),
+                        name: "lookup".to_string(),
+                        response: HashMap::new(),
+                    }),
+                    ..Part::default()
+                }],
+            },
+        ];
+
+        let out = normalize_contents(contents, "gemini-3-pro-high");
+        let call = out[0].parts[0].function_call.as_ref().unwrap();
+        let resp = out[1].parts[0].function_response.as_ref().unwrap();
+        assert!(!call.id.is_empty());
+        assert_eq!(call.id, resp.id);
+        assert_eq!(out[0].parts[0].thought_signature, FALLBACK_SIGNATURE);
+    }
+}
@@ -0,0 +1,835 @@
+use super::convert::{
+    stream_chunk_response, to_gemini_model, to_generate_content_response, to_vertex_request,
+};
+use super::types::{GenerateContentRequest, ListModelsResponse};
+use crate::capture::{self, CaptureProvider, CaptureRecorder};
+use crate::gateway::claude::ClaudeState;
+use crate::gateway::common::AccountContext;
+use crate::gateway::common::api_auth::resolve_key_name;
+use crate::gateway::common::auth_retry::is_auth_failure;
+use crate::gateway::common::conversation::conversation_key;
+use crate::gateway::common::model_catalog;
+use crate::gateway::common::retry::{
+    MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE, MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES,
+    should_retry_with_next_token,
+};
+use crate::gateway::common::{json_response_logged, log_model_request};
+use crate::ledger::LedgerEntry;
+use crate::logging;
+use crate::metrics;
+use crate::runtime_config;
+use crate::util::id;
+use crate::vertex::client::ApiError;
+use axum::Json;
+use axum::body::{Body, Bytes};
+use axum::extract::{OriginalUri, Path, Query, State};
+use axum::http::{HeaderMap, Method, StatusCode, header};
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use futures::StreamExt;
+use serde::Deserialize;
+use sonic_rs::JsonValueTrait;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Gemini 与 OpenAI/Claude 共用同一份网关状态。
+pub type GeminiState = ClaudeState;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct GenerateQuery {
+    #[serde(default)]
+    pub alt: String,
+}
+
+/// GET /v1beta/models
+pub async fn handle_list_models(
+    State(state): State<Arc<GeminiState>>,
+    method: Method,
+    uri: OriginalUri,
+    headers: HeaderMap,
+) -> Response {
+    let start = Instant::now();
+    let log_level = state.cfg.log_level();
+    log_model_request(log_level, &method, &uri, &headers);
+
+    let models = match model_catalog::load_or_fetch(&state).await {
+        Ok(v) => v,
+        Err((status, msg)) => return gemini_error_logged(log_level, start, status, &msg),
+    };
+    let out = ListModelsResponse {
+        models: models.iter().map(|m| to_gemini_model(m, &m.id)).collect(),
+    };
+    json_response_logged(log_level, start, &out)
+}
+
+/// GET /v1beta/models/{model}
+pub async fn handle_get_model(
+    State(state): State<Arc<GeminiState>>,
+    Path(model): Path<String>,
+    method: Method,
+    uri: OriginalUri,
+    headers: HeaderMap,
+) -> Response {
+    let start = Instant::now();
+    let log_level = state.cfg.log_level();
+    log_model_request(log_level, &method, &uri, &headers);
+
+    if let Err((status, msg)) = model_catalog::load_or_fetch(&state).await {
+        return gemini_error_logged(log_level, start, status, &msg);
+    }
+    let Some(info) = model_catalog::catalog().get(&runtime_config::map_client_model_id(&model))
+    else {
+        let msg = format!("模型不存在：{model}");
+        return gemini_error_logged(log_level, start, StatusCode::NOT_FOUND, &msg);
+    };
+    let id = model.strip_prefix("models/").unwrap_or(&model);
+    json_response_logged(log_level, start, &to_gemini_model(&info, id))
+}
+
+/// POST /v1beta/models/{model}:generateContent | :streamGenerateContent
+pub async fn handle_generate_content(
+    State(state): State<Arc<GeminiState>>,
+    Path(model_action): Path<String>,
+    Query(query): Query<GenerateQuery>,
+    method: Method,
+    uri: OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let start = Instant::now();
+    let log_level = state.cfg.log_level();
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            logging::client_request_raw(method.as_str(), uri.0.path(), &headers, body.as_ref());
+        } else {
+            logging::client_request(method.as_str(), uri.0.path(), &headers, body.as_ref());
+        }
+    }
+
+    let (model, is_stream) = match model_action.rsplit_once(':') {
+        Some((m, "generateContent")) => (m, false),
+        Some((m, "streamGenerateContent")) => (m, true),
+        _ => {
+            let msg = format!("不支持的方法：{model_action}");
+            if log_level.client_enabled() {
+                if log_level.raw_enabled() {
+                    let body = gemini_error_body(StatusCode::NOT_FOUND, &msg);
+                    logging::client_response_raw(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        start.elapsed(),
+                        body.as_bytes(),
+                    );
+                } else {
+                    let err = gemini_error_value(StatusCode::NOT_FOUND, &msg);
+                    logging::client_response(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        start.elapsed(),
+                        Some(&err),
+                    );
+                }
+            }
+            return gemini_error(StatusCode::NOT_FOUND, &msg);
+        }
+    };
+
+    let endpoint = runtime_config::current_endpoint();
+    let req: GenerateContentRequest = match sonic_rs::from_slice(body.as_ref()) {
+        Ok(v) => v,
+        Err(_) => {
+            let msg = "请求 JSON 解析失败，请检查请求体格式。";
+            if log_level.client_enabled() {
+                if log_level.raw_enabled() {
+                    let body = gemini_error_body(StatusCode::BAD_REQUEST, msg);
+                    logging::client_response_raw(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        start.elapsed(),
+                        body.as_bytes(),
+                    );
+                } else {
+                    let err = gemini_error_value(StatusCode::BAD_REQUEST, msg);
+                    logging::client_response(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        start.elapsed(),
+                        Some(&err),
+                    );
+                }
+            }
+            return gemini_error(StatusCode::BAD_REQUEST, msg);
+        }
+    };
+
+    // 模型 ID 映射：允许客户端使用自定义模型名，后端自动替换为原始模型名。
+    let client_model = model.to_string();
+    let model = runtime_config::map_client_model_id(model);
+
+    // API Key 配额：模型白名单 + RPM + 每日 token。
+    let key_name = resolve_key_name(&headers, &uri.0);
+    if let Err(rejection) = state
+        .key_quota
+        .check_and_record(key_name.as_deref(), &model)
+    {
+        let status = rejection.status();
+        let msg = rejection.to_string();
+        if log_level.client_enabled() {
+            if log_level.raw_enabled() {
+                let body = gemini_error_body(status, &msg);
+                logging::client_response_raw(status.as_u16(), start.elapsed(), body.as_bytes());
+            } else {
+                let err = gemini_error_value(status, &msg);
+                logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
+            }
+        }
+        return gemini_error(status, &msg);
+    }
+
+    metrics::set_request_model(&model);
+
+    let placeholder = AccountContext {
+        project_id: id::project_id(),
+        session_id: id::session_id(),
+        access_token: String::new(),
+        email: String::new(),
+        api_key_name: key_name.clone(),
+    };
+
+    let (mut vreq, request_id) = match to_vertex_request(&state.cfg, req, &model, &placeholder) {
+        Ok(v) => v,
+        Err(e) => {
+            if log_level.client_enabled() {
+                if log_level.raw_enabled() {
+                    let body = gemini_error_body(StatusCode::BAD_REQUEST, &e.to_string());
+                    logging::client_response_raw(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        start.elapsed(),
+                        body.as_bytes(),
+                    );
+                } else {
+                    let err = gemini_error_value(StatusCode::BAD_REQUEST, &e.to_string());
+                    logging::client_response(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        start.elapsed(),
+                        Some(&err),
+                    );
+                }
+            }
+            return gemini_error(StatusCode::BAD_REQUEST, &e.to_string());
+        }
+    };
+    let conversation = conversation_key(&headers, None, key_name.as_deref(), &vreq.request);
+    let capture = capture::begin(
+        &state.cfg,
+        CaptureProvider::Gemini,
+        uri.0.path(),
+        &model,
+        key_name.as_deref(),
+        is_stream,
+        body.as_ref(),
+        &vreq,
+    );
+    let mut ledger = state.ledger.begin(
+        start,
+        key_name.as_deref(),
+        &client_model,
+        &vreq.model,
+        is_stream,
+    );
+
+    let mut attempts = state.store.enabled_count().await;
+    if attempts < 1 {
+        attempts = 1;
+    }
+
+    if is_stream {
+        let sse = query.alt.eq_ignore_ascii_case("sse");
+        return handle_stream_with_retry(
+            state,
+            vreq,
+            request_id,
+            model,
+            key_name,
+            conversation,
+            sse,
+            capture,
+            ledger,
+            attempts,
+            start,
+        )
+        .await;
+    }
+
+    let mut last_err: Option<ApiError> = None;
+    let mut vresp = None;
+    let mut used_sessions: HashSet<String> = HashSet::new();
+    let mut model_capacity_failures = 0usize;
+
+    for _ in 0..attempts {
+        let acc = match state
+            .store
+            .get_token_for_conversation(
+                &model,
+                &state.quota_pool,
+                &used_sessions,
+                &state.affinity,
+                conversation.as_deref(),
+            )
+            .await
+        {
+            Ok(v) => v,
+            Err(e) => {
+                ledger
+                    .finish(StatusCode::SERVICE_UNAVAILABLE.as_u16(), None)
+                    .await;
+                let status = StatusCode::SERVICE_UNAVAILABLE;
+                if log_level.client_enabled() {
+                    if log_level.raw_enabled() {
+                        let body = gemini_error_body(status, &e.to_string());
+                        logging::client_response_raw(
+                            status.as_u16(),
+                            start.elapsed(),
+                            body.as_bytes(),
+                        );
+                    } else {
+                        let err = gemini_error_value(status, &e.to_string());
+                        logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
+                    }
+                }
+                return gemini_error(status, &e.to_string());
+            }
+        };
+        let session_id = acc.session_id.clone();
+        used_sessions.insert(session_id.clone());
+        ledger.attempt(&acc.email);
+        let project_id = if acc.project_id.is_empty() {
+            id::project_id()
+        } else {
+            acc.project_id.clone()
+        };
+
+        vreq.project = project_id;
+        vreq.request.session_id = acc.session_id;
+
+        match state
+            .vertex
+            .for_proxy(&acc.proxy)
+            .generate_content(&endpoint, &acc.access_token, &vreq, &acc.email)
+            .await
+        {
+            Ok(v) => {
+                vresp = Some(v);
+                last_err = None;
+                break;
+            }
+            Err(e) => {
+                // 认证失败：立即切换到下一个凭证，同时后台触发刷新（不阻塞请求路径）。
+                if is_auth_failure(&e) {
+                    state
+                        .store
+                        .trigger_background_refresh(session_id.clone(), state.cfg.clone());
+                }
+                // 429/容量不足：在模型所属配额池中冷却该账号，避免被其他请求继续选中。
+                state
+                    .quota_pool
+                    .cooldown_on_error(&session_id, &model, &e, state.cfg.rate_limit_cooldown())
+                    .await;
+                if e.is_model_capacity_exhausted() {
+                    model_capacity_failures += 1;
+                } else {
+                    model_capacity_failures = 0;
+                }
+                let retry = should_retry_with_next_token(&e);
+                last_err = Some(e);
+                if model_capacity_failures >= MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES {
+                    break;
+                }
+                if !retry {
+                    break;
+                }
+            }
+        }
+    }
+
+    let Some(vresp) = vresp else {
+        ledger.finish_error(last_err.as_ref()).await;
+        let status = last_err
+            .as_ref()
+            .and_then(|e| e.status())
+            .and_then(|s| StatusCode::from_u16(s).ok())
+            .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+        let mut msg = last_err
+            .as_ref()
+            .map(|e| e.to_string())
+            .unwrap_or_else(|| "后端请求失败".to_string());
+        if model_capacity_failures >= MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES
+            && last_err
+                .as_ref()
+                .is_some_and(|e| e.is_model_capacity_exhausted())
+        {
+            msg = MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE.to_string();
+        }
+        if let Some(mut capture) = capture {
+            capture.set_upstream_error(&msg);
+            capture.finish().await;
+        }
+        if log_level.client_enabled() {
+            if log_level.raw_enabled() {
+                let body = gemini_error_body(status, &msg);
+                logging::client_response_raw(status.as_u16(), start.elapsed(), body.as_bytes());
+            } else {
+                let err = gemini_error_value(status, &msg);
+                logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
+            }
+        }
+        return gemini_error(status, &msg);
+    };
+
+    state
+        .key_quota
+        .record_usage(key_name.as_deref(), vresp.response.usage_metadata.as_ref());
+    metrics::record_usage(&model, vresp.response.usage_metadata.as_ref());
+    ledger
+        .finish(
+            StatusCode::OK.as_u16(),
+            vresp.response.usage_metadata.as_ref(),
+        )
+        .await;
+    if let Some(mut capture) = capture {
+        capture.push_response(&vresp);
+        capture.finish().await;
+    }
+    let out = to_generate_content_response(vresp, &model, &request_id);
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            if let Ok(bytes) = serde_json::to_vec(&out) {
+                logging::client_response_raw(StatusCode::OK.as_u16(), start.elapsed(), &bytes);
+            }
+        } else if let Ok(v) = sonic_rs::to_value(&out) {
+            logging::client_response(StatusCode::OK.as_u16(), start.elapsed(), Some(&v));
+        }
+    }
+    (StatusCode::OK, Json(out)).into_response()
+}
+
+/// 流式输出分帧：`?alt=sse` 时每个 chunk 为一个 SSE data 事件，否则输出 JSON 数组流。
+struct ChunkFramer {
+    sse: bool,
+    started: bool,
+}
+
+impl ChunkFramer {
+    fn frame(&mut self, json: String) -> String {
+        if self.sse {
+            return json;
+        }
+        let prefix = if self.started { ",\r\n" } else { "[" };
+        self.started = true;
+        format!("{prefix}{json}")
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        if self.sse {
+            return None;
+        }
+        Some(if self.started { "]" } else { "[]" }.to_string())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_stream_with_retry(
+    state: Arc<GeminiState>,
+    mut vreq: crate::vertex::types::Request,
+    request_id: String,
+    model: String,
+    key_name: Option<String>,
+    conversation: Option<String>,
+    sse: bool,
+    mut capture: Option<CaptureRecorder>,
+    mut ledger: LedgerEntry,
+    attempts: usize,
+    started_at: Instant,
+) -> Response {
+    let (tx, rx) = mpsc::channel::<String>(256);
+    let endpoint = runtime_config::current_endpoint();
+
+    tokio::spawn(async move {
+        let log_level = state.cfg.log_level();
+        let client_log = log_level.client_enabled();
+        let backend_log = log_level.backend_enabled();
+        let raw_log = log_level.raw_enabled();
+
+        // RAW 模式下用于在“后端响应/客户端响应”之间切换时打印分割线。
+        // 0 = none, 1 = backend, 2 = client
+        let raw_section = std::sync::Arc::new(std::sync::atomic::AtomicU8::new(0));
+        let mut framer = ChunkFramer {
+            sse,
+            started: false,
+        };
+
+        let mut last_err: Option<ApiError> = None;
+        let mut resp = None;
+        let mut used_sessions: HashSet<String> = HashSet::new();
+        let mut model_capacity_failures = 0usize;
+
+        for _ in 0..attempts {
+            let acc = match state
+                .store
+                .get_token_for_conversation(
+                    &model,
+                    &state.quota_pool,
+                    &used_sessions,
+                    &state.affinity,
+                    conversation.as_deref(),
+                )
+                .await
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    ledger
+                        .finish(StatusCode::SERVICE_UNAVAILABLE.as_u16(), None)
+                        .await;
+                    let status = StatusCode::SERVICE_UNAVAILABLE;
+                    if client_log && !raw_log {
+                        let err = gemini_error_value(status, &e.to_string());
+                        logging::client_stream_response(
+                            StatusCode::OK.as_u16(),
+                            started_at.elapsed(),
+                            &[err],
+                        );
+                    }
+                    send_stream_error(
+                        &tx,
+                        &mut framer,
+                        status,
+                        &e.to_string(),
+                        client_log && raw_log,
+                        raw_section.clone(),
+                    )
+                    .await;
+                    return;
+                }
+            };
+            let session_id = acc.session_id.clone();
+            used_sessions.insert(session_id.clone());
+            ledger.attempt(&acc.email);
+
+            let project_id = if acc.project_id.is_empty() {
+                id::project_id()
+            } else {
+                acc.project_id.clone()
+            };
+            vreq.project = project_id;
+            vreq.request.session_id = acc.session_id;
+
+            match state
+                .vertex
+                .for_proxy(&acc.proxy)
+                .generate_content_stream(&endpoint, &acc.access_token, &vreq, &acc.email)
+                .await
+            {
+                Ok(r) => {
+                    resp = Some(r);
+                    last_err = None;
+                    break;
+                }
+                Err(e) => {
+                    // 认证失败：立即切换到下一个凭证，同时后台触发刷新（不阻塞请求路径）。
+                    if is_auth_failure(&e) {
+                        state
+                            .store
+                            .trigger_background_refresh(session_id.clone(), state.cfg.clone());
+                    }
+                    // 429/容量不足：在模型所属配额池中冷却该账号，避免被其他请求继续选中。
+                    state
+                        .quota_pool
+                        .cooldown_on_error(&session_id, &model, &e, state.cfg.rate_limit_cooldown())
+                        .await;
+                    if e.is_model_capacity_exhausted() {
+                        model_capacity_failures += 1;
+                    } else {
+                        model_capacity_failures = 0;
+                    }
+                    let retry = should_retry_with_next_token(&e);
+                    last_err = Some(e);
+                    if model_capacity_failures >= MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES {
+                        break;
+                    }
+                    if !retry {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let Some(resp) = resp else {
+            ledger.finish_error(last_err.as_ref()).await;
+            let status = last_err
+                .as_ref()
+                .and_then(|e| e.status())
+                .and_then(|s| StatusCode::from_u16(s).ok())
+                .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+            let mut msg = last_err
+                .as_ref()
+                .map(|e| e.to_string())
+                .unwrap_or_else(|| "后端请求失败".to_string());
+            if model_capacity_failures >= MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES
+                && last_err
+                    .as_ref()
+                    .is_some_and(|e| e.is_model_capacity_exhausted())
+            {
+                msg = MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE.to_string();
+            }
+            if let Some(mut capture) = capture {
+                capture.set_upstream_error(&msg);
+                capture.finish().await;
+            }
+            if client_log && !raw_log {
+                let err = gemini_error_value(status, &msg);
+                logging::client_stream_response(
+                    StatusCode::OK.as_u16(),
+                    started_at.elapsed(),
+                    &[err],
+                );
+            }
+            send_stream_error(
+                &tx,
+                &mut framer,
+                status,
+                &msg,
+                client_log && raw_log,
+                raw_section.clone(),
+            )
+            .await;
+            return;
+        };
+
+        let backend_raw = backend_log && raw_log;
+        let build_merged = (backend_log || client_log) && !raw_log;
+        let parse_res = crate::vertex::stream::parse_stream_with_result(
+            resp,
+            |data| {
+                let chunk = stream_chunk_response(data, &model, &request_id);
+                let json = sonic_rs::to_string(&chunk)
+                    .ok()
+                    .map(|json| framer.frame(json));
+
+                let tx = tx.clone();
+                let raw_section = raw_section.clone();
+                async move {
+                    let Some(json) = json else {
+                        return Ok(());
+                    };
+                    if client_log && raw_log {
+                        if raw_section.swap(2, std::sync::atomic::Ordering::Relaxed) != 2 {
+                            logging::client_response_divider_raw();
+                        }
+                        logging::client_stream_event_raw(None, &json);
+                    }
+                    if tx.send(json).await.is_err() {
+                        anyhow::bail!("客户端已断开连接");
+                    }
+                    Ok(())
+                }
+            },
+            build_merged,
+            {
+                let raw_section = raw_section.clone();
+                let mut capture = capture.as_mut();
+                move |line| {
+                    if let Some(capture) = capture.as_mut() {
+                        capture.push_line(line);
+                    }
+                    if !backend_raw {
+                        return;
+                    }
+                    if raw_section.swap(1, std::sync::atomic::Ordering::Relaxed) != 1 {
+                        logging::backend_response_divider_raw();
+                    }
+                    if line.starts_with(b"data:") || line.starts_with(b":") {
+                        logging::backend_stream_line_raw(line);
+                    }
+                }
+            },
+        )
+        .await;
+
+        let stream_result = match parse_res {
+            Ok(r) => r,
+            Err(e) => e.result,
+        };
+        state
+            .key_quota
+            .record_usage(key_name.as_deref(), stream_result.usage.as_ref());
+        metrics::record_usage(&model, stream_result.usage.as_ref());
+        ledger
+            .finish(StatusCode::OK.as_u16(), stream_result.usage.as_ref())
+            .await;
+        if let Some(capture) = capture {
+            capture.finish().await;
+        }
+
+        if let Some(tail) = framer.finish() {
+            let _ = tx.send(tail).await;
+        }
+
+        let duration = started_at.elapsed();
+        if !raw_log {
+            if backend_log {
+                logging::backend_stream_response(
+                    StatusCode::OK.as_u16(),
+                    duration,
+                    stream_result.merged_response.as_ref(),
+                );
+            }
+            if client_log {
+                let merged: Vec<sonic_rs::Value> = stream_result
+                    .merged_response
+                    .as_ref()
+                    .and_then(|v| v.get("response"))
+                    .map(|v| vec![v.to_owned()])
+                    .unwrap_or_default();
+                logging::client_stream_response(StatusCode::OK.as_u16(), duration, &merged);
+            }
+        }
+    });
+
+    let stream = ReceiverStream::new(rx);
+    if sse {
+        return Sse::new(stream.map(|json| Ok::<_, Infallible>(Event::default().data(json))))
+            .into_response();
+    }
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Body::from_stream(stream.map(|s| Ok::<_, Infallible>(Bytes::from(s)))),
+    )
+        .into_response()
+}
+
+async fn send_stream_error(
+    tx: &mpsc::Sender<String>,
+    framer: &mut ChunkFramer,
+    status: StatusCode,
+    msg: &str,
+    raw_log: bool,
+    raw_section: std::sync::Arc<std::sync::atomic::AtomicU8>,
+) {
+    let mut out = vec![framer.frame(gemini_error_body(status, msg))];
+    out.extend(framer.finish());
+    for ev in out {
+        if raw_log {
+            if raw_section.swap(2, std::sync::atomic::Ordering::Relaxed) != 2 {
+                logging::client_response_divider_raw();
+            }
+            logging::client_stream_event_raw(None, &ev);
+        }
+        let _ = tx.send(ev).await;
+    }
+}
+
+/// HTTP 状态码 -> google.rpc.Code 名称。
+pub(crate) fn gemini_status_name(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        400 => "INVALID_ARGUMENT",
+        401 => "UNAUTHENTICATED",
+        403 => "PERMISSION_DENIED",
+        404 => "NOT_FOUND",
+        429 => "RESOURCE_EXHAUSTED",
+        499 => "CANCELLED",
+        500 => "INTERNAL",
+        501 => "UNIMPLEMENTED",
+        503 => "UNAVAILABLE",
+        504 => "DEADLINE_EXCEEDED",
+        _ => "UNKNOWN",
+    }
+}
+
+/// 记录客户端日志并返回 Gemini 格式错误。
+fn gemini_error_logged(
+    log_level: logging::LogLevel,
+    start: Instant,
+    status: StatusCode,
+    msg: &str,
+) -> Response {
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            let body = gemini_error_body(status, msg);
+            logging::client_response_raw(status.as_u16(), start.elapsed(), body.as_bytes());
+        } else {
+            let err = gemini_error_value(status, msg);
+            logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
+        }
+    }
+    gemini_error(status, msg)
+}
+
+fn gemini_error(status: StatusCode, msg: &str) -> Response {
+    let body = gemini_error_body(status, msg);
+    (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+pub(crate) fn gemini_error_body(status: StatusCode, msg: &str) -> String {
+    sonic_rs::to_string(&gemini_error_value(status, msg)).unwrap_or_default()
+}
+
+fn gemini_error_value(status: StatusCode, msg: &str) -> sonic_rs::Value {
+    let mut err = sonic_rs::Object::new();
+    err.insert("code", status.as_u16());
+    err.insert("message", msg);
+    err.insert("status", gemini_status_name(status));
+    let mut outer = sonic_rs::Object::new();
+    outer.insert("error", err);
+    outer.into_value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonic_rs::JsonContainerTrait;
+
+    #[test]
+    fn chunk_framer_builds_json_array() {
+        let mut f = ChunkFramer {
+            sse: false,
+            started: false,
+        };
+        let mut out = f.frame("{\"a\":1}".to_string());
+        out.push_str(&f.frame("{\"b\":2}".to_string()));
+        out.push_str(&f.finish().unwrap());
+        let v: sonic_rs::Value = sonic_rs::from_str(&out).unwrap();
+        assert_eq!(v.as_array().map(|a| a.len()), Some(2));
+
+        let mut empty = ChunkFramer {
+            sse: false,
+            started: false,
+        };
+        assert_eq!(empty.finish().as_deref(), Some("[]"));
+
+        let mut sse = ChunkFramer {
+            sse: true,
+            started: false,
+        };
+        assert_eq!(sse.frame("{}".to_string()), "{}");
+        assert!(sse.finish().is_none());
+    }
+
+    #[test]
+    fn error_body_uses_google_rpc_shape() {
+        let body = gemini_error_body(StatusCode::TOO_MANY_REQUESTS, "slow down");
+        let v: sonic_rs::Value = sonic_rs::from_str(&body).unwrap();
+        assert_eq!(
+            v.get("error")
+                .and_then(|e| e.get("status"))
+                .and_then(|s| s.as_str()),
+            Some("RESOURCE_EXHAUSTED")
+        );
+        assert_eq!(
+            v.get("error")
+                .and_then(|e| e.get("code"))
+                .and_then(|s| s.as_u64()),
+            Some(429)
+        );
+    }
+}
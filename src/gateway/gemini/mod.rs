@@ -0,0 +1,7 @@
+mod convert;
+mod handler;
+mod types;
+
+pub(crate) use convert::{stream_chunk_response, to_generate_content_response, to_vertex_request};
+pub use handler::{handle_generate_content, handle_get_model, handle_list_models};
+pub use types::*;
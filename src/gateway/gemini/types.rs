@@ -0,0 +1,54 @@
+use crate::vertex::types::{
+    Candidate, Content, GenerationConfig, SystemInstruction, Tool, ToolConfig, UsageMetadata,
+};
+use serde::{Deserialize, Serialize};
+
+/// Gemini 公开 REST 请求体（`models/{model}:generateContent`）。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentRequest {
+    #[serde(default)]
+    pub contents: Vec<Content>,
+    #[serde(default, alias = "system_instruction")]
+    pub system_instruction: Option<SystemInstruction>,
+    #[serde(default)]
+    pub tools: Vec<Tool>,
+    #[serde(default, alias = "tool_config")]
+    pub tool_config: Option<ToolConfig>,
+    #[serde(default, alias = "generation_config")]
+    pub generation_config: Option<GenerationConfig>,
+    #[serde(default, alias = "safety_settings")]
+    pub safety_settings: Vec<sonic_rs::Value>,
+}
+
+/// Gemini 公开 REST 响应体（即 v1internal 响应去掉外层 `response` 包装）。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentResponse {
+    pub candidates: Vec<Candidate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage_metadata: Option<UsageMetadata>,
+    pub model_version: String,
+    pub response_id: String,
+}
+
+/// Gemini `models.list` 响应。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListModelsResponse {
+    pub models: Vec<Model>,
+}
+
+/// Gemini 模型元数据（`models/{model}`）。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+    pub name: String,
+    pub base_model_id: String,
+    pub version: String,
+    pub display_name: String,
+    pub input_token_limit: u32,
+    pub output_token_limit: u32,
+    pub supported_generation_methods: Vec<String>,
+    pub thinking: bool,
+}
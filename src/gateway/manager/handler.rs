@@ -3,8 +3,8 @@
 //! 实现与 Go 版本完全一致的 WebUI 功能。
 
 use axum::{
-    Form, Json,
-    extract::{OriginalUri, Query, State},
+    Extension, Form, Json,
+    extract::{ConnectInfo, OriginalUri, Query, State},
     http::{HeaderMap, Method, StatusCode, header},
     response::sse::{Event, Sse},
     response::{Html, IntoResponse, Redirect, Response},
@@ -12,6 +12,7 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::mpsc;
@@ -21,14 +22,18 @@ use crate::config::Config;
 use crate::credential::oauth;
 use crate::credential::store::Store;
 use crate::credential::types::Account;
+use crate::gateway::common::model_catalog;
+use crate::gateway::manager::session::{LoginThrottle, ManagerSession, SESSION_TTL, SessionStore};
 use crate::gateway::manager::templates::{self, ViewAccount, ViewQuotaGroup, to_view_accounts};
+use crate::ledger::UsageLedger;
 use crate::logging;
 use crate::quota_pool::QuotaPoolManager;
 use crate::quota_pool::{AccountQuota, QuotaGroup};
-use crate::runtime_config::{self, WebUISettings};
+use crate::runtime_config::{self, WebUISettings, constant_time_eq};
 use crate::signature;
 use crate::util::id;
 use crate::util::model as modelutil;
+use crate::util::{system_prompt, virtual_model};
 
 use askama::Template;
 use futures::StreamExt;
@@ -61,48 +66,59 @@ pub struct ManagerState {
     pub quota_pool: Arc<QuotaPoolManager>,
     pub data_dir: String,
     pub cfg: Config,
+    pub ledger: Arc<UsageLedger>,
+    pub sessions: SessionStore,
+    pub login_throttle: LoginThrottle,
 }
 
 /// Cookie 名称
 const SESSION_COOKIE_NAME: &str = "grok_admin_session";
-/// Cookie 值
-const SESSION_COOKIE_VALUE: &str = "authenticated";
+/// CSRF 令牌请求头
+const CSRF_HEADER: &str = "X-CSRF-Token";
 
 // ============================================================================
 // 认证相关
 // ============================================================================
 
-/// 检查请求是否已认证
-fn is_authenticated(headers: &HeaderMap) -> bool {
+/// 从 Cookie 中读取会话令牌
+fn session_token(headers: &HeaderMap) -> Option<&str> {
+    let cookies = headers.get(header::COOKIE)?.to_str().ok()?;
+    cookies.split(';').find_map(|c| {
+        let (name, value) = c.trim().split_once('=')?;
+        (name == SESSION_COOKIE_NAME).then_some(value)
+    })
+}
+
+/// 查找当前请求对应的有效会话
+fn current_session(state: &ManagerState, headers: &HeaderMap) -> Option<ManagerSession> {
+    state.sessions.get(session_token(headers)?)
+}
+
+/// 经 HTTPS 反代访问时为 Cookie 加上 Secure
+fn is_https(headers: &HeaderMap) -> bool {
     headers
-        .get(header::COOKIE)
+        .get("X-Forwarded-Proto")
         .and_then(|v| v.to_str().ok())
-        .map(|cookies| {
-            cookies.split(';').any(|c| {
-                let c = c.trim();
-                c == format!("{}={}", SESSION_COOKIE_NAME, SESSION_COOKIE_VALUE)
-                    || c.starts_with(&format!(
-                        "{}={};",
-                        SESSION_COOKIE_NAME, SESSION_COOKIE_VALUE
-                    ))
-            })
-        })
-        .unwrap_or(false)
+        .is_some_and(|v| v.eq_ignore_ascii_case("https"))
 }
 
 /// 设置认证 Cookie
-fn set_auth_cookie() -> String {
-    let expires = chrono::Utc::now() + chrono::Duration::hours(24);
-    let expires_str = expires.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+fn set_auth_cookie(token: &str, secure: bool) -> String {
     format!(
-        "{}={}; Path=/; HttpOnly; Expires={}",
-        SESSION_COOKIE_NAME, SESSION_COOKIE_VALUE, expires_str
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}",
+        SESSION_COOKIE_NAME,
+        token,
+        SESSION_TTL.as_secs(),
+        if secure { "; Secure" } else { "" }
     )
 }
 
 /// 清除认证 Cookie
 fn clear_auth_cookie() -> String {
-    format!("{}=; Path=/; HttpOnly; Max-Age=0", SESSION_COOKIE_NAME)
+    format!(
+        "{}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0",
+        SESSION_COOKIE_NAME
+    )
 }
 
 // ============================================================================
@@ -110,8 +126,11 @@ fn clear_auth_cookie() -> String {
 // ============================================================================
 
 /// GET /login - 显示登录页面
-pub async fn handle_login_view(headers: HeaderMap) -> Response {
-    if is_authenticated(&headers) {
+pub async fn handle_login_view(
+    State(state): State<Arc<ManagerState>>,
+    headers: HeaderMap,
+) -> Response {
+    if current_session(&state, &headers).is_some() {
         return Redirect::to("/").into_response();
     }
 
@@ -127,39 +146,67 @@ pub struct LoginForm {
     password: String,
 }
 
+fn login_error(msg: String) -> Response {
+    let tmpl = templates::LoginTemplate { error_msg: msg };
+    Html(tmpl.render().unwrap_or_default()).into_response()
+}
+
 /// POST /login - 处理登录
-pub async fn handle_login(Form(form): Form<LoginForm>) -> Response {
+pub async fn handle_login(
+    State(state): State<Arc<ManagerState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Form(form): Form<LoginForm>,
+) -> Response {
     let settings = runtime_config::get();
 
     // 检查密码是否配置
     if settings.webui_password.is_empty() {
-        let tmpl = templates::LoginTemplate {
-            error_msg: "管理密码未配置，请设置 WEBUI_PASSWORD 环境变量".to_string(),
-        };
-        return Html(tmpl.render().unwrap_or_default()).into_response();
+        return login_error("管理密码未配置，请设置 WEBUI_PASSWORD 环境变量".to_string());
+    }
+
+    // 按直连对端地址限流：部署在反向代理之后时所有请求共用代理的地址，
+    // 任一来源连续输错都会锁定全部管理员（见 LoginThrottle 文档）。
+    let ip = peer.ip();
+    if let Some(remaining) = state.login_throttle.locked_for(ip) {
+        let minutes = remaining.as_secs().div_ceil(60);
+        return login_error(format!("尝试次数过多，请 {minutes} 分钟后再试"));
     }
 
     // 验证密码
-    if form.password == settings.webui_password {
-        let mut headers = HeaderMap::new();
-        headers.insert(header::SET_COOKIE, set_auth_cookie().parse().unwrap());
-        headers.insert("HX-Redirect", "/".parse().unwrap());
+    if constant_time_eq(&form.password, &settings.webui_password) {
+        state.login_throttle.reset(ip);
+        let session = state.sessions.create();
 
-        (headers, "登录成功").into_response()
+        let mut resp_headers = HeaderMap::new();
+        resp_headers.insert(
+            header::SET_COOKIE,
+            set_auth_cookie(&session.token, is_https(&headers))
+                .parse()
+                .unwrap(),
+        );
+        resp_headers.insert("HX-Redirect", "/".parse().unwrap());
+
+        (resp_headers, "登录成功").into_response()
     } else {
-        let tmpl = templates::LoginTemplate {
-            error_msg: "密码错误".to_string(),
-        };
-        Html(tmpl.render().unwrap_or_default()).into_response()
+        state.login_throttle.record_failure(ip);
+        tracing::warn!("管理面板登录失败: {ip}");
+        login_error("密码错误".to_string())
     }
 }
 
-/// GET /logout - 登出
-pub async fn handle_logout() -> Response {
-    let mut headers = HeaderMap::new();
-    headers.insert(header::SET_COOKIE, clear_auth_cookie().parse().unwrap());
+/// POST /logout - 登出（受保护路由，经认证中间件校验 CSRF 令牌）
+pub async fn handle_logout(
+    State(state): State<Arc<ManagerState>>,
+    Extension(session): Extension<ManagerSession>,
+) -> Response {
+    state.sessions.revoke(&session.token);
+
+    let mut resp_headers = HeaderMap::new();
+    resp_headers.insert(header::SET_COOKIE, clear_auth_cookie().parse().unwrap());
+    resp_headers.insert("HX-Redirect", "/login".parse().unwrap());
 
-    (headers, Redirect::to("/login")).into_response()
+    (resp_headers, "已登出").into_response()
 }
 
 // ============================================================================
@@ -167,7 +214,10 @@ pub async fn handle_logout() -> Response {
 // ============================================================================
 
 /// GET / - Dashboard 主页面
-pub async fn handle_dashboard(State(state): State<Arc<ManagerState>>) -> Response {
+pub async fn handle_dashboard(
+    State(state): State<Arc<ManagerState>>,
+    Extension(session): Extension<ManagerSession>,
+) -> Response {
     let mut accounts = state.store.get_all().await;
     sort_accounts_by_created_at_desc(&mut accounts);
     let stats = templates::calculate_stats(&accounts);
@@ -176,6 +226,7 @@ pub async fn handle_dashboard(State(state): State<Arc<ManagerState>>) -> Respons
     let tmpl = templates::DashboardTemplate {
         accounts: view_accounts,
         stats,
+        csrf_token: session.csrf_token,
     };
 
     Html(tmpl.render().unwrap_or_default()).into_response()
@@ -245,7 +296,7 @@ pub async fn handle_list(
 
 /// 按创建时间降序排序账号
 fn sort_accounts_by_created_at_desc(accounts: &mut [Account]) {
-    accounts.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    accounts.sort_by_key(|a| std::cmp::Reverse(a.created_at));
 }
 
 // ============================================================================
@@ -263,16 +314,13 @@ pub async fn handle_delete(
     State(state): State<Arc<ManagerState>>,
     Query(query): Query<IdQuery>,
 ) -> Response {
-    let idx = find_index_by_session_id(&state.store, &query.id).await;
-
-    if let Some(idx) = idx
-        && state.store.delete(idx).await.is_ok()
-    {
-        state.quota_pool.remove_session(&query.id).await;
-        return "".into_response();
+    match state.store.delete(&query.id).await {
+        Ok(removed) => {
+            state.quota_pool.remove_session(&removed.session_id).await;
+            "".into_response()
+        }
+        Err(_) => (StatusCode::NOT_FOUND, "未找到").into_response(),
     }
-
-    (StatusCode::NOT_FOUND, "未找到").into_response()
 }
 
 /// POST /manager/api/toggle - 切换启用/禁用
@@ -280,33 +328,66 @@ pub async fn handle_toggle(
     State(state): State<Arc<ManagerState>>,
     Query(query): Query<IdQuery>,
 ) -> Response {
-    let idx = find_index_by_session_id(&state.store, &query.id).await;
-
-    if let Some(idx) = idx {
-        let accounts = state.store.get_all().await;
-        if idx < accounts.len() {
-            let new_state = !accounts[idx].enable;
-            let _ = state.store.set_enable(idx, new_state).await;
-            if !new_state {
-                // 账号禁用后立即从配额池移除，避免仍被网关选择。
-                state.quota_pool.remove_session(&query.id).await;
-            }
+    let Some(account) = state.store.get_by_id(&query.id).await else {
+        return "".into_response();
+    };
+    let Ok(account) = state.store.set_enable(&account.id, !account.enable).await else {
+        return "".into_response();
+    };
+    if !account.enable {
+        // 账号禁用后立即从配额池移除，避免仍被网关选择。
+        state.quota_pool.remove_session(&account.session_id).await;
+    }
 
-            // 重新获取更新后的账号
-            let accounts = state.store.get_all().await;
-            if idx < accounts.len() {
-                let view_account = ViewAccount::from_account(&accounts[idx]);
-                let tmpl = templates::TokenCardTemplate {
-                    account: view_account,
-                    quota_open: false,
-                };
-                let html = tmpl.render().unwrap_or_default();
-                return Html(html).into_response();
-            }
+    let tmpl = templates::TokenCardTemplate {
+        account: ViewAccount::from_account(&account),
+        quota_open: false,
+    };
+    Html(tmpl.render().unwrap_or_default()).into_response()
+}
+
+/// 代理表单
+#[derive(Deserialize, Default)]
+pub struct ProxyForm {
+    #[serde(default)]
+    proxy: String,
+}
+
+/// POST /manager/api/proxy - 设置账号出口代理
+pub async fn handle_proxy(
+    State(state): State<Arc<ManagerState>>,
+    Query(query): Query<IdQuery>,
+    Form(form): Form<ProxyForm>,
+) -> Response {
+    let (toast_type, toast_msg) = match state.store.set_proxy(&query.id, &form.proxy).await {
+        Ok(_) => {
+            tracing::info!(account_id = %query.id, "已更新账号代理");
+            ("success", "代理已保存".to_string())
         }
-    }
+        Err(e) => ("error", format!("保存代理失败：{e}")),
+    };
 
-    "".into_response()
+    let Some(acc) = state.store.get_by_id(&query.id).await else {
+        return (StatusCode::NOT_FOUND, "未找到").into_response();
+    };
+    let tmpl = templates::TokenCardTemplate {
+        account: ViewAccount::from_account(&acc),
+        quota_open: false,
+    };
+    let html = tmpl.render().unwrap_or_default();
+
+    let mut headers = HeaderMap::new();
+    let trigger = hx_trigger_value(serde_json::json!({
+        "showMessage": { "message": toast_msg, "type": toast_type }
+    }));
+    headers.insert("HX-Trigger", trigger.parse().unwrap());
+    headers.insert(
+        "HX-Trigger-After-Settle",
+        hx_trigger_value(serde_json::json!({ "refreshQuota": true }))
+            .parse()
+            .unwrap(),
+    );
+    (headers, Html(html)).into_response()
 }
 
 /// 刷新表单
@@ -323,52 +404,46 @@ pub async fn handle_refresh(
     Form(form): Form<RefreshForm>,
 ) -> Response {
     let quota_open = form.quota_open.trim() == "1";
-    let idx = find_index_by_session_id(&state.store, &query.id).await;
-
-    if let Some(idx) = idx {
-        // 刷新账号
-        let mut toast_type = "success";
-        let mut toast_msg = "凭证刷新成功";
-        match state.store.refresh_account(idx).await {
-            Ok(()) => {
-                tracing::info!(session_id = %query.id, "手动刷新凭证成功");
-            }
-            Err(e) => {
-                tracing::warn!(session_id = %query.id, error = ?e, "手动刷新凭证失败");
-                toast_type = "error";
-                toast_msg = "凭证刷新失败";
-            }
-        }
-
-        // 返回更新后的卡片
-        let accounts = state.store.get_all().await;
-        if idx < accounts.len() {
-            let view_account = ViewAccount::from_account(&accounts[idx]);
-            let tmpl = templates::TokenCardTemplate {
-                account: view_account,
-                quota_open,
-            };
-            let html = tmpl.render().unwrap_or_default();
-
-            let mut headers = HeaderMap::new();
-            let trigger = hx_trigger_value(serde_json::json!({
-                "showMessage": { "message": toast_msg, "type": toast_type }
-            }));
-            headers.insert("HX-Trigger", trigger.parse().unwrap());
-
-            // 卡片替换完成后，立即触发一次配额刷新，避免等待 30s 轮询。
-            headers.insert(
-                "HX-Trigger-After-Settle",
-                hx_trigger_value(serde_json::json!({ "refreshQuota": true }))
-                    .parse()
-                    .unwrap(),
-            );
 
-            return (headers, Html(html)).into_response();
+    // 刷新账号
+    let mut toast_type = "success";
+    let mut toast_msg = "凭证刷新成功";
+    match state.store.refresh_account(&query.id).await {
+        Ok(()) => {
+            tracing::info!(account_id = %query.id, "手动刷新凭证成功");
+        }
+        Err(e) => {
+            tracing::warn!(account_id = %query.id, error = ?e, "手动刷新凭证失败");
+            toast_type = "error";
+            toast_msg = "凭证刷新失败";
         }
     }
 
-    "".into_response()
+    // 返回更新后的卡片
+    let Some(account) = state.store.get_by_id(&query.id).await else {
+        return "".into_response();
+    };
+    let tmpl = templates::TokenCardTemplate {
+        account: ViewAccount::from_account(&account),
+        quota_open,
+    };
+    let html = tmpl.render().unwrap_or_default();
+
+    let mut headers = HeaderMap::new();
+    let trigger = hx_trigger_value(serde_json::json!({
+        "showMessage": { "message": toast_msg, "type": toast_type }
+    }));
+    headers.insert("HX-Trigger", trigger.parse().unwrap());
+
+    // 卡片替换完成后，立即触发一次配额刷新，避免等待 30s 轮询。
+    headers.insert(
+        "HX-Trigger-After-Settle",
+        hx_trigger_value(serde_json::json!({ "refreshQuota": true }))
+            .parse()
+            .unwrap(),
+    );
+
+    (headers, Html(html)).into_response()
 }
 
 /// POST /manager/api/refresh_all - 刷新所有账号
@@ -386,12 +461,6 @@ pub async fn handle_refresh_all(State(state): State<Arc<ManagerState>>) -> Respo
     (headers, "").into_response()
 }
 
-/// 查找账号索引
-async fn find_index_by_session_id(store: &Store, session_id: &str) -> Option<usize> {
-    let accounts = store.get_all().await;
-    accounts.iter().position(|acc| acc.session_id == session_id)
-}
-
 // ============================================================================
 // 配额处理器
 // ============================================================================
@@ -723,7 +792,7 @@ pub async fn handle_oauth_parse_url(
     if !project_id.is_empty() {
         tracing::info!("使用用户自定义项目ID: {project_id}");
     } else if !token_resp.access_token.is_empty() {
-        match oauth::fetch_project_id(&cfg, &token_resp.access_token).await {
+        match oauth::fetch_project_id(&cfg, &token_resp.access_token, "").await {
             Ok(pid) => {
                 project_id = pid.trim().to_string();
                 if !project_id.is_empty() {
@@ -752,6 +821,7 @@ pub async fn handle_oauth_parse_url(
     // 创建账号
     let now = chrono::Utc::now();
     let account = Account {
+        id: String::new(),         // 由 store.add 生成
         session_id: String::new(), // 由 store.add 生成
         access_token: token_resp.access_token,
         refresh_token: token_resp.refresh_token,
@@ -761,6 +831,7 @@ pub async fn handle_oauth_parse_url(
         email: email.clone(),
         enable: true,
         created_at: now,
+        proxy: String::new(),
     };
 
     if let Err(e) = state.store.add(account).await {
@@ -830,8 +901,23 @@ pub async fn handle_settings_get(headers: HeaderMap) -> Response {
     let webui_settings = WebUISettings::from_runtime(&settings);
 
     if is_htmx(&headers) {
+        let api_keys = runtime_config::get_api_keys();
+        let api_keys_json = if api_keys.is_empty() {
+            String::new()
+        } else {
+            serde_json::to_string_pretty(api_keys.as_ref()).unwrap_or_default()
+        };
+        let system_prompt_policy_json =
+            serde_json::to_string_pretty(system_prompt::get_system_prompt_policy().as_ref())
+                .unwrap_or_default();
+        let endpoint_statuses = crate::vertex::endpoints::pool().statuses(
+            &runtime_config::normalize_endpoint_mode(&settings.endpoint_mode),
+        );
         let tmpl = templates::SettingsTemplate {
             settings: webui_settings,
+            api_keys_json,
+            system_prompt_policy_json,
+            endpoint_statuses,
         };
 
         let mut resp_headers = HeaderMap::new();
@@ -856,7 +942,11 @@ struct SettingsResponse {
 }
 
 /// POST /manager/api/settings - 保存设置
-pub async fn handle_settings_post(Json(req): Json<WebUISettings>) -> Response {
+pub async fn handle_settings_post(
+    State(state): State<Arc<ManagerState>>,
+    Extension(session): Extension<ManagerSession>,
+    Json(req): Json<WebUISettings>,
+) -> Response {
     // 验证
     if let Err(msg) = req.validate() {
         return Json(SettingsResponse {
@@ -879,8 +969,15 @@ pub async fn handle_settings_post(Json(req): Json<WebUISettings>) -> Response {
     // 更新运行时配置
     let current = runtime_config::get();
     let new_settings = req.apply_to_runtime(&current);
+    let password_changed = new_settings.webui_password != current.webui_password;
     runtime_config::update(new_settings.clone());
 
+    // 修改密码后注销其他会话（保留当前操作者的会话）
+    if password_changed {
+        let revoked = state.sessions.revoke_all_except(Some(&session.token));
+        tracing::info!("管理密码已修改，已注销 {revoked} 个会话");
+    }
+
     tracing::info!(
         "设置已更新: Debug={}, UserAgent={}, EndpointMode={}",
         new_settings.debug,
@@ -928,6 +1025,95 @@ pub async fn handle_cache_cleanup(State(state): State<Arc<ManagerState>>) -> Res
     }
 }
 
+/// GET /manager/api/api-keys - 获取命名 API Key 列表
+pub async fn handle_api_keys_get() -> Response {
+    let keys = runtime_config::get_api_keys();
+    Json(keys.as_ref()).into_response()
+}
+
+/// POST /manager/api/api-keys - 保存命名 API Key 列表（立即生效）
+pub async fn handle_api_keys_post(
+    State(state): State<Arc<ManagerState>>,
+    Json(req): Json<Vec<runtime_config::ApiKeyEntry>>,
+) -> Response {
+    let normalized = match runtime_config::validate_and_normalize_api_keys(req) {
+        Ok(v) => v,
+        Err(e) => {
+            return Json(SettingsResponse {
+                success: false,
+                error: Some(e),
+            })
+            .into_response();
+        }
+    };
+
+    if let Err(e) = runtime_config::persist_api_keys_to_data_dir(&state.data_dir, &normalized) {
+        tracing::error!("保存 API Key 失败: {e}");
+        return Json(SettingsResponse {
+            success: false,
+            error: Some(e),
+        })
+        .into_response();
+    }
+
+    tracing::info!("命名 API Key 已更新: 共 {} 个", normalized.len());
+    runtime_config::update_api_keys(normalized);
+
+    Json(SettingsResponse {
+        success: true,
+        error: None,
+    })
+    .into_response()
+}
+
+/// GET /manager/api/system-prompt-policy - 获取系统提示词注入策略
+pub async fn handle_system_prompt_policy_get() -> Response {
+    let policy = system_prompt::get_system_prompt_policy();
+    Json(policy.as_ref()).into_response()
+}
+
+/// POST /manager/api/system-prompt-policy - 保存系统提示词注入策略（立即生效）
+pub async fn handle_system_prompt_policy_post(
+    State(state): State<Arc<ManagerState>>,
+    Json(req): Json<system_prompt::SystemPromptPolicy>,
+) -> Response {
+    let normalized = match system_prompt::validate_and_normalize_system_prompt_policy(req) {
+        Ok(v) => v,
+        Err(e) => {
+            return Json(SettingsResponse {
+                success: false,
+                error: Some(e),
+            })
+            .into_response();
+        }
+    };
+
+    if let Err(e) =
+        system_prompt::persist_system_prompt_policy_to_data_dir(&state.data_dir, &normalized)
+    {
+        tracing::error!("保存系统提示词策略失败: {e}");
+        return Json(SettingsResponse {
+            success: false,
+            error: Some(e),
+        })
+        .into_response();
+    }
+
+    tracing::info!(
+        "系统提示词策略已更新: 全局={}, 模型规则 {} 条, API Key 规则 {} 条",
+        normalized.mode.as_str(),
+        normalized.models.len(),
+        normalized.api_keys.len()
+    );
+    system_prompt::update_system_prompt_policy(normalized);
+
+    Json(SettingsResponse {
+        success: true,
+        error: None,
+    })
+    .into_response()
+}
+
 // ============================================================================
 // 模型设置处理器
 // ============================================================================
@@ -1026,6 +1212,127 @@ pub async fn handle_model_id_mapping_post(
     .into_response()
 }
 
+/// GET /manager/api/virtual-models - 获取虚拟模型定义（JSON 数组）
+pub async fn handle_virtual_models_get() -> Response {
+    let models = virtual_model::get_virtual_models();
+    Json(models.as_ref()).into_response()
+}
+
+/// POST /manager/api/virtual-models - 保存虚拟模型定义（JSON 数组），保存后重建模型目录
+pub async fn handle_virtual_models_post(
+    State(state): State<Arc<ManagerState>>,
+    Json(req): Json<Vec<virtual_model::VirtualModel>>,
+) -> Response {
+    let normalized = match virtual_model::validate_and_normalize_virtual_models(req) {
+        Ok(v) => v,
+        Err(e) => {
+            return Json(ModelIdMappingSaveResponse {
+                success: false,
+                error: Some(e),
+            })
+            .into_response();
+        }
+    };
+
+    if let Err(e) = virtual_model::persist_virtual_models_to_data_dir(&state.data_dir, &normalized)
+    {
+        tracing::error!("保存虚拟模型失败: {e}");
+        return Json(ModelIdMappingSaveResponse {
+            success: false,
+            error: Some(e),
+        })
+        .into_response();
+    }
+
+    virtual_model::update_virtual_models(normalized);
+    model_catalog::catalog().rebuild();
+
+    Json(ModelIdMappingSaveResponse {
+        success: true,
+        error: None,
+    })
+    .into_response()
+}
+
+// ============================================================================
+// 用量统计处理器
+// ============================================================================
+
+const DEFAULT_USAGE_DAYS: u32 = 7;
+const DEFAULT_USAGE_RECORDS: usize = 100;
+const MAX_USAGE_RECORDS: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    pub days: Option<u32>,
+}
+
+/// GET /manager/api/usage - 用量汇总（按天/模型/账号/密钥）
+pub async fn handle_usage(
+    State(state): State<Arc<ManagerState>>,
+    headers: HeaderMap,
+    Query(q): Query<UsageQuery>,
+) -> Response {
+    let days = q.days.unwrap_or(DEFAULT_USAGE_DAYS);
+    let summary = match state.ledger.summary(days).await {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("读取用量账本失败: {e:#}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": e.to_string() })),
+            )
+                .into_response();
+        }
+    };
+
+    if is_htmx(&headers) {
+        let tmpl = templates::UsageTemplate { summary };
+
+        let mut resp_headers = HeaderMap::new();
+        resp_headers.insert(
+            header::CONTENT_TYPE,
+            "text/html; charset=utf-8".parse().unwrap(),
+        );
+
+        return (resp_headers, Html(tmpl.render().unwrap_or_default())).into_response();
+    }
+
+    Json(summary).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct UsageRecordsQuery {
+    /// YYYY-MM-DD（UTC+8），缺省为今天。
+    pub date: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// GET /manager/api/usage/records - 某天最近的请求记录（按时间倒序）
+pub async fn handle_usage_records(
+    State(state): State<Arc<ManagerState>>,
+    Query(q): Query<UsageRecordsQuery>,
+) -> Response {
+    let date = q
+        .date
+        .unwrap_or_else(|| crate::key_quota::day_key(chrono::Utc::now()));
+    let limit = q
+        .limit
+        .unwrap_or(DEFAULT_USAGE_RECORDS)
+        .min(MAX_USAGE_RECORDS);
+
+    match state.ledger.recent_records(&date, limit).await {
+        Ok(records) => {
+            Json(serde_json::json!({ "date": date, "records": records })).into_response()
+        }
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": e.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
 // ============================================================================
 // 聊天测试处理器
 // ============================================================================
@@ -1042,6 +1349,12 @@ pub struct ChatTestRequest {
     provider: String,
     /// 测试提示词
     prompt: String,
+    /// 模拟客户端系统提示词（可选）
+    #[serde(default)]
+    system_prompt: String,
+    /// 模拟发起请求的 API Key 名称（可选，用于预览按 Key 的系统提示词策略）
+    #[serde(default)]
+    api_key_name: Option<String>,
 }
 
 /// POST /manager/api/chat/test - 聊天测试（SSE 流式输出）
@@ -1108,6 +1421,13 @@ pub async fn handle_chat_test(
     let model_owned = model_mapped;
     let prompt_owned = prompt.to_string();
     let provider_owned = provider.to_string();
+    let client_system_prompt = req.system_prompt.trim().to_string();
+    let api_key_name = req
+        .api_key_name
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
     let started_at_inner = started_at;
 
     // 在后台任务中执行请求
@@ -1161,12 +1481,10 @@ pub async fn handle_chat_test(
         if project_id.is_empty() {
             let mut oauth_cfg = cfg.clone();
             oauth_cfg.api_user_agent = settings.api_user_agent.clone();
-            match oauth::fetch_project_id(&oauth_cfg, &account.access_token).await {
+            match oauth::fetch_project_id(&oauth_cfg, &account.access_token, &account.proxy).await {
                 Ok(pid) if !pid.trim().is_empty() => {
                     project_id = pid.trim().to_string();
-                    let _ = store
-                        .update_project_id_by_session_id(&account.session_id, &project_id)
-                        .await;
+                    let _ = store.update_project_id(&account.id, &project_id).await;
                 }
                 Ok(_) | Err(_) => {}
             }
@@ -1213,6 +1531,8 @@ pub async fn handle_chat_test(
             thinking_config: modelutil::forced_thinking_config(&model_owned),
             image_config: None,
             media_resolution: String::new(),
+            response_mime_type: String::new(),
+            response_schema: None,
         };
 
         if modelutil::is_gemini3(&model_owned)
@@ -1222,6 +1542,50 @@ pub async fn handle_chat_test(
         {
             generation_config.media_resolution = v;
         }
+        virtual_model::apply_generation_overrides(&model_owned, &mut generation_config);
+
+        let mut system_instruction =
+            (!client_system_prompt.is_empty()).then(|| crate::vertex::types::SystemInstruction {
+                role: "user".to_string(),
+                parts: vec![crate::vertex::types::Part {
+                    text: client_system_prompt,
+                    ..crate::vertex::types::Part::default()
+                }],
+            });
+        let injected = system_prompt::apply(
+            &model_owned,
+            api_key_name.as_deref(),
+            &mut system_instruction,
+        );
+
+        // 先把最终生效的系统提示词发给浏览器，便于预览注入策略的效果。
+        let resolved = system_prompt::resolve(&model_owned, api_key_name.as_deref());
+        let final_system_prompt = system_instruction
+            .as_ref()
+            .map(|si| {
+                si.parts
+                    .iter()
+                    .map(|p| p.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+        emit_client_event(
+            &tx,
+            &mut merged_client_events,
+            client_log,
+            raw_log,
+            serde_json::json!({
+                "systemPromptPreview": {
+                    "mode": resolved.mode.as_str(),
+                    "source": resolved.source,
+                    "injected": injected,
+                    "systemInstruction": final_system_prompt,
+                }
+            })
+            .to_string(),
+        )
+        .await;
 
         let vertex_request = crate::vertex::types::Request {
             project: project_id.clone(),
@@ -1237,10 +1601,11 @@ pub async fn handle_chat_test(
                         ..crate::vertex::types::Part::default()
                     }],
                 }],
-                system_instruction: None,
+                system_instruction,
                 generation_config: Some(generation_config),
                 tools: Vec::new(),
                 tool_config: None,
+                safety_settings: Vec::new(),
                 session_id: account.session_id.clone(),
             },
         };
@@ -1279,8 +1644,13 @@ pub async fn handle_chat_test(
             client_builder =
                 client_builder.timeout(std::time::Duration::from_millis(cfg.timeout_ms));
         }
-        if !cfg.proxy.trim().is_empty() {
-            match reqwest::Proxy::all(cfg.proxy.trim()) {
+        // 与正式转发一致：优先使用账号出口代理，避免从共享出口访问该账号。
+        let proxy = match account.proxy.trim() {
+            "" => cfg.proxy.trim(),
+            p => p,
+        };
+        if !proxy.is_empty() {
+            match reqwest::Proxy::all(proxy) {
                 Ok(p) => client_builder = client_builder.proxy(p),
                 Err(e) => {
                     emit_error_event(
@@ -1394,14 +1764,13 @@ pub async fn handle_chat_test(
             if status == StatusCode::FORBIDDEN && body_text.contains("CONSUMER_INVALID") {
                 let mut oauth_cfg = cfg.clone();
                 oauth_cfg.api_user_agent = settings.api_user_agent.clone();
-                if let Ok(pid) = oauth::fetch_project_id(&oauth_cfg, &account.access_token).await
+                if let Ok(pid) =
+                    oauth::fetch_project_id(&oauth_cfg, &account.access_token, &account.proxy).await
                     && !pid.trim().is_empty()
                     && pid.trim() != project_id.as_str()
                 {
                     let new_project_id = pid.trim().to_string();
-                    let _ = store
-                        .update_project_id_by_session_id(&account.session_id, &new_project_id)
-                        .await;
+                    let _ = store.update_project_id(&account.id, &new_project_id).await;
 
                     let mut retry_req = vertex_request.clone();
                     retry_req.project = new_project_id.clone();
@@ -1541,8 +1910,7 @@ pub async fn handle_chat_test(
                 let line = buffer[..pos].to_string();
                 buffer = buffer[pos + 1..].to_string();
 
-                if line.starts_with("data: ") {
-                    let data = &line[6..];
+                if let Some(data) = line.strip_prefix("data: ") {
                     if data == "[DONE]" {
                         continue;
                     }
@@ -1612,22 +1980,22 @@ fn convert_vertex_to_openai_event(vertex_data: &serde_json::Value, model: &str)
         .and_then(|p| p.get("text"))
         .and_then(|t| t.as_str());
 
-    if let Some(text) = text {
-        if !text.is_empty() {
-            let event = serde_json::json!({
-                "id": format!("chatcmpl-{}", id::chat_completion_id()),
-                "object": "chat.completion.chunk",
-                "model": model,
-                "choices": [{
-                    "index": 0,
-                    "delta": {
-                        "content": text
-                    },
-                    "finish_reason": null
-                }]
-            });
-            return Some(event.to_string());
-        }
+    if let Some(text) = text
+        && !text.is_empty()
+    {
+        let event = serde_json::json!({
+            "id": format!("chatcmpl-{}", id::chat_completion_id()),
+            "object": "chat.completion.chunk",
+            "model": model,
+            "choices": [{
+                "index": 0,
+                "delta": {
+                    "content": text
+                },
+                "finish_reason": null
+            }]
+        });
+        return Some(event.to_string());
     }
 
     None
@@ -1647,19 +2015,19 @@ fn convert_vertex_to_claude_event(vertex_data: &serde_json::Value, _model: &str)
         .and_then(|p| p.get("text"))
         .and_then(|t| t.as_str());
 
-    if let Some(text) = text {
-        if !text.is_empty() {
-            // 构造 Claude 格式的 content_block_delta 事件
-            let event = serde_json::json!({
-                "type": "content_block_delta",
-                "index": 0,
-                "delta": {
-                    "type": "text_delta",
-                    "text": text
-                }
-            });
-            return Some(event.to_string());
-        }
+    if let Some(text) = text
+        && !text.is_empty()
+    {
+        // 构造 Claude 格式的 content_block_delta 事件
+        let event = serde_json::json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": {
+                "type": "text_delta",
+                "text": text
+            }
+        });
+        return Some(event.to_string());
     }
 
     None
@@ -1705,24 +2073,40 @@ fn chat_test_error(log_level: logging::LogLevel, started_at: Instant, msg: &str)
 use axum::extract::Request;
 use axum::middleware::Next;
 
-/// Manager 认证中间件
-pub async fn manager_auth_middleware(headers: HeaderMap, request: Request, next: Next) -> Response {
-    // 检查是否已认证
-    if is_authenticated(&headers) {
-        return next.run(request).await;
-    }
-
+/// Manager 认证中间件：校验会话，写操作额外校验 CSRF 令牌
+pub async fn manager_auth_middleware(
+    State(state): State<Arc<ManagerState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
     let path = request.uri().path();
+    let is_api = path.starts_with("/manager/api");
+
+    let Some(session) = current_session(&state, request.headers()) else {
+        // API 路径返回 401
+        if is_api {
+            return (
+                StatusCode::UNAUTHORIZED,
+                "未登录或会话已过期，请先登录管理面板",
+            )
+                .into_response();
+        }
 
-    // API 路径返回 401
-    if path.starts_with("/manager/api") {
-        return (
-            StatusCode::UNAUTHORIZED,
-            "未登录或会话已过期，请先登录管理面板",
-        )
-            .into_response();
+        // 其他路径重定向到登录页
+        return Redirect::to("/login").into_response();
+    };
+
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        let csrf_ok = request
+            .headers()
+            .get(CSRF_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| constant_time_eq(v, &session.csrf_token));
+        if !csrf_ok {
+            return (StatusCode::FORBIDDEN, "CSRF 校验失败，请刷新页面后重试").into_response();
+        }
     }
 
-    // 其他路径重定向到登录页
-    Redirect::to("/login").into_response()
+    request.extensions_mut().insert(session);
+    next.run(request).await
 }
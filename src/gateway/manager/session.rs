@@ -0,0 +1,200 @@
+//! Manager WebUI 会话与登录限流。
+//!
+//! - 会话令牌为服务端随机生成并保存在内存中，进程重启或修改密码后旧 Cookie 全部失效
+//! - 每个会话附带一个 CSRF 令牌，POST 等写操作需通过 `X-CSRF-Token` 头回传
+//! - 登录失败按客户端 IP 计数，超过阈值后在一段时间内拒绝尝试
+//!
+//! 注意：限流使用 TCP 直连对端地址，不信任 `X-Forwarded-For`（可被客户端伪造）。
+//! 部署在反向代理之后时，所有登录请求的对端都是代理地址，共享同一个计数：
+//! 任一来源连续输错密码都会让全部管理员在锁定期内无法登录。此类部署应在代理层
+//! 另行限制 `/login` 的访问频率（或仅在内网暴露管理面板）。
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 会话有效期。
+pub const SESSION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// 统计登录失败的时间窗口。
+const LOGIN_FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+/// 时间窗口内允许的最大失败次数。
+const MAX_LOGIN_FAILURES: u32 = 5;
+/// 超过阈值后的锁定时长。
+const LOGIN_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+/// 已登录的会话（经认证中间件校验后放入请求 extensions）。
+#[derive(Debug, Clone)]
+pub struct ManagerSession {
+    pub token: String,
+    pub csrf_token: String,
+    expires_at: Instant,
+}
+
+/// 内存会话表。
+#[derive(Debug, Default)]
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, ManagerSession>>,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, ManagerSession>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 创建新会话。
+    pub fn create(&self) -> ManagerSession {
+        let now = Instant::now();
+        let session = ManagerSession {
+            token: random_token(),
+            csrf_token: random_token(),
+            expires_at: now + SESSION_TTL,
+        };
+        let mut sessions = self.lock();
+        sessions.retain(|_, s| s.expires_at > now);
+        sessions.insert(session.token.clone(), session.clone());
+        session
+    }
+
+    /// 查找未过期的会话。
+    pub fn get(&self, token: &str) -> Option<ManagerSession> {
+        if token.is_empty() {
+            return None;
+        }
+        let mut sessions = self.lock();
+        let session = sessions.get(token)?;
+        if session.expires_at <= Instant::now() {
+            sessions.remove(token);
+            return None;
+        }
+        Some(session.clone())
+    }
+
+    /// 注销单个会话。
+    pub fn revoke(&self, token: &str) {
+        self.lock().remove(token);
+    }
+
+    /// 注销除 `keep` 以外的全部会话（修改密码时使用）。
+    pub fn revoke_all_except(&self, keep: Option<&str>) -> usize {
+        let mut sessions = self.lock();
+        let before = sessions.len();
+        sessions.retain(|token, _| Some(token.as_str()) == keep);
+        before - sessions.len()
+    }
+}
+
+/// 两个 UUIDv4（系统 CSPRNG）拼接，约 244 位随机量。
+fn random_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FailureState {
+    count: u32,
+    window_start: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// 登录失败限流（按 TCP 对端 IP；反向代理之后所有客户端共享代理地址的计数，见模块文档）。
+#[derive(Debug, Default)]
+pub struct LoginThrottle {
+    failures: Mutex<HashMap<IpAddr, FailureState>>,
+}
+
+impl LoginThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<IpAddr, FailureState>> {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 若该 IP 处于锁定期，返回剩余锁定时长。
+    pub fn locked_for(&self, ip: IpAddr) -> Option<Duration> {
+        let now = Instant::now();
+        let failures = self.lock();
+        let until = failures.get(&ip)?.locked_until?;
+        until.checked_duration_since(now).filter(|d| !d.is_zero())
+    }
+
+    /// 记录一次失败；达到阈值时进入锁定。
+    pub fn record_failure(&self, ip: IpAddr) {
+        let now = Instant::now();
+        let mut failures = self.lock();
+        failures.retain(|_, s| {
+            now.duration_since(s.window_start) < LOGIN_FAILURE_WINDOW
+                || s.locked_until.is_some_and(|t| t > now)
+        });
+        let state = failures.entry(ip).or_insert(FailureState {
+            count: 0,
+            window_start: now,
+            locked_until: None,
+        });
+        if now.duration_since(state.window_start) >= LOGIN_FAILURE_WINDOW {
+            *state = FailureState {
+                count: 0,
+                window_start: now,
+                locked_until: None,
+            };
+        }
+        state.count += 1;
+        if state.count >= MAX_LOGIN_FAILURES {
+            state.locked_until = Some(now + LOGIN_LOCKOUT);
+        }
+    }
+
+    /// 登录成功后清除失败计数。
+    pub fn reset(&self, ip: IpAddr) {
+        self.lock().remove(&ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_are_random_and_revocable() {
+        let store = SessionStore::new();
+        let a = store.create();
+        let b = store.create();
+        assert_ne!(a.token, b.token);
+        assert_ne!(a.token, a.csrf_token);
+        assert_eq!(store.get(&a.token).unwrap().csrf_token, a.csrf_token);
+        assert!(store.get("authenticated").is_none());
+
+        assert_eq!(store.revoke_all_except(Some(&b.token)), 1);
+        assert!(store.get(&a.token).is_none());
+        assert!(store.get(&b.token).is_some());
+
+        store.revoke(&b.token);
+        assert!(store.get(&b.token).is_none());
+    }
+
+    #[test]
+    fn login_is_locked_after_repeated_failures() {
+        let throttle = LoginThrottle::new();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+        for _ in 0..MAX_LOGIN_FAILURES - 1 {
+            throttle.record_failure(ip);
+        }
+        assert!(throttle.locked_for(ip).is_none());
+        throttle.record_failure(ip);
+        assert!(throttle.locked_for(ip).is_some());
+        assert!(throttle.locked_for(other).is_none());
+
+        throttle.reset(ip);
+        assert!(throttle.locked_for(ip).is_none());
+    }
+}
@@ -6,8 +6,13 @@ use askama::Template;
 use chrono::DateTime;
 
 use crate::credential::types::Account;
+use crate::ledger::{UsageRow, UsageSummary};
 use crate::quota_pool::QuotaGroup;
-use crate::runtime_config::WebUISettings;
+use crate::runtime_config::{
+    ENDPOINT_MODE_AUTOPUSH, ENDPOINT_MODE_DAILY, ENDPOINT_MODE_FAILOVER, ENDPOINT_MODE_PRODUCTION,
+    ENDPOINT_MODE_ROUND_ROBIN, ENDPOINT_MODE_ROUND_ROBIN_DP, WebUISettings,
+};
+use crate::vertex::endpoints::EndpointStatus;
 
 /// 中国时区 (UTC+8)
 fn china_tz() -> chrono::FixedOffset {
@@ -107,6 +112,8 @@ pub struct ViewQuotaGroup {
     pub label: String,
     pub remaining_fraction: Option<f64>,
     pub reset_time: Option<String>,
+    /// 请求限流冷却截止时间
+    pub cooldown_until: Option<String>,
 }
 
 impl ViewQuotaGroup {
@@ -115,6 +122,22 @@ impl ViewQuotaGroup {
             label: g.group_name.clone(),
             remaining_fraction: g.remaining_fraction,
             reset_time: g.reset_time.clone(),
+            cooldown_until: g.cooldown_until.clone(),
+        }
+    }
+
+    pub fn is_cooling(&self) -> bool {
+        self.cooldown_until.is_some()
+    }
+
+    /// 冷却截止时间（HH:MM:SS，UTC+8）。
+    pub fn format_cooldown_until(&self) -> String {
+        let Some(until) = self.cooldown_until.as_deref() else {
+            return String::new();
+        };
+        match DateTime::parse_from_rfc3339(until) {
+            Ok(dt) => dt.with_timezone(&china_tz()).format("%H:%M:%S").to_string(),
+            Err(_) => until.to_string(),
         }
     }
 
@@ -138,11 +161,15 @@ impl ViewQuotaGroup {
 /// 视图用的账号（预计算状态）
 #[derive(Debug, Clone)]
 pub struct ViewAccount {
+    /// 持久化账号 ID（管理操作使用）
+    pub id: String,
     pub session_id: String,
     pub display_name: String,
     pub enable: bool,
     pub is_expired: bool,
     pub status: AccountStatus,
+    /// 账号出口代理（为空表示使用全局代理）
+    pub proxy: String,
 }
 
 /// 账号状态
@@ -175,11 +202,13 @@ impl ViewAccount {
         };
 
         Self {
+            id: acc.id.clone(),
             session_id: acc.session_id.clone(),
             display_name,
             enable: acc.enable,
             is_expired,
             status,
+            proxy: acc.proxy.clone(),
         }
     }
 
@@ -214,6 +243,8 @@ pub struct LoginTemplate {
 pub struct DashboardTemplate {
     pub accounts: Vec<ViewAccount>,
     pub stats: Stats,
+    /// 当前会话的 CSRF 令牌（写操作通过 X-CSRF-Token 头回传）
+    pub csrf_token: String,
 }
 
 /// 统计卡片片段
@@ -266,6 +297,46 @@ pub struct QuotaSkeletonTemplate;
 #[template(path = "fragments/settings.html")]
 pub struct SettingsTemplate {
     pub settings: WebUISettings,
+    /// 命名 API Key（格式化 JSON，供设置页编辑）
+    pub api_keys_json: String,
+    /// 系统提示词注入策略（格式化 JSON，供设置页编辑）
+    pub system_prompt_policy_json: String,
+    /// 当前端点模式下各后端主机的健康状态
+    pub endpoint_statuses: Vec<EndpointStatus>,
+}
+
+impl SettingsTemplate {
+    /// 端点模式选项（值，标题，说明）。
+    pub fn endpoint_modes(&self) -> Vec<(&'static str, &'static str, &'static str)> {
+        vec![
+            (
+                ENDPOINT_MODE_PRODUCTION,
+                "production",
+                "cloudcode-pa（默认）",
+            ),
+            (
+                ENDPOINT_MODE_DAILY,
+                "daily",
+                "daily-cloudcode-pa（Sandbox）",
+            ),
+            (
+                ENDPOINT_MODE_AUTOPUSH,
+                "autopush",
+                "autopush-cloudcode-pa（Sandbox）",
+            ),
+            (ENDPOINT_MODE_ROUND_ROBIN, "round-robin", "三个地址轮询"),
+            (
+                ENDPOINT_MODE_ROUND_ROBIN_DP,
+                "round-robin-dp",
+                "daily 与 production 轮询",
+            ),
+            (
+                ENDPOINT_MODE_FAILOVER,
+                "failover",
+                "优先 production，故障时切换",
+            ),
+        ]
+    }
 }
 
 /// 模型设置页面片段（聊天测试 UI）
@@ -274,3 +345,22 @@ pub struct SettingsTemplate {
 pub struct ModelSettingsTemplate {
     pub accounts: Vec<ViewAccount>,
 }
+
+/// 用量统计页面片段
+#[derive(Template)]
+#[template(path = "fragments/usage.html")]
+pub struct UsageTemplate {
+    pub summary: UsageSummary,
+}
+
+impl UsageTemplate {
+    /// 分组表格（标题，行）。
+    pub fn sections(&self) -> Vec<(&'static str, &[UsageRow])> {
+        vec![
+            ("按日期", self.summary.by_day.as_slice()),
+            ("按模型", self.summary.by_model.as_slice()),
+            ("按账号", self.summary.by_account.as_slice()),
+            ("按密钥", self.summary.by_key.as_slice()),
+        ]
+    }
+}
@@ -0,0 +1,69 @@
+//! GET /metrics：Prometheus 文本格式导出。
+
+use crate::gateway::claude::ClaudeState;
+use crate::metrics::{self, escape, write_header};
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+pub async fn handle_metrics(State(state): State<Arc<ClaudeState>>) -> Response {
+    let mut out = String::with_capacity(4096);
+    metrics::render(&mut out);
+
+    let total = state.store.count().await;
+    let enabled = state.store.enabled_count().await;
+    write_header(&mut out, "ant2api_accounts", "gauge", "账号数量");
+    let _ = writeln!(out, "ant2api_accounts{{state=\"enabled\"}} {enabled}");
+    let _ = writeln!(
+        out,
+        "ant2api_accounts{{state=\"disabled\"}} {}",
+        total.saturating_sub(enabled)
+    );
+
+    let pools = state.quota_pool.pool_stats().await;
+    write_header(
+        &mut out,
+        "ant2api_quota_pool_accounts",
+        "gauge",
+        "配额池账号数（active/cooldown）",
+    );
+    for p in &pools {
+        let name = escape(&p.name);
+        let _ = writeln!(
+            out,
+            "ant2api_quota_pool_accounts{{pool=\"{name}\",state=\"active\"}} {}",
+            p.active
+        );
+        let _ = writeln!(
+            out,
+            "ant2api_quota_pool_accounts{{pool=\"{name}\",state=\"cooldown\"}} {}",
+            p.cooldown
+        );
+    }
+    write_header(
+        &mut out,
+        "ant2api_quota_pool_remaining_fraction",
+        "gauge",
+        "配额池 active 账号剩余配额比例之和",
+    );
+    for p in &pools {
+        let _ = writeln!(
+            out,
+            "ant2api_quota_pool_remaining_fraction{{pool=\"{}\"}} {}",
+            escape(&p.name),
+            p.remaining_fraction_sum
+        );
+    }
+
+    (
+        StatusCode::OK,
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        out,
+    )
+        .into_response()
+}
@@ -1,4 +1,6 @@
 pub mod claude;
 pub mod common;
+pub mod gemini;
 pub mod manager;
+pub mod metrics;
 pub mod openai;
@@ -1,15 +1,16 @@
 use crate::config::Config;
 use crate::gateway::common::extract::{extract_system_from_messages, extract_text_from_content};
+use crate::gateway::common::media::{self, image_signature};
+use crate::gateway::common::model_catalog::ModelInfo;
+use crate::gateway::common::output_limit;
 use crate::gateway::common::{AccountContext, find_function_name};
 use crate::signature::manager::Manager as SignatureManager;
 use crate::signature::types::FALLBACK_SIGNATURE;
-use crate::util::{id, model as modelutil};
-use crate::vertex::sanitize::{
-    inject_agent_system_prompt, sanitize_contents, sanitize_function_parameters_schema,
-};
+use crate::util::{id, model as modelutil, system_prompt, virtual_model};
+use crate::vertex::sanitize::{sanitize_contents, sanitize_function_parameters_schema};
 use crate::vertex::types::{
     Content, FunctionCall as VFunctionCall, FunctionCallingConfig, FunctionDeclaration,
-    FunctionResponse, GenerationConfig, ImageConfig, InnerReq, Part, Request, SystemInstruction,
+    FunctionResponse, GenerationConfig, InnerReq, Part, Request, StreamResult, SystemInstruction,
     Tool as VTool, ToolConfig, UsageMetadata,
 };
 use chrono::Utc;
@@ -17,9 +18,14 @@ use sonic_rs::prelude::*;
 use std::collections::HashMap;
 
 use super::types::{
-    ChatCompletion, ChatRequest, Choice, Message, ModelItem, ModelsResponse, Tool, ToolCall, Usage,
+    ChatCompletion, ChatRequest, Choice, CompletionTokensDetails, Message, ModelCapabilities,
+    ModelItem, ModelsResponse, PromptTokensDetails, Tool, ToolCall, Usage,
 };
 
+/// Claude 等无法使用 responseSchema 的场景下，用于模拟结构化输出的保留工具名；
+/// 模型对它的调用参数会作为消息正文返回，而不是 tool_calls。
+pub const STRUCTURED_OUTPUT_TOOL: &str = "structured_output";
+
 pub async fn to_vertex_request(
     cfg: &Config,
     sig_mgr: &SignatureManager,
@@ -27,9 +33,6 @@ pub async fn to_vertex_request(
     account: &AccountContext,
 ) -> anyhow::Result<(Request, String)> {
     let model_name = req.model.clone();
-    let model = req.model.trim();
-    let is_image_model = modelutil::is_image_model(model);
-    let is_gemini3_flash = modelutil::is_gemini3_flash(model);
 
     let request_id = id::request_id();
     let vertex_model = modelutil::backend_model_id(&model_name);
@@ -46,6 +49,7 @@ pub async fn to_vertex_request(
             generation_config: None,
             tools: Vec::new(),
             tool_config: None,
+            safety_settings: Vec::new(),
             session_id: account.session_id.clone(),
         },
     };
@@ -63,28 +67,25 @@ pub async fn to_vertex_request(
 
     if !req.tools.is_empty() {
         vreq.request.tools = to_vertex_tools(&req.tools);
-        vreq.request.tool_config = Some(ToolConfig {
-            function_calling_config: Some(FunctionCallingConfig {
-                mode: "AUTO".to_string(),
-                allowed_function_names: Vec::new(),
-            }),
-        });
+        vreq.request.tool_config = Some(to_tool_config(req.tool_choice.as_ref()));
     }
 
     vreq.request.generation_config = Some(build_generation_config(cfg, req));
-    let contents = to_vertex_contents(req, sig_mgr).await?;
+    apply_response_format(&mut vreq.request, req);
+    let contents = to_vertex_contents(cfg, req, sig_mgr).await?;
     vreq.request.contents = sanitize_contents(contents);
 
-    let should_skip_system_prompt = is_image_model || is_gemini3_flash;
-    if !should_skip_system_prompt {
-        let sys = vreq.request.system_instruction.take();
-        vreq.request.system_instruction = Some(inject_agent_system_prompt(sys));
-    }
+    system_prompt::apply(
+        &model_name,
+        account.api_key_name.as_deref(),
+        &mut vreq.request.system_instruction,
+    );
 
     Ok((vreq, request_id))
 }
 
 async fn to_vertex_contents(
+    cfg: &Config,
     req: &mut ChatRequest,
     sig_mgr: &SignatureManager,
 ) -> anyhow::Result<Vec<Content>> {
@@ -108,7 +109,7 @@ async fn to_vertex_contents(
             "system" => continue,
             "user" => {
                 let parts =
-                    extract_user_parts(&mut m.content, sig_mgr, is_gemini_pro_image).await?;
+                    extract_user_parts(cfg, &mut m.content, sig_mgr, is_gemini_pro_image).await?;
                 out.push(Content {
                     role: "user".to_string(),
                     parts,
@@ -331,6 +332,7 @@ async fn merge_tool_only_assistant_messages(req: &mut ChatRequest, sig_mgr: &Sig
 }
 
 async fn extract_user_parts(
+    cfg: &Config,
     content: &mut sonic_rs::Value,
     sig_mgr: &SignatureManager,
     is_gemini_pro_image: bool,
@@ -371,25 +373,17 @@ async fn extract_user_parts(
                     continue;
                 };
                 let url = img.get(&"url").and_then(|v| v.as_str()).unwrap_or("");
-                let Some(inline) = parse_image_url(url) else {
+                if url.trim().is_empty() {
                     continue;
-                };
+                }
+                let inline = media::resolve_url(cfg, url)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("image_url 无法转换: {e:#}"))?;
 
-                let image_key = image_signature_key(&inline, is_gemini_pro_image);
-                let sig = if image_key.is_empty() {
-                    String::new()
+                let sig = if inline.mime_type.starts_with("image/") {
+                    image_signature(sig_mgr, &inline, is_gemini_pro_image).await
                 } else {
-                    sig_mgr
-                        .lookup_by_image_key_strict(&image_key)
-                        .await
-                        .map(|e| e.signature)
-                        .unwrap_or_else(|| {
-                            if is_gemini_pro_image {
-                                FALLBACK_SIGNATURE.to_string()
-                            } else {
-                                String::new()
-                            }
-                        })
+                    String::new()
                 };
 
                 out.push(Part {
@@ -403,6 +397,42 @@ async fn extract_user_parts(
                     *v = sonic_rs::to_value("").unwrap_or_default();
                 }
             }
+            "file" => {
+                let file = obj.get(&"file").and_then(|v| v.as_object());
+                let field = |k: &str| {
+                    file.and_then(|f| f.get(&k))
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("")
+                        .trim()
+                };
+                let inline =
+                    file_inline_data(field("file_data"), field("filename"), field("file_id"))
+                        .map_err(|e| anyhow::anyhow!("file 无法转换: {e:#}"))?;
+                out.push(Part {
+                    inline_data: Some(inline),
+                    ..Part::default()
+                });
+            }
+            "input_audio" => {
+                let audio = obj.get(&"input_audio").and_then(|v| v.as_object());
+                let field = |k: &str| {
+                    audio
+                        .and_then(|a| a.get(&k))
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("")
+                        .trim()
+                };
+                let (data, format) = (field("data"), field("format"));
+                if data.is_empty() {
+                    anyhow::bail!("input_audio 缺少 data");
+                }
+                let mime_type = media::normalize_mime(&format!("audio/{format}"))
+                    .map_err(|e| anyhow::anyhow!("input_audio 无法转换: {e:#}"))?;
+                out.push(Part {
+                    inline_data: Some(crate::vertex::types::InlineData::new(mime_type, data)),
+                    ..Part::default()
+                });
+            }
             _ => {}
         }
     }
@@ -412,8 +442,6 @@ async fn extract_user_parts(
 
 fn build_generation_config(cfg: &Config, req: &ChatRequest) -> GenerationConfig {
     let model = req.model.trim();
-    let is_claude = modelutil::is_claude(model);
-    let is_gemini = modelutil::is_gemini(model);
     let is_image_model = modelutil::is_image_model(model);
 
     let mut out = GenerationConfig {
@@ -426,14 +454,19 @@ fn build_generation_config(cfg: &Config, req: &ChatRequest) -> GenerationConfig
         thinking_config: None,
         image_config: None,
         media_resolution: String::new(),
+        response_mime_type: String::new(),
+        response_schema: None,
     };
 
-    // Gemini：maxOutputTokens 固定为 65535。
-    if is_gemini {
-        out.max_output_tokens = modelutil::GEMINI_MAX_OUTPUT_TOKENS;
-    } else if req.max_tokens > 0 && !is_claude {
-        out.max_output_tokens = req.max_tokens;
-    }
+    out.stop_sequences = req.stop.iter().filter(|s| !s.is_empty()).cloned().collect();
+
+    // maxOutputTokens：按 MAX_TOKENS_POLICY 处理客户端 max_completion_tokens / max_tokens。
+    let requested = if req.max_completion_tokens > 0 {
+        req.max_completion_tokens
+    } else {
+        req.max_tokens
+    };
+    out.max_output_tokens = output_limit::resolve_max_output_tokens(cfg, model, requested);
 
     if let Some(v) = req.temperature {
         out.temperature = Some(v);
@@ -446,46 +479,8 @@ fn build_generation_config(cfg: &Config, req: &ChatRequest) -> GenerationConfig
         out.thinking_config = Some(tc);
     }
 
-    // Claude：maxOutputTokens 固定为 64000。
-    if is_claude {
-        out.max_output_tokens = modelutil::CLAUDE_MAX_OUTPUT_TOKENS;
-    }
-
-    // 当使用 thinkingBudget 时，确保与 maxOutputTokens 兼容（与 Go 行为一致）。
-    if let Some(tc) = out.thinking_config.as_mut()
-        && tc.thinking_budget > 0
-    {
-        if out.max_output_tokens <= 0 {
-            out.max_output_tokens =
-                tc.thinking_budget + modelutil::THINKING_MAX_OUTPUT_TOKENS_OVERHEAD_TOKENS;
-        }
-        if is_claude {
-            let mut max_budget = out.max_output_tokens - modelutil::THINKING_BUDGET_HEADROOM_TOKENS;
-            if max_budget < modelutil::THINKING_BUDGET_MIN_TOKENS {
-                max_budget = modelutil::THINKING_BUDGET_MIN_TOKENS;
-            }
-            if tc.thinking_budget > max_budget {
-                tc.thinking_budget = max_budget;
-            }
-        } else if is_gemini && out.max_output_tokens <= tc.thinking_budget {
-            let mut max_budget = out.max_output_tokens - modelutil::THINKING_BUDGET_HEADROOM_TOKENS;
-            if max_budget < modelutil::THINKING_BUDGET_MIN_TOKENS {
-                max_budget = modelutil::THINKING_BUDGET_MIN_TOKENS;
-            }
-            tc.thinking_budget = max_budget;
-        } else if out.max_output_tokens <= tc.thinking_budget {
-            out.max_output_tokens =
-                tc.thinking_budget + modelutil::THINKING_MAX_OUTPUT_TOKENS_OVERHEAD_TOKENS;
-        }
-    }
-
-    // Gemini image size 虚拟模型：由 modelName 强制 imageConfig.imageSize。
-    if let Some((image_size, _backend)) = modelutil::gemini_pro_image_size_config(model) {
-        out.image_config = Some(ImageConfig {
-            aspect_ratio: String::new(),
-            image_size,
-        });
-    }
+    // thinkingBudget 需小于 maxOutputTokens。
+    output_limit::fit_thinking_budget(&mut out, model);
 
     // Gemini 3：应用全局 mediaResolution（非 image 模型）。
     if modelutil::is_gemini3(model)
@@ -496,9 +491,158 @@ fn build_generation_config(cfg: &Config, req: &ChatRequest) -> GenerationConfig
         out.media_resolution = v;
     }
 
+    // 虚拟模型：强制图片尺寸/宽高比、mediaResolution、temperature。
+    virtual_model::apply_generation_overrides(model, &mut out);
+
     out
 }
 
+/// `response_format` -> 结构化输出：
+/// - Gemini（无工具）：responseMimeType=application/json，json_schema 经清洗后作为 responseSchema
+/// - Claude，或 Gemini 同时带工具（后端不支持 JSON MIME 与函数调用并用）：
+///   追加保留工具 `structured_output`，参数即为 JSON 结果；与客户端 tool_choice 合并：
+///   无工具或 required 时强制调用，指定函数时加入 allowedFunctionNames，
+///   auto（或 Claude thinking 不允许强制调用）时保持 AUTO 并在 system 中提示，none 时不追加
+fn apply_response_format(inner: &mut InnerReq, req: &ChatRequest) {
+    let Some(rf) = req.response_format.as_ref() else {
+        return;
+    };
+    let json_schema = match rf.typ.as_str() {
+        "json_object" => None,
+        "json_schema" => rf.json_schema.as_ref(),
+        _ => return,
+    };
+    let schema = json_schema
+        .map(|s| sanitize_function_parameters_schema(&s.schema))
+        .filter(|s| !s.is_empty());
+
+    let model = req.model.trim();
+    let client = inner
+        .tool_config
+        .as_ref()
+        .and_then(|tc| tc.function_calling_config.clone());
+    if client.as_ref().is_some_and(|fc| fc.mode == "NONE") {
+        return;
+    }
+    if !modelutil::is_claude(model) && req.tools.is_empty() {
+        if let Some(gc) = inner.generation_config.as_mut() {
+            gc.response_mime_type = "application/json".to_string();
+            gc.response_schema = schema;
+        }
+        return;
+    }
+
+    let mut description =
+        "Return the final answer by calling this tool; its arguments are the JSON response."
+            .to_string();
+    if let Some(s) = json_schema {
+        for extra in [s.name.trim(), s.description.trim()] {
+            if !extra.is_empty() {
+                description.push(' ');
+                description.push_str(extra);
+            }
+        }
+    }
+    let parameters = schema.unwrap_or_else(|| {
+        HashMap::from([(
+            "type".to_string(),
+            sonic_rs::to_value("object").unwrap_or_default(),
+        )])
+    });
+    let decl = FunctionDeclaration {
+        name: STRUCTURED_OUTPUT_TOOL.to_string(),
+        description,
+        parameters: Some(parameters),
+    };
+    match inner.tools.first_mut() {
+        Some(t) => t.function_declarations.push(decl),
+        None => inner.tools.push(VTool {
+            function_declarations: vec![decl],
+        }),
+    }
+
+    // Claude 开启 thinking 时不允许强制工具调用：退回 AUTO，并在 system 中提示。
+    let thinking = modelutil::is_claude_thinking(model)
+        || inner
+            .generation_config
+            .as_ref()
+            .and_then(|gc| gc.thinking_config.as_ref())
+            .is_some_and(|tc| tc.thinking_budget > 0);
+    let forced = match client {
+        _ if modelutil::is_claude(model) && thinking => None,
+        // 无用户工具时只允许结构化输出工具。
+        None => Some(vec![STRUCTURED_OUTPUT_TOOL.to_string()]),
+        Some(fc) if fc.mode == "ANY" => {
+            // required：任一工具均可（用户工具照常返回 tool_calls）；指定函数：在其基础上允许结构化输出。
+            let mut names = fc.allowed_function_names;
+            if !names.is_empty() {
+                names.push(STRUCTURED_OUTPUT_TOOL.to_string());
+            }
+            Some(names)
+        }
+        Some(_) => None,
+    };
+
+    let Some(allowed_function_names) = forced else {
+        inner.tool_config = Some(to_tool_config(None));
+        let hint = Part {
+            text: format!(
+                "Always finish by calling the `{STRUCTURED_OUTPUT_TOOL}` tool with the final answer."
+            ),
+            ..Part::default()
+        };
+        match inner.system_instruction.as_mut() {
+            Some(sys) => sys.parts.push(hint),
+            None => {
+                inner.system_instruction = Some(SystemInstruction {
+                    role: "user".to_string(),
+                    parts: vec![hint],
+                })
+            }
+        }
+        return;
+    };
+    inner.tool_config = Some(ToolConfig {
+        function_calling_config: Some(FunctionCallingConfig {
+            mode: "ANY".to_string(),
+            allowed_function_names,
+        }),
+    });
+}
+
+/// OpenAI tool_choice -> functionCallingConfig：
+/// - "none" / "auto" / "required" -> NONE / AUTO / ANY
+/// - {"type":"function","function":{"name":..}} -> ANY + allowedFunctionNames
+/// - 缺省或无法识别时为 AUTO
+fn to_tool_config(tool_choice: Option<&sonic_rs::Value>) -> ToolConfig {
+    let mut mode = "AUTO";
+    let mut allowed_function_names = Vec::new();
+    if let Some(tc) = tool_choice {
+        if let Some(s) = tc.as_str() {
+            mode = match s.trim().to_ascii_lowercase().as_str() {
+                "none" => "NONE",
+                "required" => "ANY",
+                _ => "AUTO",
+            };
+        } else if let Some(name) = tc
+            .get("function")
+            .and_then(|f| f.get("name"))
+            .and_then(|n| n.as_str())
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+        {
+            mode = "ANY";
+            allowed_function_names.push(name.to_string());
+        }
+    }
+    ToolConfig {
+        function_calling_config: Some(FunctionCallingConfig {
+            mode: mode.to_string(),
+            allowed_function_names,
+        }),
+    }
+}
+
 fn to_vertex_tools(tools: &[Tool]) -> Vec<VTool> {
     let mut out: Vec<VTool> = Vec::with_capacity(tools.len());
     for t in tools {
@@ -546,22 +690,7 @@ async fn parse_markdown_images(
         let Some(inline) = match_markdown_inline_data(&m.mime_type, &m.data) else {
             continue;
         };
-        let image_key = image_signature_key(&inline, is_gemini_pro_image);
-        let sig = if image_key.is_empty() {
-            String::new()
-        } else {
-            sig_mgr
-                .lookup_by_image_key_strict(&image_key)
-                .await
-                .map(|e| e.signature)
-                .unwrap_or_else(|| {
-                    if is_gemini_pro_image {
-                        FALLBACK_SIGNATURE.to_string()
-                    } else {
-                        String::new()
-                    }
-                })
-        };
+        let sig = image_signature(sig_mgr, &inline, is_gemini_pro_image).await;
         out.push(MarkdownImage {
             inline,
             signature: sig,
@@ -632,41 +761,28 @@ fn match_markdown_inline_data(
     ))
 }
 
-fn parse_image_url(url: &str) -> Option<crate::vertex::types::InlineData> {
-    const DATA_PREFIX: &str = "data:";
-    const BASE64_MARK: &str = ";base64,";
-    if !url.starts_with(DATA_PREFIX) || !url.starts_with("data:image/") {
-        return None;
-    }
-    let marker = url.find(BASE64_MARK)?;
-    if marker < DATA_PREFIX.len() {
-        return None;
-    }
-    let mime_type = &url[DATA_PREFIX.len()..marker];
-    let base64_data = &url[(marker + BASE64_MARK.len())..];
-    if mime_type.is_empty() || base64_data.is_empty() {
-        return None;
-    }
-    Some(crate::vertex::types::InlineData::new(
-        mime_type.to_string(),
-        base64_data.to_string(),
-    ))
-}
-
-fn image_signature_key(
-    inline: &crate::vertex::types::InlineData,
-    is_gemini_pro_image: bool,
-) -> String {
-    if !is_gemini_pro_image {
-        return inline.signature_key();
+/// `file` part：file_data 可为 data URL 或裸 base64（此时按文件名推断 MIME）；不支持 file_id。
+fn file_inline_data(
+    file_data: &str,
+    filename: &str,
+    file_id: &str,
+) -> anyhow::Result<crate::vertex::types::InlineData> {
+    if file_data.is_empty() {
+        if !file_id.is_empty() {
+            anyhow::bail!("不支持 file_id 引用，请改用 file_data 内联文件内容");
+        }
+        anyhow::bail!("缺少 file_data");
     }
-
-    let s = inline.data.as_str();
-    if s.is_empty() {
-        return String::new();
+    if let Some(inline) = media::parse_data_url(file_data) {
+        let mime_type = media::normalize_mime(&inline.mime_type)?;
+        return Ok(crate::vertex::types::InlineData::new(
+            mime_type,
+            inline.data.as_str(),
+        ));
     }
-    let n = s.len().min(100);
-    s[..n].to_string()
+    let mime_type = media::mime_from_path(filename)
+        .ok_or_else(|| anyhow::anyhow!("无法根据文件名识别类型: {filename}"))?;
+    Ok(crate::vertex::types::InlineData::new(mime_type, file_data))
 }
 
 fn parse_args(args: &str) -> HashMap<String, sonic_rs::Value> {
@@ -700,8 +816,14 @@ pub fn convert_usage(metadata: Option<&UsageMetadata>) -> Option<Usage> {
     let m = metadata?;
     Some(Usage {
         prompt_tokens: m.prompt_token_count,
-        completion_tokens: m.candidates_token_count,
+        completion_tokens: m.candidates_token_count + m.thoughts_token_count,
         total_tokens: m.total_token_count,
+        prompt_tokens_details: Some(PromptTokensDetails {
+            cached_tokens: m.cached_content_token_count,
+        }),
+        completion_tokens_details: Some(CompletionTokensDetails {
+            reasoning_tokens: m.thoughts_token_count,
+        }),
     })
 }
 
@@ -761,7 +883,7 @@ pub async fn to_chat_completion(
             continue;
         }
         if let Some(inline) = &p.inline_data {
-            let image_key = image_signature_key(inline, is_gemini_pro_image);
+            let image_key = media::image_signature_key(inline, is_gemini_pro_image);
             if !p.thought_signature.is_empty() {
                 sig_mgr
                     .save_image_key(
@@ -784,6 +906,11 @@ pub async fn to_chat_completion(
             continue;
         }
         if let Some(fc) = &p.function_call {
+            if fc.name == STRUCTURED_OUTPUT_TOOL {
+                content
+                    .push_str(&sonic_rs::to_string(&fc.args).unwrap_or_else(|_| "{}".to_string()));
+                continue;
+            }
             let tool_call_id = if fc.id.is_empty() {
                 id::tool_call_id()
             } else {
@@ -850,13 +977,9 @@ pub async fn to_chat_completion(
         }
     }
 
-    let finish = if tool_calls.is_empty() {
-        "stop".to_string()
-    } else {
-        "tool_calls".to_string()
-    };
+    let finish = to_finish_reason(&cand.finish_reason, !tool_calls.is_empty());
     if let Some(choice) = out.choices.first_mut() {
-        choice.finish_reason = Some(finish);
+        choice.finish_reason = Some(finish.to_string());
         if let Some(msg) = choice.message.as_mut() {
             msg.content = sonic_rs::to_value(&content).unwrap_or_default();
             msg.reasoning = reasoning;
@@ -867,27 +990,298 @@ pub async fn to_chat_completion(
     out
 }
 
-pub fn to_models_response(
-    models: &std::collections::HashMap<String, sonic_rs::Value>,
-) -> ModelsResponse {
-    let ids = modelutil::build_sorted_model_ids(models);
-    let mut items: Vec<ModelItem> = Vec::with_capacity(ids.len());
-    for mid in ids {
-        let owned_by = if mid.starts_with("claude-") {
-            "anthropic"
-        } else if mid.starts_with("gpt-") {
-            "openai"
-        } else {
-            "google"
-        };
-        items.push(ModelItem {
-            id: mid,
-            object: "model".to_string(),
-            owned_by: owned_by.to_string(),
-        });
-    }
+pub fn to_models_response(models: &[ModelInfo], created: i64) -> ModelsResponse {
     ModelsResponse {
         object: "list".to_string(),
-        data: items,
+        data: models.iter().map(|m| to_model_item(m, created)).collect(),
+    }
+}
+
+pub fn to_model_item(m: &ModelInfo, created: i64) -> ModelItem {
+    ModelItem {
+        id: m.id.clone(),
+        object: "model".to_string(),
+        created,
+        owned_by: m.owned_by.to_string(),
+        context_window: m.context_window,
+        max_output_tokens: m.max_output_tokens,
+        capabilities: ModelCapabilities {
+            thinking: m.thinking,
+            vision: m.vision,
+        },
+        quota_group: m.quota_group.to_string(),
+    }
+}
+
+/// 流式结果 -> OpenAI finish_reason（结构化输出的内部工具调用不算工具调用）。
+pub fn stream_finish_reason(result: &StreamResult) -> &'static str {
+    let has_tool_calls = result
+        .tool_calls
+        .iter()
+        .any(|c| c.name != STRUCTURED_OUTPUT_TOOL);
+    to_finish_reason(&result.finish_reason, has_tool_calls)
+}
+
+/// 后端 finishReason -> OpenAI finish_reason：
+/// - 有工具调用：tool_calls
+/// - MAX_TOKENS：length
+/// - 安全 / 版权等拦截：content_filter
+/// - 其他（STOP 或缺省）：stop
+pub fn to_finish_reason(finish_reason: &str, has_tool_calls: bool) -> &'static str {
+    if has_tool_calls {
+        return "tool_calls";
+    }
+    match finish_reason {
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => {
+            "content_filter"
+        }
+        _ => "stop",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode_of(tool_choice: Option<&str>) -> (String, Vec<String>) {
+        let v: Option<sonic_rs::Value> = tool_choice.map(|s| sonic_rs::from_str(s).unwrap());
+        let fc = to_tool_config(v.as_ref()).function_calling_config.unwrap();
+        (fc.mode, fc.allowed_function_names)
+    }
+
+    #[test]
+    fn tool_choice_maps_to_function_calling_mode() {
+        assert_eq!(mode_of(None).0, "AUTO");
+        assert_eq!(mode_of(Some(r#""auto""#)).0, "AUTO");
+        assert_eq!(mode_of(Some(r#""none""#)).0, "NONE");
+        assert_eq!(mode_of(Some(r#""required""#)).0, "ANY");
+        assert_eq!(mode_of(Some(r#""required""#)).1, Vec::<String>::new());
+    }
+
+    #[test]
+    fn named_tool_choice_restricts_allowed_functions() {
+        let (mode, names) = mode_of(Some(
+            r#"{"type":"function","function":{"name":"get_weather"}}"#,
+        ));
+        assert_eq!(mode, "ANY");
+        assert_eq!(names, vec!["get_weather".to_string()]);
+    }
+
+    #[test]
+    fn stop_accepts_string_or_array() {
+        let one: ChatRequest = sonic_rs::from_str(r#"{"model":"m","stop":"\n\n"}"#).unwrap();
+        assert_eq!(one.stop, vec!["\n\n".to_string()]);
+
+        let many: ChatRequest =
+            sonic_rs::from_str(r#"{"model":"m","stop":["END","---"],"max_tokens":16}"#).unwrap();
+        assert_eq!(many.stop, vec!["END".to_string(), "---".to_string()]);
+        let gc = build_generation_config(&Config::for_test(), &many);
+        assert_eq!(gc.stop_sequences, many.stop);
+
+        let none: ChatRequest = sonic_rs::from_str(r#"{"model":"m","stop":null}"#).unwrap();
+        assert!(none.stop.is_empty());
+    }
+
+    #[test]
+    fn client_max_tokens_is_respected_and_finish_reason_mapped() {
+        let req: ChatRequest = sonic_rs::from_str(
+            r#"{"model":"claude-sonnet-4-5-thinking","max_tokens":100000,"max_completion_tokens":4096}"#,
+        )
+        .unwrap();
+        let gc = build_generation_config(&Config::for_test(), &req);
+        assert_eq!(gc.max_output_tokens, 4096);
+        let tc = gc.thinking_config.unwrap();
+        assert_eq!(
+            tc.thinking_budget,
+            4096 - modelutil::THINKING_BUDGET_HEADROOM_TOKENS
+        );
+
+        let mut cfg = Config::for_test();
+        cfg.max_tokens_policy = crate::config::MaxTokensPolicy::ModelMax;
+        let gc = build_generation_config(&cfg, &req);
+        assert_eq!(gc.max_output_tokens, modelutil::CLAUDE_MAX_OUTPUT_TOKENS);
+
+        assert_eq!(to_finish_reason("MAX_TOKENS", false), "length");
+        assert_eq!(to_finish_reason("MAX_TOKENS", true), "tool_calls");
+        assert_eq!(to_finish_reason("SAFETY", false), "content_filter");
+        assert_eq!(to_finish_reason("", false), "stop");
+    }
+
+    #[tokio::test]
+    async fn user_media_parts_become_inline_data() {
+        let dir =
+            std::env::temp_dir().join(format!("ant2api-openai-media-{}", uuid::Uuid::new_v4()));
+        let sig_mgr = SignatureManager::new(&dir.to_string_lossy()).await.unwrap();
+        let cfg = Config::for_test();
+
+        let mut content: sonic_rs::Value = sonic_rs::from_str(
+            r#"[
+                {"type":"text","text":"summarize"},
+                {"type":"image_url","image_url":{"url":"data:image/jpg;base64,/9j/4AAQ"}},
+                {"type":"file","file":{"file_data":"data:application/pdf;base64,JVBERi0=","filename":"a.pdf"}},
+                {"type":"file","file":{"file_data":"JVBERi0=","filename":"b.PDF"}},
+                {"type":"input_audio","input_audio":{"data":"UklGRg==","format":"wav"}}
+            ]"#,
+        )
+        .unwrap();
+        let parts = extract_user_parts(&cfg, &mut content, &sig_mgr, false)
+            .await
+            .unwrap();
+        let mimes: Vec<&str> = parts
+            .iter()
+            .filter_map(|p| p.inline_data.as_ref().map(|d| d.mime_type.as_str()))
+            .collect();
+        assert_eq!(
+            mimes,
+            [
+                "image/jpeg",
+                "application/pdf",
+                "application/pdf",
+                "audio/wav"
+            ]
+        );
+
+        for bad in [
+            r#"[{"type":"file","file":{"file_id":"file-abc"}}]"#,
+            r#"[{"type":"input_audio","input_audio":{"data":"AAAA","format":"midi"}}]"#,
+            r#"[{"type":"image_url","image_url":{"url":"data:text/html;base64,PGI+"}}]"#,
+            r#"[{"type":"image_url","image_url":{"url":"http://127.0.0.1/a.png"}}]"#,
+            r#"[{"type":"image_url","image_url":{"url":"ftp://example.com/a.png"}}]"#,
+        ] {
+            let mut content: sonic_rs::Value = sonic_rs::from_str(bad).unwrap();
+            assert!(
+                extract_user_parts(&cfg, &mut content, &sig_mgr, false)
+                    .await
+                    .is_err(),
+                "{bad}"
+            );
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn internal_image_urls_are_rejected() {
+        let dir =
+            std::env::temp_dir().join(format!("ant2api-openai-ssrf-{}", uuid::Uuid::new_v4()));
+        let sig_mgr = SignatureManager::new(&dir.to_string_lossy()).await.unwrap();
+        let cfg = Config::for_test();
+
+        // 元数据地址、IPv4 映射的 IPv6、CGNAT，以及带结尾点的 localhost。
+        for url in [
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::ffff:127.0.0.1]/a.png",
+            "http://100.64.0.1/a.png",
+            "http://localhost./a.png",
+        ] {
+            let mut content: sonic_rs::Value = sonic_rs::from_str(&format!(
+                r#"[{{"type":"image_url","image_url":{{"url":"{url}"}}}}]"#
+            ))
+            .unwrap();
+            let err = extract_user_parts(&cfg, &mut content, &sig_mgr, false)
+                .await
+                .unwrap_err();
+            assert!(format!("{err:#}").contains("内网"), "{url}: {err:#}");
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    fn structured(model: &str, tools: &str) -> InnerReq {
+        structured_with_choice(model, tools, "null")
+    }
+
+    fn structured_with_choice(model: &str, tools: &str, tool_choice: &str) -> InnerReq {
+        let req: ChatRequest = sonic_rs::from_str(&format!(
+            r#"{{"model":"{model}","tools":{tools},"tool_choice":{tool_choice},"response_format":{{"type":"json_schema","json_schema":{{"name":"weather","strict":true,"schema":{{"type":"object","properties":{{"city":{{"type":"string"}}}},"additionalProperties":false}}}}}}}}"#
+        ))
+        .unwrap();
+        let mut inner = InnerReq {
+            contents: Vec::new(),
+            system_instruction: None,
+            generation_config: Some(build_generation_config(&Config::for_test(), &req)),
+            tools: to_vertex_tools(&req.tools),
+            tool_config: (!req.tools.is_empty()).then(|| to_tool_config(req.tool_choice.as_ref())),
+            safety_settings: Vec::new(),
+            session_id: String::new(),
+        };
+        apply_response_format(&mut inner, &req);
+        inner
+    }
+
+    #[test]
+    fn response_format_maps_to_schema_or_forced_tool() {
+        let gemini = structured("gemini-2.5-flash", "[]");
+        let gc = gemini.generation_config.unwrap();
+        assert_eq!(gc.response_mime_type, "application/json");
+        let schema = gc.response_schema.unwrap();
+        assert!(schema.contains_key("properties"));
+        assert!(!schema.contains_key("additionalProperties"));
+        assert!(gemini.tools.is_empty());
+
+        let claude = structured("claude-sonnet-4-5", "[]");
+        assert!(claude.generation_config.unwrap().response_schema.is_none());
+        let decls = &claude.tools[0].function_declarations;
+        assert_eq!(decls.len(), 1);
+        assert_eq!(decls[0].name, STRUCTURED_OUTPUT_TOOL);
+        let fc = claude.tool_config.unwrap().function_calling_config.unwrap();
+        assert_eq!(fc.mode, "ANY");
+        assert_eq!(fc.allowed_function_names, [STRUCTURED_OUTPUT_TOOL]);
+
+        let with_tools = structured(
+            "gemini-2.5-flash",
+            r#"[{"type":"function","function":{"name":"lookup"}}]"#,
+        );
+        assert!(
+            with_tools
+                .generation_config
+                .unwrap()
+                .response_mime_type
+                .is_empty()
+        );
+        assert_eq!(with_tools.tools[0].function_declarations.len(), 2);
+        let fc = with_tools
+            .tool_config
+            .unwrap()
+            .function_calling_config
+            .unwrap();
+        assert!(fc.allowed_function_names.is_empty());
+    }
+
+    #[test]
+    fn response_format_respects_client_tool_choice() {
+        let tools = r#"[{"type":"function","function":{"name":"lookup"}}]"#;
+        let mode = |inner: InnerReq| inner.tool_config.unwrap().function_calling_config.unwrap();
+
+        // none：不追加结构化输出工具，也不强制调用。
+        let none = structured_with_choice("claude-sonnet-4-5", tools, r#""none""#);
+        assert_eq!(none.tools[0].function_declarations.len(), 1);
+        assert_eq!(mode(none).mode, "NONE");
+
+        // auto：保持 AUTO，在 system 中提示调用结构化输出工具。
+        let auto = structured_with_choice("claude-sonnet-4-5", tools, r#""auto""#);
+        assert_eq!(auto.tools[0].function_declarations.len(), 2);
+        assert!(auto.system_instruction.is_some());
+        assert_eq!(mode(auto).mode, "AUTO");
+
+        // required：任一工具。
+        let required = mode(structured_with_choice(
+            "gemini-2.5-flash",
+            tools,
+            r#""required""#,
+        ));
+        assert_eq!(required.mode, "ANY");
+        assert!(required.allowed_function_names.is_empty());
+
+        // 指定函数：保留限制并允许结构化输出。
+        let named = mode(structured_with_choice(
+            "claude-sonnet-4-5",
+            tools,
+            r#"{"type":"function","function":{"name":"lookup"}}"#,
+        ));
+        assert_eq!(named.mode, "ANY");
+        assert_eq!(
+            named.allowed_function_names,
+            ["lookup", STRUCTURED_OUTPUT_TOOL]
+        );
     }
 }
@@ -1,13 +1,23 @@
-use super::convert::{convert_usage, to_chat_completion, to_models_response, to_vertex_request};
+use super::convert::{
+    to_chat_completion, to_model_item, to_models_response,
+    to_vertex_request,
+};
 use super::stream::{StreamWriter, now_unix, sse_error_events};
 use super::types::ChatRequest;
+use crate::capture::{self, CaptureProvider, CaptureRecorder};
 use crate::gateway::common::AccountContext;
+use crate::gateway::common::api_auth::resolve_key_name;
 use crate::gateway::common::auth_retry::is_auth_failure;
+use crate::gateway::common::conversation::conversation_key;
+use crate::gateway::common::model_catalog;
 use crate::gateway::common::retry::{
     MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE, MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES,
     should_retry_with_next_token,
 };
+use crate::gateway::common::{json_response_logged, log_model_request};
+use crate::ledger::LedgerEntry;
 use crate::logging;
+use crate::metrics;
 use crate::runtime_config;
 use crate::util::id;
 use crate::util::model as modelutil;
@@ -15,6 +25,7 @@ use crate::vertex::client::ApiError;
 use axum::Json;
 use axum::body::Bytes;
 use axum::extract::OriginalUri;
+use axum::extract::Path;
 use axum::extract::Query;
 use axum::extract::State;
 use axum::http::StatusCode;
@@ -39,6 +50,7 @@ pub struct ModelsListQuery {
     pub raw: bool,
 }
 
+/// GET /v1/models：从模型目录返回；带 `anthropic-version` 头的请求返回 Claude 格式。
 pub async fn handle_list_models(
     State(state): State<Arc<OpenAIState>>,
     method: Method,
@@ -46,103 +58,21 @@ pub async fn handle_list_models(
     headers: HeaderMap,
     Query(query): Query<ModelsListQuery>,
 ) -> Response {
-    let start = Instant::now();
-    let log_level = state.cfg.log_level();
-    if log_level.client_enabled() {
-        if log_level.raw_enabled() {
-            logging::client_request_raw(method.as_str(), uri.0.path(), &headers, &[]);
-        } else {
-            logging::client_request(method.as_str(), uri.0.path(), &headers, &[]);
-        }
+    if is_anthropic_request(&headers) {
+        return crate::gateway::claude::handle_list_models(State(state), method, uri, headers)
+            .await;
     }
 
-    let endpoint = runtime_config::current_endpoint();
-    let mut attempts = state.store.enabled_count().await;
-    if attempts < 1 {
-        attempts = 1;
-    }
-
-    let mut last_err: Option<ApiError> = None;
-    let mut models = None;
-
-    for _ in 0..attempts {
-        let acc = match state.store.get_token().await {
-            Ok(v) => v,
-            Err(e) => {
-                let status = StatusCode::SERVICE_UNAVAILABLE;
-                if log_level.client_enabled() {
-                    if log_level.raw_enabled() {
-                        let body = openai_error_body(&e.to_string());
-                        logging::client_response_raw(
-                            status.as_u16(),
-                            start.elapsed(),
-                            body.as_bytes(),
-                        );
-                    } else {
-                        let err = openai_error_value(&e.to_string());
-                        logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
-                    }
-                }
-                return openai_error(status, &e.to_string());
-            }
-        };
-
-        let project_id = if acc.project_id.is_empty() {
-            id::project_id()
-        } else {
-            acc.project_id.clone()
-        };
-
-        match state
-            .vertex
-            .fetch_available_models(&endpoint, &project_id, &acc.access_token, &acc.email)
-            .await
-        {
-            Ok(v) => {
-                models = Some(v.models);
-                last_err = None;
-                break;
-            }
-            Err(e) => {
-                tracing::warn!(error = ?e, "fetchAvailableModels 失败");
-                // 认证失败：立即切换到下一个凭证，同时后台触发刷新（不阻塞请求路径）。
-                if is_auth_failure(&e) {
-                    state
-                        .store
-                        .trigger_background_refresh(acc.session_id.clone(), state.cfg.clone());
-                }
-                let retry = should_retry_with_next_token(&e);
-                last_err = Some(e);
-                if !retry {
-                    break;
-                }
-            }
-        }
-    }
+    let start = Instant::now();
+    let log_level = state.cfg.log_level();
+    log_model_request(log_level, &method, &uri, &headers);
 
-    let Some(models) = models else {
-        let status = last_err
-            .as_ref()
-            .and_then(|e| e.status())
-            .and_then(|s| StatusCode::from_u16(s).ok())
-            .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
-        let msg = last_err
-            .as_ref()
-            .map(|e| e.to_string())
-            .unwrap_or_else(|| "后端请求失败".to_string());
-        if log_level.client_enabled() {
-            if log_level.raw_enabled() {
-                let body = openai_error_body(&msg);
-                logging::client_response_raw(status.as_u16(), start.elapsed(), body.as_bytes());
-            } else {
-                let err = openai_error_value(&msg);
-                logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
-            }
-        }
-        return openai_error(status, &msg);
+    let models = match model_catalog::load_or_fetch(&state).await {
+        Ok(v) => v,
+        Err((status, msg)) => return openai_error_logged(log_level, start, status, &msg),
     };
 
-    let mut out = to_models_response(&models);
+    let mut out = to_models_response(&models, catalog_created());
     if !query.raw {
         let inv = runtime_config::invert_model_id_mapping();
         if !inv.is_empty() {
@@ -157,16 +87,74 @@ pub async fn handle_list_models(
             out.data.retain(|m| seen.insert(m.id.clone()));
         }
     }
+    json_response_logged(log_level, start, &out)
+}
+
+/// GET /v1/models/{id}：支持映射后的别名；带 `anthropic-version` 头的请求返回 Claude 格式。
+pub async fn handle_get_model(
+    State(state): State<Arc<OpenAIState>>,
+    Path(model): Path<String>,
+    method: Method,
+    uri: OriginalUri,
+    headers: HeaderMap,
+) -> Response {
+    if is_anthropic_request(&headers) {
+        return crate::gateway::claude::handle_get_model(
+            State(state),
+            Path(model),
+            method,
+            uri,
+            headers,
+        )
+        .await;
+    }
+
+    let start = Instant::now();
+    let log_level = state.cfg.log_level();
+    log_model_request(log_level, &method, &uri, &headers);
+
+    if let Err((status, msg)) = model_catalog::load_or_fetch(&state).await {
+        return openai_error_logged(log_level, start, status, &msg);
+    }
+    let Some(info) = model_catalog::catalog().get(&runtime_config::map_client_model_id(&model))
+    else {
+        let msg = format!("模型不存在：{model}");
+        return openai_error_logged(log_level, start, StatusCode::NOT_FOUND, &msg);
+    };
+
+    let mut out = to_model_item(&info, catalog_created());
+    out.id = model;
+    json_response_logged(log_level, start, &out)
+}
+
+fn is_anthropic_request(headers: &HeaderMap) -> bool {
+    headers.contains_key("anthropic-version")
+}
+
+fn catalog_created() -> i64 {
+    model_catalog::catalog()
+        .updated_at()
+        .map(|t| t.timestamp())
+        .unwrap_or_else(now_unix)
+}
+
+/// 记录客户端日志并返回 OpenAI 格式错误。
+fn openai_error_logged(
+    log_level: logging::LogLevel,
+    start: Instant,
+    status: StatusCode,
+    msg: &str,
+) -> Response {
     if log_level.client_enabled() {
         if log_level.raw_enabled() {
-            if let Ok(bytes) = serde_json::to_vec(&out) {
-                logging::client_response_raw(StatusCode::OK.as_u16(), start.elapsed(), &bytes);
-            }
-        } else if let Ok(v) = sonic_rs::to_value(&out) {
-            logging::client_response(StatusCode::OK.as_u16(), start.elapsed(), Some(&v));
+            let body = openai_error_body(msg);
+            logging::client_response_raw(status.as_u16(), start.elapsed(), body.as_bytes());
+        } else {
+            let err = openai_error_value(msg);
+            logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
         }
     }
-    (StatusCode::OK, Json(out)).into_response()
+    openai_error(status, msg)
 }
 
 pub async fn handle_chat_completions(
@@ -231,13 +219,37 @@ pub async fn handle_chat_completions(
     };
 
     // 模型 ID 映射：允许客户端使用自定义模型名，后端自动替换为原始模型名。
+    let client_model = req.model.clone();
     req.model = runtime_config::map_client_model_id(&req.model);
 
+    // API Key 配额：模型白名单 + RPM + 每日 token。
+    let key_name = resolve_key_name(&headers, &uri.0);
+    if let Err(rejection) = state
+        .key_quota
+        .check_and_record(key_name.as_deref(), &req.model)
+    {
+        let status = rejection.status();
+        let msg = rejection.to_string();
+        if log_level.client_enabled() {
+            if log_level.raw_enabled() {
+                let body = openai_error_body(&msg);
+                logging::client_response_raw(status.as_u16(), start.elapsed(), body.as_bytes());
+            } else {
+                let err = openai_error_value(&msg);
+                logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
+            }
+        }
+        return openai_error(status, &msg);
+    }
+
+    metrics::set_request_model(&req.model);
+
     let placeholder = AccountContext {
         project_id: id::project_id(),
         session_id: id::session_id(),
         access_token: String::new(),
         email: String::new(),
+        api_key_name: key_name.clone(),
     };
 
     let (mut vreq, request_id) =
@@ -267,7 +279,26 @@ pub async fn handle_chat_completions(
 
     let model = req.model.clone();
     let is_stream = req.stream;
+    let include_usage = req.stream_options.as_ref().is_some_and(|o| o.include_usage);
     drop(req);
+    let capture = capture::begin(
+        &state.cfg,
+        CaptureProvider::Openai,
+        uri.0.path(),
+        &model,
+        key_name.as_deref(),
+        is_stream,
+        body.as_ref(),
+        &vreq,
+    );
+    let conversation = conversation_key(&headers, None, key_name.as_deref(), &vreq.request);
+    let mut ledger = state.ledger.begin(
+        start,
+        key_name.as_deref(),
+        &client_model,
+        &vreq.model,
+        is_stream,
+    );
 
     let mut attempts = state.store.enabled_count().await;
     if attempts < 1 {
@@ -275,7 +306,20 @@ pub async fn handle_chat_completions(
     }
 
     if is_stream {
-        return handle_stream_with_retry(state, vreq, request_id, model, attempts, start).await;
+        return handle_stream_with_retry(
+            state,
+            vreq,
+            request_id,
+            model,
+            key_name,
+            conversation,
+            include_usage,
+            capture,
+            ledger,
+            attempts,
+            start,
+        )
+        .await;
     }
 
     let mut last_err: Option<ApiError> = None;
@@ -286,11 +330,20 @@ pub async fn handle_chat_completions(
     for _ in 0..attempts {
         let acc = match state
             .store
-            .get_token_for_model_excluding(&model, &state.quota_pool, &used_sessions)
+            .get_token_for_conversation(
+                &model,
+                &state.quota_pool,
+                &used_sessions,
+                &state.affinity,
+                conversation.as_deref(),
+            )
             .await
         {
             Ok(v) => v,
             Err(e) => {
+                ledger
+                    .finish(StatusCode::SERVICE_UNAVAILABLE.as_u16(), None)
+                    .await;
                 if log_level.client_enabled() {
                     if log_level.raw_enabled() {
                         let body = openai_error_body(&e.to_string());
@@ -313,6 +366,7 @@ pub async fn handle_chat_completions(
         };
         let session_id = acc.session_id.clone();
         used_sessions.insert(session_id.clone());
+        ledger.attempt(&acc.email);
         let project_id = if acc.project_id.is_empty() {
             id::project_id()
         } else {
@@ -324,6 +378,7 @@ pub async fn handle_chat_completions(
 
         match state
             .vertex
+            .for_proxy(&acc.proxy)
             .generate_content(&endpoint, &acc.access_token, &vreq, &acc.email)
             .await
         {
@@ -339,6 +394,11 @@ pub async fn handle_chat_completions(
                         .store
                         .trigger_background_refresh(session_id.clone(), state.cfg.clone());
                 }
+                // 429/容量不足：在模型所属配额池中冷却该账号，避免被其他请求继续选中。
+                state
+                    .quota_pool
+                    .cooldown_on_error(&session_id, &model, &e, state.cfg.rate_limit_cooldown())
+                    .await;
                 if e.is_model_capacity_exhausted() {
                     model_capacity_failures += 1;
                 } else {
@@ -357,6 +417,7 @@ pub async fn handle_chat_completions(
     }
 
     let Some(vresp) = vresp else {
+        ledger.finish_error(last_err.as_ref()).await;
         let status = last_err
             .as_ref()
             .and_then(|e| e.status())
@@ -373,6 +434,10 @@ pub async fn handle_chat_completions(
         {
             msg = MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE.to_string();
         }
+        if let Some(mut capture) = capture {
+            capture.set_upstream_error(&msg);
+            capture.finish().await;
+        }
         if log_level.client_enabled() {
             if log_level.raw_enabled() {
                 let body = openai_error_body(&msg);
@@ -385,6 +450,20 @@ pub async fn handle_chat_completions(
         return openai_error(status, &msg);
     };
 
+    state
+        .key_quota
+        .record_usage(key_name.as_deref(), vresp.response.usage_metadata.as_ref());
+    metrics::record_usage(&model, vresp.response.usage_metadata.as_ref());
+    ledger
+        .finish(
+            StatusCode::OK.as_u16(),
+            vresp.response.usage_metadata.as_ref(),
+        )
+        .await;
+    if let Some(mut capture) = capture {
+        capture.push_response(&vresp);
+        capture.finish().await;
+    }
     let out = to_chat_completion(&vresp, &model, &request_id, &state.sig_mgr).await;
 
     if log_level.client_enabled() {
@@ -399,11 +478,17 @@ pub async fn handle_chat_completions(
     (StatusCode::OK, Json(out)).into_response()
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_stream_with_retry(
     state: Arc<OpenAIState>,
     mut vreq: crate::vertex::types::Request,
     request_id: String,
     model: String,
+    key_name: Option<String>,
+    conversation: Option<String>,
+    include_usage: bool,
+    mut capture: Option<CaptureRecorder>,
+    mut ledger: LedgerEntry,
     attempts: usize,
     started_at: Instant,
 ) -> Response {
@@ -428,11 +513,20 @@ async fn handle_stream_with_retry(
         for _ in 0..attempts {
             let acc = match state
                 .store
-                .get_token_for_model_excluding(&model, &state.quota_pool, &used_sessions)
+                .get_token_for_conversation(
+                    &model,
+                    &state.quota_pool,
+                    &used_sessions,
+                    &state.affinity,
+                    conversation.as_deref(),
+                )
                 .await
             {
                 Ok(v) => v,
                 Err(e) => {
+                    ledger
+                        .finish(StatusCode::SERVICE_UNAVAILABLE.as_u16(), None)
+                        .await;
                     if client_log && !raw_log {
                         let err = openai_error_value(&e.to_string());
                         logging::client_stream_response(
@@ -453,6 +547,7 @@ async fn handle_stream_with_retry(
             };
             let session_id = acc.session_id.clone();
             used_sessions.insert(session_id.clone());
+            ledger.attempt(&acc.email);
             let project_id = if acc.project_id.is_empty() {
                 id::project_id()
             } else {
@@ -463,6 +558,7 @@ async fn handle_stream_with_retry(
 
             match state
                 .vertex
+                .for_proxy(&acc.proxy)
                 .generate_content_stream(&endpoint, &acc.access_token, &vreq, &acc.email)
                 .await
             {
@@ -478,6 +574,11 @@ async fn handle_stream_with_retry(
                             .store
                             .trigger_background_refresh(session_id.clone(), state.cfg.clone());
                     }
+                    // 429/容量不足：在模型所属配额池中冷却该账号，避免被其他请求继续选中。
+                    state
+                        .quota_pool
+                        .cooldown_on_error(&session_id, &model, &e, state.cfg.rate_limit_cooldown())
+                        .await;
                     if e.is_model_capacity_exhausted() {
                         model_capacity_failures += 1;
                     } else {
@@ -496,6 +597,7 @@ async fn handle_stream_with_retry(
         }
 
         let Some(resp) = resp else {
+            ledger.finish_error(last_err.as_ref()).await;
             let mut msg = last_err
                 .as_ref()
                 .map(|e| e.to_string())
@@ -507,6 +609,10 @@ async fn handle_stream_with_retry(
             {
                 msg = MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE.to_string();
             }
+            if let Some(mut capture) = capture {
+                capture.set_upstream_error(&msg);
+                capture.finish().await;
+            }
             if client_log && !raw_log {
                 let err = openai_error_value(&msg);
                 logging::client_stream_response(
@@ -524,6 +630,7 @@ async fn handle_stream_with_retry(
             now_unix(),
             model.clone(),
             request_id.clone(),
+            include_usage,
             client_log && !raw_log,
         );
 
@@ -532,19 +639,7 @@ async fn handle_stream_with_retry(
         let parse_res = crate::vertex::stream::parse_stream_with_result(
             resp,
             |data| {
-                let mut events: Vec<String> = Vec::new();
-                let mut saves: Vec<super::stream::SignatureSave> = Vec::new();
-
-                if let Some(cand) = data.response.candidates.first() {
-                    for p in &cand.content.parts {
-                        let (ev, sv) = writer.process_part(p);
-                        events.extend(ev);
-                        saves.extend(sv);
-                    }
-                    if !cand.finish_reason.is_empty() {
-                        events.extend(writer.flush_tool_calls());
-                    }
-                }
+                let (events, saves) = writer.process_chunk(data);
 
                 let tx = tx.clone();
                 let sig_mgr = state.sig_mgr.clone();
@@ -590,7 +685,11 @@ async fn handle_stream_with_retry(
             build_merged,
             {
                 let raw_section = raw_section.clone();
+                let mut capture = capture.as_mut();
                 move |line| {
+                    if let Some(capture) = capture.as_mut() {
+                        capture.push_line(line);
+                    }
                     if !backend_raw {
                         return;
                     }
@@ -609,17 +708,20 @@ async fn handle_stream_with_retry(
             Ok(r) => r,
             Err(e) => e.result,
         };
-
-        let finish = if stream_result.finish_reason.is_empty() {
-            "stop".to_string()
-        } else {
-            stream_result.finish_reason
-        };
-        let usage = convert_usage(stream_result.usage.as_ref());
+        state
+            .key_quota
+            .record_usage(key_name.as_deref(), stream_result.usage.as_ref());
+        metrics::record_usage(&model, stream_result.usage.as_ref());
+        ledger
+            .finish(StatusCode::OK.as_u16(), stream_result.usage.as_ref())
+            .await;
+        if let Some(capture) = capture {
+            capture.finish().await;
+        }
 
         // 即使客户端已断开，也尽量生成完整的日志（调试期更重要）。
         let mut client_disconnected = false;
-        for ev in writer.finish_events(&finish, usage) {
+        for ev in writer.finish_stream(&stream_result) {
             if client_disconnected {
                 continue;
             }
@@ -670,7 +772,7 @@ async fn send_sse_error(
     }
 }
 
-fn openai_error(status: StatusCode, msg: &str) -> Response {
+pub(super) fn openai_error(status: StatusCode, msg: &str) -> Response {
     let body = openai_error_body(msg);
     (
         status,
@@ -680,12 +782,12 @@ fn openai_error(status: StatusCode, msg: &str) -> Response {
         .into_response()
 }
 
-fn openai_error_body(msg: &str) -> String {
+pub(super) fn openai_error_body(msg: &str) -> String {
     let encoded = sonic_rs::to_string(msg).unwrap_or_else(|_| "\"\"".to_string());
     format!("{{\"error\":{{\"message\":{encoded},\"type\":\"server_error\"}}}}")
 }
 
-fn openai_error_value(msg: &str) -> sonic_rs::Value {
+pub(super) fn openai_error_value(msg: &str) -> sonic_rs::Value {
     let mut err = sonic_rs::Object::new();
     err.insert("message", msg);
     err.insert("type", "server_error");
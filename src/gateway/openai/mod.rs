@@ -1,4 +1,5 @@
 pub mod convert;
 pub mod handler;
+pub mod responses;
 pub mod stream;
 pub mod types;
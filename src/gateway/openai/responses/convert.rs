@@ -0,0 +1,390 @@
+use crate::gateway::common::extract::extract_text_from_content;
+use crate::signature::manager::Manager as SignatureManager;
+use crate::util::id;
+use crate::vertex::types::{Response, UsageMetadata};
+use sonic_rs::prelude::*;
+
+use super::super::convert::to_chat_completion;
+use super::super::types::{
+    ChatRequest, Function, FunctionCall, JsonSchemaFormat, Message, ResponseFormat, Tool, ToolCall,
+};
+use super::types::{
+    InputTokensDetails, OutputItem, OutputText, OutputTokensDetails, ResponseMeta, ResponseUsage,
+    ResponsesRequest, SummaryText,
+};
+
+pub(super) fn message(role: &str, content: sonic_rs::Value) -> Message {
+    Message {
+        role: role.to_string(),
+        content,
+        tool_calls: Vec::new(),
+        tool_call_id: String::new(),
+        name: String::new(),
+        reasoning: String::new(),
+        reasoning_content: String::new(),
+    }
+}
+
+/// input（字符串或 item 数组）-> Chat 消息，追加在 previous_response_id 的历史之后。
+///
+/// - message：developer 视为 system；input_text/output_text -> text，input_image -> image_url，input_file -> file
+/// - function_call：挂到上一条 assistant 消息（没有则新建），call_id 即 tool_call id
+/// - function_call_output：tool 消息
+/// - reasoning：summary 文本作为下一条 assistant 消息的 reasoning
+pub fn to_chat_messages(input: &sonic_rs::Value, history: Vec<Message>) -> Vec<Message> {
+    let mut out = history;
+
+    if let Some(s) = input.as_str() {
+        out.push(message("user", sonic_rs::to_value(s).unwrap_or_default()));
+        return out;
+    }
+    let Some(items) = input.as_array() else {
+        return out;
+    };
+
+    let mut pending_reasoning = String::new();
+    for item in items.iter() {
+        let typ = item.get("type").and_then(|v| v.as_str()).unwrap_or("");
+        let role = item.get("role").and_then(|v| v.as_str()).unwrap_or("");
+        match typ {
+            "message" | "" if !role.is_empty() => {
+                let role = if role == "developer" { "system" } else { role };
+                let content = item.get("content").map(to_chat_content).unwrap_or_default();
+                let mut m = message(role, content);
+                if role == "assistant" {
+                    m.reasoning = std::mem::take(&mut pending_reasoning);
+                }
+                out.push(m);
+            }
+            "function_call" => {
+                let call_id = item
+                    .get("call_id")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string();
+                let name = item
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string();
+                let arguments = item
+                    .get("arguments")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("{}")
+                    .to_string();
+
+                if out.last().is_none_or(|m| m.role != "assistant") {
+                    out.push(message("assistant", sonic_rs::Value::default()));
+                }
+                if let Some(m) = out.last_mut() {
+                    if m.reasoning.is_empty() {
+                        m.reasoning = std::mem::take(&mut pending_reasoning);
+                    }
+                    m.tool_calls.push(ToolCall {
+                        index: None,
+                        id: call_id,
+                        typ: "function".to_string(),
+                        function: FunctionCall { name, arguments },
+                    });
+                }
+            }
+            "function_call_output" => {
+                let output = match item.get("output") {
+                    Some(v) if v.is_str() => v.as_str().unwrap_or("").to_string(),
+                    Some(v) => extract_text_from_content(&to_chat_content(v), "\n", false),
+                    None => String::new(),
+                };
+                let mut m = message("tool", sonic_rs::to_value(&output).unwrap_or_default());
+                m.tool_call_id = item
+                    .get("call_id")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string();
+                out.push(m);
+            }
+            "reasoning" => {
+                if let Some(summary) = item.get("summary").and_then(|v| v.as_array()) {
+                    for s in summary.iter() {
+                        let t = s.get("text").and_then(|v| v.as_str()).unwrap_or("");
+                        if t.is_empty() {
+                            continue;
+                        }
+                        if !pending_reasoning.is_empty() {
+                            pending_reasoning.push('\n');
+                        }
+                        pending_reasoning.push_str(t);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Responses content（字符串或 part 数组）-> Chat content。
+fn to_chat_content(content: &sonic_rs::Value) -> sonic_rs::Value {
+    if content.is_str() {
+        return content.clone();
+    }
+    let Some(parts) = content.as_array() else {
+        return sonic_rs::Value::default();
+    };
+
+    let mut out: Vec<sonic_rs::Value> = Vec::with_capacity(parts.len());
+    for p in parts.iter() {
+        match p.get("type").and_then(|v| v.as_str()).unwrap_or("") {
+            "input_text" | "output_text" | "text" => {
+                let text = p.get("text").and_then(|v| v.as_str()).unwrap_or("");
+                out.push(sonic_rs::json!({ "type": "text", "text": text }));
+            }
+            "input_image" => {
+                let url = p.get("image_url").and_then(|v| v.as_str()).unwrap_or("");
+                if url.is_empty() {
+                    continue;
+                }
+                out.push(sonic_rs::json!({ "type": "image_url", "image_url": { "url": url } }));
+            }
+            "input_file" => {
+                let field = |k: &str| p.get(k).and_then(|v| v.as_str()).unwrap_or("");
+                out.push(sonic_rs::json!({
+                    "type": "file",
+                    "file": {
+                        "file_data": field("file_data"),
+                        "filename": field("filename"),
+                        "file_id": field("file_id"),
+                    }
+                }));
+            }
+            _ => {}
+        }
+    }
+    sonic_rs::to_value(&out).unwrap_or_default()
+}
+
+/// Responses 请求 -> ChatRequest（复用 Chat Completions 的签名感知转换）。
+pub fn to_chat_request(req: &ResponsesRequest, model: &str, messages: Vec<Message>) -> ChatRequest {
+    let mut all = Vec::with_capacity(messages.len() + 1);
+    if let Some(instructions) = req.instructions.as_ref().filter(|s| !s.trim().is_empty()) {
+        all.push(message(
+            "system",
+            sonic_rs::to_value(instructions).unwrap_or_default(),
+        ));
+    }
+    all.extend(messages);
+
+    ChatRequest {
+        model: model.to_string(),
+        messages: all,
+        stream: req.stream,
+        temperature: req.temperature,
+        top_p: req.top_p,
+        max_tokens: req.max_output_tokens.unwrap_or(0),
+        max_completion_tokens: 0,
+        stop: Vec::new(),
+        tools: req
+            .tools
+            .iter()
+            .filter(|t| t.typ == "function" && !t.name.trim().is_empty())
+            .map(|t| Tool {
+                typ: "function".to_string(),
+                function: Function {
+                    name: t.name.clone(),
+                    description: t.description.clone().unwrap_or_default(),
+                    parameters: t.parameters.clone().unwrap_or_default(),
+                },
+            })
+            .collect(),
+        tool_choice: req.tool_choice.as_ref().map(to_chat_tool_choice),
+        reasoning_effort: req
+            .reasoning
+            .as_ref()
+            .and_then(|r| r.effort.clone())
+            .unwrap_or_default(),
+        response_format: req.text.as_ref().and_then(|t| t.format.as_ref()).map(|f| {
+            ResponseFormat {
+                typ: f.typ.clone(),
+                json_schema: (f.typ == "json_schema").then(|| JsonSchemaFormat {
+                    name: f.name.clone(),
+                    description: f.description.clone(),
+                    schema: f.schema.clone(),
+                    strict: f.strict,
+                }),
+            }
+        }),
+        stream_options: None,
+    }
+}
+
+/// {"type":"function","name":..} -> {"type":"function","function":{"name":..}}；字符串原样保留。
+fn to_chat_tool_choice(tc: &sonic_rs::Value) -> sonic_rs::Value {
+    match tc.get("name").and_then(|v| v.as_str()) {
+        Some(name) => sonic_rs::json!({ "type": "function", "function": { "name": name } }),
+        None => tc.clone(),
+    }
+}
+
+pub fn to_response_usage(metadata: Option<&UsageMetadata>) -> Option<ResponseUsage> {
+    let m = metadata?;
+    Some(ResponseUsage {
+        input_tokens: m.prompt_token_count,
+        input_tokens_details: InputTokensDetails {
+            cached_tokens: m.cached_content_token_count,
+        },
+        output_tokens: m.candidates_token_count + m.thoughts_token_count,
+        output_tokens_details: OutputTokensDetails {
+            reasoning_tokens: m.thoughts_token_count,
+        },
+        total_tokens: m.total_token_count,
+    })
+}
+
+/// Chat 响应中的 assistant 消息 -> Responses output items。
+pub fn to_output_items(msg: &Message) -> Vec<OutputItem> {
+    let mut out = Vec::with_capacity(2 + msg.tool_calls.len());
+    if !msg.reasoning.is_empty() {
+        out.push(OutputItem::Reasoning {
+            id: id::response_item_id("rs"),
+            summary: vec![SummaryText {
+                typ: "summary_text",
+                text: msg.reasoning.clone(),
+            }],
+        });
+    }
+    let text = extract_text_from_content(&msg.content, "\n", false);
+    if !text.is_empty() {
+        out.push(OutputItem::Message {
+            id: id::response_item_id("msg"),
+            status: "completed",
+            role: "assistant",
+            content: vec![OutputText {
+                typ: "output_text",
+                text,
+                annotations: Vec::new(),
+            }],
+        });
+    }
+    for tc in &msg.tool_calls {
+        out.push(OutputItem::FunctionCall {
+            id: id::response_item_id("fc"),
+            call_id: tc.id.clone(),
+            name: tc.function.name.clone(),
+            arguments: tc.function.arguments.clone(),
+            status: "completed",
+        });
+    }
+    out
+}
+
+/// 非流式：上游响应 -> Chat Completion -> Response 对象，同时返回用于续接的 assistant 消息。
+pub async fn to_response_object(
+    meta: &ResponseMeta,
+    resp: &Response,
+    model: &str,
+    request_id: &str,
+    sig_mgr: &SignatureManager,
+) -> (super::types::ResponseObject, Message) {
+    let completion = to_chat_completion(resp, model, request_id, sig_mgr).await;
+    let usage = resp.response.usage_metadata.as_ref();
+    let msg = completion
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|c| c.message)
+        .unwrap_or_else(|| message("assistant", sonic_rs::Value::default()));
+    let obj = meta.to_object("completed", to_output_items(&msg), to_response_usage(usage));
+    (obj, msg)
+}
+
+/// 请求中需要在响应里回显的字段。
+pub fn response_meta(req: &ResponsesRequest, model: &str, created_at: i64) -> ResponseMeta {
+    ResponseMeta {
+        id: id::response_id(),
+        created_at,
+        model: model.to_string(),
+        previous_response_id: req.previous_response_id.clone(),
+        instructions: req.instructions.clone(),
+        parallel_tool_calls: req.parallel_tool_calls.unwrap_or(true),
+        tool_choice: req
+            .tool_choice
+            .clone()
+            .unwrap_or_else(|| sonic_rs::to_value("auto").unwrap_or_default()),
+        tools: req
+            .tools
+            .iter()
+            .filter_map(|t| sonic_rs::to_value(t).ok())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_items_become_chat_messages() {
+        let input: sonic_rs::Value = sonic_rs::from_str(
+            r#"[
+                {"role": "developer", "content": "be terse"},
+                {"type": "message", "role": "user", "content": [
+                    {"type": "input_text", "text": "weather?"},
+                    {"type": "input_image", "image_url": "data:image/png;base64,AAAA"}
+                ]},
+                {"type": "reasoning", "summary": [{"type": "summary_text", "text": "need a tool"}]},
+                {"type": "function_call", "call_id": "call_1", "name": "get_weather", "arguments": "{\"city\":\"Paris\"}"},
+                {"type": "function_call_output", "call_id": "call_1", "output": "sunny"}
+            ]"#,
+        )
+        .unwrap();
+
+        let history = vec![message("user", sonic_rs::to_value("hi").unwrap())];
+        let msgs = to_chat_messages(&input, history);
+        let roles: Vec<&str> = msgs.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "system", "user", "assistant", "tool"]);
+
+        let user = &msgs[2];
+        assert_eq!(user.content.as_array().map(|a| a.len()), Some(2));
+
+        let assistant = &msgs[3];
+        assert_eq!(assistant.reasoning, "need a tool");
+        assert_eq!(assistant.tool_calls[0].id, "call_1");
+        assert_eq!(assistant.tool_calls[0].function.name, "get_weather");
+
+        assert_eq!(msgs[4].tool_call_id, "call_1");
+        assert_eq!(msgs[4].content.as_str(), Some("sunny"));
+    }
+
+    #[test]
+    fn request_fields_map_onto_chat_request() {
+        let req: ResponsesRequest = sonic_rs::from_str(
+            r#"{
+                "model": "gemini-3-flash",
+                "input": "hello",
+                "instructions": "sys",
+                "reasoning": {"effort": "high"},
+                "max_output_tokens": 256,
+                "tools": [
+                    {"type": "function", "name": "lookup", "parameters": {"type": "object"}},
+                    {"type": "web_search"}
+                ],
+                "tool_choice": {"type": "function", "name": "lookup"}
+            }"#,
+        )
+        .unwrap();
+        let msgs = to_chat_messages(&req.input, Vec::new());
+        let chat = to_chat_request(&req, "gemini-3-flash", msgs);
+
+        assert_eq!(chat.messages[0].role, "system");
+        assert_eq!(chat.messages[1].content.as_str(), Some("hello"));
+        assert_eq!(chat.reasoning_effort, "high");
+        assert_eq!(chat.max_tokens, 256);
+        assert_eq!(chat.tools.len(), 1);
+        let tc = chat.tool_choice.unwrap();
+        assert_eq!(
+            tc.get("function")
+                .and_then(|f| f.get("name"))
+                .and_then(|n| n.as_str()),
+            Some("lookup")
+        );
+    }
+}
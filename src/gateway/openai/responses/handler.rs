@@ -0,0 +1,617 @@
+use super::super::convert::to_vertex_request;
+use super::super::handler::{OpenAIState, openai_error, openai_error_body, openai_error_value};
+use super::super::stream::now_unix;
+use super::super::types::Message;
+use super::convert::{response_meta, to_chat_messages, to_chat_request, to_response_object};
+use super::store::StoredResponse;
+use super::stream::{ResponsesStreamWriter, sse_error_events};
+use super::types::{ResponseMeta, ResponsesRequest};
+use crate::capture::{self, CaptureProvider, CaptureRecorder};
+use crate::gateway::common::AccountContext;
+use crate::gateway::common::api_auth::resolve_key_name;
+use crate::gateway::common::auth_retry::is_auth_failure;
+use crate::gateway::common::conversation::conversation_key;
+use crate::gateway::common::retry::{
+    MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE, MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES,
+    should_retry_with_next_token,
+};
+use crate::ledger::LedgerEntry;
+use crate::logging::{self, LogLevel};
+use crate::metrics;
+use crate::runtime_config;
+use crate::util::id;
+use crate::vertex::client::ApiError;
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{OriginalUri, State};
+use axum::http::{HeaderMap, Method, StatusCode};
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// POST /v1/responses
+pub async fn handle_responses(
+    State(state): State<Arc<OpenAIState>>,
+    method: Method,
+    uri: OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let start = Instant::now();
+    let log_level = state.cfg.log_level();
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            logging::client_request_raw(method.as_str(), uri.0.path(), &headers, body.as_ref());
+        } else {
+            logging::client_request(method.as_str(), uri.0.path(), &headers, body.as_ref());
+        }
+    }
+
+    let endpoint = runtime_config::current_endpoint();
+    let req: ResponsesRequest = match sonic_rs::from_slice(body.as_ref()) {
+        Ok(v) => v,
+        Err(_) => {
+            return error_response(
+                log_level,
+                start,
+                StatusCode::BAD_REQUEST,
+                "请求 JSON 解析失败，请检查请求体格式。",
+            );
+        }
+    };
+
+    // 模型 ID 映射：允许客户端使用自定义模型名，后端自动替换为原始模型名。
+    let client_model = req.model.clone();
+    let model = runtime_config::map_client_model_id(&req.model);
+
+    // API Key 配额：模型白名单 + RPM + 每日 token。
+    let key_name = resolve_key_name(&headers, &uri.0);
+    if let Err(rejection) = state
+        .key_quota
+        .check_and_record(key_name.as_deref(), &model)
+    {
+        return error_response(log_level, start, rejection.status(), &rejection.to_string());
+    }
+
+    metrics::set_request_model(&model);
+
+    // previous_response_id：在已保存的对话历史之后追加本轮 input。
+    let mut history: Vec<Message> = Vec::new();
+    if let Some(prev_id) = req
+        .previous_response_id
+        .as_deref()
+        .filter(|s| !s.is_empty())
+    {
+        // 属于其他 API Key 的响应与不存在的响应同样返回 404。
+        let Some(prev) = state.responses.get(prev_id, key_name.as_deref()).await else {
+            let msg = format!("Previous response with id '{prev_id}' not found.");
+            return error_response(log_level, start, StatusCode::NOT_FOUND, &msg);
+        };
+        history = prev.messages.clone();
+    }
+    // 捕获时单独记录历史，重放不依赖响应存储。
+    let previous = state
+        .cfg
+        .capture_enabled_for(&model)
+        .then(|| history.clone());
+    let messages = to_chat_messages(&req.input, history);
+    if messages.is_empty() {
+        return error_response(log_level, start, StatusCode::BAD_REQUEST, "input 不能为空");
+    }
+
+    let meta = response_meta(&req, &model, now_unix());
+    // store=false 时不保存，后续也无法通过 previous_response_id 续接。
+    let history_to_store = (req.store != Some(false)).then(|| messages.clone());
+    let mut chat = to_chat_request(&req, &model, messages);
+    drop(req);
+
+    let placeholder = AccountContext {
+        project_id: id::project_id(),
+        session_id: id::session_id(),
+        access_token: String::new(),
+        email: String::new(),
+        api_key_name: key_name.clone(),
+    };
+
+    let (mut vreq, request_id) =
+        match to_vertex_request(&state.cfg, &state.sig_mgr, &mut chat, &placeholder).await {
+            Ok(v) => v,
+            Err(e) => {
+                return error_response(log_level, start, StatusCode::BAD_REQUEST, &e.to_string());
+            }
+        };
+    let is_stream = chat.stream;
+    drop(chat);
+    let mut capture = capture::begin(
+        &state.cfg,
+        CaptureProvider::Responses,
+        uri.0.path(),
+        &model,
+        key_name.as_deref(),
+        is_stream,
+        body.as_ref(),
+        &vreq,
+    );
+    if let (Some(capture), Some(previous)) = (capture.as_mut(), previous.as_deref()) {
+        capture.set_previous_messages(previous);
+    }
+    let conversation = conversation_key(&headers, None, key_name.as_deref(), &vreq.request);
+    let mut ledger = state.ledger.begin(
+        start,
+        key_name.as_deref(),
+        &client_model,
+        &vreq.model,
+        is_stream,
+    );
+
+    let mut attempts = state.store.enabled_count().await;
+    if attempts < 1 {
+        attempts = 1;
+    }
+
+    if is_stream {
+        return handle_stream_with_retry(
+            state,
+            vreq,
+            request_id,
+            meta,
+            history_to_store,
+            key_name,
+            conversation,
+            capture,
+            ledger,
+            attempts,
+            start,
+        )
+        .await;
+    }
+
+    let mut last_err: Option<ApiError> = None;
+    let mut vresp = None;
+    let mut used_sessions: HashSet<String> = HashSet::new();
+    let mut model_capacity_failures = 0usize;
+
+    for _ in 0..attempts {
+        let acc = match state
+            .store
+            .get_token_for_conversation(
+                &model,
+                &state.quota_pool,
+                &used_sessions,
+                &state.affinity,
+                conversation.as_deref(),
+            )
+            .await
+        {
+            Ok(v) => v,
+            Err(e) => {
+                ledger
+                    .finish(StatusCode::SERVICE_UNAVAILABLE.as_u16(), None)
+                    .await;
+                return error_response(
+                    log_level,
+                    start,
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    &e.to_string(),
+                );
+            }
+        };
+        let session_id = acc.session_id.clone();
+        used_sessions.insert(session_id.clone());
+        ledger.attempt(&acc.email);
+        let project_id = if acc.project_id.is_empty() {
+            id::project_id()
+        } else {
+            acc.project_id.clone()
+        };
+
+        vreq.project = project_id;
+        vreq.request.session_id = acc.session_id;
+
+        match state
+            .vertex
+            .for_proxy(&acc.proxy)
+            .generate_content(&endpoint, &acc.access_token, &vreq, &acc.email)
+            .await
+        {
+            Ok(v) => {
+                vresp = Some(v);
+                last_err = None;
+                break;
+            }
+            Err(e) => {
+                // 认证失败：立即切换到下一个凭证，同时后台触发刷新（不阻塞请求路径）。
+                if is_auth_failure(&e) {
+                    state
+                        .store
+                        .trigger_background_refresh(session_id.clone(), state.cfg.clone());
+                }
+                // 429/容量不足：在模型所属配额池中冷却该账号，避免被其他请求继续选中。
+                state
+                    .quota_pool
+                    .cooldown_on_error(&session_id, &model, &e, state.cfg.rate_limit_cooldown())
+                    .await;
+                if e.is_model_capacity_exhausted() {
+                    model_capacity_failures += 1;
+                } else {
+                    model_capacity_failures = 0;
+                }
+                let retry = should_retry_with_next_token(&e);
+                last_err = Some(e);
+                if model_capacity_failures >= MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES {
+                    break;
+                }
+                if !retry {
+                    break;
+                }
+            }
+        }
+    }
+
+    let Some(vresp) = vresp else {
+        ledger.finish_error(last_err.as_ref()).await;
+        let (status, msg) = final_error(last_err.as_ref(), model_capacity_failures);
+        if let Some(mut capture) = capture {
+            capture.set_upstream_error(&msg);
+            capture.finish().await;
+        }
+        return error_response(log_level, start, status, &msg);
+    };
+
+    if let Some(mut capture) = capture {
+        capture.push_response(&vresp);
+        capture.finish().await;
+    }
+
+    let usage = vresp.response.usage_metadata.clone();
+    state
+        .key_quota
+        .record_usage(key_name.as_deref(), usage.as_ref());
+    metrics::record_usage(&model, usage.as_ref());
+    ledger.finish(StatusCode::OK.as_u16(), usage.as_ref()).await;
+    let (out, assistant) =
+        to_response_object(&meta, &vresp, &model, &request_id, &state.sig_mgr).await;
+    if let Some(mut messages) = history_to_store {
+        messages.push(assistant);
+        save_response(&state, &meta, messages, key_name.as_deref()).await;
+    }
+
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            if let Ok(bytes) = serde_json::to_vec(&out) {
+                logging::client_response_raw(StatusCode::OK.as_u16(), start.elapsed(), &bytes);
+            }
+        } else if let Ok(v) = sonic_rs::to_value(&out) {
+            logging::client_response(StatusCode::OK.as_u16(), start.elapsed(), Some(&v));
+        }
+    }
+    (StatusCode::OK, Json(out)).into_response()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_stream_with_retry(
+    state: Arc<OpenAIState>,
+    mut vreq: crate::vertex::types::Request,
+    request_id: String,
+    meta: ResponseMeta,
+    history_to_store: Option<Vec<Message>>,
+    key_name: Option<String>,
+    conversation: Option<String>,
+    mut capture: Option<CaptureRecorder>,
+    mut ledger: LedgerEntry,
+    attempts: usize,
+    started_at: Instant,
+) -> Response {
+    let (tx, rx) = mpsc::channel::<Result<Event, Infallible>>(256);
+    let endpoint = runtime_config::current_endpoint();
+    let model = meta.model.clone();
+
+    tokio::spawn(async move {
+        let log_level = state.cfg.log_level();
+        let client_log = log_level.client_enabled();
+        let backend_log = log_level.backend_enabled();
+        let raw_log = log_level.raw_enabled();
+
+        // RAW 模式下用于在“后端响应/客户端响应”之间切换时打印分割线。
+        // 0 = none, 1 = backend, 2 = client
+        let raw_section = std::sync::Arc::new(std::sync::atomic::AtomicU8::new(0));
+
+        let mut last_err: Option<ApiError> = None;
+        let mut resp = None;
+        let mut used_sessions: HashSet<String> = HashSet::new();
+        let mut model_capacity_failures = 0usize;
+
+        for _ in 0..attempts {
+            let acc = match state
+                .store
+                .get_token_for_conversation(
+                    &model,
+                    &state.quota_pool,
+                    &used_sessions,
+                    &state.affinity,
+                    conversation.as_deref(),
+                )
+                .await
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    ledger
+                        .finish(StatusCode::SERVICE_UNAVAILABLE.as_u16(), None)
+                        .await;
+                    if client_log && !raw_log {
+                        let err = openai_error_value(&e.to_string());
+                        logging::client_stream_response(
+                            StatusCode::OK.as_u16(),
+                            started_at.elapsed(),
+                            &[err],
+                        );
+                    }
+                    send_sse_error(&tx, &e.to_string(), client_log && raw_log, &raw_section).await;
+                    return;
+                }
+            };
+            let session_id = acc.session_id.clone();
+            used_sessions.insert(session_id.clone());
+            ledger.attempt(&acc.email);
+
+            let project_id = if acc.project_id.is_empty() {
+                id::project_id()
+            } else {
+                acc.project_id.clone()
+            };
+            vreq.project = project_id;
+            vreq.request.session_id = acc.session_id;
+
+            match state
+                .vertex
+                .for_proxy(&acc.proxy)
+                .generate_content_stream(&endpoint, &acc.access_token, &vreq, &acc.email)
+                .await
+            {
+                Ok(r) => {
+                    resp = Some(r);
+                    last_err = None;
+                    break;
+                }
+                Err(e) => {
+                    // 认证失败：立即切换到下一个凭证，同时后台触发刷新（不阻塞请求路径）。
+                    if is_auth_failure(&e) {
+                        state
+                            .store
+                            .trigger_background_refresh(session_id.clone(), state.cfg.clone());
+                    }
+                    // 429/容量不足：在模型所属配额池中冷却该账号，避免被其他请求继续选中。
+                    state
+                        .quota_pool
+                        .cooldown_on_error(&session_id, &model, &e, state.cfg.rate_limit_cooldown())
+                        .await;
+                    if e.is_model_capacity_exhausted() {
+                        model_capacity_failures += 1;
+                    } else {
+                        model_capacity_failures = 0;
+                    }
+                    let retry = should_retry_with_next_token(&e);
+                    last_err = Some(e);
+                    if model_capacity_failures >= MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES {
+                        break;
+                    }
+                    if !retry {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let Some(resp) = resp else {
+            ledger.finish_error(last_err.as_ref()).await;
+            let (_, msg) = final_error(last_err.as_ref(), model_capacity_failures);
+            if let Some(mut capture) = capture {
+                capture.set_upstream_error(&msg);
+                capture.finish().await;
+            }
+            if client_log && !raw_log {
+                let err = openai_error_value(&msg);
+                logging::client_stream_response(
+                    StatusCode::OK.as_u16(),
+                    started_at.elapsed(),
+                    &[err],
+                );
+            }
+            send_sse_error(&tx, &msg, client_log && raw_log, &raw_section).await;
+            return;
+        };
+
+        let mut writer = ResponsesStreamWriter::new(meta.clone(), request_id.clone());
+        let start_events = writer.start();
+        send_events(&tx, start_events, client_log && raw_log, &raw_section).await;
+
+        let backend_raw = backend_log && raw_log;
+        let build_merged = backend_log && !raw_log;
+        let parse_res = crate::vertex::stream::parse_stream_with_result(
+            resp,
+            |data| {
+                let (events, saves) = writer.process_chunk(data);
+
+                let tx = tx.clone();
+                let sig_mgr = state.sig_mgr.clone();
+                let raw_section = raw_section.clone();
+                async move {
+                    for s in saves {
+                        if s.is_image_key {
+                            sig_mgr
+                                .save_image_key(
+                                    s.request_id,
+                                    s.tool_call_id,
+                                    s.signature,
+                                    s.reasoning,
+                                    s.model,
+                                )
+                                .await;
+                        } else {
+                            sig_mgr
+                                .save_owned(
+                                    s.request_id,
+                                    s.tool_call_id,
+                                    s.signature,
+                                    s.reasoning,
+                                    s.model,
+                                )
+                                .await;
+                        }
+                    }
+                    send_events(&tx, events, client_log && raw_log, &raw_section).await;
+                    Ok(())
+                }
+            },
+            build_merged,
+            {
+                let raw_section = raw_section.clone();
+                let mut capture = capture.as_mut();
+                move |line| {
+                    if let Some(capture) = capture.as_mut() {
+                        capture.push_line(line);
+                    }
+                    if !backend_raw {
+                        return;
+                    }
+                    if raw_section.swap(1, std::sync::atomic::Ordering::Relaxed) != 1 {
+                        logging::backend_response_divider_raw();
+                    }
+                    if line.starts_with(b"data:") || line.starts_with(b":") {
+                        logging::backend_stream_line_raw(line);
+                    }
+                }
+            },
+        )
+        .await;
+
+        let stream_result = match parse_res {
+            Ok(r) => r,
+            Err(e) => e.result,
+        };
+        state
+            .key_quota
+            .record_usage(key_name.as_deref(), stream_result.usage.as_ref());
+        metrics::record_usage(&model, stream_result.usage.as_ref());
+        ledger
+            .finish(StatusCode::OK.as_u16(), stream_result.usage.as_ref())
+            .await;
+        if let Some(capture) = capture {
+            capture.finish().await;
+        }
+
+        let finish_events = writer.finish_stream(&stream_result);
+        send_events(&tx, finish_events, client_log && raw_log, &raw_section).await;
+
+        if let Some(mut messages) = history_to_store {
+            messages.push(writer.assistant_message());
+            save_response(&state, &meta, messages, key_name.as_deref()).await;
+        }
+
+        let duration = started_at.elapsed();
+        if !raw_log {
+            if backend_log {
+                logging::backend_stream_response(
+                    StatusCode::OK.as_u16(),
+                    duration,
+                    stream_result.merged_response.as_ref(),
+                );
+            }
+            if client_log {
+                let merged = writer.take_completed_for_log();
+                logging::client_stream_response(StatusCode::OK.as_u16(), duration, &merged);
+            }
+        }
+    });
+
+    Sse::new(ReceiverStream::new(rx)).into_response()
+}
+
+async fn save_response(
+    state: &OpenAIState,
+    meta: &ResponseMeta,
+    messages: Vec<Message>,
+    api_key_name: Option<&str>,
+) {
+    let stored = StoredResponse {
+        id: meta.id.clone(),
+        model: meta.model.clone(),
+        created_at: meta.created_at,
+        messages,
+        api_key_name: api_key_name.map(str::to_string),
+    };
+    if let Err(e) = state.responses.put(stored).await {
+        tracing::warn!("保存 Responses 历史失败: {e:#}");
+    }
+}
+
+/// 重试耗尽后返回给客户端的状态码与消息。
+fn final_error(
+    last_err: Option<&ApiError>,
+    model_capacity_failures: usize,
+) -> (StatusCode, String) {
+    let status = last_err
+        .and_then(|e| e.status())
+        .and_then(|s| StatusCode::from_u16(s).ok())
+        .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+    let mut msg = last_err
+        .map(|e| e.to_string())
+        .unwrap_or_else(|| "后端请求失败".to_string());
+    if model_capacity_failures >= MODEL_CAPACITY_EXHAUSTED_MAX_RETRIES
+        && last_err.is_some_and(|e| e.is_model_capacity_exhausted())
+    {
+        msg = MODEL_CAPACITY_EXHAUSTED_CLIENT_MESSAGE.to_string();
+    }
+    (status, msg)
+}
+
+/// 记录客户端日志并返回 OpenAI 格式错误。
+fn error_response(log_level: LogLevel, start: Instant, status: StatusCode, msg: &str) -> Response {
+    if log_level.client_enabled() {
+        if log_level.raw_enabled() {
+            let body = openai_error_body(msg);
+            logging::client_response_raw(status.as_u16(), start.elapsed(), body.as_bytes());
+        } else {
+            let err = openai_error_value(msg);
+            logging::client_response(status.as_u16(), start.elapsed(), Some(&err));
+        }
+    }
+    openai_error(status, msg)
+}
+
+async fn send_events(
+    tx: &mpsc::Sender<Result<Event, Infallible>>,
+    events: Vec<(&'static str, String)>,
+    raw_log: bool,
+    raw_section: &std::sync::atomic::AtomicU8,
+) {
+    for (event_name, ev) in events {
+        if raw_log {
+            if raw_section.swap(2, std::sync::atomic::Ordering::Relaxed) != 2 {
+                logging::client_response_divider_raw();
+            }
+            logging::client_stream_event_raw(Some(event_name), &ev);
+        }
+        if tx
+            .send(Ok(Event::default().event(event_name).data(ev)))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+async fn send_sse_error(
+    tx: &mpsc::Sender<Result<Event, Infallible>>,
+    msg: &str,
+    raw_log: bool,
+    raw_section: &std::sync::atomic::AtomicU8,
+) {
+    send_events(tx, sse_error_events(msg), raw_log, raw_section).await;
+}
@@ -0,0 +1,11 @@
+mod convert;
+mod handler;
+mod store;
+mod stream;
+mod types;
+
+pub(crate) use convert::{response_meta, to_chat_messages, to_chat_request, to_response_object};
+pub use handler::handle_responses;
+pub use store::{ResponseStore, spawn_cleanup_task};
+pub(crate) use stream::ResponsesStreamWriter;
+pub use types::*;
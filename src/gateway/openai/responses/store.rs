@@ -0,0 +1,181 @@
+//! previous_response_id 续接存储。
+//!
+//! 每个响应保存为 data_dir/responses/<id>.json（完整对话历史，不含 instructions），
+//! 最近使用的条目常驻内存；文件按 `cache_retention_days` 每日清理。
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use super::super::types::Message;
+
+const RESPONSES_DIRNAME: &str = "responses";
+const HOT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredResponse {
+    pub id: String,
+    pub model: String,
+    pub created_at: i64,
+    /// 截至本次响应（含本次 assistant 输出）的完整消息历史。
+    pub messages: Vec<Message>,
+    /// 创建该响应的 API Key 名称（未启用鉴权时为 None）；只有同一密钥可以续接。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_name: Option<String>,
+}
+
+#[derive(Default)]
+struct Hot {
+    entries: HashMap<String, Arc<StoredResponse>>,
+    order: VecDeque<String>,
+}
+
+pub struct ResponseStore {
+    dir: PathBuf,
+    hot: Mutex<Hot>,
+}
+
+impl ResponseStore {
+    pub fn new(data_dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(data_dir).join(RESPONSES_DIRNAME),
+            hot: Mutex::new(Hot::default()),
+        }
+    }
+
+    pub async fn put(&self, resp: StoredResponse) -> anyhow::Result<()> {
+        let Some(path) = self.path_for(&resp.id) else {
+            anyhow::bail!("非法的 response id: {}", resp.id);
+        };
+        let data = sonic_rs::to_vec(&resp)?;
+        self.remember(Arc::new(resp));
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+
+    /// 按 id 读取响应；不属于 `api_key_name` 的响应视为不存在。
+    pub async fn get(&self, id: &str, api_key_name: Option<&str>) -> Option<Arc<StoredResponse>> {
+        self.load(id)
+            .await
+            .filter(|r| r.api_key_name.as_deref() == api_key_name)
+    }
+
+    async fn load(&self, id: &str) -> Option<Arc<StoredResponse>> {
+        if let Some(hit) = self
+            .hot
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entries
+            .get(id)
+        {
+            return Some(hit.clone());
+        }
+        let path = self.path_for(id)?;
+        let data = tokio::fs::read(path).await.ok()?;
+        let resp: StoredResponse = sonic_rs::from_slice(&data).ok()?;
+        let resp = Arc::new(resp);
+        self.remember(resp.clone());
+        Some(resp)
+    }
+
+    /// 删除超过保留天数的响应文件，返回删除数量。
+    pub async fn cleanup(&self, retention_days: u32) -> anyhow::Result<usize> {
+        let max_age = Duration::from_secs(u64::from(retention_days.max(1)) * 24 * 60 * 60);
+        let mut dir = match tokio::fs::read_dir(&self.dir).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let now = SystemTime::now();
+        let mut deleted = 0usize;
+        while let Some(de) = dir.next_entry().await? {
+            let Ok(modified) = de.metadata().await.and_then(|m| m.modified()) else {
+                continue;
+            };
+            if now.duration_since(modified).unwrap_or_default() < max_age {
+                continue;
+            }
+            match tokio::fs::remove_file(de.path()).await {
+                Ok(_) => deleted += 1,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(deleted)
+    }
+
+    fn remember(&self, resp: Arc<StoredResponse>) {
+        let mut hot = self.hot.lock().unwrap_or_else(|e| e.into_inner());
+        if hot.entries.insert(resp.id.clone(), resp.clone()).is_none() {
+            hot.order.push_back(resp.id.clone());
+        }
+        while hot.order.len() > HOT_CAPACITY {
+            if let Some(old) = hot.order.pop_front() {
+                hot.entries.remove(&old);
+            }
+        }
+    }
+
+    /// id 只允许字母数字、`_`、`-`，避免路径穿越。
+    fn path_for(&self, id: &str) -> Option<PathBuf> {
+        let valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        valid.then(|| self.dir.join(format!("{id}.json")))
+    }
+}
+
+/// 后台每日清理过期的响应文件。
+pub fn spawn_cleanup_task(store: Arc<ResponseStore>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+        loop {
+            tick.tick().await;
+            let days = crate::runtime_config::get().cache_retention_days;
+            if let Err(e) = store.cleanup(days).await {
+                tracing::warn!("清理 Responses 存储失败: {e:#}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::openai::responses::convert::message;
+
+    #[tokio::test]
+    async fn stored_response_survives_reload() {
+        let dir = std::env::temp_dir().join(format!("ant2api-responses-{}", uuid::Uuid::new_v4()));
+        let data_dir = dir.to_string_lossy().to_string();
+
+        let store = ResponseStore::new(&data_dir);
+        store
+            .put(StoredResponse {
+                id: "resp_abc".to_string(),
+                model: "gemini-3-flash".to_string(),
+                created_at: 1,
+                messages: vec![message("user", sonic_rs::to_value("hi").unwrap())],
+                api_key_name: Some("team-a".to_string()),
+            })
+            .await
+            .unwrap();
+
+        let reloaded = ResponseStore::new(&data_dir);
+        let got = reloaded.get("resp_abc", Some("team-a")).await.unwrap();
+        assert_eq!(got.messages.len(), 1);
+        assert!(reloaded.get("../accounts", None).await.is_none());
+        assert!(reloaded.get("resp_missing", Some("team-a")).await.is_none());
+
+        // 其他密钥（或未带密钥）不能读取 / 续接该响应。
+        assert!(reloaded.get("resp_abc", Some("team-b")).await.is_none());
+        assert!(reloaded.get("resp_abc", None).await.is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,566 @@
+use super::super::stream::SignatureSave;
+use super::super::types::{FunctionCall, Message, ToolCall};
+use super::convert::{message, to_response_usage};
+use super::types::{OutputItem, OutputText, ResponseMeta, ResponseUsage, SummaryText};
+use crate::util::{id, model as modelutil};
+use crate::vertex::types::{StreamData, StreamDataPart, StreamResult};
+use sonic_rs::JsonValueMutTrait;
+
+/// Responses SSE: 写入 `error` 事件并结束。
+pub fn sse_error_events(msg: &str) -> Vec<(&'static str, String)> {
+    let v = sonic_rs::json!({
+        "type": "error",
+        "code": "server_error",
+        "message": msg,
+        "param": null,
+        "sequence_number": 0
+    });
+    vec![("error", sonic_rs::to_string(&v).unwrap_or_default())]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ItemKind {
+    Reasoning,
+    Message,
+}
+
+struct OpenItem {
+    kind: ItemKind,
+    id: String,
+    output_index: usize,
+    text: String,
+}
+
+/// 把后端流式 part 转为 Responses API 的类型化 SSE 事件序列。
+pub struct ResponsesStreamWriter {
+    meta: ResponseMeta,
+    request_id: String,
+    seq: u64,
+
+    output: Vec<OutputItem>,
+    current: Option<OpenItem>,
+
+    // 用于 previous_response_id 续接的 assistant 消息
+    content: String,
+    reasoning: String,
+    tool_calls: Vec<ToolCall>,
+
+    pending_reasoning: String,
+    pending_sig: String,
+    is_claude_thinking: bool,
+    is_gemini_pro_image: bool,
+
+    completed: Option<sonic_rs::Value>,
+}
+
+impl ResponsesStreamWriter {
+    pub fn new(meta: ResponseMeta, request_id: String) -> Self {
+        let is_claude_thinking = modelutil::is_claude_thinking(&meta.model);
+        let is_gemini_pro_image = modelutil::is_gemini_pro_image(&meta.model);
+        Self {
+            meta,
+            request_id,
+            seq: 0,
+            output: Vec::new(),
+            current: None,
+            content: String::new(),
+            reasoning: String::new(),
+            tool_calls: Vec::new(),
+            pending_reasoning: String::new(),
+            pending_sig: String::new(),
+            is_claude_thinking,
+            is_gemini_pro_image,
+            completed: None,
+        }
+    }
+
+    /// response.created + response.in_progress
+    pub fn start(&mut self) -> Vec<(&'static str, String)> {
+        let obj = self.meta.to_object("in_progress", Vec::new(), None);
+        let v = sonic_rs::to_value(&obj).unwrap_or_default();
+        vec![
+            self.emit(
+                "response.created",
+                sonic_rs::json!({ "response": v.clone() }),
+            ),
+            self.emit("response.in_progress", sonic_rs::json!({ "response": v })),
+        ]
+    }
+
+    /// 处理上游一个 SSE 数据块：逐个处理首个候选的 Part。
+    pub fn process_chunk(
+        &mut self,
+        data: &StreamData,
+    ) -> (Vec<(&'static str, String)>, Vec<SignatureSave>) {
+        let mut events = Vec::new();
+        let mut saves = Vec::new();
+        if let Some(cand) = data.response.candidates.first() {
+            for p in &cand.content.parts {
+                let (ev, sv) = self.process_part(p);
+                events.extend(ev);
+                saves.extend(sv);
+            }
+        }
+        (events, saves)
+    }
+
+    pub fn process_part(
+        &mut self,
+        part: &StreamDataPart,
+    ) -> (Vec<(&'static str, String)>, Vec<SignatureSave>) {
+        let mut events = Vec::new();
+        let mut saves = Vec::new();
+
+        // Claude thinking：把签名绑定到后续第一个 tool call。
+        if self.is_claude_thinking && part.thought && !part.thought_signature.is_empty() {
+            self.pending_sig = part.thought_signature.clone();
+        }
+
+        if part.thought {
+            self.pending_reasoning.push_str(&part.text);
+            self.reasoning.push_str(&part.text);
+            events.extend(self.write_text(ItemKind::Reasoning, &part.text));
+            return (events, saves);
+        }
+
+        if !part.text.is_empty() {
+            self.content.push_str(&part.text);
+            events.extend(self.write_text(ItemKind::Message, &part.text));
+            return (events, saves);
+        }
+
+        if let Some(inline) = &part.inline_data {
+            let image_key = if self.is_gemini_pro_image {
+                let s = inline.data.as_str();
+                s[..s.len().min(100)].to_string()
+            } else {
+                inline.signature_key()
+            };
+            if !part.thought_signature.is_empty() && !image_key.is_empty() {
+                saves.push(SignatureSave {
+                    request_id: self.request_id.clone(),
+                    tool_call_id: image_key,
+                    is_image_key: true,
+                    signature: part.thought_signature.clone(),
+                    reasoning: std::mem::take(&mut self.pending_reasoning),
+                    model: self.meta.model.clone(),
+                });
+            }
+
+            let data = inline.data.as_str();
+            let mut sb = String::with_capacity(10 + inline.mime_type.len() + data.len());
+            sb.push_str("![image](data:");
+            sb.push_str(&inline.mime_type);
+            sb.push_str(";base64,");
+            sb.push_str(data);
+            sb.push(')');
+            self.content.push_str(&sb);
+            events.extend(self.write_text(ItemKind::Message, &sb));
+            return (events, saves);
+        }
+
+        if let Some(fc) = &part.function_call {
+            let call_id = if fc.id.is_empty() {
+                id::tool_call_id()
+            } else {
+                fc.id.clone()
+            };
+
+            let mut signature_to_save: Option<String> = None;
+            if self.is_claude_thinking {
+                if !self.pending_sig.is_empty() {
+                    signature_to_save = Some(std::mem::take(&mut self.pending_sig));
+                } else if !part.thought_signature.is_empty() {
+                    signature_to_save = Some(part.thought_signature.clone());
+                }
+            } else if !part.thought_signature.is_empty() {
+                signature_to_save = Some(part.thought_signature.clone());
+            }
+            if let Some(sig) = signature_to_save {
+                saves.push(SignatureSave {
+                    request_id: self.request_id.clone(),
+                    tool_call_id: call_id.clone(),
+                    is_image_key: false,
+                    signature: sig,
+                    reasoning: std::mem::take(&mut self.pending_reasoning),
+                    model: self.meta.model.clone(),
+                });
+            }
+
+            let arguments = if fc.args.is_empty() {
+                "{}".to_string()
+            } else {
+                sonic_rs::to_string(&fc.args).unwrap_or_else(|_| "{}".to_string())
+            };
+            self.tool_calls.push(ToolCall {
+                index: None,
+                id: call_id.clone(),
+                typ: "function".to_string(),
+                function: FunctionCall {
+                    name: fc.name.clone(),
+                    arguments: arguments.clone(),
+                },
+            });
+
+            events.extend(self.close_current());
+            events.extend(self.write_function_call(call_id, fc.name.clone(), arguments));
+        }
+
+        (events, saves)
+    }
+
+    /// 上游流结束后调用：按解析结果的 usage 收尾。
+    pub fn finish_stream(&mut self, result: &StreamResult) -> Vec<(&'static str, String)> {
+        self.finish(to_response_usage(result.usage.as_ref()))
+    }
+
+    /// 关闭未完成的 item，并输出 response.completed。
+    pub fn finish(&mut self, usage: Option<ResponseUsage>) -> Vec<(&'static str, String)> {
+        let mut events = self.close_current();
+        let obj = self.meta.to_object("completed", self.output.clone(), usage);
+        let v = sonic_rs::to_value(&obj).unwrap_or_default();
+        self.completed = Some(v.clone());
+        events.push(self.emit("response.completed", sonic_rs::json!({ "response": v })));
+        events
+    }
+
+    /// 本次输出对应的 assistant 消息（写入续接存储）。
+    pub fn assistant_message(&self) -> Message {
+        let mut m = message(
+            "assistant",
+            sonic_rs::to_value(&self.content).unwrap_or_default(),
+        );
+        m.reasoning = self.reasoning.clone();
+        m.tool_calls = self.tool_calls.clone();
+        m
+    }
+
+    pub fn take_completed_for_log(&mut self) -> Vec<sonic_rs::Value> {
+        self.completed.take().into_iter().collect()
+    }
+
+    fn write_text(&mut self, kind: ItemKind, text: &str) -> Vec<(&'static str, String)> {
+        let mut events = Vec::new();
+        if self.current.as_ref().map(|c| c.kind) != Some(kind) {
+            events.extend(self.close_current());
+            events.extend(self.open(kind));
+        }
+        if text.is_empty() {
+            return events;
+        }
+        let Some(cur) = self.current.as_mut() else {
+            return events;
+        };
+        cur.text.push_str(text);
+        let (item_id, output_index) = (cur.id.clone(), cur.output_index);
+        let ev = match kind {
+            ItemKind::Reasoning => self.emit(
+                "response.reasoning_summary_text.delta",
+                sonic_rs::json!({
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "summary_index": 0,
+                    "delta": text
+                }),
+            ),
+            ItemKind::Message => self.emit(
+                "response.output_text.delta",
+                sonic_rs::json!({
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "content_index": 0,
+                    "delta": text
+                }),
+            ),
+        };
+        events.push(ev);
+        events
+    }
+
+    fn open(&mut self, kind: ItemKind) -> Vec<(&'static str, String)> {
+        let output_index = self.output.len();
+        let (item_id, item, part_event, part) = match kind {
+            ItemKind::Reasoning => {
+                let item_id = id::response_item_id("rs");
+                let item = sonic_rs::json!({ "type": "reasoning", "id": item_id, "summary": [] });
+                let part = sonic_rs::json!({
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "summary_index": 0,
+                    "part": { "type": "summary_text", "text": "" }
+                });
+                (item_id, item, "response.reasoning_summary_part.added", part)
+            }
+            ItemKind::Message => {
+                let item_id = id::response_item_id("msg");
+                let item = sonic_rs::json!({
+                    "type": "message",
+                    "id": item_id,
+                    "status": "in_progress",
+                    "role": "assistant",
+                    "content": []
+                });
+                let part = sonic_rs::json!({
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "content_index": 0,
+                    "part": { "type": "output_text", "text": "", "annotations": [] }
+                });
+                (item_id, item, "response.content_part.added", part)
+            }
+        };
+        self.current = Some(OpenItem {
+            kind,
+            id: item_id,
+            output_index,
+            text: String::new(),
+        });
+        vec![
+            self.emit(
+                "response.output_item.added",
+                sonic_rs::json!({ "output_index": output_index, "item": item }),
+            ),
+            self.emit(part_event, part),
+        ]
+    }
+
+    fn close_current(&mut self) -> Vec<(&'static str, String)> {
+        let Some(cur) = self.current.take() else {
+            return Vec::new();
+        };
+        let (item, events) = match cur.kind {
+            ItemKind::Reasoning => {
+                let item = OutputItem::Reasoning {
+                    id: cur.id.clone(),
+                    summary: vec![SummaryText {
+                        typ: "summary_text",
+                        text: cur.text.clone(),
+                    }],
+                };
+                let events = vec![
+                    self.emit(
+                        "response.reasoning_summary_text.done",
+                        sonic_rs::json!({
+                            "item_id": cur.id,
+                            "output_index": cur.output_index,
+                            "summary_index": 0,
+                            "text": cur.text
+                        }),
+                    ),
+                    self.emit(
+                        "response.reasoning_summary_part.done",
+                        sonic_rs::json!({
+                            "item_id": cur.id,
+                            "output_index": cur.output_index,
+                            "summary_index": 0,
+                            "part": { "type": "summary_text", "text": cur.text }
+                        }),
+                    ),
+                ];
+                (item, events)
+            }
+            ItemKind::Message => {
+                let item = OutputItem::Message {
+                    id: cur.id.clone(),
+                    status: "completed",
+                    role: "assistant",
+                    content: vec![OutputText {
+                        typ: "output_text",
+                        text: cur.text.clone(),
+                        annotations: Vec::new(),
+                    }],
+                };
+                let events = vec![
+                    self.emit(
+                        "response.output_text.done",
+                        sonic_rs::json!({
+                            "item_id": cur.id,
+                            "output_index": cur.output_index,
+                            "content_index": 0,
+                            "text": cur.text
+                        }),
+                    ),
+                    self.emit(
+                        "response.content_part.done",
+                        sonic_rs::json!({
+                            "item_id": cur.id,
+                            "output_index": cur.output_index,
+                            "content_index": 0,
+                            "part": { "type": "output_text", "text": cur.text, "annotations": [] }
+                        }),
+                    ),
+                ];
+                (item, events)
+            }
+        };
+        let mut events = events;
+        events.push(self.item_done(cur.output_index, &item));
+        self.output.push(item);
+        events
+    }
+
+    fn write_function_call(
+        &mut self,
+        call_id: String,
+        name: String,
+        arguments: String,
+    ) -> Vec<(&'static str, String)> {
+        let output_index = self.output.len();
+        let item_id = id::response_item_id("fc");
+        let mut events = vec![
+            self.emit(
+                "response.output_item.added",
+                sonic_rs::json!({
+                    "output_index": output_index,
+                    "item": {
+                        "type": "function_call",
+                        "id": item_id,
+                        "call_id": call_id,
+                        "name": name,
+                        "arguments": "",
+                        "status": "in_progress"
+                    }
+                }),
+            ),
+            self.emit(
+                "response.function_call_arguments.delta",
+                sonic_rs::json!({
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "delta": arguments
+                }),
+            ),
+            self.emit(
+                "response.function_call_arguments.done",
+                sonic_rs::json!({
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "arguments": arguments
+                }),
+            ),
+        ];
+        let item = OutputItem::FunctionCall {
+            id: item_id,
+            call_id,
+            name,
+            arguments,
+            status: "completed",
+        };
+        events.push(self.item_done(output_index, &item));
+        self.output.push(item);
+        events
+    }
+
+    fn item_done(&mut self, output_index: usize, item: &OutputItem) -> (&'static str, String) {
+        let item = sonic_rs::to_value(item).unwrap_or_default();
+        self.emit(
+            "response.output_item.done",
+            sonic_rs::json!({ "output_index": output_index, "item": item }),
+        )
+    }
+
+    fn emit(&mut self, typ: &'static str, mut body: sonic_rs::Value) -> (&'static str, String) {
+        if let Some(obj) = body.as_object_mut() {
+            obj.insert("type", typ);
+            obj.insert("sequence_number", self.seq);
+        }
+        self.seq += 1;
+        (typ, sonic_rs::to_string(&body).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::types::FunctionCall as VFunctionCall;
+    use sonic_rs::{JsonContainerTrait, JsonValueTrait};
+    use std::collections::HashMap;
+
+    fn meta() -> ResponseMeta {
+        ResponseMeta {
+            id: "resp_test".to_string(),
+            created_at: 0,
+            model: "gemini-3-flash".to_string(),
+            previous_response_id: None,
+            instructions: None,
+            parallel_tool_calls: true,
+            tool_choice: sonic_rs::to_value("auto").unwrap(),
+            tools: Vec::new(),
+        }
+    }
+
+    fn part(text: &str, thought: bool) -> StreamDataPart {
+        StreamDataPart {
+            text: text.to_string(),
+            function_call: None,
+            inline_data: None,
+            thought,
+            thought_signature: String::new(),
+        }
+    }
+
+    #[test]
+    fn emits_typed_event_sequence() {
+        let mut w = ResponsesStreamWriter::new(meta(), "req".to_string());
+        let mut names: Vec<&str> = w.start().into_iter().map(|(n, _)| n).collect();
+        for p in [part("think", true), part("Hel", false), part("lo", false)] {
+            names.extend(w.process_part(&p).0.into_iter().map(|(n, _)| n));
+        }
+        let mut args = HashMap::new();
+        args.insert("q".to_string(), sonic_rs::to_value("x").unwrap());
+        let call = StreamDataPart {
+            function_call: Some(VFunctionCall {
+                id: "call_1".to_string(),
+                name: "lookup".to_string(),
+                args,
+            }),
+            ..part("", false)
+        };
+        names.extend(w.process_part(&call).0.into_iter().map(|(n, _)| n));
+        let finish = w.finish(None);
+        names.extend(finish.iter().map(|(n, _)| *n));
+
+        assert_eq!(
+            names,
+            [
+                "response.created",
+                "response.in_progress",
+                "response.output_item.added",
+                "response.reasoning_summary_part.added",
+                "response.reasoning_summary_text.delta",
+                "response.reasoning_summary_text.done",
+                "response.reasoning_summary_part.done",
+                "response.output_item.done",
+                "response.output_item.added",
+                "response.content_part.added",
+                "response.output_text.delta",
+                "response.output_text.delta",
+                "response.output_text.done",
+                "response.content_part.done",
+                "response.output_item.done",
+                "response.output_item.added",
+                "response.function_call_arguments.delta",
+                "response.function_call_arguments.done",
+                "response.output_item.done",
+                "response.completed",
+            ]
+        );
+
+        let completed: sonic_rs::Value = sonic_rs::from_str(&finish[0].1).unwrap();
+        assert_eq!(
+            completed.get("sequence_number").and_then(|v| v.as_u64()),
+            Some(19)
+        );
+        let output = completed
+            .get("response")
+            .and_then(|r| r.get("output"))
+            .and_then(|o| o.as_array())
+            .map(|a| a.len());
+        assert_eq!(output, Some(3));
+
+        let msg = w.assistant_message();
+        assert_eq!(msg.content.as_str(), Some("Hello"));
+        assert_eq!(msg.reasoning, "think");
+        assert_eq!(msg.tool_calls[0].id, "call_1");
+    }
+}
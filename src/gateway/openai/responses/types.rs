@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// OpenAI Responses API 请求体（`POST /v1/responses`）。
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResponsesRequest {
+    pub model: String,
+    /// 字符串或 input item 数组。
+    #[serde(default)]
+    pub input: sonic_rs::Value,
+    #[serde(default)]
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub previous_response_id: Option<String>,
+    #[serde(default)]
+    pub stream: bool,
+    /// 缺省为 true：保存本次响应，供后续 previous_response_id 续接。
+    #[serde(default)]
+    pub store: Option<bool>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub top_p: Option<f64>,
+    #[serde(default)]
+    pub max_output_tokens: Option<i32>,
+    #[serde(default)]
+    pub reasoning: Option<ReasoningParam>,
+    #[serde(default)]
+    pub tools: Vec<ResponsesTool>,
+    #[serde(default)]
+    pub tool_choice: Option<sonic_rs::Value>,
+    #[serde(default)]
+    pub parallel_tool_calls: Option<bool>,
+    #[serde(default)]
+    pub text: Option<TextParam>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReasoningParam {
+    #[serde(default)]
+    pub effort: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TextParam {
+    #[serde(default)]
+    pub format: Option<TextFormat>,
+}
+
+/// `text.format`：与 Chat 的 response_format 相同，但 json_schema 字段是扁平的。
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TextFormat {
+    #[serde(rename = "type", default)]
+    pub typ: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub schema: HashMap<String, sonic_rs::Value>,
+    #[serde(default)]
+    pub strict: Option<bool>,
+}
+
+/// Responses API 工具定义（扁平结构，仅支持 type=function）。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponsesTool {
+    #[serde(rename = "type")]
+    pub typ: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<HashMap<String, sonic_rs::Value>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseObject {
+    pub id: String,
+    pub object: &'static str,
+    pub created_at: i64,
+    pub status: &'static str,
+    pub model: String,
+    pub output: Vec<OutputItem>,
+    pub previous_response_id: Option<String>,
+    pub instructions: Option<String>,
+    pub parallel_tool_calls: bool,
+    pub tool_choice: sonic_rs::Value,
+    pub tools: Vec<sonic_rs::Value>,
+    pub usage: Option<ResponseUsage>,
+    pub error: Option<sonic_rs::Value>,
+    pub incomplete_details: Option<sonic_rs::Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputItem {
+    Reasoning {
+        id: String,
+        summary: Vec<SummaryText>,
+    },
+    Message {
+        id: String,
+        status: &'static str,
+        role: &'static str,
+        content: Vec<OutputText>,
+    },
+    FunctionCall {
+        id: String,
+        call_id: String,
+        name: String,
+        arguments: String,
+        status: &'static str,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryText {
+    #[serde(rename = "type")]
+    pub typ: &'static str,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputText {
+    #[serde(rename = "type")]
+    pub typ: &'static str,
+    pub text: String,
+    pub annotations: Vec<sonic_rs::Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseUsage {
+    pub input_tokens: i32,
+    pub input_tokens_details: InputTokensDetails,
+    pub output_tokens: i32,
+    pub output_tokens_details: OutputTokensDetails,
+    pub total_tokens: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InputTokensDetails {
+    pub cached_tokens: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputTokensDetails {
+    pub reasoning_tokens: i32,
+}
+
+/// 一次响应中与输出无关、需要原样回显的字段。
+#[derive(Debug, Clone)]
+pub struct ResponseMeta {
+    pub id: String,
+    pub created_at: i64,
+    pub model: String,
+    pub previous_response_id: Option<String>,
+    pub instructions: Option<String>,
+    pub parallel_tool_calls: bool,
+    pub tool_choice: sonic_rs::Value,
+    pub tools: Vec<sonic_rs::Value>,
+}
+
+impl ResponseMeta {
+    pub fn to_object(
+        &self,
+        status: &'static str,
+        output: Vec<OutputItem>,
+        usage: Option<ResponseUsage>,
+    ) -> ResponseObject {
+        ResponseObject {
+            id: self.id.clone(),
+            object: "response",
+            created_at: self.created_at,
+            status,
+            model: self.model.clone(),
+            output,
+            previous_response_id: self.previous_response_id.clone(),
+            instructions: self.instructions.clone(),
+            parallel_tool_calls: self.parallel_tool_calls,
+            tool_choice: self.tool_choice.clone(),
+            tools: self.tools.clone(),
+            usage,
+            error: None,
+            incomplete_details: None,
+        }
+    }
+}
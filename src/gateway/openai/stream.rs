@@ -1,6 +1,7 @@
+use super::convert::{convert_usage, stream_finish_reason};
 use super::types::{ChatCompletion, Choice, Delta, ToolCall, Usage};
 use crate::util::{id, model as modelutil};
-use crate::vertex::types::StreamDataPart;
+use crate::vertex::types::{StreamData, StreamDataPart, StreamResult};
 use chrono::Utc;
 
 /// OpenAI SSE: 写入 `{"error":{"message":...,"type":"server_error"}}` 事件并结束。
@@ -52,6 +53,7 @@ pub struct StreamWriter {
     pending_sig: String,
     is_claude_thinking: bool,
     is_gemini_pro_image: bool,
+    include_usage: bool,
 
     log_enabled: bool,
     log_events: Vec<sonic_rs::Value>,
@@ -65,6 +67,7 @@ impl StreamWriter {
         created: i64,
         model: String,
         request_id: String,
+        include_usage: bool,
         log_enabled: bool,
     ) -> Self {
         let is_claude_thinking = modelutil::is_claude_thinking(&model);
@@ -82,6 +85,7 @@ impl StreamWriter {
             pending_sig: String::new(),
             is_claude_thinking,
             is_gemini_pro_image,
+            include_usage,
 
             log_enabled,
             log_events: Vec::new(),
@@ -90,6 +94,23 @@ impl StreamWriter {
         }
     }
 
+    /// 处理上游一个 SSE 数据块：逐个处理首个候选的 Part，候选结束时输出缓冲的 tool calls。
+    pub fn process_chunk(&mut self, data: &StreamData) -> (Vec<String>, Vec<SignatureSave>) {
+        let mut events = Vec::new();
+        let mut saves = Vec::new();
+        if let Some(cand) = data.response.candidates.first() {
+            for p in &cand.content.parts {
+                let (ev, sv) = self.process_part(p);
+                events.extend(ev);
+                saves.extend(sv);
+            }
+            if !cand.finish_reason.is_empty() {
+                events.extend(self.flush_tool_calls());
+            }
+        }
+        (events, saves)
+    }
+
     pub fn process_part(&mut self, part: &StreamDataPart) -> (Vec<String>, Vec<SignatureSave>) {
         let mut saves: Vec<SignatureSave> = Vec::new();
 
@@ -131,6 +152,11 @@ impl StreamWriter {
         }
 
         if let Some(fc) = &part.function_call {
+            // 结构化输出模拟：参数作为正文输出。
+            if fc.name == super::convert::STRUCTURED_OUTPUT_TOOL {
+                let json = sonic_rs::to_string(&fc.args).unwrap_or_else(|_| "{}".to_string());
+                return (self.write_content(&json), saves);
+            }
             let tool_call_id = if fc.id.is_empty() {
                 id::tool_call_id()
             } else {
@@ -188,9 +214,23 @@ impl StreamWriter {
         self.write_tool_calls(&calls)
     }
 
+    /// 上游流结束后调用：按解析结果确定 finish_reason 与 usage 并输出结束事件。
+    pub fn finish_stream(&mut self, result: &StreamResult) -> Vec<String> {
+        let finish = stream_finish_reason(result);
+        let usage = convert_usage(result.usage.as_ref());
+        self.finish_events(finish, usage)
+    }
+
+    /// 结束事件：默认把 usage 附在带 finish_reason 的 chunk 上；
+    /// `stream_options.include_usage` 时改为单独追加一个 choices 为空的 usage chunk。
     pub fn finish_events(&mut self, finish_reason: &str, usage: Option<Usage>) -> Vec<String> {
         let mut out = Vec::new();
         out.extend(self.write_role());
+        let (finish_usage, trailing_usage) = if self.include_usage {
+            (None, usage)
+        } else {
+            (usage, None)
+        };
         out.extend(self.write_chunk(
             Delta {
                 role: String::new(),
@@ -199,8 +239,24 @@ impl StreamWriter {
                 reasoning: String::new(),
             },
             Some(finish_reason.to_string()),
-            usage,
+            finish_usage,
         ));
+        if let Some(usage) = trailing_usage {
+            let chunk = ChatCompletion {
+                id: self.id.clone(),
+                object: "chat.completion.chunk".to_string(),
+                created: self.created,
+                model: self.model.clone(),
+                choices: Vec::new(),
+                usage: Some(usage),
+            };
+            if self.log_enabled {
+                self.collect_chunk_for_log(&chunk);
+            }
+            if let Ok(s) = sonic_rs::to_string(&chunk) {
+                out.push(s);
+            }
+        }
         out.push("[DONE]".to_string());
         out
     }
@@ -439,3 +495,55 @@ fn take_valid_utf8(buf: &mut Vec<u8>) -> Option<String> {
 pub fn now_unix() -> i64 {
     Utc::now().timestamp()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::openai::convert::convert_usage;
+    use crate::vertex::types::UsageMetadata;
+    use sonic_rs::prelude::*;
+
+    fn usage() -> Option<Usage> {
+        convert_usage(Some(&UsageMetadata {
+            prompt_token_count: 100,
+            candidates_token_count: 20,
+            total_token_count: 150,
+            thoughts_token_count: 30,
+            cached_content_token_count: 64,
+        }))
+    }
+
+    fn writer(include_usage: bool) -> StreamWriter {
+        StreamWriter::new(
+            "chatcmpl-1".to_string(),
+            0,
+            "gemini-2.5-flash".to_string(),
+            "req".to_string(),
+            include_usage,
+            false,
+        )
+    }
+
+    #[test]
+    fn include_usage_emits_trailing_usage_chunk() {
+        let events = writer(true).finish_events("stop", usage());
+        assert_eq!(events.last().map(String::as_str), Some("[DONE]"));
+        let finish: sonic_rs::Value = sonic_rs::from_str(&events[events.len() - 3]).unwrap();
+        assert!(finish.get("usage").is_none());
+        let trailing: sonic_rs::Value = sonic_rs::from_str(&events[events.len() - 2]).unwrap();
+        assert_eq!(trailing["choices"].as_array().map(|a| a.len()), Some(0));
+        assert_eq!(trailing["usage"]["completion_tokens"].as_i64(), Some(50));
+        assert_eq!(
+            trailing["usage"]["completion_tokens_details"]["reasoning_tokens"].as_i64(),
+            Some(30)
+        );
+        assert_eq!(
+            trailing["usage"]["prompt_tokens_details"]["cached_tokens"].as_i64(),
+            Some(64)
+        );
+
+        let events = writer(false).finish_events("stop", usage());
+        let finish: sonic_rs::Value = sonic_rs::from_str(&events[events.len() - 2]).unwrap();
+        assert_eq!(finish["usage"]["total_tokens"].as_i64(), Some(150));
+    }
+}
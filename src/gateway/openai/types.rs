@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ChatRequest {
@@ -13,16 +13,71 @@ pub struct ChatRequest {
     pub top_p: Option<f64>,
     #[serde(rename = "max_tokens", default)]
     pub max_tokens: i32,
-    /// Stop 为 OpenAI 兼容字段：当前未映射到 Vertex generationConfig.stopSequences（保持历史行为）。
-    #[serde(default)]
+    /// 新版客户端使用的 max_tokens 替代字段，优先于 max_tokens。
+    #[serde(rename = "max_completion_tokens", default)]
+    pub max_completion_tokens: i32,
+    /// 字符串或字符串数组，映射到 generationConfig.stopSequences。
+    #[serde(default, deserialize_with = "deserialize_stop")]
     pub stop: Vec<String>,
     #[serde(default)]
     pub tools: Vec<Tool>,
-    /// ToolChoice 为 OpenAI 兼容字段：当前未实现 tool_choice 语义（保持历史行为）。
+    /// none / auto / required / {"type":"function","function":{"name":..}}
     #[serde(rename = "tool_choice", default)]
     pub tool_choice: Option<sonic_rs::Value>,
     #[serde(rename = "reasoning_effort", default)]
     pub reasoning_effort: String,
+    #[serde(rename = "response_format", default)]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(rename = "stream_options", default)]
+    pub stream_options: Option<StreamOptions>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StreamOptions {
+    /// 为 true 时在 `[DONE]` 前追加一个 choices 为空、仅含 usage 的 chunk。
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+/// OpenAI `response_format`：text / json_object / json_schema。
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ResponseFormat {
+    #[serde(rename = "type", default)]
+    pub typ: String,
+    #[serde(default)]
+    pub json_schema: Option<JsonSchemaFormat>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JsonSchemaFormat {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub schema: std::collections::HashMap<String, sonic_rs::Value>,
+    /// Gemini responseSchema 总是强制生效；工具模拟路径无法保证严格匹配。
+    #[serde(default)]
+    pub strict: Option<bool>,
+}
+
+/// OpenAI `stop`：允许 `"x"`、`["x","y"]` 或 null。
+fn deserialize_stop<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stop {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Option::<Stop>::deserialize(deserializer)? {
+        Some(Stop::One(s)) => vec![s],
+        Some(Stop::Many(v)) => v,
+        None => Vec::new(),
+    })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -121,8 +176,23 @@ pub struct Delta {
 #[derive(Debug, Clone, Serialize)]
 pub struct Usage {
     pub prompt_tokens: i32,
+    /// 包含 reasoning token（与 OpenAI 语义一致）。
     pub completion_tokens: i32,
     pub total_tokens: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptTokensDetails {
+    pub cached_tokens: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionTokensDetails {
+    pub reasoning_tokens: i32,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -135,5 +205,16 @@ pub struct ModelsResponse {
 pub struct ModelItem {
     pub id: String,
     pub object: String,
+    pub created: i64,
     pub owned_by: String,
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+    pub capabilities: ModelCapabilities,
+    pub quota_group: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelCapabilities {
+    pub thinking: bool,
+    pub vision: bool,
 }
@@ -0,0 +1,326 @@
+//! 按 API Key 的配额与限流。
+//!
+//! - RPM：60 秒滑动窗口内的请求数
+//! - 每日 token：按 UTC+8 自然日累计（以后端 usageMetadata 为准）
+//! - 模型白名单：与 `runtime_config::map_client_model_id` 映射后的模型比较
+//!
+//! 计数持久化到 data_dir/api_key_usage.json（与 accounts.json 同目录），重启后继续生效。
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::runtime_config::{self, ApiKeyEntry};
+use crate::vertex::types::UsageMetadata;
+
+const USAGE_FILENAME: &str = "api_key_usage.json";
+const RPM_WINDOW_MS: i64 = 60_000;
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 单个密钥的用量计数。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyUsage {
+    /// 最近 60 秒内的请求时间戳（毫秒）
+    #[serde(default)]
+    pub recent_requests: VecDeque<i64>,
+    /// 当前计数所属自然日（YYYY-MM-DD，UTC+8）
+    #[serde(default)]
+    pub day: String,
+    /// 当日已消耗 token
+    #[serde(default)]
+    pub day_tokens: u64,
+}
+
+impl KeyUsage {
+    fn roll(&mut self, now_ms: i64, day: &str) {
+        while self
+            .recent_requests
+            .front()
+            .is_some_and(|t| now_ms - *t >= RPM_WINDOW_MS)
+        {
+            self.recent_requests.pop_front();
+        }
+        if self.day != day {
+            self.day = day.to_string();
+            self.day_tokens = 0;
+        }
+    }
+}
+
+/// 配额拒绝原因。
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QuotaRejection {
+    #[error("API Key \"{key}\" 无权使用模型 {model}")]
+    ModelNotAllowed { key: String, model: String },
+    #[error("API Key \"{key}\" 已超过每分钟 {limit} 次请求限制，请稍后重试")]
+    RateLimited { key: String, limit: u32 },
+    #[error("API Key \"{key}\" 已用完今日 {limit} tokens 配额")]
+    DailyTokensExhausted { key: String, limit: u64 },
+}
+
+impl QuotaRejection {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            QuotaRejection::ModelNotAllowed { .. } => StatusCode::FORBIDDEN,
+            _ => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+}
+
+pub struct KeyQuotaManager {
+    path: PathBuf,
+    usage: Mutex<HashMap<String, KeyUsage>>,
+    dirty: AtomicBool,
+}
+
+impl KeyQuotaManager {
+    pub fn new(data_dir: &str) -> Self {
+        let path = PathBuf::from(data_dir).join(USAGE_FILENAME);
+        let usage = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                tracing::warn!("API Key 用量文件解析失败（{}）：{e}", path.display());
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            path,
+            usage: Mutex::new(usage),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// 请求准入检查：通过时计入 RPM 窗口。
+    /// `key_name` 为 None（未启用鉴权）或默认密钥时不做限制。
+    pub fn check_and_record(
+        &self,
+        key_name: Option<&str>,
+        model: &str,
+    ) -> Result<(), QuotaRejection> {
+        let Some(entry) = key_name.and_then(runtime_config::find_api_key_by_name) else {
+            return Ok(());
+        };
+        self.check_at(&entry, model, Utc::now())
+    }
+
+    /// 仅校验模型白名单，不计入 RPM（用于 count_tokens 等辅助接口）。
+    pub fn check_model(&self, key_name: Option<&str>, model: &str) -> Result<(), QuotaRejection> {
+        let Some(entry) = key_name.and_then(runtime_config::find_api_key_by_name) else {
+            return Ok(());
+        };
+        if !entry.allows_model(model) {
+            return Err(QuotaRejection::ModelNotAllowed {
+                key: entry.name.clone(),
+                model: model.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// 记录一次请求消耗的 token。
+    pub fn record_usage(&self, key_name: Option<&str>, usage: Option<&UsageMetadata>) {
+        let (Some(name), Some(usage)) = (key_name, usage) else {
+            return;
+        };
+        if runtime_config::find_api_key_by_name(name).is_none() {
+            return;
+        }
+        self.add_tokens_at(name, usage_total_tokens(usage), Utc::now());
+    }
+
+    /// 当前用量快照（key = 密钥名称）。
+    pub fn snapshot(&self) -> HashMap<String, KeyUsage> {
+        let now = Utc::now();
+        let day = day_key(now);
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        for u in usage.values_mut() {
+            u.roll(now.timestamp_millis(), &day);
+        }
+        usage.clone()
+    }
+
+    fn check_at(
+        &self,
+        entry: &ApiKeyEntry,
+        model: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), QuotaRejection> {
+        if !entry.allows_model(model) {
+            return Err(QuotaRejection::ModelNotAllowed {
+                key: entry.name.clone(),
+                model: model.to_string(),
+            });
+        }
+
+        let now_ms = now.timestamp_millis();
+        let day = day_key(now);
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        let u = usage.entry(entry.name.clone()).or_default();
+        u.roll(now_ms, &day);
+
+        if entry.rpm > 0 && u.recent_requests.len() >= entry.rpm as usize {
+            return Err(QuotaRejection::RateLimited {
+                key: entry.name.clone(),
+                limit: entry.rpm,
+            });
+        }
+        if entry.tokens_per_day > 0 && u.day_tokens >= entry.tokens_per_day {
+            return Err(QuotaRejection::DailyTokensExhausted {
+                key: entry.name.clone(),
+                limit: entry.tokens_per_day,
+            });
+        }
+
+        u.recent_requests.push_back(now_ms);
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn add_tokens_at(&self, name: &str, tokens: u64, now: DateTime<Utc>) {
+        if tokens == 0 {
+            return;
+        }
+        let day = day_key(now);
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        let u = usage.entry(name.to_string()).or_default();
+        u.roll(now.timestamp_millis(), &day);
+        u.day_tokens = u.day_tokens.saturating_add(tokens);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 有变更时写回磁盘。
+    pub async fn persist(&self) -> anyhow::Result<()> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let data = {
+            let usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+            serde_json::to_vec_pretty(&*usage)?
+        };
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        if let Err(e) = tokio::fs::write(&self.path, data).await {
+            self.dirty.store(true, Ordering::Relaxed);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+/// 后台定期持久化用量计数。
+pub fn spawn_persist_task(mgr: Arc<KeyQuotaManager>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PERSIST_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = mgr.persist().await {
+                tracing::warn!("保存 API Key 用量失败: {e:#}");
+            }
+        }
+    });
+}
+
+/// 一次请求的总 token（后端缺少 totalTokenCount 时按分项求和）。
+pub fn usage_total_tokens(usage: &UsageMetadata) -> u64 {
+    let total = if usage.total_token_count > 0 {
+        usage.total_token_count
+    } else {
+        usage.prompt_token_count + usage.candidates_token_count + usage.thoughts_token_count
+    };
+    total.max(0) as u64
+}
+
+pub(crate) fn day_key(now: DateTime<Utc>) -> String {
+    let tz = FixedOffset::east_opt(8 * 3600).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    now.with_timezone(&tz).format("%Y-%m-%d").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_data_dir() -> String {
+        let dir = std::env::temp_dir().join(format!("ant2api-key-quota-{}", uuid::Uuid::new_v4()));
+        dir.to_string_lossy().to_string()
+    }
+
+    fn entry(rpm: u32, tokens_per_day: u64) -> ApiKeyEntry {
+        ApiKeyEntry {
+            name: "team".to_string(),
+            key: "sk-team".to_string(),
+            rpm,
+            tokens_per_day,
+            allowed_models: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn rpm_limit_uses_sliding_window() {
+        let mgr = KeyQuotaManager::new(&temp_data_dir());
+        let e = entry(2, 0);
+        let t0 = Utc::now();
+
+        assert!(mgr.check_at(&e, "m", t0).is_ok());
+        assert!(mgr.check_at(&e, "m", t0).is_ok());
+        assert!(matches!(
+            mgr.check_at(&e, "m", t0 + chrono::Duration::seconds(30)),
+            Err(QuotaRejection::RateLimited { .. })
+        ));
+        assert!(
+            mgr.check_at(&e, "m", t0 + chrono::Duration::seconds(61))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn daily_tokens_reset_on_new_day() {
+        let mgr = KeyQuotaManager::new(&temp_data_dir());
+        let e = entry(0, 100);
+        let t0 = Utc::now();
+
+        assert!(mgr.check_at(&e, "m", t0).is_ok());
+        mgr.add_tokens_at("team", 150, t0);
+        let err = mgr.check_at(&e, "m", t0).unwrap_err();
+        assert_eq!(err.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        assert!(
+            mgr.check_at(&e, "m", t0 + chrono::Duration::days(1))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn disallowed_model_is_forbidden() {
+        let mgr = KeyQuotaManager::new(&temp_data_dir());
+        let mut e = entry(0, 0);
+        e.allowed_models = vec!["gemini-3-flash".to_string()];
+
+        assert!(mgr.check_at(&e, "gemini-3-flash", Utc::now()).is_ok());
+        let err = mgr.check_at(&e, "claude-opus-4-5", Utc::now()).unwrap_err();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn usage_survives_reload() {
+        let data_dir = temp_data_dir();
+        let mgr = KeyQuotaManager::new(&data_dir);
+        let now = Utc::now();
+        mgr.add_tokens_at("team", 42, now);
+        mgr.persist().await.unwrap();
+
+        let reloaded = KeyQuotaManager::new(&data_dir);
+        let snap = reloaded.snapshot();
+        assert_eq!(snap.get("team").map(|u| u.day_tokens), Some(42));
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
@@ -0,0 +1,10 @@
+//! 请求用量账本。
+//!
+//! 每个完成的请求（成功或重试耗尽）追加一条记录到 data_dir/usage/<YYYY-MM-DD>.jsonl
+//! （按 UTC+8 自然日切分），供 WebUI 按天/模型/账号/密钥汇总。
+
+pub mod store;
+pub mod types;
+
+pub use store::UsageLedger;
+pub use types::{LedgerEntry, UsageRecord, UsageRow, UsageSummary};
@@ -0,0 +1,342 @@
+use super::types::{LedgerEntry, SummaryBuilder, UsageRecord, UsageSummary};
+use crate::key_quota::day_key;
+use anyhow::Context;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{Mutex, mpsc, oneshot};
+use tokio::time::{Duration, interval};
+
+const USAGE_DIRNAME: &str = "usage";
+const QUEUE_CAPACITY: usize = 1024;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const BATCH_SIZE: usize = 256;
+/// 汇总查询允许的最大天数。
+pub const MAX_SUMMARY_DAYS: u32 = 90;
+
+#[derive(Debug)]
+pub struct UsageLedger {
+    dir: PathBuf,
+    queue_tx: mpsc::Sender<UsageRecord>,
+    /// 立即刷盘请求（退出前调用），刷盘完成后回复。
+    flush_tx: mpsc::Sender<oneshot::Sender<()>>,
+    writer: Mutex<WriterState>,
+}
+
+#[derive(Debug, Default)]
+struct WriterState {
+    date: String,
+    file: Option<tokio::fs::File>,
+}
+
+impl UsageLedger {
+    pub fn new(data_dir: &str) -> Arc<Self> {
+        let (queue_tx, queue_rx) = mpsc::channel::<UsageRecord>(QUEUE_CAPACITY);
+        let (flush_tx, flush_rx) = mpsc::channel::<oneshot::Sender<()>>(1);
+        let ledger = Arc::new(Self {
+            dir: PathBuf::from(data_dir).join(USAGE_DIRNAME),
+            queue_tx,
+            flush_tx,
+            writer: Mutex::new(WriterState::default()),
+        });
+
+        // 启动后台刷盘任务
+        UsageLedger::start_worker(ledger.clone(), queue_rx, flush_rx);
+        ledger
+    }
+
+    /// 开始记录一个请求。
+    pub fn begin(
+        self: &Arc<Self>,
+        started_at: Instant,
+        key: Option<&str>,
+        client_model: &str,
+        backend_model: &str,
+        stream: bool,
+    ) -> LedgerEntry {
+        LedgerEntry::new(
+            self.clone(),
+            started_at,
+            key,
+            client_model,
+            backend_model,
+            stream,
+        )
+    }
+
+    pub(super) async fn append(&self, rec: UsageRecord) {
+        // 与 signature store 一致：队列满时背压等待。
+        let _ = self.queue_tx.send(rec).await;
+    }
+
+    /// 立即写入队列中已有的全部记录（退出前调用，避免丢失最后一个刷盘周期内的记录）。
+    pub async fn flush(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.flush_tx.send(done_tx).await.is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+
+    /// 汇总最近 `days` 天（含今天）的用量。
+    pub async fn summary(&self, days: u32) -> anyhow::Result<UsageSummary> {
+        let days = days.clamp(1, MAX_SUMMARY_DAYS);
+        let mut builder = SummaryBuilder::default();
+        for day in recent_days(Utc::now(), days) {
+            self.scan_day(&day, |rec| builder.add(&day, rec)).await?;
+        }
+        Ok(builder.build(days))
+    }
+
+    /// 读取某天最近的 `limit` 条记录（按时间倒序）。
+    pub async fn recent_records(
+        &self,
+        day: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<UsageRecord>> {
+        let mut out: Vec<UsageRecord> = Vec::new();
+        self.scan_day(day, |rec| out.push(rec.clone())).await?;
+        out.reverse();
+        out.truncate(limit);
+        Ok(out)
+    }
+
+    async fn scan_day(&self, day: &str, mut f: impl FnMut(&UsageRecord)) -> anyhow::Result<()> {
+        let Some(path) = self.path_for(day) else {
+            anyhow::bail!("非法日期: {day}");
+        };
+        let file = match tokio::fs::File::open(&path).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).with_context(|| format!("打开用量文件失败: {path:?}")),
+        };
+        let mut lines = BufReader::new(file).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.is_empty() {
+                continue;
+            }
+            // 进程崩溃可能留下半行：跳过无法解析的记录。
+            if let Ok(rec) = sonic_rs::from_str::<UsageRecord>(&line) {
+                f(&rec);
+            }
+        }
+        Ok(())
+    }
+
+    /// 仅接受 YYYY-MM-DD，避免路径穿越。
+    fn path_for(&self, day: &str) -> Option<PathBuf> {
+        chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?;
+        Some(self.dir.join(format!("{day}.jsonl")))
+    }
+
+    fn start_worker(
+        ledger: Arc<Self>,
+        mut rx: mpsc::Receiver<UsageRecord>,
+        mut flush_rx: mpsc::Receiver<oneshot::Sender<()>>,
+    ) {
+        tokio::spawn(async move {
+            let mut tick = interval(FLUSH_INTERVAL);
+            let mut batch: Vec<UsageRecord> = Vec::new();
+
+            loop {
+                tokio::select! {
+                    Some(rec) = rx.recv() => {
+                        batch.push(rec);
+                        if batch.len() >= BATCH_SIZE {
+                            ledger.flush_batch(&mut batch).await;
+                        }
+                    }
+                    _ = tick.tick() => {
+                        ledger.flush_batch(&mut batch).await;
+                    }
+                    Some(done) = flush_rx.recv() => {
+                        while let Ok(rec) = rx.try_recv() {
+                            batch.push(rec);
+                        }
+                        ledger.flush_batch(&mut batch).await;
+                        let _ = done.send(());
+                    }
+                    else => break,
+                }
+            }
+        });
+    }
+
+    async fn flush_batch(&self, batch: &mut Vec<UsageRecord>) {
+        if batch.is_empty() {
+            return;
+        }
+        // 磁盘写入失败：batch 中只保留未写入的天，下次 tick 再试。
+        if let Err(e) = self.persist(batch).await {
+            tracing::warn!("写入用量账本失败: {e:#}");
+        }
+    }
+
+    /// 按天依次写入，每天写入成功后立即从 batch 移除，避免重试时重复写入。
+    async fn persist(&self, batch: &mut Vec<UsageRecord>) -> anyhow::Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .context("创建 usage 目录失败")?;
+
+        let mut writer = self.writer.lock().await;
+        while let Some(first) = batch.first() {
+            let date = record_day(first);
+            let n = batch
+                .iter()
+                .take_while(|rec| record_day(rec) == date)
+                .count();
+            let mut buf: Vec<u8> = Vec::new();
+            for rec in &batch[..n] {
+                buf.extend_from_slice(&sonic_rs::to_vec(rec).context("序列化用量记录失败")?);
+                buf.push(b'\n');
+            }
+            self.write_day(&mut writer, &date, &buf).await?;
+            batch.drain(..n);
+        }
+        Ok(())
+    }
+
+    async fn write_day(
+        &self,
+        writer: &mut WriterState,
+        date: &str,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        if writer.date != date || writer.file.is_none() {
+            let path = self.dir.join(format!("{date}.jsonl"));
+            let file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+                .with_context(|| format!("打开用量文件失败: {path:?}"))?;
+            writer.date = date.to_string();
+            writer.file = Some(file);
+        }
+        let file = writer
+            .file
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("writer 未初始化"))?;
+        file.write_all(data).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+fn record_day(rec: &UsageRecord) -> String {
+    let ts = DateTime::<Utc>::from_timestamp_millis(rec.ts).unwrap_or_else(Utc::now);
+    day_key(ts)
+}
+
+/// 最近 `days` 天的日期键（UTC+8，升序，含今天）。
+fn recent_days(now: DateTime<Utc>, days: u32) -> Vec<String> {
+    (0..days as i64)
+        .rev()
+        .map(|i| day_key(now - ChronoDuration::days(i)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::types::UsageMetadata;
+
+    #[tokio::test]
+    async fn records_are_appended_and_summarized() {
+        let dir = std::env::temp_dir().join(format!("ant2api-usage-{}", uuid::Uuid::new_v4()));
+        let ledger = UsageLedger::new(&dir.to_string_lossy());
+
+        let mut ok = ledger.begin(
+            Instant::now(),
+            Some("team-a"),
+            "gpt-x",
+            "gemini-3-flash",
+            false,
+        );
+        ok.attempt("a@example.com");
+        ok.attempt("b@example.com");
+        ok.finish(
+            200,
+            Some(&UsageMetadata {
+                prompt_token_count: 10,
+                candidates_token_count: 4,
+                total_token_count: 16,
+                thoughts_token_count: 2,
+                cached_content_token_count: 0,
+            }),
+        )
+        .await;
+        let mut failed = ledger.begin(Instant::now(), None, "gpt-x", "gemini-3-flash", true);
+        failed.attempt("a@example.com");
+        failed.finish(429, None).await;
+
+        // 立即刷盘，不等待定时周期。
+        ledger.flush().await;
+
+        let summary = ledger.summary(1).await.unwrap();
+        assert_eq!(summary.total.requests, 2);
+        assert_eq!(summary.total.errors, 1);
+        assert_eq!(summary.total.total_tokens(), 16);
+        assert_eq!(summary.by_model[0].name, "gpt-x");
+        assert_eq!(summary.by_account[0].name, "a@example.com");
+        assert_eq!(summary.by_key.len(), 2);
+
+        let today = day_key(Utc::now());
+        let records = ledger.recent_records(&today, 10).await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].status, 429);
+        assert_eq!(records[1].retries, 1);
+        assert!(ledger.recent_records("../x", 10).await.is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn partial_flush_failure_keeps_only_unwritten_days() {
+        let dir = std::env::temp_dir().join(format!("ant2api-usage-{}", uuid::Uuid::new_v4()));
+        let ledger = UsageLedger::new(&dir.to_string_lossy());
+        let record = |ts: i64| UsageRecord {
+            ts,
+            key: String::new(),
+            client_model: "gpt-x".to_string(),
+            backend_model: "gemini-3-flash".to_string(),
+            account: String::new(),
+            stream: false,
+            prompt_tokens: 1,
+            completion_tokens: 1,
+            thought_tokens: 0,
+            latency_ms: 1,
+            status: 200,
+            retries: 0,
+        };
+        // 2023-11-14 与 2023-11-15（UTC+8）各一条。
+        let day1 = record(1_699_900_000_000);
+        let day2 = record(1_700_000_000_000);
+        let (date1, date2) = (record_day(&day1), record_day(&day2));
+        assert_ne!(date1, date2);
+
+        // 第二天的文件路径被目录占用，写入失败。
+        let blocked = ledger.dir.join(format!("{date2}.jsonl"));
+        std::fs::create_dir_all(&blocked).unwrap();
+        let mut batch = vec![day1, day2];
+        ledger.flush_batch(&mut batch).await;
+        assert_eq!(batch.len(), 1);
+        assert_eq!(record_day(&batch[0]), date2);
+
+        std::fs::remove_dir(&blocked).unwrap();
+        ledger.flush_batch(&mut batch).await;
+        assert!(batch.is_empty());
+        assert_eq!(ledger.recent_records(&date1, 10).await.unwrap().len(), 1);
+        assert_eq!(ledger.recent_records(&date2, 10).await.unwrap().len(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn recent_days_are_ascending() {
+        let now = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+        let days = recent_days(now, 3);
+        assert_eq!(days, vec!["2023-11-13", "2023-11-14", "2023-11-15"]);
+    }
+}
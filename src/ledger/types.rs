@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::store::UsageLedger;
+use crate::vertex::client::ApiError;
+use crate::vertex::types::UsageMetadata;
+
+/// 账本中的一条请求记录（每行一个 JSON）。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRecord {
+    /// 完成时间（Unix 毫秒）。
+    pub ts: i64,
+    /// API Key 名称（未启用鉴权或默认密钥时为空）。
+    #[serde(default)]
+    pub key: String,
+    pub client_model: String,
+    pub backend_model: String,
+    /// 最后一次尝试使用的账号邮箱。
+    #[serde(default)]
+    pub account: String,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub prompt_tokens: i32,
+    #[serde(default)]
+    pub completion_tokens: i32,
+    #[serde(default)]
+    pub thought_tokens: i32,
+    pub latency_ms: u64,
+    pub status: u16,
+    /// 换号重试次数（首次尝试不计）。
+    #[serde(default)]
+    pub retries: u32,
+}
+
+/// 进行中的请求记录：handler 每次换号调用 `attempt`，结束时调用 `finish` 写入账本。
+pub struct LedgerEntry {
+    ledger: Arc<UsageLedger>,
+    started_at: Instant,
+    attempts: u32,
+    record: UsageRecord,
+}
+
+impl LedgerEntry {
+    pub(super) fn new(
+        ledger: Arc<UsageLedger>,
+        started_at: Instant,
+        key: Option<&str>,
+        client_model: &str,
+        backend_model: &str,
+        stream: bool,
+    ) -> Self {
+        Self {
+            ledger,
+            started_at,
+            attempts: 0,
+            record: UsageRecord {
+                ts: 0,
+                key: key.unwrap_or_default().to_string(),
+                client_model: client_model.to_string(),
+                backend_model: backend_model.to_string(),
+                account: String::new(),
+                stream,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                thought_tokens: 0,
+                latency_ms: 0,
+                status: 0,
+                retries: 0,
+            },
+        }
+    }
+
+    /// 记录一次使用某账号的后端尝试。
+    pub fn attempt(&mut self, account: &str) {
+        self.attempts += 1;
+        self.record.account = account.to_string();
+    }
+
+    /// 结束请求并写入账本。
+    pub async fn finish(mut self, status: u16, usage: Option<&UsageMetadata>) {
+        let rec = &mut self.record;
+        rec.ts = chrono::Utc::now().timestamp_millis();
+        rec.status = status;
+        rec.retries = self.attempts.saturating_sub(1);
+        rec.latency_ms = self.started_at.elapsed().as_millis() as u64;
+        if let Some(u) = usage {
+            rec.prompt_tokens = u.prompt_token_count;
+            rec.completion_tokens = u.candidates_token_count;
+            rec.thought_tokens = u.thoughts_token_count;
+        }
+        self.ledger.append(self.record).await;
+    }
+
+    /// 重试耗尽：按最后一次上游错误的状态码（缺省 503）写入账本。
+    pub async fn finish_error(self, last_err: Option<&ApiError>) {
+        let status = last_err.and_then(|e| e.status()).unwrap_or(503);
+        self.finish(status, None).await;
+    }
+}
+
+/// 一个分组维度（天/模型/账号/密钥）下的汇总行。
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRow {
+    pub name: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub thought_tokens: u64,
+    pub total_latency_ms: u64,
+}
+
+impl UsageRow {
+    fn add(&mut self, rec: &UsageRecord) {
+        self.requests += 1;
+        if !(200..300).contains(&rec.status) {
+            self.errors += 1;
+        }
+        self.prompt_tokens += rec.prompt_tokens.max(0) as u64;
+        self.completion_tokens += rec.completion_tokens.max(0) as u64;
+        self.thought_tokens += rec.thought_tokens.max(0) as u64;
+        self.total_latency_ms += rec.latency_ms;
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens + self.thought_tokens
+    }
+
+    pub fn avg_latency_ms(&self) -> u64 {
+        self.total_latency_ms
+            .checked_div(self.requests)
+            .unwrap_or(0)
+    }
+}
+
+/// 多维度用量汇总。
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageSummary {
+    pub days: u32,
+    pub total: UsageRow,
+    /// 按日期升序。
+    pub by_day: Vec<UsageRow>,
+    /// 以下按请求数降序。
+    pub by_model: Vec<UsageRow>,
+    pub by_account: Vec<UsageRow>,
+    pub by_key: Vec<UsageRow>,
+}
+
+#[derive(Default)]
+pub(super) struct SummaryBuilder {
+    total: UsageRow,
+    by_day: HashMap<String, UsageRow>,
+    by_model: HashMap<String, UsageRow>,
+    by_account: HashMap<String, UsageRow>,
+    by_key: HashMap<String, UsageRow>,
+}
+
+impl SummaryBuilder {
+    pub(super) fn add(&mut self, day: &str, rec: &UsageRecord) {
+        self.total.add(rec);
+        for (map, name) in [
+            (&mut self.by_day, day),
+            (&mut self.by_model, rec.client_model.as_str()),
+            (&mut self.by_account, rec.account.as_str()),
+            (&mut self.by_key, rec.key.as_str()),
+        ] {
+            map.entry(name.to_string())
+                .or_insert_with(|| UsageRow {
+                    name: name.to_string(),
+                    ..UsageRow::default()
+                })
+                .add(rec);
+        }
+    }
+
+    pub(super) fn build(self, days: u32) -> UsageSummary {
+        fn sorted(map: HashMap<String, UsageRow>) -> Vec<UsageRow> {
+            let mut rows: Vec<UsageRow> = map.into_values().collect();
+            rows.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.name.cmp(&b.name)));
+            rows
+        }
+        let mut by_day: Vec<UsageRow> = self.by_day.into_values().collect();
+        by_day.sort_by(|a, b| a.name.cmp(&b.name));
+        UsageSummary {
+            days,
+            total: UsageRow {
+                name: "total".to_string(),
+                ..self.total
+            },
+            by_day,
+            by_model: sorted(self.by_model),
+            by_account: sorted(self.by_account),
+            by_key: sorted(self.by_key),
+        }
+    }
+}
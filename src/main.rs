@@ -3,12 +3,16 @@
 #[global_allocator]
 static ALLOC: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
+pub mod capture;
 pub mod config;
 pub mod credential;
 pub mod error;
 pub mod gateway;
+pub mod key_quota;
+pub mod ledger;
 pub mod logging;
 pub mod memory;
+pub mod metrics;
 pub mod quota_pool;
 pub mod runtime_config;
 pub mod signature;
@@ -28,6 +32,15 @@ async fn main() -> anyhow::Result<()> {
 
     init_tracing(&cfg);
 
+    // 维护子命令：生成 / 轮换 accounts.json 加密密钥（执行后退出，不启动服务）。
+    if let Some(result) = run_accounts_key_command(&cfg).await {
+        return result;
+    }
+    // 调试子命令：离线重放捕获包（执行后退出，不启动服务）。
+    if let Some(result) = run_replay_command(&cfg).await {
+        return result;
+    }
+
     // 初始化运行时配置
     runtime_config::init(&cfg);
 
@@ -40,6 +53,8 @@ async fn main() -> anyhow::Result<()> {
     if let Err(e) = store.load().await {
         tracing::warn!("加载 accounts.json 失败: {e:#}");
     }
+    // accounts.json 被外部修改时自动重新加载。
+    credential::store::spawn_reload_task(store.clone());
     // 启动时先刷新所有账号 token，避免 WebUI 首次加载配额时出现大量 401/未知状态。
     // 刷新失败不阻塞启动：保留原始账号信息，后续按需再刷新。
     let account_count = store.count().await;
@@ -74,6 +89,19 @@ async fn main() -> anyhow::Result<()> {
         quota_pool.clone(),
     );
 
+    // API Key 配额计数（定期持久化到 data_dir）。
+    let key_quota = Arc::new(key_quota::KeyQuotaManager::new(&cfg.data_dir));
+    key_quota::spawn_persist_task(key_quota.clone());
+
+    // Responses API 续接存储（previous_response_id）。
+    let responses = Arc::new(gateway::openai::responses::ResponseStore::new(
+        &cfg.data_dir,
+    ));
+    gateway::openai::responses::spawn_cleanup_task(responses.clone());
+
+    // 请求用量账本（data_dir/usage/<date>.jsonl）。
+    let ledger = ledger::UsageLedger::new(&cfg.data_dir);
+
     // API 网关状态（OpenAI/Claude 共用同一份字段集合，便于注册多套路由）。
     let api_state = Arc::new(gateway::claude::ClaudeState {
         cfg: cfg.clone(),
@@ -81,6 +109,12 @@ async fn main() -> anyhow::Result<()> {
         store: store.clone(),
         quota_pool: quota_pool.clone(),
         sig_mgr,
+        key_quota: key_quota.clone(),
+        responses,
+        ledger: ledger.clone(),
+        affinity: Arc::new(credential::affinity::AccountAffinity::new(
+            std::time::Duration::from_secs(cfg.sticky_session_ttl_secs),
+        )),
     });
 
     // Manager WebUI 状态
@@ -89,6 +123,9 @@ async fn main() -> anyhow::Result<()> {
         quota_pool: quota_pool.clone(),
         data_dir: cfg.data_dir.clone(),
         cfg: cfg.clone(),
+        ledger: ledger.clone(),
+        sessions: gateway::manager::session::SessionStore::new(),
+        login_throttle: gateway::manager::session::LoginThrottle::new(),
     });
 
     // === 公开路由（不需要认证）===
@@ -97,14 +134,18 @@ async fn main() -> anyhow::Result<()> {
         .route("/debug/pprof/heap", get(handle_pprof_heap))
         .route("/login", get(gateway::manager::handle_login_view))
         .route("/login", post(gateway::manager::handle_login))
-        .route("/logout", get(gateway::manager::handle_logout));
+        .with_state(manager_state.clone());
 
-    // === API 路由 ===
+    // === API 路由（API Key 鉴权）===
     let api_routes = Router::new()
         .route(
             "/v1/models",
             get(gateway::openai::handler::handle_list_models),
         )
+        .route(
+            "/v1/models/{model}",
+            get(gateway::openai::handler::handle_get_model),
+        )
         .route(
             "/v1/chat/completions",
             post(gateway::openai::handler::handle_chat_completions),
@@ -114,10 +155,30 @@ async fn main() -> anyhow::Result<()> {
             "/v1/chat/completions/",
             post(gateway::openai::handler::handle_chat_completions),
         )
+        .route(
+            "/v1/responses",
+            post(gateway::openai::responses::handle_responses),
+        )
         .route("/v1/messages", post(gateway::claude::handle_messages))
         // 兼容 Go ServeMux：允许尾随斜杠的同一路径
         .route("/v1/messages/", post(gateway::claude::handle_messages))
-        .with_state(api_state);
+        .route(
+            "/v1/messages/count_tokens",
+            post(gateway::claude::handle_count_tokens),
+        )
+        // Gemini 原生：{model}:generateContent / {model}:streamGenerateContent
+        .route("/v1beta/models", get(gateway::gemini::handle_list_models))
+        .route(
+            "/v1beta/models/{model_action}",
+            post(gateway::gemini::handle_generate_content).get(gateway::gemini::handle_get_model),
+        )
+        // Prometheus 指标（与 /v1 共用 API Key 鉴权）
+        .route("/metrics", get(gateway::metrics::handle_metrics))
+        .layer(middleware::from_fn(
+            gateway::common::api_auth::api_key_auth_middleware,
+        ))
+        .layer(middleware::from_fn(metrics::track_requests))
+        .with_state(api_state.clone());
 
     // === Manager API 路由（需要认证）===
     let manager_api_routes = Router::new()
@@ -125,6 +186,7 @@ async fn main() -> anyhow::Result<()> {
         .route("/manager/api/list", get(gateway::manager::handle_list))
         .route("/manager/api/delete", post(gateway::manager::handle_delete))
         .route("/manager/api/toggle", post(gateway::manager::handle_toggle))
+        .route("/manager/api/proxy", post(gateway::manager::handle_proxy))
         .route(
             "/manager/api/refresh",
             post(gateway::manager::handle_refresh),
@@ -170,15 +232,50 @@ async fn main() -> anyhow::Result<()> {
             "/manager/api/model-id-mapping",
             post(gateway::manager::handle_model_id_mapping_post),
         )
+        .route(
+            "/manager/api/virtual-models",
+            get(gateway::manager::handle_virtual_models_get),
+        )
+        .route(
+            "/manager/api/virtual-models",
+            post(gateway::manager::handle_virtual_models_post),
+        )
+        .route(
+            "/manager/api/api-keys",
+            get(gateway::manager::handle_api_keys_get),
+        )
+        .route(
+            "/manager/api/api-keys",
+            post(gateway::manager::handle_api_keys_post),
+        )
+        .route(
+            "/manager/api/system-prompt-policy",
+            get(gateway::manager::handle_system_prompt_policy_get),
+        )
+        .route(
+            "/manager/api/system-prompt-policy",
+            post(gateway::manager::handle_system_prompt_policy_post),
+        )
+        .route("/manager/api/usage", get(gateway::manager::handle_usage))
+        .route(
+            "/manager/api/usage/records",
+            get(gateway::manager::handle_usage_records),
+        )
         .route(
             "/manager/api/chat/test",
             post(gateway::manager::handle_chat_test),
         )
+        // WebUI 使用会话鉴权获取模型列表（与 /v1/models 相同，不受 API Key 限制）。
+        .route(
+            "/manager/api/models",
+            get(gateway::openai::handler::handle_list_models).with_state(api_state),
+        )
         .with_state(manager_state.clone());
 
     // === Dashboard 路由（需要认证）===
     let dashboard_routes = Router::new()
         .route("/", get(gateway::manager::handle_dashboard))
+        .route("/logout", post(gateway::manager::handle_logout))
         // 捕获 /oauth-callback 等路径，也显示 dashboard
         .fallback(gateway::manager::handle_dashboard)
         .with_state(manager_state.clone());
@@ -187,7 +284,8 @@ async fn main() -> anyhow::Result<()> {
     let protected_routes = Router::new()
         .merge(manager_api_routes)
         .merge(dashboard_routes)
-        .layer(middleware::from_fn(
+        .layer(middleware::from_fn_with_state(
+            manager_state.clone(),
             gateway::manager::manager_auth_middleware,
         ));
 
@@ -207,11 +305,107 @@ async fn main() -> anyhow::Result<()> {
         .await
         .context("绑定监听端口失败")?;
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+    // 登录限流需要客户端地址
+    let served = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .context("服务异常退出");
+
+    // 定时任务每 5 秒才保存一次、账本每秒才刷盘一次：退出前补存最后一段用量。
+    if let Err(e) = key_quota.persist().await {
+        tracing::warn!("保存 API Key 用量失败: {e:#}");
+    }
+    ledger.flush().await;
+
+    served
+}
+
+/// `gen-accounts-key`：输出新的随机密钥；
+/// `rotate-accounts-key [--new-key-file <path>] [--decrypt]`：用当前密钥解密 accounts.json（含备份），
+/// 再以新密钥（`--new-key-file` 或环境变量 `ACCOUNTS_ENCRYPTION_NEW_KEY`）重新加密；`--decrypt` 写回明文。
+/// 轮换需在服务停止时执行，完成后将配置中的密钥替换为新密钥。
+async fn run_accounts_key_command(cfg: &config::Config) -> Option<anyhow::Result<()>> {
+    let mut args = std::env::args().skip(1);
+    match args.next()?.as_str() {
+        "gen-accounts-key" => Some(credential::crypto::MasterKey::generate().map(|key| {
+            println!("{}", key.encode());
+        })),
+        "rotate-accounts-key" => {
+            let mut new_key_file = None;
+            let mut decrypt = false;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--new-key-file" => new_key_file = args.next(),
+                    "--decrypt" => decrypt = true,
+                    _ => {}
+                }
+            }
+            Some(rotate_accounts_key(cfg, new_key_file, decrypt).await)
+        }
+        _ => None,
+    }
+}
+
+async fn rotate_accounts_key(
+    cfg: &config::Config,
+    new_key_file: Option<String>,
+    decrypt: bool,
+) -> anyhow::Result<()> {
+    use credential::crypto::MasterKey;
+
+    let new_key = if decrypt {
+        None
+    } else if let Some(path) = new_key_file {
+        Some(MasterKey::from_file(&path)?)
+    } else {
+        let encoded = std::env::var("ACCOUNTS_ENCRYPTION_NEW_KEY")
+            .context("请通过 --new-key-file 或环境变量 ACCOUNTS_ENCRYPTION_NEW_KEY 提供新密钥")?;
+        Some(MasterKey::parse(&encoded)?)
+    };
+    let count = credential::store::rotate_encryption_key(cfg, new_key.as_ref()).await?;
+    match new_key {
+        Some(key) => println!(
+            "已使用新密钥 {} 重新加密 {count} 个账号，请更新 ACCOUNTS_ENCRYPTION_KEY / ACCOUNTS_ENCRYPTION_KEY_FILE",
+            key.id()
+        ),
+        None => println!(
+            "已解密 {count} 个账号，请移除 ACCOUNTS_ENCRYPTION_KEY / ACCOUNTS_ENCRYPTION_KEY_FILE"
+        ),
+    }
+    Ok(())
+}
+
+/// `replay-capture <bundle.json>`：按捕获包重新执行请求转换与响应改写（上游为本地 mock），
+/// 输出 JSON 报告；`vertexRequestChanged` 为 true 表示转换结果与捕获时不一致。
+async fn run_replay_command(cfg: &config::Config) -> Option<anyhow::Result<()>> {
+    let mut args = std::env::args().skip(1);
+    if args.next()? != "replay-capture" {
+        return None;
+    }
+    let Some(path) = args.next() else {
+        return Some(Err(anyhow::anyhow!(
+            "用法：replay-capture <data_dir/captures/xxx.json>"
+        )));
+    };
+    Some(replay_capture(cfg, &path).await)
+}
+
+async fn replay_capture(cfg: &config::Config, path: &str) -> anyhow::Result<()> {
+    runtime_config::init(cfg);
+    let bundle = capture::CaptureBundle::load(std::path::Path::new(path))?;
+
+    // 使用临时目录的签名缓存，避免污染 data_dir。
+    let sig_dir = std::env::temp_dir().join(format!("ant2api-replay-{}", uuid::Uuid::new_v4()));
+    let sig_mgr = signature::manager::Manager::new(&sig_dir.to_string_lossy())
         .await
-        .context("服务异常退出")?;
+        .context("初始化 signature manager 失败")?;
+    let report = capture::replay::replay_bundle(cfg, &sig_mgr, &bundle).await;
+    let _ = std::fs::remove_dir_all(&sig_dir);
 
+    println!("{}", serde_json::to_string_pretty(&report?)?);
     Ok(())
 }
 
@@ -216,11 +216,11 @@ mod jemalloc {
     pub fn purge_all() -> anyhow::Result<()> {
         // Best-effort：先 flush 当前线程 tcache（对大对象可能无效，但可减少小对象抖动）。
         unsafe {
-            mallctl_noargs(CStr::from_bytes_with_nul(b"thread.tcache.flush\\0").unwrap()).ok();
+            mallctl_noargs(c"thread.tcache.flush").ok();
         }
 
         let narenas: u32 = unsafe {
-            mallctl_read(CStr::from_bytes_with_nul(b"arenas.narenas\\0").unwrap())
+            mallctl_read(c"arenas.narenas")
                 .map_err(|e| anyhow::anyhow!("mallctl arenas.narenas failed: {e}"))?
         };
 
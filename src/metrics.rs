@@ -0,0 +1,319 @@
+//! Prometheus 指标（文本格式，手写导出，无额外依赖）。
+//!
+//! - 网关请求：按 route/model/status 计数，按 route/model 统计延迟直方图
+//!   （model 仅取模型目录中的 ID，其余归入 `other`，未通过校验的请求为空）
+//! - 上游错误：按 HTTP 状态与是否 model_capacity_exhausted 计数
+//! - token 用量：按 model 与类型（prompt/candidates/thoughts）累计
+//! - 账号刷新失败：按账号 ID 累计
+//!
+//! 账号/配额池等瞬时状态由 `/metrics` handler 在抓取时现算，见 `gateway::metrics`。
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::gateway::common::model_catalog;
+use crate::vertex::client::ApiError;
+use crate::vertex::types::UsageMetadata;
+
+/// 模型目录中不存在的模型统一使用的标签值。
+const OTHER_MODEL_LABEL: &str = "other";
+
+/// 延迟直方图桶（秒）。
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+tokio::task_local! {
+    /// 当前请求解析出的模型（由 handler 填写，供请求中间件打标签）。
+    static REQUEST_MODEL: RefCell<String>;
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, v: f64) {
+        for (i, le) in LATENCY_BUCKETS.iter().enumerate() {
+            if v <= *le {
+                self.buckets[i] += 1;
+            }
+        }
+        self.sum += v;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    /// (route, model, status)
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// (route, model)
+    latency: BTreeMap<(String, String), Histogram>,
+    /// (status, model_capacity_exhausted)
+    upstream_errors: BTreeMap<(u16, bool), u64>,
+    /// (model, kind)
+    tokens: BTreeMap<(String, &'static str), u64>,
+    /// account_id
+    refresh_failures: BTreeMap<String, u64>,
+}
+
+static REGISTRY: LazyLock<Mutex<Registry>> = LazyLock::new(|| Mutex::new(Registry::default()));
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 记录当前请求使用的模型（在请求中间件作用域外调用时忽略）。
+/// 应在请求通过校验后调用；标签取模型目录中的 ID，避免客户端输入使标签基数无限增长。
+pub fn set_request_model(model: &str) {
+    let label = model_label(model);
+    let _ = REQUEST_MODEL.try_with(|m| *m.borrow_mut() = label);
+}
+
+fn model_label(model: &str) -> String {
+    model_catalog::catalog()
+        .get(model)
+        .map(|m| m.id)
+        .unwrap_or_else(|| OTHER_MODEL_LABEL.to_string())
+}
+
+/// 请求计数与延迟中间件（流式请求的延迟为首包耗时）。
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let start = Instant::now();
+    let (model, resp) = REQUEST_MODEL
+        .scope(RefCell::new(String::new()), async move {
+            let resp = next.run(request).await;
+            (REQUEST_MODEL.with(|m| m.take()), resp)
+        })
+        .await;
+    record_request(
+        &route,
+        &model,
+        resp.status().as_u16(),
+        start.elapsed().as_secs_f64(),
+    );
+    resp
+}
+
+fn record_request(route: &str, model: &str, status: u16, secs: f64) {
+    let mut reg = registry();
+    *reg.requests
+        .entry((route.to_string(), model.to_string(), status))
+        .or_default() += 1;
+    reg.latency
+        .entry((route.to_string(), model.to_string()))
+        .or_default()
+        .observe(secs);
+}
+
+/// 记录一次上游 API 错误（仅统计带 HTTP 状态的错误）。
+pub fn record_upstream_error(err: &ApiError) {
+    let Some(status) = err.status() else {
+        return;
+    };
+    let key = (status, err.is_model_capacity_exhausted());
+    *registry().upstream_errors.entry(key).or_default() += 1;
+}
+
+/// 累计一次请求的 token 用量（模型标签同样限定在模型目录内）。
+pub fn record_usage(model: &str, usage: Option<&UsageMetadata>) {
+    let Some(usage) = usage else {
+        return;
+    };
+    let model = model_label(model);
+    let mut reg = registry();
+    for (kind, n) in [
+        ("prompt", usage.prompt_token_count),
+        ("candidates", usage.candidates_token_count),
+        ("thoughts", usage.thoughts_token_count),
+    ] {
+        if n > 0 {
+            *reg.tokens.entry((model.clone(), kind)).or_default() += n as u64;
+        }
+    }
+}
+
+/// 记录一次账号 token 刷新失败（按持久化的账号 ID，跨重启稳定）。
+pub fn record_refresh_failure(account_id: &str) {
+    *registry()
+        .refresh_failures
+        .entry(account_id.to_string())
+        .or_default() += 1;
+}
+
+/// 以 Prometheus 文本格式导出累计型指标。
+pub fn render(out: &mut String) {
+    let reg = registry();
+
+    write_header(out, "ant2api_requests_total", "counter", "网关请求数");
+    for ((route, model, status), n) in &reg.requests {
+        let _ = writeln!(
+            out,
+            "ant2api_requests_total{{route=\"{}\",model=\"{}\",status=\"{status}\"}} {n}",
+            escape(route),
+            escape(model)
+        );
+    }
+
+    write_header(
+        out,
+        "ant2api_request_duration_seconds",
+        "histogram",
+        "网关请求延迟（流式为首包耗时）",
+    );
+    for ((route, model), h) in &reg.latency {
+        let labels = format!("route=\"{}\",model=\"{}\"", escape(route), escape(model));
+        for (le, n) in LATENCY_BUCKETS.iter().zip(h.buckets.iter()) {
+            let _ = writeln!(
+                out,
+                "ant2api_request_duration_seconds_bucket{{{labels},le=\"{le}\"}} {n}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "ant2api_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+            h.count
+        );
+        let _ = writeln!(
+            out,
+            "ant2api_request_duration_seconds_sum{{{labels}}} {}",
+            h.sum
+        );
+        let _ = writeln!(
+            out,
+            "ant2api_request_duration_seconds_count{{{labels}}} {}",
+            h.count
+        );
+    }
+
+    write_header(
+        out,
+        "ant2api_upstream_errors_total",
+        "counter",
+        "上游 API 错误数",
+    );
+    for ((status, capacity), n) in &reg.upstream_errors {
+        let _ = writeln!(
+            out,
+            "ant2api_upstream_errors_total{{status=\"{status}\",model_capacity_exhausted=\"{capacity}\"}} {n}"
+        );
+    }
+
+    write_header(
+        out,
+        "ant2api_tokens_total",
+        "counter",
+        "后端 usageMetadata 上报的 token 数",
+    );
+    for ((model, kind), n) in &reg.tokens {
+        let _ = writeln!(
+            out,
+            "ant2api_tokens_total{{model=\"{}\",type=\"{kind}\"}} {n}",
+            escape(model)
+        );
+    }
+
+    write_header(
+        out,
+        "ant2api_account_refresh_failures_total",
+        "counter",
+        "账号 token 刷新失败次数",
+    );
+    for (account_id, n) in &reg.refresh_failures {
+        let _ = writeln!(
+            out,
+            "ant2api_account_refresh_failures_total{{account_id=\"{}\"}} {n}",
+            escape(account_id)
+        );
+    }
+}
+
+pub fn write_header(out: &mut String, name: &str, typ: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {typ}");
+}
+
+/// 标签值转义：`\`、`"`、换行。
+pub fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut h = Histogram::default();
+        h.observe(0.07);
+        h.observe(3.0);
+        assert_eq!(h.buckets[0], 0);
+        assert_eq!(h.buckets[1], 1);
+        assert_eq!(h.buckets[6], 2);
+        assert_eq!(h.count, 2);
+    }
+
+    #[test]
+    fn render_includes_labels() {
+        // 仅目录中的模型保留原名（使用独立 ID，避免影响依赖目录的其他测试）。
+        let models: std::collections::HashMap<String, sonic_rs::Value> =
+            sonic_rs::from_str(r#"{"gemini-test-metrics": {}}"#).unwrap();
+        model_catalog::catalog().replace(&models);
+
+        record_request("/v1/test-render", "gemini-test-metrics", 200, 0.2);
+        record_usage(
+            "gemini-test-metrics",
+            Some(&UsageMetadata {
+                prompt_token_count: 10,
+                candidates_token_count: 5,
+                total_token_count: 15,
+                thoughts_token_count: 0,
+                cached_content_token_count: 0,
+            }),
+        );
+        let mut out = String::new();
+        render(&mut out);
+        assert!(out.contains(
+            "ant2api_requests_total{route=\"/v1/test-render\",model=\"gemini-test-metrics\",status=\"200\"}"
+        ));
+        assert!(
+            out.contains("ant2api_tokens_total{model=\"gemini-test-metrics\",type=\"prompt\"}")
+        );
+        assert!(!out.contains("type=\"thoughts\"} 0"));
+        assert_eq!(model_label("not-a-real-model-x"), OTHER_MODEL_LABEL);
+
+        record_usage(
+            "not-a-real-model-x",
+            Some(&UsageMetadata {
+                prompt_token_count: 0,
+                candidates_token_count: 3,
+                total_token_count: 3,
+                thoughts_token_count: 0,
+                cached_content_token_count: 0,
+            }),
+        );
+        let mut out = String::new();
+        render(&mut out);
+        assert!(out.contains("ant2api_tokens_total{model=\"other\",type=\"candidates\"}"));
+        assert!(!out.contains("not-a-real-model-x"));
+        assert_eq!(escape("a\"b\\"), "a\\\"b\\\\");
+    }
+}
@@ -1,9 +1,10 @@
 use crate::quota_pool::selector;
-use crate::quota_pool::types::{PoolEntry, QuotaPool};
+use crate::quota_pool::types::{CooldownEntry, CooldownReason, PoolEntry, QuotaPool};
+use crate::vertex::client::ApiError;
 use chrono::{DateTime, Utc};
 use sonic_rs::{JsonContainerTrait, JsonValueTrait};
 use std::collections::{HashMap, HashSet};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
 /// 配额分组键常量（同时也是池名）。
@@ -31,6 +32,9 @@ pub struct QuotaGroup {
     pub remaining_fraction: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reset_time: Option<String>,
+    /// 请求被限流（429/容量不足）后的冷却截止时间。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_until: Option<String>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub model_list: Vec<String>,
 }
@@ -44,6 +48,16 @@ pub struct AccountQuota {
     pub fetched_at: DateTime<Utc>,
 }
 
+/// 单个池的规模快照。
+#[derive(Debug, Clone)]
+pub struct PoolStats {
+    pub name: String,
+    pub active: usize,
+    pub cooldown: usize,
+    /// active 账号剩余配额比例之和（可视为“剩余账号当量”）。
+    pub remaining_fraction_sum: f64,
+}
+
 /// 配额池管理器：集中维护所有分组（pool）的账号配额视图，并提供按模型分组的账号选择。
 #[derive(Debug)]
 pub struct QuotaPoolManager {
@@ -106,12 +120,20 @@ impl QuotaPoolManager {
                     if should_cooldown {
                         pool.active.remove(session_id);
                         if let Some(rt) = reset_dt {
-                            pool.cooldown.insert(session_id.to_string(), rt);
+                            pool.cooldown
+                                .insert(session_id.to_string(), CooldownEntry::quota_exhausted(rt));
                         }
                         continue;
                     }
 
-                    pool.cooldown.remove(session_id);
+                    // 配额仍有余额，但请求限流冷却未到期：保留冷却。
+                    if !pool
+                        .cooldown
+                        .get(session_id)
+                        .is_some_and(|c| c.is_rate_limited_at(now))
+                    {
+                        pool.cooldown.remove(session_id);
+                    }
                     pool.active.insert(
                         session_id.to_string(),
                         PoolEntry {
@@ -124,7 +146,8 @@ impl QuotaPoolManager {
                 None => {
                     if let Some(rt) = reset_dt {
                         pool.active.remove(session_id);
-                        pool.cooldown.insert(session_id.to_string(), rt);
+                        pool.cooldown
+                            .insert(session_id.to_string(), CooldownEntry::quota_exhausted(rt));
                     }
                 }
             }
@@ -143,7 +166,96 @@ impl QuotaPoolManager {
         }
         let inner = self.inner.read().await;
         let pool = inner.pools.get(pool_name)?;
-        selector::select_weighted_excluding(&pool.active, exclude)
+
+        let limited = rate_limited_in(pool, Utc::now());
+        if limited.is_empty() {
+            return selector::select_weighted_excluding(&pool.active, exclude);
+        }
+        let mut exclude = exclude.clone();
+        exclude.extend(limited);
+        selector::select_weighted_excluding(&pool.active, &exclude)
+    }
+
+    /// 指定 pool 中处于请求限流冷却的账号。
+    pub async fn rate_limited_sessions(&self, pool_name: &str) -> HashSet<String> {
+        let inner = self.inner.read().await;
+        inner
+            .pools
+            .get(pool_name.trim())
+            .map(|pool| rate_limited_in(pool, Utc::now()))
+            .unwrap_or_default()
+    }
+
+    /// 账号在指定 pool 中是否处于冷却（配额耗尽或请求限流）。
+    pub async fn is_cooling(&self, pool_name: &str, session_id: &str) -> bool {
+        let now = Utc::now();
+        let inner = self.inner.read().await;
+        inner
+            .pools
+            .get(pool_name.trim())
+            .and_then(|pool| pool.cooldown.get(session_id))
+            .is_some_and(|c| c.reason == CooldownReason::QuotaExhausted || c.until > now)
+    }
+
+    /// 若上游错误为 429 或容量不足，则让该账号在模型所属 pool 中冷却：
+    /// 时长取错误中的 RetryInfo，缺省为 `default_delay`。返回是否进入冷却。
+    pub async fn cooldown_on_error(
+        &self,
+        session_id: &str,
+        model: &str,
+        err: &ApiError,
+        default_delay: Duration,
+    ) -> bool {
+        if err.status() != Some(429) && !err.is_model_capacity_exhausted() {
+            return false;
+        }
+        let delay = err.retry_delay().unwrap_or(default_delay);
+        self.mark_rate_limited(session_id, group_quota_key(model), delay)
+            .await;
+        true
+    }
+
+    /// 将账号标记为请求限流冷却，直到 `delay` 之后。
+    pub async fn mark_rate_limited(&self, session_id: &str, pool_name: &str, delay: Duration) {
+        let session_id = session_id.trim();
+        if session_id.is_empty() || delay.is_zero() {
+            return;
+        }
+        let now = Utc::now();
+        let until = now + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::zero());
+
+        let mut inner = self.inner.write().await;
+        let pool = inner
+            .pools
+            .entry(pool_name.to_string())
+            .or_insert_with(|| QuotaPool::new(pool_name));
+        // 顺带清理已到期的限流冷却。
+        pool.cooldown
+            .retain(|_, c| c.reason != CooldownReason::RateLimited || c.until > now);
+
+        match pool.cooldown.get_mut(session_id) {
+            // 配额耗尽的冷却更长：保持不变。
+            Some(c) if c.reason == CooldownReason::QuotaExhausted && c.until >= until => {}
+            Some(c) => {
+                c.until = c.until.max(until);
+                c.reason = CooldownReason::RateLimited;
+            }
+            None => {
+                pool.cooldown.insert(
+                    session_id.to_string(),
+                    CooldownEntry {
+                        until,
+                        reason: CooldownReason::RateLimited,
+                    },
+                );
+            }
+        }
+        tracing::info!(
+            session_id = session_id,
+            pool = pool_name,
+            "账号被限流，冷却 {}s",
+            delay.as_secs()
+        );
     }
 
     /// 移除指定 sessionId 在所有池中的状态（用于账号删除/禁用后的清理）。
@@ -175,8 +287,8 @@ impl QuotaPoolManager {
         let inner = self.inner.read().await;
         let mut out: HashSet<String> = HashSet::new();
         for pool in inner.pools.values() {
-            for (sid, rt) in &pool.cooldown {
-                if *rt <= now {
+            for (sid, c) in &pool.cooldown {
+                if c.until <= now {
                     out.insert(sid.clone());
                 }
             }
@@ -184,16 +296,49 @@ impl QuotaPoolManager {
         out.into_iter().collect()
     }
 
+    /// 各池的规模快照（用于 /metrics）。
+    pub async fn pool_stats(&self) -> Vec<PoolStats> {
+        let now = Utc::now();
+        let inner = self.inner.read().await;
+        let mut out: Vec<PoolStats> = inner
+            .pools
+            .iter()
+            .map(|(name, pool)| {
+                // 限流冷却中的账号仍在 active 里，这里按冷却计。
+                let limited = rate_limited_in(pool, now);
+                let available = pool
+                    .active
+                    .iter()
+                    .filter(|(sid, _)| !limited.contains(*sid));
+                PoolStats {
+                    name: name.clone(),
+                    active: available.clone().count(),
+                    cooldown: pool
+                        .cooldown
+                        .values()
+                        .filter(|c| c.reason == CooldownReason::QuotaExhausted || c.until > now)
+                        .count(),
+                    remaining_fraction_sum: available
+                        .fold(0.0, |acc, (_, e)| acc + e.remaining_fraction),
+                }
+            })
+            .collect();
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        out
+    }
+
     /// 获取指定账号在所有预定义分组下的配额快照（用于 WebUI 展示）。
     ///
     /// 规则：
     /// - 若在 active：返回当前 remaining_fraction（并尽量附带 reset_time）
     /// - 若在 cooldown：remaining_fraction 固定为 0.0，并附带 reset_time
     /// - 若缺失：remaining_fraction 固定为 0.0
+    /// - 若处于请求限流冷却：额外附带 cooldown_until
     ///
     /// 注意：为保证 UI 稳定性，未知/未入池的 sessionId 也会返回“全 0 分组”而非 404。
     pub async fn get_session_quota_groups(&self, session_id: &str) -> Vec<QuotaGroup> {
         let session_id = session_id.trim();
+        let now = Utc::now();
         let inner = self.inner.read().await;
 
         let mut out = Vec::with_capacity(QUOTA_GROUP_ORDER.len());
@@ -202,6 +347,7 @@ impl QuotaPoolManager {
                 group_name: pool_name.to_string(),
                 remaining_fraction: Some(0.0),
                 reset_time: None,
+                cooldown_until: None,
                 model_list: Vec::new(),
             };
 
@@ -213,9 +359,16 @@ impl QuotaPoolManager {
             if let Some(e) = pool.active.get(session_id) {
                 g.remaining_fraction = Some(e.remaining_fraction);
                 g.reset_time = e.reset_time.as_ref().map(|rt| rt.to_rfc3339());
-            } else if let Some(rt) = pool.cooldown.get(session_id) {
+            } else if let Some(c) = pool.cooldown.get(session_id)
+                && c.reason == CooldownReason::QuotaExhausted
+            {
                 g.remaining_fraction = Some(0.0);
-                g.reset_time = Some(rt.to_rfc3339());
+                g.reset_time = Some(c.until.to_rfc3339());
+            }
+            if let Some(c) = pool.cooldown.get(session_id)
+                && c.is_rate_limited_at(now)
+            {
+                g.cooldown_until = Some(c.until.to_rfc3339());
             }
 
             out.push(g);
@@ -231,6 +384,14 @@ impl Default for QuotaPoolManager {
     }
 }
 
+fn rate_limited_in(pool: &QuotaPool, now: DateTime<Utc>) -> HashSet<String> {
+    pool.cooldown
+        .iter()
+        .filter(|(_, c)| c.is_rate_limited_at(now))
+        .map(|(sid, _)| sid.clone())
+        .collect()
+}
+
 fn parse_reset_time(v: Option<&str>) -> Option<DateTime<Utc>> {
     let s = v?.trim();
     if s.is_empty() {
@@ -274,6 +435,7 @@ pub(crate) fn group_quota_groups(models: &HashMap<String, sonic_rs::Value>) -> V
             group_name: group_name.to_string(),
             remaining_fraction: None,
             reset_time: None,
+            cooldown_until: None,
             model_list: Vec::new(),
         });
 
@@ -413,3 +575,102 @@ fn any_to_float64(v: &sonic_rs::Value) -> Option<f64> {
 fn clamp01(v: f64) -> f64 {
     v.clamp(0.0, 1.0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota(frac: f64) -> Vec<QuotaGroup> {
+        vec![QuotaGroup {
+            group_name: QUOTA_GROUP_GEMINI3_FLASH.to_string(),
+            remaining_fraction: Some(frac),
+            reset_time: None,
+            cooldown_until: None,
+            model_list: Vec::new(),
+        }]
+    }
+
+    fn http_error(status: u16, retry_delay: Duration) -> ApiError {
+        ApiError::Http {
+            status,
+            message: "x".to_string(),
+            retry_delay,
+            disable_token: false,
+            model_capacity_exhausted: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limited_session_is_skipped_until_delay_passes() {
+        let mgr = QuotaPoolManager::new();
+        mgr.update_from_quota("a", &quota(0.9)).await;
+        mgr.update_from_quota("b", &quota(0.1)).await;
+
+        let default_delay = Duration::from_secs(60);
+        assert!(
+            !mgr.cooldown_on_error(
+                "a",
+                "gemini-3-flash",
+                &http_error(500, Duration::ZERO),
+                default_delay
+            )
+            .await
+        );
+        assert!(
+            mgr.cooldown_on_error(
+                "a",
+                "gemini-3-flash",
+                &http_error(429, Duration::from_secs(30)),
+                default_delay
+            )
+            .await
+        );
+
+        for _ in 0..10 {
+            let got = mgr
+                .get_account_for_pool_excluding(QUOTA_GROUP_GEMINI3_FLASH, &HashSet::new())
+                .await;
+            assert_eq!(got.as_deref(), Some("b"));
+        }
+        let limited = mgr.rate_limited_sessions(QUOTA_GROUP_GEMINI3_FLASH).await;
+        assert!(limited.contains("a"));
+
+        // 定时刷新不应提前解除限流冷却。
+        mgr.update_from_quota("a", &quota(0.8)).await;
+        let groups = mgr.get_session_quota_groups("a").await;
+        let flash = groups
+            .iter()
+            .find(|g| g.group_name == QUOTA_GROUP_GEMINI3_FLASH)
+            .unwrap();
+        assert_eq!(flash.remaining_fraction, Some(0.8));
+        assert!(flash.cooldown_until.is_some());
+
+        let stats = mgr.pool_stats().await;
+        let flash = stats
+            .iter()
+            .find(|s| s.name == QUOTA_GROUP_GEMINI3_FLASH)
+            .unwrap();
+        assert_eq!((flash.active, flash.cooldown), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn expired_rate_limit_returns_session_to_selection() {
+        let mgr = QuotaPoolManager::new();
+        mgr.update_from_quota("a", &quota(0.5)).await;
+        mgr.mark_rate_limited("a", QUOTA_GROUP_GEMINI3_FLASH, Duration::from_millis(50))
+            .await;
+        assert!(
+            mgr.get_account_for_pool_excluding(QUOTA_GROUP_GEMINI3_FLASH, &HashSet::new())
+                .await
+                .is_none()
+        );
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert_eq!(
+            mgr.get_account_for_pool_excluding(QUOTA_GROUP_GEMINI3_FLASH, &HashSet::new())
+                .await
+                .as_deref(),
+            Some("a")
+        );
+    }
+}
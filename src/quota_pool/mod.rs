@@ -9,6 +9,7 @@ mod selector;
 mod types;
 
 pub use manager::AccountQuota;
+pub use manager::PoolStats;
 pub use manager::QuotaGroup;
 pub use manager::QuotaPoolManager;
 pub(crate) use manager::{group_quota_groups, group_quota_key};
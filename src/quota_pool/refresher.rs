@@ -1,13 +1,14 @@
-//! 后台刷新任务：周期性拉取各账号配额并更新 QuotaPoolManager。
+//! 后台刷新任务：周期性拉取各账号配额并更新 QuotaPoolManager 与模型目录。
 
 use crate::config::Config;
 use crate::credential::store::Store;
 use crate::gateway::common::auth_retry::is_auth_failure;
+use crate::gateway::common::model_catalog;
 use crate::quota_pool::QuotaPoolManager;
 use crate::quota_pool::group_quota_groups;
 use crate::runtime_config;
 use crate::vertex::client::VertexClient;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -54,6 +55,8 @@ async fn refresh_once(
     }
     pool_mgr.sync_valid_sessions(&enabled_sessions).await;
 
+    // 各账号可用模型的并集，用于刷新模型目录。
+    let mut catalog_models = HashMap::new();
     for acc in accounts {
         if !acc.enable {
             continue;
@@ -70,12 +73,16 @@ async fn refresh_once(
         };
 
         match vertex
+            .for_proxy(&acc.proxy)
             .fetch_available_models(&endpoint, &project_id, &acc.access_token, &acc.email)
             .await
         {
             Ok(resp) => {
                 let groups = group_quota_groups(&resp.models);
                 pool_mgr.update_from_quota(sid, &groups).await;
+                for (k, v) in resp.models {
+                    catalog_models.entry(k).or_insert(v);
+                }
                 tracing::info!("账号 {} 配额已更新", sid);
             }
             Err(e) => {
@@ -90,6 +97,7 @@ async fn refresh_once(
         // 限速：每秒最多 5 个账号请求
         tokio::time::sleep(Duration::from_millis(200)).await;
     }
+    model_catalog::catalog().replace(&catalog_models);
 
     Ok(())
 }
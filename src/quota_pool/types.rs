@@ -15,13 +15,43 @@ pub struct PoolEntry {
     pub last_updated: Instant,
 }
 
+/// 冷却原因。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CooldownReason {
+    /// 配额刷新发现余额耗尽：移出 active，等待 resetTime 后由刷新任务恢复。
+    QuotaExhausted,
+    /// 请求时后端返回 429 / 容量不足：保留在 active，仅在到期前跳过选择。
+    RateLimited,
+}
+
+/// 冷却条目。
+#[derive(Debug, Clone, Copy)]
+pub struct CooldownEntry {
+    pub until: DateTime<Utc>,
+    pub reason: CooldownReason,
+}
+
+impl CooldownEntry {
+    pub fn quota_exhausted(until: DateTime<Utc>) -> Self {
+        Self {
+            until,
+            reason: CooldownReason::QuotaExhausted,
+        }
+    }
+
+    /// 是否为尚未到期的请求限流冷却。
+    pub fn is_rate_limited_at(&self, now: DateTime<Utc>) -> bool {
+        self.reason == CooldownReason::RateLimited && self.until > now
+    }
+}
+
 /// 一个配额池，代表某类模型共享的配额分组（例如 Claude/GPT、Gemini 3 Flash 等）。
 #[derive(Debug, Clone)]
 pub struct QuotaPool {
     /// 可用账号：用于选择 token。
     pub active: HashMap<String, PoolEntry>,
-    /// 冷却账号：配额耗尽且已知 resetTime，等待到点后再刷新。
-    pub cooldown: HashMap<String, DateTime<Utc>>,
+    /// 冷却账号：配额耗尽（等待 resetTime）或请求被限流（等待 retryDelay）。
+    pub cooldown: HashMap<String, CooldownEntry>,
 }
 
 impl QuotaPool {
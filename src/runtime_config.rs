@@ -10,12 +10,29 @@ use std::sync::Arc;
 
 use crate::config::Config;
 use crate::logging::LogLevel;
+use crate::util::fs::write_atomic;
 use crate::util::model as modelutil;
+use crate::util::{system_prompt, virtual_model};
 
 pub const DEFAULT_BACKEND_HOST: &str = "cloudcode-pa.googleapis.com";
 pub const DAILY_BACKEND_HOST: &str = "daily-cloudcode-pa.sandbox.googleapis.com";
+pub const AUTOPUSH_BACKEND_HOST: &str = "autopush-cloudcode-pa.sandbox.googleapis.com";
 pub const ENDPOINT_MODE_PRODUCTION: &str = "production";
 pub const ENDPOINT_MODE_DAILY: &str = "daily";
+pub const ENDPOINT_MODE_AUTOPUSH: &str = "autopush";
+pub const ENDPOINT_MODE_ROUND_ROBIN: &str = "round-robin";
+pub const ENDPOINT_MODE_ROUND_ROBIN_DP: &str = "round-robin-dp";
+pub const ENDPOINT_MODE_FAILOVER: &str = "failover";
+
+/// 全部可用的端点模式（见 `vertex::endpoints`）。
+pub const ENDPOINT_MODES: &[&str] = &[
+    ENDPOINT_MODE_PRODUCTION,
+    ENDPOINT_MODE_DAILY,
+    ENDPOINT_MODE_AUTOPUSH,
+    ENDPOINT_MODE_ROUND_ROBIN,
+    ENDPOINT_MODE_ROUND_ROBIN_DP,
+    ENDPOINT_MODE_FAILOVER,
+];
 
 /// 运行时配置快照。
 #[derive(Debug, Clone)]
@@ -28,7 +45,7 @@ pub struct RuntimeSettings {
     pub gemini3_media_resolution: String,
     /// 调试日志级别
     pub debug: String,
-    /// API Key（仅存储，当前不用于鉴权）
+    /// 默认 API Key（非空时与 data_dir/api_keys.json 中的命名密钥一起用于 /v1 鉴权）
     pub api_key: String,
     /// 后端请求地址模式
     pub endpoint_mode: String,
@@ -68,6 +85,52 @@ const MODEL_ID_MAPPING_FILENAME: &str = "model_id_mapping.json";
 static MODEL_ID_MAPPING: std::sync::OnceLock<ArcSwap<HashMap<String, String>>> =
     std::sync::OnceLock::new();
 
+const API_KEYS_FILENAME: &str = "api_keys.json";
+
+/// `API_KEY`（.env / WebUI 设置）对应的密钥名称。
+pub const DEFAULT_API_KEY_NAME: &str = "default";
+
+/// 命名 API Key（持久化于 data_dir/api_keys.json）。
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyEntry {
+    pub name: String,
+    pub key: String,
+    /// 每分钟请求数上限（0 = 不限制）
+    #[serde(default, skip_serializing_if = "is_zero_u32")]
+    pub rpm: u32,
+    /// 每日 token 上限（0 = 不限制）
+    #[serde(default, skip_serializing_if = "is_zero_u64")]
+    pub tokens_per_day: u64,
+    /// 允许使用的模型（按映射后的原始模型ID比较；为空 = 不限制）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_models: Vec<String>,
+}
+
+impl ApiKeyEntry {
+    /// 判断映射后的模型是否在白名单内（白名单条目同样经过模型 ID 映射）。
+    pub fn allows_model(&self, model: &str) -> bool {
+        if self.allowed_models.is_empty() {
+            return true;
+        }
+        let model = modelutil::canonical_model_id(model);
+        self.allowed_models
+            .iter()
+            .any(|m| map_client_model_id(m).eq_ignore_ascii_case(&model))
+    }
+}
+
+fn is_zero_u32(v: &u32) -> bool {
+    *v == 0
+}
+
+fn is_zero_u64(v: &u64) -> bool {
+    *v == 0
+}
+
+/// 命名 API Key 列表。
+static API_KEYS: std::sync::OnceLock<ArcSwap<Vec<ApiKeyEntry>>> = std::sync::OnceLock::new();
+
 /// 初始化运行时配置（在 main 中调用一次）。
 pub fn init(cfg: &Config) {
     let settings = RuntimeSettings::from_config(cfg);
@@ -76,6 +139,20 @@ pub fn init(cfg: &Config) {
     // 初始化模型 ID 映射（用于 /v1/models 输出 + 请求模型名重写）。
     let mapping = load_model_id_mapping_from_data_dir(&cfg.data_dir);
     let _ = MODEL_ID_MAPPING.set(ArcSwap::from_pointee(mapping));
+
+    // 初始化虚拟模型定义（文件不存在时使用内置定义）。
+    virtual_model::update_virtual_models(virtual_model::load_virtual_models_from_data_dir(
+        &cfg.data_dir,
+    ));
+
+    // 初始化系统提示词注入策略（文件不存在时使用内置策略）。
+    system_prompt::update_system_prompt_policy(
+        system_prompt::load_system_prompt_policy_from_data_dir(&cfg.data_dir),
+    );
+
+    // 初始化命名 API Key（用于 /v1 鉴权）。
+    let keys = load_api_keys_from_data_dir(&cfg.data_dir);
+    let _ = API_KEYS.set(ArcSwap::from_pointee(keys));
 }
 
 /// 获取当前运行时配置快照。
@@ -217,6 +294,143 @@ pub fn persist_model_id_mapping_to_data_dir(
     Ok(())
 }
 
+/// 获取当前命名 API Key 快照。
+pub fn get_api_keys() -> Arc<Vec<ApiKeyEntry>> {
+    API_KEYS
+        .get()
+        .map(|s| s.load_full())
+        .unwrap_or_else(|| Arc::new(Vec::new()))
+}
+
+/// 更新命名 API Key（立即生效）。
+pub fn update_api_keys(new_keys: Vec<ApiKeyEntry>) {
+    if let Some(store) = API_KEYS.get() {
+        store.store(Arc::new(new_keys));
+    }
+}
+
+/// 是否启用 /v1 鉴权：默认 API Key 或任一命名密钥非空即启用。
+pub fn api_auth_enabled() -> bool {
+    !get().api_key.is_empty() || !get_api_keys().is_empty()
+}
+
+/// 按名称查找命名密钥（默认密钥不在列表中，无配额限制）。
+pub fn find_api_key_by_name(name: &str) -> Option<ApiKeyEntry> {
+    get_api_keys().iter().find(|k| k.name == name).cloned()
+}
+
+/// 校验客户端提供的密钥，命中时返回密钥名称。
+pub fn authenticate_api_key(presented: &str) -> Option<String> {
+    if presented.is_empty() {
+        return None;
+    }
+    let settings = get();
+    if !settings.api_key.is_empty() && constant_time_eq(&settings.api_key, presented) {
+        return Some(DEFAULT_API_KEY_NAME.to_string());
+    }
+    get_api_keys()
+        .iter()
+        .find(|k| constant_time_eq(&k.key, presented))
+        .map(|k| k.name.clone())
+}
+
+/// 常量时间比较，避免通过响应时间猜测密钥、令牌或密码。
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 规范化 + 校验命名密钥（name/key 均要求非空，且不允许重复）。
+pub fn validate_and_normalize_api_keys(keys: Vec<ApiKeyEntry>) -> Result<Vec<ApiKeyEntry>, String> {
+    let mut out: Vec<ApiKeyEntry> = Vec::with_capacity(keys.len());
+    let mut names: HashSet<String> = HashSet::with_capacity(keys.len());
+    let mut values: HashSet<String> = HashSet::with_capacity(keys.len());
+
+    for k in keys {
+        let name = k.name.trim().to_string();
+        let key = k.key.trim().to_string();
+
+        if name.is_empty() {
+            return Err("存在空的密钥名称".to_string());
+        }
+        if name == DEFAULT_API_KEY_NAME {
+            return Err(format!(
+                "密钥名称 \"{DEFAULT_API_KEY_NAME}\" 为保留名称（对应 API_KEY）"
+            ));
+        }
+        if key.is_empty() {
+            return Err(format!("密钥 \"{name}\" 的值不能为空"));
+        }
+        if !names.insert(name.clone()) {
+            return Err(format!("密钥名称重复：\"{name}\""));
+        }
+        if !values.insert(key.clone()) {
+            return Err(format!("密钥 \"{name}\" 的值与其他密钥重复"));
+        }
+
+        let mut allowed_models: Vec<String> = Vec::with_capacity(k.allowed_models.len());
+        for m in &k.allowed_models {
+            let m = modelutil::canonical_model_id(m);
+            if !m.is_empty() && !allowed_models.contains(&m) {
+                allowed_models.push(m);
+            }
+        }
+
+        out.push(ApiKeyEntry {
+            name,
+            key,
+            rpm: k.rpm,
+            tokens_per_day: k.tokens_per_day,
+            allowed_models,
+        });
+    }
+
+    Ok(out)
+}
+
+pub fn api_keys_file_path(data_dir: &str) -> std::path::PathBuf {
+    Path::new(data_dir).join(API_KEYS_FILENAME)
+}
+
+pub fn load_api_keys_from_data_dir(data_dir: &str) -> Vec<ApiKeyEntry> {
+    let path = api_keys_file_path(data_dir);
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+
+    let parsed = match serde_json::from_str::<Vec<ApiKeyEntry>>(&text) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("API Key 文件解析失败（{}）：{e}", path.display());
+            return Vec::new();
+        }
+    };
+
+    match validate_and_normalize_api_keys(parsed) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("API Key 文件校验失败（{}）：{e}", path.display());
+            Vec::new()
+        }
+    }
+}
+
+pub fn persist_api_keys_to_data_dir(data_dir: &str, keys: &[ApiKeyEntry]) -> Result<(), String> {
+    let normalized = validate_and_normalize_api_keys(keys.to_vec())?;
+    let dir = Path::new(data_dir);
+    std::fs::create_dir_all(dir).map_err(|e| format!("无法创建数据目录 {}: {e}", dir.display()))?;
+
+    let path = api_keys_file_path(data_dir);
+    let content =
+        serde_json::to_string_pretty(&normalized).map_err(|e| format!("序列化失败: {e}"))?;
+    write_atomic(&path, format!("{content}\n").as_bytes())
+        .map_err(|e| format!("无法写入 {}: {e}", path.display()))?;
+    Ok(())
+}
+
 /// WebUI 可编辑的设置（用于 JSON 序列化）。
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -263,8 +477,8 @@ impl WebUISettings {
         {
             return Err("日志级别必须是 off、low、medium 或 high");
         }
-        let endpoint_mode = self.normalized_endpoint_mode();
-        if endpoint_mode != ENDPOINT_MODE_PRODUCTION && endpoint_mode != ENDPOINT_MODE_DAILY {
+        let endpoint_mode = self.endpoint_mode.trim().to_lowercase();
+        if !endpoint_mode.is_empty() && !ENDPOINT_MODES.contains(&endpoint_mode.as_str()) {
             return Err("后端请求地址无效");
         }
         Ok(())
@@ -316,35 +530,23 @@ fn normalize_media_resolution(value: &str) -> String {
 }
 
 pub fn normalize_endpoint_mode(value: &str) -> String {
-    let v = value.trim();
-    if v.eq_ignore_ascii_case(ENDPOINT_MODE_DAILY) {
-        ENDPOINT_MODE_DAILY.to_string()
-    } else {
-        ENDPOINT_MODE_PRODUCTION.to_string()
-    }
-}
-
-pub fn endpoint_host_for_mode(value: &str) -> &'static str {
-    let v = value.trim();
-    if v.eq_ignore_ascii_case(ENDPOINT_MODE_DAILY) {
-        DAILY_BACKEND_HOST
-    } else {
-        DEFAULT_BACKEND_HOST
-    }
+    let v = value.trim().to_lowercase();
+    ENDPOINT_MODES
+        .iter()
+        .find(|m| **m == v)
+        .unwrap_or(&ENDPOINT_MODE_PRODUCTION)
+        .to_string()
 }
 
 pub fn current_endpoint_host() -> String {
-    let settings = get();
-    endpoint_host_for_mode(&settings.endpoint_mode).to_string()
+    current_endpoint().host
 }
 
+/// 按当前端点模式从端点池选择本次请求使用的主机。
 pub fn current_endpoint() -> crate::vertex::client::Endpoint {
     let settings = get();
     let mode = normalize_endpoint_mode(&settings.endpoint_mode);
-    crate::vertex::client::Endpoint {
-        key: mode,
-        host: endpoint_host_for_mode(&settings.endpoint_mode).to_string(),
-    }
+    crate::vertex::endpoints::pool().select(&mode)
 }
 
 /// 持久化设置到 .env 文件。
@@ -529,6 +731,11 @@ mod tests {
             normalize_endpoint_mode("production"),
             ENDPOINT_MODE_PRODUCTION
         );
+        assert_eq!(
+            normalize_endpoint_mode(" Round-Robin-DP "),
+            ENDPOINT_MODE_ROUND_ROBIN_DP
+        );
+        assert_eq!(normalize_endpoint_mode("failover"), ENDPOINT_MODE_FAILOVER);
         assert_eq!(normalize_endpoint_mode(""), ENDPOINT_MODE_PRODUCTION);
         assert_eq!(normalize_endpoint_mode("invalid"), ENDPOINT_MODE_PRODUCTION);
     }
@@ -554,4 +761,53 @@ mod tests {
         dup_value.insert("b".to_string(), "gpt-5".to_string());
         assert!(validate_and_normalize_model_id_mapping(dup_value).is_err());
     }
+
+    #[test]
+    fn test_validate_and_normalize_api_keys() {
+        let entry = |name: &str, key: &str| ApiKeyEntry {
+            name: name.to_string(),
+            key: key.to_string(),
+            ..Default::default()
+        };
+
+        let out =
+            validate_and_normalize_api_keys(vec![entry(" alice ", " sk-a "), entry("bob", "sk-b")])
+                .unwrap();
+        assert_eq!(out, vec![entry("alice", "sk-a"), entry("bob", "sk-b")]);
+
+        assert!(validate_and_normalize_api_keys(vec![entry("", "sk-a")]).is_err());
+        assert!(validate_and_normalize_api_keys(vec![entry("a", " ")]).is_err());
+        assert!(validate_and_normalize_api_keys(vec![entry(DEFAULT_API_KEY_NAME, "sk")]).is_err());
+        assert!(
+            validate_and_normalize_api_keys(vec![entry("a", "sk-1"), entry("a", "sk-2")]).is_err()
+        );
+        assert!(
+            validate_and_normalize_api_keys(vec![entry("a", "sk-1"), entry("b", "sk-1")]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_api_key_entry_allowed_models() {
+        let mut entry = ApiKeyEntry {
+            name: "a".to_string(),
+            key: "sk-a".to_string(),
+            ..Default::default()
+        };
+        assert!(entry.allows_model("anything"));
+
+        entry.allowed_models = vec![" models/Gemini-3-Pro-High ".to_string(), "".to_string()];
+        entry = validate_and_normalize_api_keys(vec![entry])
+            .unwrap()
+            .remove(0);
+        assert_eq!(entry.allowed_models, vec!["Gemini-3-Pro-High".to_string()]);
+        assert!(entry.allows_model("gemini-3-pro-high"));
+        assert!(!entry.allows_model("claude-opus-4-5"));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("sk-abc", "sk-abc"));
+        assert!(!constant_time_eq("sk-abc", "sk-abd"));
+        assert!(!constant_time_eq("sk-abc", "sk-ab"));
+    }
 }
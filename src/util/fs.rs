@@ -0,0 +1,43 @@
+//! 配置文件落盘：先写临时文件并 fsync，再 rename 覆盖，避免崩溃时留下半个文件。
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// 原子写入：`<path>.tmp` 写入并 fsync 后 rename 为 `path`（同目录，rename 原子）。
+pub fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut f = std::fs::File::create(&tmp)?;
+        f.write_all(data)?;
+        f.sync_all()?;
+    }
+    std::fs::rename(&tmp, path)?;
+    #[cfg(unix)]
+    if let Some(dir) = path.parent()
+        && let Ok(d) = std::fs::File::open(dir)
+    {
+        let _ = d.sync_all();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_atomic_replaces_file_without_leaving_tmp() {
+        let dir = std::env::temp_dir().join(format!("ant2api-fs-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("keys.json");
+
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert!(!dir.join("keys.json.tmp").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
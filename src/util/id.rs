@@ -10,6 +10,11 @@ pub fn session_id() -> String {
     format!("-{n}")
 }
 
+/// 持久化账号 ID。
+pub fn account_id() -> String {
+    format!("acc_{}", Uuid::new_v4().simple())
+}
+
 pub fn project_id() -> String {
     const ADJECTIVES: [&str; 10] = [
         "useful", "bright", "swift", "calm", "bold", "happy", "clever", "gentle", "quick", "brave",
@@ -35,6 +40,16 @@ pub fn chat_completion_id() -> String {
     format!("chatcmpl-{short}")
 }
 
+/// Responses API 响应 ID（resp_ 前缀）。
+pub fn response_id() -> String {
+    format!("resp_{}", Uuid::new_v4().simple())
+}
+
+/// Responses API output item ID（如 msg_ / fc_ / rs_ 前缀）。
+pub fn response_item_id(prefix: &str) -> String {
+    format!("{prefix}_{}", Uuid::new_v4().simple())
+}
+
 fn random_u64() -> u64 {
     // 复用 UUID v4 的随机源，避免额外引入 rand/getrandom 依赖。
     let b = *Uuid::new_v4().as_bytes();
@@ -1,2 +1,6 @@
+pub mod fs;
 pub mod id;
 pub mod model;
+pub mod proxy;
+pub mod system_prompt;
+pub mod virtual_model;
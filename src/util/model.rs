@@ -1,3 +1,4 @@
+use crate::util::virtual_model;
 use crate::vertex::types::ThinkingConfig;
 
 pub const CLAUDE_MAX_OUTPUT_TOKENS: i32 = 64_000;
@@ -22,21 +23,16 @@ pub fn canonical_model_id(model: &str) -> String {
     m.trim().to_string()
 }
 
+/// 规范化并小写；虚拟模型按其后端模型识别。
 fn canonical_lower(model: &str) -> String {
-    canonical_model_id(model).to_lowercase()
+    backend_model_id(model).to_lowercase()
 }
 
 pub fn backend_model_id(model: &str) -> String {
-    if let Some((_, backend)) = gemini3_flash_thinking_config(model) {
-        return backend;
+    match virtual_model::lookup(model) {
+        Some(vm) => vm.backend,
+        None => canonical_model_id(model),
     }
-    if let Some((_, backend)) = claude_opus45_thinking_config(model) {
-        return backend;
-    }
-    if let Some((_, backend)) = gemini_pro_image_size_config(model) {
-        return backend;
-    }
-    canonical_model_id(model)
 }
 
 pub fn is_claude(model: &str) -> bool {
@@ -80,6 +76,9 @@ pub fn to_api_media_resolution(value: &str) -> Option<String> {
 }
 
 pub fn is_claude_thinking(model: &str) -> bool {
+    if virtual_model::lookup(model).is_some_and(|vm| vm.thinking_disabled()) {
+        return false;
+    }
     let m = canonical_lower(model);
     if !m.starts_with("claude-") {
         return false;
@@ -92,55 +91,13 @@ pub fn is_image_model(model: &str) -> bool {
 }
 
 pub fn is_gemini3_flash(model: &str) -> bool {
-    gemini3_flash_thinking_config(model).is_some()
+    canonical_lower(model).starts_with("gemini-3-flash")
 }
 
 pub fn is_gemini_pro_image(model: &str) -> bool {
     canonical_lower(model).contains("gemini-3-pro-image")
 }
 
-pub fn gemini3_flash_thinking_config(model: &str) -> Option<(String, String)> {
-    let mut m = model.trim();
-    m = m.strip_prefix("models/").unwrap_or(m);
-    let m = m.trim().to_lowercase();
-    if m.is_empty() {
-        return None;
-    }
-
-    const BASE: &str = "gemini-3-flash";
-    const THINKING: &str = "gemini-3-flash-thinking";
-
-    if let Some(suffix) = m.strip_prefix(THINKING) {
-        return Some(("high".to_string(), format!("{BASE}{suffix}")));
-    }
-    if m.starts_with(BASE) {
-        return Some((String::new(), m));
-    }
-
-    None
-}
-
-pub fn claude_opus45_thinking_config(model: &str) -> Option<(i32, String)> {
-    let mut m = model.trim();
-    m = m.strip_prefix("models/").unwrap_or(m);
-    let m = m.trim().to_lowercase();
-    if m.is_empty() {
-        return None;
-    }
-
-    const BASE: &str = "claude-opus-4-5";
-    const THINKING: &str = "claude-opus-4-5-thinking";
-
-    if m.starts_with(THINKING) {
-        return Some((DEFAULT_CLAUDE_THINKING_BUDGET_TOKENS, m));
-    }
-    if let Some(suffix) = m.strip_prefix(BASE) {
-        return Some((0, format!("{THINKING}{suffix}")));
-    }
-
-    None
-}
-
 pub fn claude_sonnet45_thinking_budget(model: &str) -> Option<i32> {
     let mut m = model.trim();
     m = m.strip_prefix("models/").unwrap_or(m);
@@ -161,52 +118,28 @@ pub fn claude_sonnet45_thinking_budget(model: &str) -> Option<i32> {
     None
 }
 
-pub fn gemini_pro_image_size_config(model: &str) -> Option<(String, String)> {
-    let m = canonical_lower(model);
-    if m.is_empty() {
-        return None;
+/// 按模型强制的 thinkingConfig：虚拟模型定义优先，其次按后端模型的固定规则。
+pub fn forced_thinking_config(model: &str) -> Option<ThinkingConfig> {
+    if let Some(tc) = virtual_model::lookup(model).and_then(|vm| vm.thinking_config()) {
+        return Some(tc);
     }
 
-    const BASE: &str = "gemini-3-pro-image";
-    match m.as_str() {
-        "gemini-3-pro-image-1k" => Some(("1K".to_string(), BASE.to_string())),
-        "gemini-3-pro-image-2k" => Some(("2K".to_string(), BASE.to_string())),
-        "gemini-3-pro-image-4k" => Some(("4K".to_string(), BASE.to_string())),
-        _ => None,
-    }
-}
+    let backend = canonical_lower(model);
+    let budget = |thinking_budget: i32| ThinkingConfig {
+        include_thoughts: true,
+        thinking_level: String::new(),
+        thinking_budget,
+    };
 
-pub fn forced_thinking_config(model: &str) -> Option<ThinkingConfig> {
-    if let Some((level, _backend)) = gemini3_flash_thinking_config(model) {
-        if level == "high" {
-            return Some(ThinkingConfig {
-                include_thoughts: true,
-                thinking_level: "high".to_string(),
-                thinking_budget: 0,
-            });
-        }
-        // gemini-3-flash（非 "-thinking"）：强制 thinkingBudget=0。
-        return Some(ThinkingConfig {
-            include_thoughts: true,
-            thinking_level: String::new(),
-            thinking_budget: 0,
-        });
+    // gemini-3-flash：未指定思考等级时强制 thinkingBudget=0。
+    if backend.starts_with("gemini-3-flash") {
+        return Some(budget(0));
     }
-
-    if let Some(budget) = claude_sonnet45_thinking_budget(model) {
-        return Some(ThinkingConfig {
-            include_thoughts: true,
-            thinking_level: String::new(),
-            thinking_budget: budget,
-        });
+    if let Some(b) = claude_sonnet45_thinking_budget(&backend) {
+        return Some(budget(b));
     }
-
-    if let Some((budget, _backend)) = claude_opus45_thinking_config(model) {
-        return Some(ThinkingConfig {
-            include_thoughts: true,
-            thinking_level: String::new(),
-            thinking_budget: budget,
-        });
+    if backend.starts_with("claude-opus-4-5-thinking") {
+        return Some(budget(DEFAULT_CLAUDE_THINKING_BUDGET_TOKENS));
     }
 
     None
@@ -273,24 +206,6 @@ pub fn thinking_config_from_openai(model: &str, reasoning_effort: &str) -> Optio
     })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn canonical_model_id_strips_suffix_after_slash() {
-        assert_eq!(
-            canonical_model_id("Claude-Opus-4-5-Thinking/Antigravity"),
-            "Claude-Opus-4-5-Thinking"
-        );
-    }
-
-    #[test]
-    fn is_claude_thinking_accepts_suffix_after_slash() {
-        assert!(is_claude_thinking("claude-opus-4-5-thinking/antigravity"));
-    }
-}
-
 pub fn thinking_config_from_claude(
     model: &str,
     thinking_type: &str,
@@ -377,6 +292,7 @@ pub fn thinking_config_from_gemini(
     Some(tc)
 }
 
+/// 后端模型 ID + 后端模型可用的虚拟模型 ID，排序去重。
 pub fn build_sorted_model_ids(
     models: &std::collections::HashMap<String, sonic_rs::Value>,
 ) -> Vec<String> {
@@ -384,58 +300,19 @@ pub fn build_sorted_model_ids(
     let mut seen: std::collections::HashSet<String> =
         std::collections::HashSet::with_capacity(models.len() + 5);
 
-    let mut has_gemini3_flash = false;
-    let mut has_gemini3_pro_image = false;
-    let mut has_claude_opus45 = false;
-    let mut has_claude_opus45_thinking = false;
-
     for k in models.keys() {
         let idv = k.trim();
         if idv.is_empty() {
             continue;
         }
-        if idv.eq_ignore_ascii_case("gemini-3-flash") {
-            has_gemini3_flash = true;
-        }
-        if idv.eq_ignore_ascii_case("gemini-3-pro-image") {
-            has_gemini3_pro_image = true;
-        }
-        let lower = idv.to_lowercase();
-        if lower.starts_with("claude-opus-4-5-thinking") {
-            has_claude_opus45_thinking = true;
-        } else if lower.starts_with("claude-opus-4-5") {
-            has_claude_opus45 = true;
-        }
-
-        if seen.insert(idv.to_string()) {
+        if seen.insert(idv.to_lowercase()) {
             ids.push(idv.to_string());
         }
     }
 
-    // Virtual model injection: only add gemini-3-flash-thinking when gemini-3-flash exists.
-    if has_gemini3_flash {
-        const VIRTUAL: &str = "gemini-3-flash-thinking";
-        if seen.insert(VIRTUAL.to_string()) {
-            ids.push(VIRTUAL.to_string());
-        }
-    }
-    // Virtual model injection: add gemini-3-pro-image-*k variants when gemini-3-pro-image exists.
-    if has_gemini3_pro_image {
-        for virtual_id in [
-            "gemini-3-pro-image-1k",
-            "gemini-3-pro-image-2k",
-            "gemini-3-pro-image-4k",
-        ] {
-            if seen.insert(virtual_id.to_string()) {
-                ids.push(virtual_id.to_string());
-            }
-        }
-    }
-    // Virtual model injection: add claude-opus-4-5 when only claude-opus-4-5-thinking* exists.
-    if has_claude_opus45_thinking && !has_claude_opus45 {
-        const VIRTUAL: &str = "claude-opus-4-5";
-        if seen.insert(VIRTUAL.to_string()) {
-            ids.push(VIRTUAL.to_string());
+    for vm in virtual_model::get_virtual_models().iter() {
+        if seen.contains(&vm.backend.to_lowercase()) && seen.insert(vm.id.to_lowercase()) {
+            ids.push(vm.id.clone());
         }
     }
 
@@ -456,3 +333,67 @@ fn map_gemini25_effort_to_budget(_effort: &str) -> i32 {
     // Keep conservative by default: Gemini 2.5 examples commonly use small budgets (e.g. 1024).
     CLAUDE_THINKING_EFFORT_LOW_TOKENS
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_model_id_strips_suffix_after_slash() {
+        assert_eq!(
+            canonical_model_id("Claude-Opus-4-5-Thinking/Antigravity"),
+            "Claude-Opus-4-5-Thinking"
+        );
+    }
+
+    #[test]
+    fn is_claude_thinking_accepts_suffix_after_slash() {
+        assert!(is_claude_thinking("claude-opus-4-5-thinking/antigravity"));
+    }
+
+    #[test]
+    fn builtin_virtual_models_resolve_to_backend() {
+        assert_eq!(
+            backend_model_id("Gemini-3-Flash-Thinking"),
+            "gemini-3-flash"
+        );
+        assert_eq!(
+            backend_model_id("gemini-3-pro-image-2k"),
+            "gemini-3-pro-image"
+        );
+        assert_eq!(
+            backend_model_id("claude-opus-4-5"),
+            "claude-opus-4-5-thinking"
+        );
+        assert!(!is_claude_thinking("claude-opus-4-5"));
+        assert!(is_gemini3_flash("gemini-3-flash-thinking"));
+
+        let tc = forced_thinking_config("gemini-3-flash-thinking").unwrap();
+        assert_eq!(tc.thinking_level, "high");
+        let tc = forced_thinking_config("gemini-3-flash").unwrap();
+        assert_eq!((tc.thinking_level.as_str(), tc.thinking_budget), ("", 0));
+        assert_eq!(
+            forced_thinking_config("claude-opus-4-5")
+                .unwrap()
+                .thinking_budget,
+            0
+        );
+        assert_eq!(
+            forced_thinking_config("claude-opus-4-5-thinking")
+                .unwrap()
+                .thinking_budget,
+            DEFAULT_CLAUDE_THINKING_BUDGET_TOKENS
+        );
+
+        let models: std::collections::HashMap<String, sonic_rs::Value> =
+            sonic_rs::from_str(r#"{"gemini-3-flash": {}, "claude-sonnet-4-5": {}}"#).unwrap();
+        assert_eq!(
+            build_sorted_model_ids(&models),
+            [
+                "claude-sonnet-4-5",
+                "gemini-3-flash",
+                "gemini-3-flash-thinking"
+            ]
+        );
+    }
+}
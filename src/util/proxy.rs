@@ -0,0 +1,102 @@
+//! 账号级出口代理：代理 URL 校验与按代理缓存的 HTTP client。
+
+use anyhow::bail;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// 允许的代理协议（socks5h 由代理端解析域名）。
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+/// 缓存的代理数量上限；超出时整体清空（client 可随时重建）。
+const MAX_CACHED_PROXIES: usize = 64;
+
+/// 校验并归一化代理 URL；空字符串表示不使用账号代理。
+pub fn normalize_proxy_url(proxy: &str) -> anyhow::Result<String> {
+    let proxy = proxy.trim();
+    if proxy.is_empty() {
+        return Ok(String::new());
+    }
+    let url = reqwest::Url::parse(proxy).map_err(|e| anyhow::anyhow!("无效的代理 URL: {e}"))?;
+    if !PROXY_SCHEMES.contains(&url.scheme()) {
+        bail!(
+            "不支持的代理协议: {}（仅支持 http/https/socks5）",
+            url.scheme()
+        );
+    }
+    if url.host_str().unwrap_or_default().is_empty() {
+        bail!("代理 URL 缺少主机");
+    }
+    Ok(proxy.to_string())
+}
+
+/// 代理 URL -> client 的小型缓存。
+#[derive(Debug)]
+pub struct ProxyClientCache<T> {
+    clients: Mutex<HashMap<String, T>>,
+}
+
+impl<T> Default for ProxyClientCache<T> {
+    fn default() -> Self {
+        Self {
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> ProxyClientCache<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取出代理对应的 client，不存在时用 `build` 构建并缓存。
+    pub fn get_or_build(
+        &self,
+        proxy: &str,
+        build: impl FnOnce(&str) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(c) = clients.get(proxy) {
+            return Ok(c.clone());
+        }
+        let client = build(proxy)?;
+        if clients.len() >= MAX_CACHED_PROXIES {
+            clients.clear();
+        }
+        clients.insert(proxy.to_string(), client.clone());
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_urls_are_validated_and_clients_cached() {
+        assert_eq!(normalize_proxy_url("  ").unwrap(), "");
+        assert_eq!(
+            normalize_proxy_url(" socks5://u:p@10.0.0.1:1080 ").unwrap(),
+            "socks5://u:p@10.0.0.1:1080"
+        );
+        assert!(normalize_proxy_url("http://127.0.0.1:7890").is_ok());
+        assert!(normalize_proxy_url("ftp://127.0.0.1").is_err());
+        assert!(normalize_proxy_url("127.0.0.1:7890").is_err());
+
+        let cache = ProxyClientCache::new();
+        let mut builds = 0;
+        for _ in 0..3 {
+            let v = cache
+                .get_or_build("http://a:1", |p| {
+                    builds += 1;
+                    Ok(p.len())
+                })
+                .unwrap();
+            assert_eq!(v, 10);
+        }
+        assert_eq!(builds, 1);
+        assert!(
+            cache
+                .get_or_build("http://b:1", |_| anyhow::bail!("boom"))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,375 @@
+//! 系统提示词注入策略：决定是否在请求的 systemInstruction 前注入 Agent 系统提示词，以及注入的文本。
+//!
+//! 策略保存在 `data_dir/system_prompt_policy.json`（可在 WebUI 设置页编辑），优先级：
+//! API Key 规则 > 模型规则（按顺序首个匹配）> 全局。文件不存在时使用内置策略
+//! （全局 always，image 模型与 gemini-3-flash 系列不注入）。
+
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::util::fs::write_atomic;
+use crate::util::model as modelutil;
+use crate::util::virtual_model;
+use crate::vertex::sanitize::{AGENT_SYSTEM_PROMPT, inject_system_prompt};
+use crate::vertex::types::SystemInstruction;
+
+const SYSTEM_PROMPT_POLICY_FILENAME: &str = "system_prompt_policy.json";
+
+/// 模板占位符：内置 Agent 系统提示词。
+const PLACEHOLDER_DEFAULT: &str = "{{default}}";
+/// 模板占位符：客户端请求的模型 ID。
+const PLACEHOLDER_MODEL: &str = "{{model}}";
+
+/// 注入模式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InjectionMode {
+    /// 总是注入（放在客户端系统提示词之前）。
+    #[default]
+    Always,
+    /// 从不注入。
+    Never,
+    /// 仅当客户端未提供系统提示词时注入。
+    OnlyWhenEmpty,
+}
+
+impl InjectionMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::Never => "never",
+            Self::OnlyWhenEmpty => "only-when-empty",
+        }
+    }
+}
+
+/// 按模型的规则；`model` 支持 `*` 通配（如 `*image*`、`gemini-3-flash*`）。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPromptRule {
+    pub model: String,
+    pub mode: InjectionMode,
+    /// 自定义模板（为空时沿用全局模板）。
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub template: String,
+}
+
+/// 按 API Key（名称）的规则。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyPromptRule {
+    pub name: String,
+    pub mode: InjectionMode,
+    /// 自定义模板（为空时沿用模型规则 / 全局模板）。
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub template: String,
+}
+
+/// 系统提示词注入策略。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemPromptPolicy {
+    #[serde(default)]
+    pub mode: InjectionMode,
+    /// 全局模板（为空时使用内置 Agent 系统提示词）；支持 `{{default}}`、`{{model}}` 占位符。
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub template: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<ModelPromptRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<ApiKeyPromptRule>,
+}
+
+/// 某次请求生效的策略。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedPrompt {
+    pub mode: InjectionMode,
+    /// 待注入的文本（已展开占位符）。
+    pub text: String,
+    /// 生效规则：`global` / `model:<pattern>` / `apiKey:<name>`。
+    pub source: String,
+}
+
+/// 内置策略（与历史行为一致：image 模型与 gemini-3-flash 系列不注入）。
+pub fn builtin_system_prompt_policy() -> SystemPromptPolicy {
+    let never = |model: &str| ModelPromptRule {
+        model: model.to_string(),
+        mode: InjectionMode::Never,
+        template: String::new(),
+    };
+    SystemPromptPolicy {
+        mode: InjectionMode::Always,
+        template: String::new(),
+        models: vec![never("*image*"), never("gemini-3-flash*")],
+        api_keys: Vec::new(),
+    }
+}
+
+impl SystemPromptPolicy {
+    /// 解析模型 + API Key 对应的策略。
+    pub fn resolve(&self, model: &str, api_key_name: Option<&str>) -> ResolvedPrompt {
+        let client_id = modelutil::canonical_model_id(model).to_lowercase();
+        let backend_id = modelutil::backend_model_id(model).to_lowercase();
+        let model_rule = self
+            .models
+            .iter()
+            .find(|r| glob_match(&r.model, &client_id) || glob_match(&r.model, &backend_id));
+        let key_rule = api_key_name.and_then(|name| self.api_keys.iter().find(|r| r.name == name));
+
+        let (mode, source) = match (key_rule, model_rule) {
+            (Some(k), _) => (k.mode, format!("apiKey:{}", k.name)),
+            (None, Some(m)) => (m.mode, format!("model:{}", m.model)),
+            (None, None) => (self.mode, "global".to_string()),
+        };
+        let template = [
+            key_rule.map(|r| r.template.as_str()),
+            model_rule.map(|r| r.template.as_str()),
+            Some(self.template.as_str()),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|t| !t.is_empty())
+        .unwrap_or(PLACEHOLDER_DEFAULT);
+
+        ResolvedPrompt {
+            mode,
+            text: render_template(template, &modelutil::canonical_model_id(model)),
+            source,
+        }
+    }
+}
+
+fn render_template(template: &str, model: &str) -> String {
+    template
+        .replace(PLACEHOLDER_DEFAULT, AGENT_SYSTEM_PROMPT)
+        .replace(PLACEHOLDER_MODEL, model)
+}
+
+/// 简单通配匹配：`*` 匹配任意长度字符（模式与文本均为小写）。
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let [first, middle @ .., last] = parts.as_slice() else {
+        return pattern == text;
+    };
+    if text.len() < first.len() + last.len() || !text.starts_with(first) || !text.ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+static POLICY: OnceLock<ArcSwap<SystemPromptPolicy>> = OnceLock::new();
+
+fn store() -> &'static ArcSwap<SystemPromptPolicy> {
+    POLICY.get_or_init(|| ArcSwap::from_pointee(builtin_system_prompt_policy()))
+}
+
+/// 当前策略快照。
+pub fn get_system_prompt_policy() -> Arc<SystemPromptPolicy> {
+    store().load_full()
+}
+
+/// 更新策略（立即生效）。
+pub fn update_system_prompt_policy(policy: SystemPromptPolicy) {
+    store().store(Arc::new(policy));
+}
+
+/// 按当前策略解析。
+pub fn resolve(model: &str, api_key_name: Option<&str>) -> ResolvedPrompt {
+    store().load().resolve(model, api_key_name)
+}
+
+/// 组装网关侧系统提示词：按策略注入 Agent 系统提示词，并追加虚拟模型的系统提示词。
+/// 最终顺序：Agent 系统提示词 → 虚拟模型系统提示词 → 客户端系统提示词。返回是否注入了 Agent 系统提示词。
+pub fn apply(model: &str, api_key_name: Option<&str>, sys: &mut Option<SystemInstruction>) -> bool {
+    let resolved = resolve(model, api_key_name);
+    let inject = match resolved.mode {
+        InjectionMode::Always => true,
+        InjectionMode::Never => false,
+        InjectionMode::OnlyWhenEmpty => is_empty_instruction(sys.as_ref()),
+    } && !resolved.text.trim().is_empty();
+
+    virtual_model::apply_system_prompt(model, sys);
+    if inject {
+        *sys = Some(inject_system_prompt(sys.take(), &resolved.text));
+    }
+    inject
+}
+
+fn is_empty_instruction(sys: Option<&SystemInstruction>) -> bool {
+    sys.is_none_or(|si| si.parts.iter().all(|p| p.text.trim().is_empty()))
+}
+
+/// 规范化 + 校验策略。
+pub fn validate_and_normalize_system_prompt_policy(
+    mut policy: SystemPromptPolicy,
+) -> Result<SystemPromptPolicy, String> {
+    policy.template = policy.template.trim().to_string();
+
+    let mut seen: HashSet<String> = HashSet::with_capacity(policy.models.len());
+    for rule in policy.models.iter_mut() {
+        rule.model = rule.model.trim().to_lowercase();
+        rule.template = rule.template.trim().to_string();
+        if rule.model.is_empty() {
+            return Err("存在空的模型规则".to_string());
+        }
+        if !seen.insert(rule.model.clone()) {
+            return Err(format!("模型规则重复：\"{}\"", rule.model));
+        }
+    }
+
+    let mut seen: HashSet<String> = HashSet::with_capacity(policy.api_keys.len());
+    for rule in policy.api_keys.iter_mut() {
+        rule.name = rule.name.trim().to_string();
+        rule.template = rule.template.trim().to_string();
+        if rule.name.is_empty() {
+            return Err("存在空的 API Key 名称".to_string());
+        }
+        if !seen.insert(rule.name.clone()) {
+            return Err(format!("API Key 规则重复：\"{}\"", rule.name));
+        }
+    }
+
+    Ok(policy)
+}
+
+pub fn system_prompt_policy_file_path(data_dir: &str) -> std::path::PathBuf {
+    Path::new(data_dir).join(SYSTEM_PROMPT_POLICY_FILENAME)
+}
+
+/// 读取策略；文件不存在或无效时返回内置策略。
+pub fn load_system_prompt_policy_from_data_dir(data_dir: &str) -> SystemPromptPolicy {
+    let path = system_prompt_policy_file_path(data_dir);
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return builtin_system_prompt_policy();
+    };
+
+    let parsed = match serde_json::from_str::<SystemPromptPolicy>(&text) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("系统提示词策略文件解析失败（{}）：{e}", path.display());
+            return builtin_system_prompt_policy();
+        }
+    };
+
+    match validate_and_normalize_system_prompt_policy(parsed) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("系统提示词策略文件校验失败（{}）：{e}", path.display());
+            builtin_system_prompt_policy()
+        }
+    }
+}
+
+pub fn persist_system_prompt_policy_to_data_dir(
+    data_dir: &str,
+    policy: &SystemPromptPolicy,
+) -> Result<(), String> {
+    let normalized = validate_and_normalize_system_prompt_policy(policy.clone())?;
+    let dir = Path::new(data_dir);
+    std::fs::create_dir_all(dir).map_err(|e| format!("无法创建数据目录 {}: {e}", dir.display()))?;
+
+    let path = system_prompt_policy_file_path(data_dir);
+    let content =
+        serde_json::to_string_pretty(&normalized).map_err(|e| format!("序列化失败: {e}"))?;
+    write_atomic(&path, format!("{content}\n").as_bytes())
+        .map_err(|e| format!("无法写入 {}: {e}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_resolves_api_key_then_model_then_global() {
+        let policy: SystemPromptPolicy = serde_json::from_str(
+            r#"{
+                "mode": "only-when-empty",
+                "template": "{{default}}\n[{{model}}]",
+                "models": [
+                    {"model": " *IMAGE* ", "mode": "never"},
+                    {"model": "claude-*", "mode": "always", "template": "Claude prompt"}
+                ],
+                "apiKeys": [{"name": "alice", "mode": "never"}]
+            }"#,
+        )
+        .unwrap();
+        let policy = validate_and_normalize_system_prompt_policy(policy).unwrap();
+
+        let global = policy.resolve("models/gemini-2.5-pro", None);
+        assert_eq!(global.mode, InjectionMode::OnlyWhenEmpty);
+        assert_eq!(global.source, "global");
+        assert_eq!(
+            global.text,
+            format!("{AGENT_SYSTEM_PROMPT}\n[gemini-2.5-pro]")
+        );
+
+        // 虚拟模型按后端模型 ID 同样可以匹配。
+        let image = policy.resolve("gemini-3-pro-image-2k", None);
+        assert_eq!(
+            (image.mode, image.source.as_str()),
+            (InjectionMode::Never, "model:*image*")
+        );
+
+        let claude = policy.resolve("claude-sonnet-4-5", Some("bob"));
+        assert_eq!(claude.mode, InjectionMode::Always);
+        assert_eq!(claude.text, "Claude prompt");
+
+        // API Key 规则优先，模板沿用模型规则。
+        let alice = policy.resolve("claude-sonnet-4-5", Some("alice"));
+        assert_eq!(
+            (alice.mode, alice.source.as_str()),
+            (InjectionMode::Never, "apiKey:alice")
+        );
+        assert_eq!(alice.text, "Claude prompt");
+
+        let dup = SystemPromptPolicy {
+            models: vec![policy.models[0].clone(), policy.models[0].clone()],
+            ..SystemPromptPolicy::default()
+        };
+        assert!(validate_and_normalize_system_prompt_policy(dup).is_err());
+
+        assert!(glob_match("gemini-3-flash*", "gemini-3-flash"));
+        assert!(glob_match("*pro*image", "gemini-3-pro-image"));
+        assert!(!glob_match("*image*", "gemini-3-pro"));
+
+        let builtin = builtin_system_prompt_policy();
+        assert_eq!(
+            builtin.resolve("gemini-3-flash-thinking", None).mode,
+            InjectionMode::Never
+        );
+        assert_eq!(
+            builtin.resolve("gemini-3-pro", None).mode,
+            InjectionMode::Always
+        );
+    }
+
+    #[test]
+    fn only_when_empty_checks_client_instruction() {
+        assert!(is_empty_instruction(None));
+        let blank = SystemInstruction {
+            role: "user".to_string(),
+            parts: vec![crate::vertex::types::Part {
+                text: "  ".to_string(),
+                ..Default::default()
+            }],
+        };
+        assert!(is_empty_instruction(Some(&blank)));
+
+        let mut sys = Some(blank);
+        sys.as_mut().unwrap().parts[0].text = "client".to_string();
+        assert!(!is_empty_instruction(sys.as_ref()));
+        let merged = inject_system_prompt(sys.take(), "gateway");
+        assert_eq!(merged.parts[0].text, "gateway\n\nclient");
+    }
+}
@@ -0,0 +1,389 @@
+//! 虚拟模型：网关对外提供、实际转发到某个后端模型并强制部分生成参数的模型 ID。
+//!
+//! 定义保存在 `data_dir/virtual_models.json`（可在 WebUI 模型设置页编辑），文件不存在时使用内置定义。
+//! 仅当后端模型可用时，虚拟模型才会出现在模型列表中。
+
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::util::fs::write_atomic;
+use crate::util::model as modelutil;
+use crate::vertex::types::{
+    GenerationConfig, ImageConfig, Part, SystemInstruction, ThinkingConfig,
+};
+
+const VIRTUAL_MODELS_FILENAME: &str = "virtual_models.json";
+
+const THINKING_LEVELS: &[&str] = &["minimal", "low", "medium", "high"];
+const IMAGE_SIZES: &[&str] = &["1K", "2K", "4K"];
+
+/// 单个虚拟模型定义（未设置的覆盖项沿用请求参数 / 后端模型默认行为）。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualModel {
+    /// 对外模型 ID。
+    pub id: String,
+    /// 实际转发的后端模型 ID。
+    pub backend: String,
+    /// 强制 thinkingBudget（0 = 关闭思考）。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking_budget: Option<i32>,
+    /// 强制 thinkingLevel（minimal / low / medium / high）。
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub thinking_level: String,
+    /// 图片尺寸（1K / 2K / 4K）。
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub image_size: String,
+    /// 图片宽高比（如 16:9）。
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub aspect_ratio: String,
+    /// 媒体分辨率（low / medium / high）。
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub media_resolution: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// 追加在客户端系统提示词之前的系统提示词。
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub system_prompt: String,
+}
+
+impl VirtualModel {
+    /// 虚拟模型强制的 thinkingConfig；未覆盖思考参数时返回 None。
+    pub fn thinking_config(&self) -> Option<ThinkingConfig> {
+        if !self.thinking_level.is_empty() {
+            return Some(ThinkingConfig {
+                include_thoughts: true,
+                thinking_level: self.thinking_level.clone(),
+                thinking_budget: 0,
+            });
+        }
+        self.thinking_budget.map(|budget| ThinkingConfig {
+            include_thoughts: true,
+            thinking_level: String::new(),
+            thinking_budget: budget,
+        })
+    }
+
+    /// 是否显式关闭了思考。
+    pub fn thinking_disabled(&self) -> bool {
+        self.thinking_level.is_empty() && self.thinking_budget == Some(0)
+    }
+
+    fn apply_generation_overrides(&self, gc: &mut GenerationConfig) {
+        if !self.image_size.is_empty() || !self.aspect_ratio.is_empty() {
+            let mut ic = gc.image_config.take().unwrap_or(ImageConfig {
+                aspect_ratio: String::new(),
+                image_size: String::new(),
+            });
+            if !self.image_size.is_empty() {
+                ic.image_size = self.image_size.clone();
+            }
+            if !self.aspect_ratio.is_empty() {
+                ic.aspect_ratio = self.aspect_ratio.clone();
+            }
+            gc.image_config = Some(ic);
+        }
+        if let Some(v) = modelutil::to_api_media_resolution(&self.media_resolution)
+            && !v.is_empty()
+        {
+            gc.media_resolution = v;
+        }
+        if self.temperature.is_some() {
+            gc.temperature = self.temperature;
+        }
+    }
+
+    fn apply_system_prompt(&self, sys: &mut Option<SystemInstruction>) {
+        if self.system_prompt.is_empty() {
+            return;
+        }
+        let part = Part {
+            text: self.system_prompt.clone(),
+            ..Part::default()
+        };
+        match sys {
+            Some(si) => si.parts.insert(0, part),
+            None => {
+                *sys = Some(SystemInstruction {
+                    role: "user".to_string(),
+                    parts: vec![part],
+                })
+            }
+        }
+    }
+}
+
+/// 内置定义（与历史硬编码行为一致）。
+pub fn builtin_virtual_models() -> Vec<VirtualModel> {
+    let image = |size: &str| VirtualModel {
+        id: format!("gemini-3-pro-image-{}", size.to_lowercase()),
+        backend: "gemini-3-pro-image".to_string(),
+        image_size: size.to_string(),
+        ..VirtualModel::default()
+    };
+    vec![
+        VirtualModel {
+            id: "gemini-3-flash-thinking".to_string(),
+            backend: "gemini-3-flash".to_string(),
+            thinking_level: "high".to_string(),
+            ..VirtualModel::default()
+        },
+        image("1K"),
+        image("2K"),
+        image("4K"),
+        VirtualModel {
+            id: "claude-opus-4-5".to_string(),
+            backend: "claude-opus-4-5-thinking".to_string(),
+            thinking_budget: Some(0),
+            ..VirtualModel::default()
+        },
+    ]
+}
+
+static VIRTUAL_MODELS: OnceLock<ArcSwap<Vec<VirtualModel>>> = OnceLock::new();
+
+fn store() -> &'static ArcSwap<Vec<VirtualModel>> {
+    VIRTUAL_MODELS.get_or_init(|| ArcSwap::from_pointee(builtin_virtual_models()))
+}
+
+/// 当前虚拟模型定义快照。
+pub fn get_virtual_models() -> Arc<Vec<VirtualModel>> {
+    store().load_full()
+}
+
+/// 更新虚拟模型定义（立即生效）。
+pub fn update_virtual_models(models: Vec<VirtualModel>) {
+    store().store(Arc::new(models));
+}
+
+/// 按模型 ID 查找虚拟模型（忽略大小写与 `models/` 前缀）。
+pub fn lookup(model: &str) -> Option<VirtualModel> {
+    let key = modelutil::canonical_model_id(model);
+    if key.is_empty() {
+        return None;
+    }
+    store()
+        .load()
+        .iter()
+        .find(|v| v.id.eq_ignore_ascii_case(&key))
+        .cloned()
+}
+
+/// 应用虚拟模型的生成参数覆盖（图片尺寸/宽高比、媒体分辨率、温度）。
+pub fn apply_generation_overrides(model: &str, gc: &mut GenerationConfig) {
+    if let Some(vm) = lookup(model) {
+        vm.apply_generation_overrides(gc);
+    }
+}
+
+/// 将虚拟模型的系统提示词放在客户端系统提示词之前。
+pub fn apply_system_prompt(model: &str, sys: &mut Option<SystemInstruction>) {
+    if let Some(vm) = lookup(model) {
+        vm.apply_system_prompt(sys);
+    }
+}
+
+/// 规范化 + 校验虚拟模型定义。
+pub fn validate_and_normalize_virtual_models(
+    models: Vec<VirtualModel>,
+) -> Result<Vec<VirtualModel>, String> {
+    let mut out = Vec::with_capacity(models.len());
+    let mut seen: HashSet<String> = HashSet::with_capacity(models.len());
+
+    for mut vm in models {
+        vm.id = modelutil::canonical_model_id(&vm.id);
+        vm.backend = modelutil::canonical_model_id(&vm.backend);
+        let id = vm.id.clone();
+
+        if id.is_empty() {
+            return Err("存在空的虚拟模型ID".to_string());
+        }
+        if vm.backend.is_empty() {
+            return Err(format!("虚拟模型 \"{id}\" 的后端模型ID不能为空"));
+        }
+        if vm.backend.eq_ignore_ascii_case(&id) {
+            return Err(format!("虚拟模型 \"{id}\" 不能指向自身"));
+        }
+        if !seen.insert(id.to_lowercase()) {
+            return Err(format!("虚拟模型ID重复：\"{id}\""));
+        }
+
+        vm.thinking_level = vm.thinking_level.trim().to_lowercase();
+        if !vm.thinking_level.is_empty() && !THINKING_LEVELS.contains(&vm.thinking_level.as_str()) {
+            return Err(format!(
+                "虚拟模型 \"{id}\" 的 thinkingLevel 无效（可选：{}）",
+                THINKING_LEVELS.join(" / ")
+            ));
+        }
+        if vm.thinking_budget.is_some_and(|b| b < 0) {
+            return Err(format!("虚拟模型 \"{id}\" 的 thinkingBudget 不能为负数"));
+        }
+        if vm.thinking_budget.is_some() && !vm.thinking_level.is_empty() {
+            return Err(format!(
+                "虚拟模型 \"{id}\" 不能同时设置 thinkingBudget 与 thinkingLevel"
+            ));
+        }
+
+        vm.image_size = vm.image_size.trim().to_uppercase();
+        if !vm.image_size.is_empty() && !IMAGE_SIZES.contains(&vm.image_size.as_str()) {
+            return Err(format!(
+                "虚拟模型 \"{id}\" 的 imageSize 无效（可选：{}）",
+                IMAGE_SIZES.join(" / ")
+            ));
+        }
+        vm.aspect_ratio = vm.aspect_ratio.trim().to_string();
+        if !vm.aspect_ratio.is_empty() && !is_valid_aspect_ratio(&vm.aspect_ratio) {
+            return Err(format!(
+                "虚拟模型 \"{id}\" 的 aspectRatio 无效（示例：16:9）"
+            ));
+        }
+        vm.media_resolution = modelutil::validate_media_resolution(&vm.media_resolution)
+            .ok_or_else(|| {
+                format!("虚拟模型 \"{id}\" 的 mediaResolution 无效（可选：low / medium / high）")
+            })?;
+        if vm
+            .temperature
+            .is_some_and(|t| !t.is_finite() || !(0.0..=2.0).contains(&t))
+        {
+            return Err(format!("虚拟模型 \"{id}\" 的 temperature 需在 0 到 2 之间"));
+        }
+        vm.system_prompt = vm.system_prompt.trim().to_string();
+
+        out.push(vm);
+    }
+
+    Ok(out)
+}
+
+fn is_valid_aspect_ratio(value: &str) -> bool {
+    let Some((w, h)) = value.split_once(':') else {
+        return false;
+    };
+    matches!((w.parse::<u32>(), h.parse::<u32>()), (Ok(w), Ok(h)) if w > 0 && h > 0)
+}
+
+pub fn virtual_models_file_path(data_dir: &str) -> std::path::PathBuf {
+    Path::new(data_dir).join(VIRTUAL_MODELS_FILENAME)
+}
+
+/// 读取虚拟模型定义；文件不存在时返回内置定义。
+pub fn load_virtual_models_from_data_dir(data_dir: &str) -> Vec<VirtualModel> {
+    let path = virtual_models_file_path(data_dir);
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return builtin_virtual_models();
+    };
+
+    let parsed = match serde_json::from_str::<Vec<VirtualModel>>(&text) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("虚拟模型文件解析失败（{}）：{e}", path.display());
+            return builtin_virtual_models();
+        }
+    };
+
+    match validate_and_normalize_virtual_models(parsed) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("虚拟模型文件校验失败（{}）：{e}", path.display());
+            builtin_virtual_models()
+        }
+    }
+}
+
+pub fn persist_virtual_models_to_data_dir(
+    data_dir: &str,
+    models: &[VirtualModel],
+) -> Result<(), String> {
+    let normalized = validate_and_normalize_virtual_models(models.to_vec())?;
+    let dir = Path::new(data_dir);
+    std::fs::create_dir_all(dir).map_err(|e| format!("无法创建数据目录 {}: {e}", dir.display()))?;
+
+    let path = virtual_models_file_path(data_dir);
+    let content =
+        serde_json::to_string_pretty(&normalized).map_err(|e| format!("序列化失败: {e}"))?;
+    write_atomic(&path, format!("{content}\n").as_bytes())
+        .map_err(|e| format!("无法写入 {}: {e}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions_are_validated_and_overrides_applied() {
+        let raw = r#"[
+            {"id": " models/my-fast ", "backend": "gemini-3-flash", "thinkingLevel": "LOW",
+             "mediaResolution": "high", "temperature": 0.3, "systemPrompt": " 简洁回答 "},
+            {"id": "poster", "backend": "gemini-3-pro-image", "imageSize": "4k", "aspectRatio": "16:9"}
+        ]"#;
+        let parsed: Vec<VirtualModel> = serde_json::from_str(raw).unwrap();
+        let out = validate_and_normalize_virtual_models(parsed).unwrap();
+        assert_eq!(out[0].id, "my-fast");
+        assert_eq!(out[0].thinking_level, "low");
+        assert_eq!(out[0].system_prompt, "简洁回答");
+        assert_eq!(out[1].image_size, "4K");
+
+        let invalid = |vm: VirtualModel| validate_and_normalize_virtual_models(vec![vm]).is_err();
+        let base = VirtualModel {
+            id: "x".to_string(),
+            backend: "gemini-3-flash".to_string(),
+            ..VirtualModel::default()
+        };
+        assert!(invalid(VirtualModel {
+            backend: "X".to_string(),
+            ..base.clone()
+        }));
+        assert!(invalid(VirtualModel {
+            thinking_budget: Some(1024),
+            thinking_level: "high".to_string(),
+            ..base.clone()
+        }));
+        assert!(invalid(VirtualModel {
+            aspect_ratio: "wide".to_string(),
+            ..base.clone()
+        }));
+        assert!(invalid(VirtualModel {
+            temperature: Some(3.0),
+            ..base.clone()
+        }));
+        assert!(validate_and_normalize_virtual_models(vec![base.clone(), base]).is_err());
+
+        let fast = &out[0];
+        assert_eq!(fast.thinking_config().unwrap().thinking_level, "low");
+        assert!(!fast.thinking_disabled());
+        let opus = builtin_virtual_models().pop().unwrap();
+        assert!(opus.thinking_disabled());
+
+        let mut sys = Some(SystemInstruction {
+            role: "user".to_string(),
+            parts: vec![Part {
+                text: "client".to_string(),
+                ..Part::default()
+            }],
+        });
+        fast.apply_system_prompt(&mut sys);
+        let texts: Vec<&str> = sys
+            .iter()
+            .flat_map(|s| &s.parts)
+            .map(|p| p.text.as_str())
+            .collect();
+        assert_eq!(texts, ["简洁回答", "client"]);
+
+        let mut gc: GenerationConfig =
+            serde_json::from_str(r#"{"temperature": 1.0, "imageConfig": {"imageSize": "1K"}}"#)
+                .unwrap();
+        fast.apply_generation_overrides(&mut gc);
+        assert_eq!(gc.temperature, Some(0.3));
+        assert_eq!(gc.media_resolution, "MEDIA_RESOLUTION_HIGH");
+        out[1].apply_generation_overrides(&mut gc);
+        let ic = gc.image_config.unwrap();
+        assert_eq!(
+            (ic.image_size.as_str(), ic.aspect_ratio.as_str()),
+            ("4K", "16:9")
+        );
+    }
+}
@@ -1,10 +1,14 @@
 use crate::config::Config;
 use crate::logging;
+use crate::metrics;
+use crate::util::proxy::ProxyClientCache;
+use crate::vertex::endpoints;
 use crate::vertex::stream;
-use crate::vertex::types::{Request, Response};
+use crate::vertex::types::{Content, Request, Response};
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue, USER_AGENT};
 use sonic_rs::{JsonContainerTrait, JsonValueMutTrait, JsonValueTrait};
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
 
@@ -29,6 +33,10 @@ impl Endpoint {
     pub fn fetch_available_models_url(&self) -> String {
         format!("https://{}/v1internal:fetchAvailableModels", self.host)
     }
+
+    pub fn count_tokens_url(&self) -> String {
+        format!("https://{}/v1internal:countTokens", self.host)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -45,6 +53,10 @@ pub enum ApiError {
     #[error("Vertex 流式响应解析失败: {message}")]
     StreamParse { message: String },
 
+    /// 账号代理 client 构建失败：拒绝请求，不回退到共享出口。
+    #[error("账号代理不可用: {message}")]
+    Proxy { message: String },
+
     #[error(transparent)]
     Transport(#[from] reqwest::Error),
 
@@ -89,55 +101,73 @@ impl ApiError {
 pub struct VertexClient {
     http: reqwest::Client,
     http_stream: reqwest::Client,
+    /// 账号代理 -> (HTTP/1.1, HTTP/2 流式) client。
+    proxied: Arc<ProxyClientCache<(reqwest::Client, reqwest::Client)>>,
+    timeout: Option<Duration>,
     retry_status_codes: Vec<u16>,
     retry_max_attempts: usize,
     user_agent: String,
     log_level: logging::LogLevel,
+    /// 账号代理 client 构建失败的原因；存在时所有请求直接返回 [`ApiError::Proxy`]。
+    proxy_error: Option<String>,
 }
 
 impl VertexClient {
     pub fn new(cfg: &Config) -> Result<Self, anyhow::Error> {
-        // 大多数后端请求维持 HTTP/1.1（拉取模型/非流式）。
-        let mut http1_builder = reqwest::Client::builder()
-            .pool_max_idle_per_host(10)
-            .pool_idle_timeout(Duration::from_secs(90))
-            .http1_only();
-
-        // 仅后端流式接口强制使用 HTTP/2（SSE）。
-        let mut http2_stream_builder = reqwest::Client::builder()
-            .pool_max_idle_per_host(10)
-            .pool_idle_timeout(Duration::from_secs(90))
-            .http2_prior_knowledge();
-
         let timeout = if cfg.timeout_ms > 0 {
             Some(Duration::from_millis(cfg.timeout_ms))
         } else {
             None
         };
-        if let Some(t) = timeout {
-            http1_builder = http1_builder.timeout(t);
-            http2_stream_builder = http2_stream_builder.timeout(t);
-        }
-
-        if !cfg.proxy.trim().is_empty() {
-            // Proxy 不保证可 Clone，这里各自构建一次避免 trait 约束。
-            http1_builder = http1_builder.proxy(reqwest::Proxy::all(cfg.proxy.trim())?);
-            http2_stream_builder =
-                http2_stream_builder.proxy(reqwest::Proxy::all(cfg.proxy.trim())?);
-        }
-
-        let http = http1_builder.build()?;
-        let http_stream = http2_stream_builder.build()?;
+        let (http, http_stream) = build_clients(timeout, cfg.proxy.trim())?;
         Ok(Self {
             http,
             http_stream,
+            proxied: Arc::new(ProxyClientCache::new()),
+            timeout,
             retry_status_codes: cfg.retry_status_codes.clone(),
             retry_max_attempts: cfg.retry_max_attempts.max(1),
             user_agent: cfg.api_user_agent.clone(),
             log_level: cfg.log_level(),
+            proxy_error: None,
         })
     }
 
+    /// 使用账号代理出口的 client（代理为空时即全局 client）。
+    /// 代理构建失败时不回退到全局 client：返回的 client 对所有请求报 [`ApiError::Proxy`]。
+    pub fn for_proxy(&self, proxy: &str) -> Self {
+        let proxy = proxy.trim();
+        if proxy.is_empty() {
+            return self.clone();
+        }
+        match self
+            .proxied
+            .get_or_build(proxy, |p| build_clients(self.timeout, p))
+        {
+            Ok((http, http_stream)) => Self {
+                http,
+                http_stream,
+                ..self.clone()
+            },
+            Err(e) => {
+                tracing::warn!("账号代理 client 构建失败，拒绝请求: {e}");
+                Self {
+                    proxy_error: Some(e.to_string()),
+                    ..self.clone()
+                }
+            }
+        }
+    }
+
+    fn check_proxy(&self) -> Result<(), ApiError> {
+        match &self.proxy_error {
+            Some(message) => Err(ApiError::Proxy {
+                message: message.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
     pub fn build_headers(&self, access_token: &str, _endpoint: &Endpoint) -> HeaderMap {
         let mut h = HeaderMap::new();
         h.insert(
@@ -195,10 +225,8 @@ impl VertexClient {
             |_| async { Ok(()) },
             true,
             |line| {
-                if backend_raw {
-                    if line.starts_with(b"data:") || line.starts_with(b":") {
-                        logging::backend_stream_line_raw(line);
-                    }
+                if backend_raw && (line.starts_with(b"data:") || line.starts_with(b":")) {
+                    logging::backend_stream_line_raw(line);
                 }
             },
         )
@@ -224,59 +252,105 @@ impl VertexClient {
         Ok(sonic_rs::from_value::<Response>(&merged)?)
     }
 
+    /// 流式请求；当前主机因连接错误或连续 5xx 被标记为不健康时，切换到端点池中的下一个主机。
     pub async fn generate_content_stream(
         &self,
         endpoint: &Endpoint,
         access_token: &str,
         req: &Request,
         email: &str,
+    ) -> Result<reqwest::Response, ApiError> {
+        self.check_proxy()?;
+        let pool = endpoints::pool();
+        let mut endpoint = endpoint.clone();
+        let max_hosts = endpoints::hosts_for_mode(&endpoint.key).len();
+        let mut tried = 1usize;
+        loop {
+            let res = self
+                .with_retry(|| async {
+                    let res = self
+                        .generate_content_stream_once(&endpoint, access_token, req, email)
+                        .await;
+                    if let Err(e) = &res {
+                        pool.report_failure(&endpoint.host, e);
+                    }
+                    res
+                })
+                .await;
+            match res {
+                Ok(resp) => {
+                    pool.report_success(&endpoint.host);
+                    return Ok(resp);
+                }
+                Err(e) if tried < max_hosts && !pool.is_healthy(&endpoint.host) => {
+                    let Some(next) = pool.next_after(&endpoint) else {
+                        return Err(e);
+                    };
+                    tracing::warn!(
+                        "后端主机 {} 不可用（{e}），切换到 {}",
+                        endpoint.host,
+                        next.host
+                    );
+                    endpoint = next;
+                    tried += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn generate_content_stream_once(
+        &self,
+        endpoint: &Endpoint,
+        access_token: &str,
+        req: &Request,
+        email: &str,
     ) -> Result<reqwest::Response, ApiError> {
         let url = endpoint.stream_url();
-        self.with_retry(|| async {
-            let body = sonic_rs::to_vec(req)?;
-            let headers = self.build_stream_headers(access_token, endpoint);
-            if self.log_level.backend_enabled() {
-                if self.log_level.raw_enabled() {
-                    logging::backend_request_raw("POST", &url, &headers, &body);
-                } else if let Ok(mut v) = sonic_rs::from_slice::<sonic_rs::Value>(&body) {
-                    if let Some(obj) = v.as_object_mut() {
-                        obj.insert("account", sonic_rs::Value::from(email));
-                        if let Ok(log_body) = sonic_rs::to_vec(&v) {
-                            logging::backend_request("POST", &url, &headers, &log_body);
-                        } else {
-                            logging::backend_request("POST", &url, &headers, &body);
-                        }
+        let body = sonic_rs::to_vec(req)?;
+        let headers = self.build_stream_headers(access_token, endpoint);
+        if self.log_level.backend_enabled() {
+            if self.log_level.raw_enabled() {
+                logging::backend_request_raw("POST", &url, &headers, &body);
+            } else if let Ok(mut v) = sonic_rs::from_slice::<sonic_rs::Value>(&body) {
+                if let Some(obj) = v.as_object_mut() {
+                    obj.insert("account", sonic_rs::Value::from(email));
+                    if let Ok(log_body) = sonic_rs::to_vec(&v) {
+                        logging::backend_request("POST", &url, &headers, &log_body);
                     } else {
                         logging::backend_request("POST", &url, &headers, &body);
                     }
                 } else {
                     logging::backend_request("POST", &url, &headers, &body);
                 }
+            } else {
+                logging::backend_request("POST", &url, &headers, &body);
             }
-            let start = std::time::Instant::now();
-            let resp = self
-                .http_stream
-                .post(url.clone())
-                .headers(headers)
-                .body(body)
-                .send()
-                .await?;
-
-            if !resp.status().is_success() {
-                let status = resp.status();
-                let bytes = resp.bytes().await?;
-                if self.log_level.backend_enabled() {
-                    if self.log_level.raw_enabled() {
-                        logging::backend_response_raw(status.as_u16(), start.elapsed(), &bytes);
-                    } else {
-                        logging::backend_response(status.as_u16(), start.elapsed(), &bytes);
-                    }
+        }
+        let start = std::time::Instant::now();
+        let resp = self
+            .http_stream
+            .post(url.clone())
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let bytes = resp.bytes().await?;
+            if self.log_level.backend_enabled() {
+                if self.log_level.raw_enabled() {
+                    logging::backend_response_raw(status.as_u16(), start.elapsed(), &bytes);
+                } else {
+                    logging::backend_response(status.as_u16(), start.elapsed(), &bytes);
                 }
-                return Err(extract_error_details(status.as_u16(), &bytes));
             }
-            Ok(resp)
-        })
-        .await
+            let err = extract_error_details(status.as_u16(), &bytes);
+            metrics::record_upstream_error(&err);
+            return Err(err);
+        }
+        Ok(resp)
     }
 
     pub async fn fetch_available_models(
@@ -286,6 +360,7 @@ impl VertexClient {
         access_token: &str,
         email: &str,
     ) -> Result<AvailableModelsResponse, ApiError> {
+        self.check_proxy()?;
         let url = endpoint.fetch_available_models_url();
         let body = sonic_rs::to_vec(&serde_payload_project(project))?;
         let headers = self.build_headers(access_token, endpoint);
@@ -327,11 +402,65 @@ impl VertexClient {
             }
         }
         if !status.is_success() {
-            return Err(extract_error_details(status.as_u16(), &bytes));
+            let err = extract_error_details(status.as_u16(), &bytes);
+            metrics::record_upstream_error(&err);
+            return Err(err);
         }
         Ok(sonic_rs::from_slice::<AvailableModelsResponse>(&bytes)?)
     }
 
+    /// 调用后端 countTokens（仅统计 contents；system/tools 需由调用方折算进 contents）。
+    pub async fn count_tokens(
+        &self,
+        endpoint: &Endpoint,
+        access_token: &str,
+        model: &str,
+        contents: &[Content],
+    ) -> Result<CountTokensResponse, ApiError> {
+        self.check_proxy()?;
+        let url = endpoint.count_tokens_url();
+        let payload = CountTokensPayload {
+            request: CountTokensInner {
+                model: format!("models/{model}"),
+                contents,
+            },
+        };
+        let body = sonic_rs::to_vec(&payload)?;
+        let headers = self.build_headers(access_token, endpoint);
+        let start = std::time::Instant::now();
+        if self.log_level.backend_enabled() {
+            if self.log_level.raw_enabled() {
+                logging::backend_request_raw("POST", &url, &headers, &body);
+            } else {
+                logging::backend_request("POST", &url, &headers, &body);
+            }
+        }
+
+        let resp = self
+            .http
+            .post(url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+
+        let status = resp.status();
+        let bytes = resp.bytes().await?;
+        if self.log_level.backend_enabled() {
+            if self.log_level.raw_enabled() {
+                logging::backend_response_raw(status.as_u16(), start.elapsed(), &bytes);
+            } else {
+                logging::backend_response(status.as_u16(), start.elapsed(), &bytes);
+            }
+        }
+        if !status.is_success() {
+            let err = extract_error_details(status.as_u16(), &bytes);
+            metrics::record_upstream_error(&err);
+            return Err(err);
+        }
+        Ok(sonic_rs::from_slice::<CountTokensResponse>(&bytes)?)
+    }
+
     async fn with_retry<F, Fut, T>(&self, mut op: F) -> Result<T, ApiError>
     where
         F: FnMut() -> Fut,
@@ -386,6 +515,37 @@ impl VertexClient {
     }
 }
 
+/// 构建 (HTTP/1.1, HTTP/2 流式) client 对；`proxy` 为空表示直连。
+fn build_clients(
+    timeout: Option<Duration>,
+    proxy: &str,
+) -> anyhow::Result<(reqwest::Client, reqwest::Client)> {
+    // 大多数后端请求维持 HTTP/1.1（拉取模型/非流式）。
+    let mut http1_builder = reqwest::Client::builder()
+        .pool_max_idle_per_host(10)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .http1_only();
+
+    // 仅后端流式接口强制使用 HTTP/2（SSE）。
+    let mut http2_stream_builder = reqwest::Client::builder()
+        .pool_max_idle_per_host(10)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .http2_prior_knowledge();
+
+    if let Some(t) = timeout {
+        http1_builder = http1_builder.timeout(t);
+        http2_stream_builder = http2_stream_builder.timeout(t);
+    }
+
+    if !proxy.is_empty() {
+        // Proxy 不保证可 Clone，这里各自构建一次避免 trait 约束。
+        http1_builder = http1_builder.proxy(reqwest::Proxy::all(proxy)?);
+        http2_stream_builder = http2_stream_builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+
+    Ok((http1_builder.build()?, http2_stream_builder.build()?))
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 struct ProjectPayload<'a> {
     project: &'a str,
@@ -395,6 +555,24 @@ fn serde_payload_project(project: &str) -> ProjectPayload<'_> {
     ProjectPayload { project }
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+struct CountTokensPayload<'a> {
+    request: CountTokensInner<'a>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct CountTokensInner<'a> {
+    model: String,
+    contents: &'a [Content],
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CountTokensResponse {
+    #[serde(default)]
+    pub total_tokens: i64,
+}
+
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct AvailableModelsResponse {
     pub models: HashMap<String, sonic_rs::Value>,
@@ -515,6 +693,24 @@ fn parse_retry_delay_seconds(s: &str) -> Option<Duration> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::gateway::common::retry::should_retry_with_next_token;
+
+    #[tokio::test]
+    async fn broken_account_proxy_fails_closed() {
+        let client = VertexClient::new(&Config::for_test())
+            .unwrap()
+            .for_proxy("http://[broken");
+        let endpoint = Endpoint {
+            key: "daily".to_string(),
+            host: "127.0.0.1:9".to_string(),
+        };
+        let err = client
+            .count_tokens(&endpoint, "token", "gemini-2.5-flash", &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Proxy { .. }), "{err}");
+        assert!(should_retry_with_next_token(&err));
+    }
 
     #[test]
     fn extract_error_details_marks_model_capacity_exhausted() {
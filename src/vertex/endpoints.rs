@@ -0,0 +1,260 @@
+//! 后端端点池：按 `ENDPOINT_MODE` 选择 Cloud Code 主机，并记录各主机健康状态。
+//!
+//! - production / daily / autopush：固定单一主机
+//! - round-robin：三个主机轮询；round-robin-dp：仅 daily 与 production 轮询
+//! - failover：优先 production，不健康时依次切换到 daily、autopush
+//!
+//! 连接失败立即将主机标记为不健康；短时间内连续出现多次 5xx 同样标记。
+//! 冷却期内跳过不健康主机（全部不健康时仍按原顺序使用），冷却结束后自动恢复。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use super::client::{ApiError, Endpoint};
+use crate::runtime_config::{
+    AUTOPUSH_BACKEND_HOST, DAILY_BACKEND_HOST, DEFAULT_BACKEND_HOST, ENDPOINT_MODE_AUTOPUSH,
+    ENDPOINT_MODE_DAILY, ENDPOINT_MODE_FAILOVER, ENDPOINT_MODE_ROUND_ROBIN,
+    ENDPOINT_MODE_ROUND_ROBIN_DP,
+};
+
+/// 时间窗口内累计多少次 5xx 视为主机故障。
+const SERVER_ERROR_BURST: u32 = 3;
+/// 5xx 计数窗口。
+const SERVER_ERROR_WINDOW: Duration = Duration::from_secs(30);
+/// 标记为不健康后的冷却时长。
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// 模式对应的主机列表（按优先级 / 轮询顺序）。
+pub fn hosts_for_mode(mode: &str) -> &'static [&'static str] {
+    match mode {
+        ENDPOINT_MODE_DAILY => &[DAILY_BACKEND_HOST],
+        ENDPOINT_MODE_AUTOPUSH => &[AUTOPUSH_BACKEND_HOST],
+        ENDPOINT_MODE_ROUND_ROBIN | ENDPOINT_MODE_FAILOVER => &[
+            DEFAULT_BACKEND_HOST,
+            DAILY_BACKEND_HOST,
+            AUTOPUSH_BACKEND_HOST,
+        ],
+        ENDPOINT_MODE_ROUND_ROBIN_DP => &[DAILY_BACKEND_HOST, DEFAULT_BACKEND_HOST],
+        _ => &[DEFAULT_BACKEND_HOST],
+    }
+}
+
+#[derive(Debug, Default)]
+struct HostHealth {
+    server_errors: u32,
+    window_start: Option<Instant>,
+    unhealthy_until: Option<Instant>,
+    last_error: String,
+}
+
+impl HostHealth {
+    fn is_healthy_at(&self, now: Instant) -> bool {
+        self.unhealthy_until.is_none_or(|t| t <= now)
+    }
+}
+
+/// WebUI 展示用的主机状态。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointStatus {
+    pub host: String,
+    pub healthy: bool,
+    /// 剩余冷却秒数（健康时为 0）。
+    pub cooldown_secs: u64,
+    pub last_error: String,
+}
+
+#[derive(Debug, Default)]
+pub struct EndpointPool {
+    health: Mutex<HashMap<String, HostHealth>>,
+    cursor: AtomicUsize,
+}
+
+impl EndpointPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, HostHealth>> {
+        self.health.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_healthy_at(&self, host: &str, now: Instant) -> bool {
+        self.lock().get(host).is_none_or(|h| h.is_healthy_at(now))
+    }
+
+    pub fn is_healthy(&self, host: &str) -> bool {
+        self.is_healthy_at(host, Instant::now())
+    }
+
+    /// 为一次请求选择主机：轮询模式推进游标，其余模式按优先级取第一个健康主机。
+    pub fn select(&self, mode: &str) -> Endpoint {
+        let hosts = hosts_for_mode(mode);
+        let start = match mode {
+            ENDPOINT_MODE_ROUND_ROBIN | ENDPOINT_MODE_ROUND_ROBIN_DP => {
+                self.cursor.fetch_add(1, Ordering::Relaxed) % hosts.len()
+            }
+            _ => 0,
+        };
+        let now = Instant::now();
+        let host = (0..hosts.len())
+            .map(|i| hosts[(start + i) % hosts.len()])
+            .find(|h| self.is_healthy_at(h, now))
+            .unwrap_or(hosts[start]);
+        endpoint(mode, host)
+    }
+
+    /// 当前主机失败后可切换到的下一个健康主机（同一模式内，不含当前主机）。
+    pub fn next_after(&self, current: &Endpoint) -> Option<Endpoint> {
+        let hosts = hosts_for_mode(&current.key);
+        let pos = hosts.iter().position(|h| *h == current.host)?;
+        let now = Instant::now();
+        (1..hosts.len())
+            .map(|i| hosts[(pos + i) % hosts.len()])
+            .find(|h| self.is_healthy_at(h, now))
+            .map(|h| endpoint(&current.key, h))
+    }
+
+    pub fn report_success(&self, host: &str) {
+        let mut health = self.lock();
+        if let Some(h) = health.get_mut(host) {
+            h.server_errors = 0;
+            h.window_start = None;
+            h.unhealthy_until = None;
+        }
+    }
+
+    /// 记录一次失败；连接错误或 5xx 达到阈值时标记为不健康，返回主机当前是否不健康。
+    /// 其他错误（4xx、解析失败等）与主机无关，不计入。
+    pub fn report_failure(&self, host: &str, err: &ApiError) -> bool {
+        let now = Instant::now();
+        let mut health = self.lock();
+        let h = health.entry(host.to_string()).or_default();
+        match err {
+            ApiError::Transport(e) if e.is_connect() || e.is_timeout() => {
+                h.unhealthy_until = Some(now + UNHEALTHY_COOLDOWN);
+                h.last_error = e.to_string();
+            }
+            ApiError::Http {
+                status, message, ..
+            } if *status >= 500 => {
+                if h.window_start
+                    .is_none_or(|t| now.duration_since(t) >= SERVER_ERROR_WINDOW)
+                {
+                    h.window_start = Some(now);
+                    h.server_errors = 0;
+                }
+                h.server_errors += 1;
+                h.last_error = format!("{status}: {message}");
+                if h.server_errors >= SERVER_ERROR_BURST {
+                    h.unhealthy_until = Some(now + UNHEALTHY_COOLDOWN);
+                }
+            }
+            _ => {}
+        }
+        let unhealthy = !h.is_healthy_at(now);
+        if unhealthy {
+            tracing::warn!("后端主机 {host} 标记为不健康：{}", h.last_error);
+        }
+        unhealthy
+    }
+
+    /// 指定模式下各主机的状态。
+    pub fn statuses(&self, mode: &str) -> Vec<EndpointStatus> {
+        let now = Instant::now();
+        let health = self.lock();
+        hosts_for_mode(mode)
+            .iter()
+            .map(|host| {
+                let h = health.get(*host);
+                let cooldown = h
+                    .and_then(|h| h.unhealthy_until)
+                    .and_then(|t| t.checked_duration_since(now))
+                    .unwrap_or_default();
+                EndpointStatus {
+                    host: host.to_string(),
+                    healthy: cooldown.is_zero(),
+                    cooldown_secs: cooldown.as_secs(),
+                    last_error: h.map(|h| h.last_error.clone()).unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+}
+
+fn endpoint(mode: &str, host: &str) -> Endpoint {
+    Endpoint {
+        key: mode.to_string(),
+        host: host.to_string(),
+    }
+}
+
+static POOL: OnceLock<EndpointPool> = OnceLock::new();
+
+/// 全局端点池。
+pub fn pool() -> &'static EndpointPool {
+    POOL.get_or_init(EndpointPool::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_error() -> ApiError {
+        ApiError::Http {
+            status: 503,
+            message: "unavailable".to_string(),
+            retry_delay: Duration::ZERO,
+            disable_token: false,
+            model_capacity_exhausted: false,
+        }
+    }
+
+    #[test]
+    fn round_robin_rotates_and_skips_unhealthy_hosts() {
+        let pool = EndpointPool::new();
+        let a = pool.select(ENDPOINT_MODE_ROUND_ROBIN_DP).host;
+        let b = pool.select(ENDPOINT_MODE_ROUND_ROBIN_DP).host;
+        assert_ne!(a, b);
+
+        for _ in 0..SERVER_ERROR_BURST - 1 {
+            assert!(!pool.report_failure(DAILY_BACKEND_HOST, &server_error()));
+        }
+        assert!(pool.report_failure(DAILY_BACKEND_HOST, &server_error()));
+        for _ in 0..4 {
+            assert_eq!(
+                pool.select(ENDPOINT_MODE_ROUND_ROBIN_DP).host,
+                DEFAULT_BACKEND_HOST
+            );
+        }
+
+        let statuses = pool.statuses(ENDPOINT_MODE_ROUND_ROBIN_DP);
+        assert!(!statuses[0].healthy);
+        assert!(statuses[1].healthy);
+
+        pool.report_success(DAILY_BACKEND_HOST);
+        assert!(pool.statuses(ENDPOINT_MODE_ROUND_ROBIN_DP)[0].healthy);
+    }
+
+    #[test]
+    fn failover_moves_to_next_healthy_host() {
+        let pool = EndpointPool::new();
+        let first = pool.select(ENDPOINT_MODE_FAILOVER);
+        assert_eq!(first.host, DEFAULT_BACKEND_HOST);
+
+        for _ in 0..SERVER_ERROR_BURST {
+            pool.report_failure(DEFAULT_BACKEND_HOST, &server_error());
+        }
+        let next = pool.next_after(&first).unwrap();
+        assert_eq!(next.host, DAILY_BACKEND_HOST);
+        assert_eq!(pool.select(ENDPOINT_MODE_FAILOVER).host, DAILY_BACKEND_HOST);
+
+        // 单主机模式没有可切换的主机。
+        let daily = pool.select(ENDPOINT_MODE_DAILY);
+        assert!(pool.next_after(&daily).is_none());
+    }
+}
@@ -1,4 +1,5 @@
 pub mod client;
+pub mod endpoints;
 pub mod sanitize;
 pub mod stream;
 pub mod types;
@@ -6,21 +6,25 @@ pub const AGENT_SYSTEM_PROMPT: &str = r#"You are Antigravity, a powerful agentic
 You are pair programming with a USER to solve their coding task. The task may require creating a new codebase, modifying or debugging an existing codebase, or simply answering a question.
 - **Proactiveness**"#;
 
-pub fn inject_agent_system_prompt(sys_instr: Option<SystemInstruction>) -> SystemInstruction {
+/// 将网关侧系统提示词放在客户端系统提示词之前（合并到第一个 part）。
+pub fn inject_system_prompt(
+    sys_instr: Option<SystemInstruction>,
+    prompt: &str,
+) -> SystemInstruction {
     match sys_instr {
         None => SystemInstruction {
             role: "user".to_string(),
             parts: vec![Part {
-                text: AGENT_SYSTEM_PROMPT.to_string(),
+                text: prompt.to_string(),
                 ..Part::default()
             }],
         },
         Some(mut si) => {
             let existing_text = si.parts.first().map(|p| p.text.as_str()).unwrap_or("");
             let combined = if existing_text.is_empty() {
-                AGENT_SYSTEM_PROMPT.to_string()
+                prompt.to_string()
             } else {
-                format!("{AGENT_SYSTEM_PROMPT}\n\n{existing_text}")
+                format!("{prompt}\n\n{existing_text}")
             };
 
             let mut parts = Vec::with_capacity(1 + si.parts.len().saturating_sub(1));
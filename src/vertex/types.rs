@@ -26,12 +26,15 @@ pub struct InnerReq {
     pub tools: Vec<Tool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_config: Option<ToolConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub safety_settings: Vec<sonic_rs::Value>,
     pub session_id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Content {
+    #[serde(default)]
     pub role: String,
     pub parts: Vec<Part>,
 }
@@ -242,6 +245,11 @@ pub struct GenerationConfig {
     pub image_config: Option<ImageConfig>,
     #[serde(skip_serializing_if = "String::is_empty", default)]
     pub media_resolution: String,
+    /// 结构化输出：`application/json` 等。
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub response_mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub response_schema: Option<HashMap<String, sonic_rs::Value>>,
 }
 
 fn is_zero_i32(v: &i32) -> bool {
@@ -251,6 +259,7 @@ fn is_zero_i32(v: &i32) -> bool {
 #[derive(Debug, Clone, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct ThinkingConfig {
+    #[serde(default)]
     pub include_thoughts: bool,
     #[serde(default)]
     pub thinking_budget: i32,
@@ -333,6 +342,9 @@ pub struct UsageMetadata {
     pub total_token_count: i32,
     #[serde(skip_serializing_if = "is_zero_i32", default)]
     pub thoughts_token_count: i32,
+    /// 命中隐式/显式缓存的 prompt token 数（已包含在 prompt_token_count 中）。
+    #[serde(skip_serializing_if = "is_zero_i32", default)]
+    pub cached_content_token_count: i32,
 }
 
 impl Response {